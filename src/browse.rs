@@ -0,0 +1,407 @@
+use crate::error::AppError;
+use crate::proc3::{Data, FoundSnapshot};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{read, Event, KeyCode, KeyEventKind};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, size, Clear, ClearType, EnterAlternateScreen,
+    LeaveAlternateScreen,
+};
+use crossterm::{execute, queue};
+use std::collections::BTreeSet;
+use std::io;
+use std::io::{Stdout, Write};
+use std::panic;
+use std::sync::Arc;
+
+/// Browses the current found files. A refinement is published
+/// as the new found files.
+///
+/// Returns the files marked for deletion.
+pub fn browse(data: &'static Data) -> Result<Vec<String>, AppError> {
+    let mut found = data.found.get()?;
+    let mut browser = Browser::default();
+
+    let previous = install_panic_hook();
+    let guard = match TerminalGuard::enter() {
+        Ok(guard) => guard,
+        Err(e) => {
+            restore_panic_hook(previous);
+            return Err(e);
+        }
+    };
+
+    let mut stdout = io::stdout();
+    let result = loop {
+        if let Err(e) = render(&mut stdout, &mut browser, &found, data) {
+            break Err(e);
+        }
+
+        let key = match read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(e) => break Err(e.into()),
+        };
+
+        match browser.key(key.code, &found.files) {
+            Action::None => {}
+            Action::Quit => break Ok(()),
+            Action::Refine(terms) => {
+                match refine(data, &found, terms) {
+                    Ok(refined) => found = refined,
+                    Err(e) => break Err(e),
+                }
+                browser.selected = 0;
+                browser.offset = 0;
+            }
+        }
+    };
+
+    drop(guard);
+    restore_panic_hook(previous);
+
+    result?;
+    Ok(browser.marked.into_iter().collect())
+}
+
+/// Result of one key-press.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    None,
+    Quit,
+    /// Narrow the result with more search terms.
+    Refine(Vec<String>),
+}
+
+/// State of the browser, independent of the terminal.
+#[derive(Debug, Default)]
+pub struct Browser {
+    pub selected: usize,
+    /// First file shown.
+    pub offset: usize,
+    /// Show the text-lines of the selected file.
+    pub expanded: bool,
+    pub marked: BTreeSet<String>,
+    /// Input for '/'.
+    pub input: Option<String>,
+}
+
+impl Browser {
+    pub fn key(&mut self, code: KeyCode, files: &[String]) -> Action {
+        if let Some(input) = &mut self.input {
+            match code {
+                KeyCode::Esc => self.input = None,
+                KeyCode::Enter => {
+                    let terms = input.split_whitespace().map(|v| v.to_string()).collect();
+                    self.input = None;
+                    return Action::Refine(terms);
+                }
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                _ => {}
+            }
+            return Action::None;
+        }
+
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.selected = self.selected.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                if self.selected + 1 < files.len() {
+                    self.selected += 1;
+                }
+            }
+            KeyCode::PageUp => {
+                self.selected = self.selected.saturating_sub(PAGE_STEP);
+            }
+            KeyCode::PageDown => {
+                self.selected = (self.selected + PAGE_STEP).min(files.len().saturating_sub(1));
+            }
+            KeyCode::Home => self.selected = 0,
+            KeyCode::End => self.selected = files.len().saturating_sub(1),
+            KeyCode::Enter => self.expanded = !self.expanded,
+            KeyCode::Char('/') => self.input = Some(String::new()),
+            KeyCode::Char('d') => {
+                if let Some(file) = files.get(self.selected) {
+                    if !self.marked.remove(file) {
+                        self.marked.insert(file.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+        Action::None
+    }
+
+    /// Keeps the selection visible in a pane of the given height.
+    pub fn scroll(&mut self, height: usize) {
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if height > 0 && self.selected >= self.offset + height {
+            self.offset = self.selected + 1 - height;
+        }
+    }
+}
+
+const PAGE_STEP: usize = 20;
+
+/// Keeps only the files that match the additional terms too.
+fn refine(
+    data: &'static Data,
+    found: &Arc<FoundSnapshot>,
+    terms: Vec<String>,
+) -> Result<Arc<FoundSnapshot>, AppError> {
+    if terms.is_empty() {
+        return Ok(Arc::clone(found));
+    }
+
+    let matched = {
+        let mut words = data.words.lock()?;
+        let ids = words.find_ids(&terms)?;
+        words.file_names(&ids)
+    };
+
+    let files = found
+        .files
+        .iter()
+        .filter(|v| matched.contains(v))
+        .cloned()
+        .collect();
+    let mut all_terms = found.terms.clone();
+    all_terms.extend(terms);
+
+    let mut refined = FoundSnapshot::new(all_terms, files, found.line_width);
+    refined.attributes = found.attributes;
+    data.found.set(refined)
+}
+
+fn render(
+    out: &mut Stdout,
+    browser: &mut Browser,
+    found: &FoundSnapshot,
+    data: &'static Data,
+) -> Result<(), AppError> {
+    let (width, height) = size()?;
+    let width = width as usize;
+    let height = height as usize;
+
+    // header, list, separator, lines, footer
+    let body = height.saturating_sub(2);
+    let list_height = if browser.expanded { body / 2 } else { body };
+
+    browser.scroll(list_height);
+    let offset = browser.offset;
+
+    queue!(out, Clear(ClearType::All), MoveTo(0, 0))?;
+    queue!(
+        out,
+        SetAttribute(Attribute::Bold),
+        Print(clip(
+            &format!(
+                "{} files, {} marked | {}",
+                found.files.len(),
+                browser.marked.len(),
+                found.terms.join(" ")
+            ),
+            width
+        )),
+        SetAttribute(Attribute::Reset)
+    )?;
+
+    for row in 0..list_height {
+        let idx = offset + row;
+        let Some(file) = found.files.get(idx) else {
+            break;
+        };
+        let mark = if browser.marked.contains(file) {
+            '*'
+        } else {
+            ' '
+        };
+        let line = clip(&format!("{}{}:{}", mark, idx, file), width);
+
+        queue!(out, MoveTo(0, (row + 1) as u16))?;
+        if idx == browser.selected {
+            queue!(
+                out,
+                SetAttribute(Attribute::Reverse),
+                Print(line),
+                SetAttribute(Attribute::Reset)
+            )?;
+        } else {
+            queue!(out, Print(line))?;
+        }
+    }
+
+    if browser.expanded && browser.selected < found.files.len() {
+        let top = list_height + 1;
+        queue!(out, MoveTo(0, top as u16), Print("-".repeat(width)))?;
+
+        let lines_height = body.saturating_sub(list_height + 1);
+        let page = found.page(&data.root, browser.selected, 1)?;
+        if let Some((_, lines)) = page.first() {
+            for (row, line) in lines.iter().take(lines_height).enumerate() {
+                queue!(
+                    out,
+                    MoveTo(0, (top + 1 + row) as u16),
+                    Print(clip(&format!("  {}", line), width))
+                )?;
+            }
+        }
+    }
+
+    let footer = match &browser.input {
+        Some(input) => format!("/{}", input),
+        None => "[up/down] select [enter] lines [/] refine [d] mark delete [q] back".to_string(),
+    };
+    queue!(
+        out,
+        MoveTo(0, height.saturating_sub(1) as u16),
+        Print(clip(&footer, width))
+    )?;
+
+    out.flush()?;
+    Ok(())
+}
+
+fn clip(txt: &str, width: usize) -> String {
+    txt.chars().take(width).collect()
+}
+
+/// Switches to the alternate screen and back.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> Result<Self, AppError> {
+        enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        Ok(TerminalGuard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+fn restore_terminal() {
+    let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+    let _ = disable_raw_mode();
+}
+
+type PanicHook = Box<dyn Fn(&panic::PanicHookInfo<'_>) + Sync + Send + 'static>;
+
+/// Restore the terminal before the panic message is printed,
+/// otherwise it's lost with the alternate screen.
+///
+/// Returns the hook that was set before, it is called after.
+fn install_panic_hook() -> Arc<PanicHook> {
+    let previous = Arc::new(panic::take_hook());
+    let chained = Arc::clone(&previous);
+    panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        chained(info);
+    }));
+    previous
+}
+
+/// Back to the hook from before install_panic_hook().
+fn restore_panic_hook(previous: Arc<PanicHook>) {
+    // drops the clone in our hook.
+    let _ = panic::take_hook();
+    match Arc::try_unwrap(previous) {
+        Ok(previous) => panic::set_hook(previous),
+        Err(previous) => panic::set_hook(Box::new(move |info| previous(info))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::browse::{install_panic_hook, restore_panic_hook, Action, Browser};
+    use crossterm::event::KeyCode;
+    use std::panic;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn files() -> Vec<String> {
+        (0..5).map(|v| format!("file{}", v)).collect()
+    }
+
+    #[test]
+    fn test_select() {
+        let files = files();
+        let mut b = Browser::default();
+
+        b.key(KeyCode::Up, &files);
+        assert_eq!(b.selected, 0);
+        b.key(KeyCode::Down, &files);
+        b.key(KeyCode::Down, &files);
+        assert_eq!(b.selected, 2);
+        b.key(KeyCode::End, &files);
+        b.key(KeyCode::Down, &files);
+        assert_eq!(b.selected, 4);
+
+        b.scroll(2);
+        assert_eq!(b.offset, 3);
+        b.key(KeyCode::Home, &files);
+        b.scroll(2);
+        assert_eq!(b.offset, 0);
+
+        assert_eq!(b.key(KeyCode::Char('q'), &files), Action::Quit);
+    }
+
+    #[test]
+    fn test_mark() {
+        let files = files();
+        let mut b = Browser::default();
+
+        b.key(KeyCode::Down, &files);
+        b.key(KeyCode::Char('d'), &files);
+        b.key(KeyCode::Down, &files);
+        b.key(KeyCode::Char('d'), &files);
+        assert_eq!(b.marked.len(), 2);
+        assert!(b.marked.contains("file1"));
+
+        // toggles
+        b.key(KeyCode::Char('d'), &files);
+        assert_eq!(b.marked.len(), 1);
+    }
+
+    #[test]
+    fn test_refine() {
+        let files = files();
+        let mut b = Browser::default();
+
+        b.key(KeyCode::Char('/'), &files);
+        for c in "fox  dog".chars() {
+            b.key(KeyCode::Char(c), &files);
+        }
+        // no quit while typing
+        assert_eq!(b.key(KeyCode::Char('q'), &files), Action::None);
+        b.key(KeyCode::Backspace, &files);
+        assert_eq!(
+            b.key(KeyCode::Enter, &files),
+            Action::Refine(vec!["fox".into(), "dog".into()])
+        );
+        assert!(b.input.is_none());
+    }
+
+    #[test]
+    fn test_panic_hook() {
+        static CALLED: AtomicBool = AtomicBool::new(false);
+
+        let default = panic::take_hook();
+        panic::set_hook(Box::new(|_| CALLED.store(true, Ordering::SeqCst)));
+
+        let previous = install_panic_hook();
+        restore_panic_hook(previous);
+        let _ = panic::catch_unwind(|| panic!("test"));
+        assert!(CALLED.load(Ordering::SeqCst));
+
+        panic::set_hook(default);
+    }
+}
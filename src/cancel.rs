@@ -0,0 +1,36 @@
+//! Cancellation of long-running commands with Ctrl-C.
+//!
+//! While readline waits for input Ctrl-C is just a key. Everywhere else
+//! it's a SIGINT which only sets the flag. The long-running loops check
+//! the flag and stop with what they have so far.
+
+use signal_hook::consts::SIGINT;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+fn flag() -> &'static Arc<AtomicBool> {
+    static FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+    FLAG.get_or_init(Default::default)
+}
+
+/// Install the SIGINT handler.
+pub fn install() -> Result<(), io::Error> {
+    signal_hook::flag::register(SIGINT, flag().clone())?;
+    Ok(())
+}
+
+/// Cancel the running command.
+pub fn cancel() {
+    flag().store(true, Ordering::Relaxed);
+}
+
+pub fn is_cancelled() -> bool {
+    flag().load(Ordering::Relaxed)
+}
+
+/// Clear the flag before the next command.
+/// Returns whether the last one was cancelled.
+pub fn reset() -> bool {
+    flag().swap(false, Ordering::Relaxed)
+}
@@ -1,10 +1,16 @@
-use crate::cmdlib::{nom_last_token, nom_usize, nom_ws, CParserResult, CSpan, Cmd, CmdParse};
+use crate::cmdlib::{
+    nom_last_token, nom_usize, nom_ws, CParserError, CParserResult, CSpan, Cmd, CmdParse,
+};
+use crate::index2::merge::MergeConflict;
+use crate::index2::pattern::Pattern;
+use crate::proc3::filter::parse_filter;
+use crate::proc3::{FileFilter, CONFIGS};
 use kparse::combinators::track;
 use kparse::prelude::*;
 use kparse::source::SourceStr;
 use kparse::{Code, ParserError, Track};
 use nom::multi::many1;
-use nom::sequence::preceded;
+use nom::sequence::{pair, preceded};
 use nom::Parser;
 use rustyline::completion::Completer;
 use rustyline::highlight::Highlighter;
@@ -128,22 +134,88 @@ pub enum CCode {
     CCommandLoop,
 
     CBase,
+    CCache,
     CDebug,
+    CErrors,
+    CHistogram,
+    CMem,
+    CBlocks,
     CDelete,
     CFiles,
     CSummary,
     CNext,
     CFirst,
     CFind,
+    CExplain,
     CHelp,
     CIndex,
+    CIndexFile,
+    CIndexFilePath,
+    CLinks,
     CStats,
     CStore,
+    CVerify,
+    CBackground,
+    CRebuildBags,
+    CRecoverWords,
+    CReindexPartial,
+    CPause,
+    CResume,
+    CCollect,
+    CCollectFlag,
+    CCollectDir,
+    CBackup,
+    CBackupDir,
+    CRestore,
+    CRestoreFlag,
+    CRestoreDir,
+    CMergeIndex,
+    CMergeFlag,
+    CMergePath,
+    CBrowse,
     CWhitespace,
     CNumber,
+    CSet,
+    CStopWords,
+    CAttributes,
+    CIndexHidden,
+    CIndexEmpty,
+    CFoldCase,
+    COnOff,
+    CSegments,
+    CBigrams,
+    CPrefixes,
+    CLineWidth,
+    CMemLimit,
+    CMemLimitSize,
+    CSkipWordlists,
+    CWordlistRatio,
+    CWordlistRatioSize,
+    CPrealloc,
+    CPreallocSize,
+    CMinWordLen,
+    CMatchLimit,
+    CMatchLimitSize,
+    CIndexTimeout,
+    CIndexTimeoutSecs,
+    CView,
+    CSegmentSize,
+    CFilter,
+    CForce,
+    CFilterKind,
+    CWhy,
+    CWhyPath,
+    CConfig,
+    CEmbed,
+    CExtract,
+    CConfigName,
+    CConfigPath,
 
     CFindMatch,
+    CFindFlag,
     CFilesMatch,
+    CFilesFlag,
+    CLinksMatch,
     CStatMatch,
     CDeleteMatch,
 }
@@ -169,23 +241,89 @@ impl CCode {
             CWhitespace => "",
             CCommand => "",
             CIndex => "index",
+            CIndexFile => "indexfile",
+            CIndexFilePath => " <path> | - <name>",
             CFind => "find",
+            CExplain => "explain",
             CHelp => "?",
 
             CFiles => "files",
             CStats => "stats",
             CDelete => "delete",
             CFindMatch => " <substr>",
+            CFindFlag => "--files-only | --literal | --force",
             CFilesMatch => " <substr>",
+            CFilesFlag => "-v",
+            CLinks => "links",
+            CLinksMatch => " <substr>",
             CDeleteMatch => " <substr>",
             CBase => "base",
+            CCache => "cache",
             CDebug => "debug",
+            CErrors => "errors",
+            CHistogram => "histogram",
+            CMem => "mem",
+            CBlocks => "blocks",
             CStore => "store",
+            CVerify => "verify",
+            CBackground => "--background",
+            CRebuildBags => "rebuild-bags",
+            CRecoverWords => "recover-words",
+            CReindexPartial => "reindex-partial",
+            CPause => "pause",
+            CResume => "resume",
+            CCollect => "collect",
+            CCollectFlag => "--force",
+            CCollectDir => " <dir>",
+            CBackup => "backup",
+            CBackupDir => " <dir>",
+            CRestore => "restore",
+            CRestoreFlag => "--force",
+            CRestoreDir => " <backup-dir>",
+            CMergeIndex => "merge-index",
+            CMergeFlag => "--skip | --replace | --error",
+            CMergePath => " <path>",
+            CBrowse => "browse",
             CStatMatch => "stats",
             CSummary => "summary",
             CNumber => "number",
             CNext => "next",
             CFirst => "first",
+            CSet => "set",
+            CStopWords => "stopwords",
+            CAttributes => "index-attributes",
+            CIndexHidden => "index-hidden",
+            CIndexEmpty => "index-empty",
+            CFoldCase => "fold-case",
+            COnOff => " on | off",
+            CSegments => "segments",
+            CBigrams => "bigrams",
+            CPrefixes => "prefixes",
+            CLineWidth => "linewidth",
+            CMemLimit => "mem-limit",
+            CMemLimitSize => " <mb> | off",
+            CSkipWordlists => "skip-wordlists",
+            CWordlistRatio => "wordlist-ratio",
+            CWordlistRatioSize => " <words-per-kb> | off",
+            CPrealloc => "prealloc",
+            CPreallocSize => " <blocks> | off",
+            CMinWordLen => "min-word-len",
+            CMatchLimit => "match-limit",
+            CMatchLimitSize => " <words> | off",
+            CIndexTimeout => "index-timeout",
+            CIndexTimeoutSecs => " <secs> | off",
+            CView => "view",
+            CSegmentSize => " <mb> | off",
+            CFilter => "filter",
+            CForce => "force",
+            CFilterKind => " <glob> text | html | ignore | off",
+            CWhy => "why",
+            CWhyPath => " <path>",
+            CConfig => "config",
+            CEmbed => "embed",
+            CExtract => "extract",
+            CConfigName => " stopwords | filter",
+            CConfigPath => " <path>",
         }
     }
 }
@@ -198,8 +336,38 @@ pub enum BCommand {
     Next(Next),
     Summary(Summary),
     Delete(Delete),
+    /// Index a single file now.
+    IndexFile(IndexFile),
     Stats(Stats),
+    Set(Set),
     Store(),
+    Verify,
+    Filter(Filter),
+    Config(Config),
+    /// How the file-type of this path is decided.
+    Why(String),
+    /// Verify in the idle time.
+    VerifyBackground,
+    /// Set the bags from the word-map blocks.
+    RebuildBags,
+    /// Placeholder words for the chains no word points to.
+    RecoverWords,
+    /// Index the partially indexed files again with a larger budget.
+    ReindexPartial,
+    /// Hold the indexing pipeline.
+    Pause,
+    Resume,
+    /// Copy the found files: force, target directory.
+    Collect(bool, String),
+    /// Copy the index into a new directory below this one.
+    Backup(String),
+    /// Replace the index with a backup: force, backup directory.
+    Restore(bool, String),
+    /// Append another index: conflict policy, path of its stored.idx.
+    MergeIndex(MergeConflict, String),
+    Browse,
+    /// Full text of a found line: file nr, line nr.
+    View(usize, usize),
     Help,
     None,
 }
@@ -209,16 +377,91 @@ pub enum Delete {
     Delete(String),
 }
 
+#[derive(Debug, Clone)]
+pub enum IndexFile {
+    /// A file below the root.
+    File(String),
+    /// The content is read from stdin and indexed under this name.
+    Stdin(String),
+}
+
 #[derive(Debug, Clone)]
 pub enum Stats {
     Base,
+    Cache,
     Debug,
+    /// Recent log messages and failed log writes.
+    Errors,
+    /// Top n bigrams.
+    Bigrams(usize),
+    /// Words grouped by a prefix of n chars.
+    Prefixes(usize),
+    /// Progress and findings of the background verify.
+    Verify,
+    /// Words added per bag.
+    Histogram,
+    /// Found lines, words in flight and the block cache.
+    Mem,
+    /// Block counts per type and how contiguous they are.
+    Blocks,
+    /// Partial files, word lists and names that differ only in case.
+    Files,
     Word(String),
 }
 
+#[derive(Debug, Clone)]
+pub enum Set {
+    StopWords(bool),
+    /// Index alt and title of html tags.
+    Attributes(bool),
+    /// Walk hidden files and dot-directories.
+    IndexHidden(bool),
+    /// Compare file names ignoring case.
+    FoldCase(bool),
+    /// Add files without words to the index.
+    IndexEmpty(bool),
+    /// Segment size in MB, 0 is off.
+    Segments(u32),
+    /// Truncate found lines to this many bytes.
+    LineWidth(usize),
+    /// Shorter words are not indexed.
+    MinWordLen(usize),
+    /// Soft limit in MB for the words in flight, 0 is off.
+    MemLimit(usize),
+    /// Blocks per type allocated ahead of a walk, 0 is off.
+    Prealloc(usize),
+    /// Drop the words of files that look like word lists.
+    SkipWordlists(bool),
+    /// Distinct words per KB for a word list, 0 is off.
+    WordlistRatio(usize),
+    /// Matched words per search-term before find asks, 0 is off.
+    MatchLimit(usize),
+    /// Time budget per file in seconds, 0 is off.
+    IndexTimeout(u64),
+}
+
+#[derive(Debug, Clone)]
+pub enum Filter {
+    List,
+    /// Force the file-type below the directories matching the glob.
+    /// None removes the rule.
+    Force(String, Option<FileFilter>),
+}
+
+#[derive(Debug, Clone)]
+pub enum Config {
+    /// Embedded configurations.
+    List,
+    /// Embed the configuration in use.
+    Embed(String),
+    /// Write the embedded configuration to a file.
+    Extract(String, String),
+}
+
 #[derive(Debug, Clone)]
 pub enum Files {
-    Files(String),
+    /// Verbose flag and pattern.
+    Files(bool, String),
 }
 
 #[derive(Debug, Clone)]
@@ -234,7 +477,23 @@ pub enum Next {
 
 #[derive(Debug, Clone)]
 pub enum Find {
-    Find(Vec<String>),
+    Find(FindFlags, Vec<String>),
+    /// The plan of the find, without running it.
+    Explain(FindFlags, Vec<String>),
+    /// Files linking to the target.
+    Links(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FindFlags {
+    /// Only list the files, don't extract the matching lines.
+    pub files_only: bool,
+    /// -v numbers per search-term, -vv per word and the block cache.
+    pub verbose: u8,
+    /// No wildcards, all terms are escaped.
+    pub literal: bool,
+    /// Run even if a search-term matches more words than the match-limit.
+    pub force: bool,
 }
 
 pub fn parse_cmds(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
@@ -248,27 +507,136 @@ pub fn parse_cmds(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
 // -----------------------------------------------------------------------
 // -----------------------------------------------------------------------
 
-const ALL_PARSERS: CmdParse<BCommand, 13> = CmdParse {
+const ALL_PARSERS: CmdParse<BCommand, 58> = CmdParse {
     parse: [
+        Cmd::P1p("indexfile", CIndexFile, parse_index_file),
         Cmd::P1("index", CIndex, BCommand::Index()),
         Cmd::P2(
             ("stats", "base"),
             (CStats, CBase),
             BCommand::Stats(Stats::Base),
         ),
+        Cmd::P2(
+            ("stats", "cache"),
+            (CStats, CCache),
+            BCommand::Stats(Stats::Cache),
+        ),
         Cmd::P2(
             ("stats", "debug"),
             (CStats, CDebug),
             BCommand::Stats(Stats::Debug),
         ),
+        Cmd::P2(
+            ("stats", "errors"),
+            (CStats, CErrors),
+            BCommand::Stats(Stats::Errors),
+        ),
+        Cmd::P2(
+            ("stats", "verify"),
+            (CStats, CVerify),
+            BCommand::Stats(Stats::Verify),
+        ),
+        Cmd::P2(
+            ("stats", "histogram"),
+            (CStats, CHistogram),
+            BCommand::Stats(Stats::Histogram),
+        ),
+        Cmd::P2(
+            ("stats", "mem"),
+            (CStats, CMem),
+            BCommand::Stats(Stats::Mem),
+        ),
+        Cmd::P2(
+            ("stats", "blocks"),
+            (CStats, CBlocks),
+            BCommand::Stats(Stats::Blocks),
+        ),
+        Cmd::P2(
+            ("stats", "files"),
+            (CStats, CFiles),
+            BCommand::Stats(Stats::Files),
+        ),
+        Cmd::P2p(("stats", "bigrams"), (CStats, CBigrams), parse_bigrams),
+        Cmd::P2p(("stats", "prefixes"), (CStats, CPrefixes), parse_prefixes),
         Cmd::P1p("stats", CStats, parse_stats),
         Cmd::P1p("delete", CDelete, parse_delete),
         Cmd::P1p("find", CFind, parse_find),
+        Cmd::P1p("explain", CExplain, parse_explain),
         Cmd::P1p("files", CFiles, parse_files),
+        Cmd::P1p("links", CLinks, parse_links),
         Cmd::P1p("summary", CSummary, parse_usize),
         Cmd::P1("next", CNext, BCommand::Next(Next::Next)),
         Cmd::P1("first", CFirst, BCommand::Next(Next::First)),
+        Cmd::P2p(("set", "stopwords"), (CSet, CStopWords), parse_stopwords),
+        Cmd::P2p(
+            ("set", "index-attributes"),
+            (CSet, CAttributes),
+            parse_attributes,
+        ),
+        Cmd::P2p(
+            ("set", "index-hidden"),
+            (CSet, CIndexHidden),
+            parse_index_hidden,
+        ),
+        Cmd::P2p(("set", "fold-case"), (CSet, CFoldCase), parse_fold_case),
+        Cmd::P2p(
+            ("set", "index-empty"),
+            (CSet, CIndexEmpty),
+            parse_index_empty,
+        ),
+        Cmd::P2p(("set", "segments"), (CSet, CSegments), parse_segments),
+        Cmd::P2p(("set", "linewidth"), (CSet, CLineWidth), parse_linewidth),
+        Cmd::P2p(
+            ("set", "min-word-len"),
+            (CSet, CMinWordLen),
+            parse_min_word_len,
+        ),
+        Cmd::P2p(("set", "mem-limit"), (CSet, CMemLimit), parse_mem_limit),
+        Cmd::P2p(("set", "prealloc"), (CSet, CPrealloc), parse_prealloc),
+        Cmd::P2p(
+            ("set", "skip-wordlists"),
+            (CSet, CSkipWordlists),
+            parse_skip_wordlists,
+        ),
+        Cmd::P2p(
+            ("set", "wordlist-ratio"),
+            (CSet, CWordlistRatio),
+            parse_wordlist_ratio,
+        ),
+        Cmd::P2p(
+            ("set", "match-limit"),
+            (CSet, CMatchLimit),
+            parse_match_limit,
+        ),
+        Cmd::P2p(
+            ("set", "index-timeout"),
+            (CSet, CIndexTimeout),
+            parse_index_timeout,
+        ),
+        Cmd::P1p("view", CView, parse_view),
+        Cmd::P2p(("filter", "force"), (CFilter, CForce), parse_force),
+        Cmd::P1("filter", CFilter, BCommand::Filter(Filter::List)),
+        Cmd::P1p("why", CWhy, parse_why),
+        Cmd::P2p(("config", "embed"), (CConfig, CEmbed), parse_embed),
+        Cmd::P2p(("config", "extract"), (CConfig, CExtract), parse_extract),
+        Cmd::P1("config", CConfig, BCommand::Config(Config::List)),
         Cmd::P1("store", CStore, BCommand::Store()),
+        Cmd::P2(
+            ("verify", "--background"),
+            (CVerify, CBackground),
+            BCommand::VerifyBackground,
+        ),
+        Cmd::P1("verify", CVerify, BCommand::Verify),
+        Cmd::P1("rebuild-bags", CRebuildBags, BCommand::RebuildBags),
+        Cmd::P1("recover-words", CRecoverWords, BCommand::RecoverWords),
+        Cmd::P1("reindex-partial", CReindexPartial, BCommand::ReindexPartial),
+        Cmd::P1("pause", CPause, BCommand::Pause),
+        Cmd::P1("resume", CResume, BCommand::Resume),
+        Cmd::P1p("collect", CCollect, parse_collect),
+        Cmd::P1p("backup", CBackup, parse_backup),
+        Cmd::P1p("restore", CRestore, parse_restore),
+        Cmd::P1p("merge-index", CMergeIndex, parse_merge_index),
+        Cmd::P1("browse", CBrowse, BCommand::Browse),
         Cmd::P1("help", CHelp, BCommand::Help),
         Cmd::P1("?", CHelp, BCommand::Help),
     ],
@@ -291,27 +659,544 @@ fn parse_stats(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
         .parse(input)
 }
 
+fn parse_bigrams(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    track(CBigrams, preceded(nom_ws, nom_usize))
+        .map(|v| BCommand::Stats(Stats::Bigrams(v)))
+        .with_code(CBigrams)
+        .err_into()
+        .parse(input)
+}
+
+fn parse_prefixes(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    track(CPrefixes, preceded(nom_ws, nom_usize))
+        .map(|v| BCommand::Stats(Stats::Prefixes(v)))
+        .with_code(CPrefixes)
+        .err_into()
+        .parse(input)
+}
+
+fn parse_linewidth(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    track(CLineWidth, preceded(nom_ws, nom_usize))
+        .map(|v| BCommand::Set(Set::LineWidth(v)))
+        .with_code(CLineWidth)
+        .err_into()
+        .parse(input)
+}
+
+fn parse_min_word_len(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    track(CMinWordLen, preceded(nom_ws, nom_usize))
+        .map(|v| BCommand::Set(Set::MinWordLen(v)))
+        .with_code(CMinWordLen)
+        .err_into()
+        .parse(input)
+}
+
+fn parse_view(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    track(
+        CView,
+        pair(preceded(nom_ws, nom_usize), preceded(nom_ws, nom_usize)),
+    )
+    .map(|(file, line)| BCommand::View(file, line))
+    .with_code(CView)
+    .err_into()
+    .parse(input)
+}
+
 fn parse_files(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
-    track(CFiles, preceded(nom_ws, nom_last_token))
-        .map(|v| BCommand::Files(Files::Files(v.fragment().to_string())))
+    Track.enter(CFiles, input);
+
+    let (rest, spans) = many1(preceded(nom_ws, nom_last_token))
         .with_code(CFilesMatch)
         .err_into()
         .parse(input)
+        .track()?;
+
+    // leading flag
+    let mut verbose = false;
+    let mut pattern = Vec::new();
+    for span in spans {
+        match *span.fragment() {
+            "-v" if pattern.is_empty() => verbose = true,
+            v if v.starts_with("--") && pattern.is_empty() => {
+                return Track.err(CParserError::new(CFilesFlag, span));
+            }
+            v => pattern.push(v),
+        }
+    }
+    if pattern.is_empty() {
+        return Track.err(CParserError::new(CFilesMatch, rest));
+    }
+
+    Track.ok(
+        rest,
+        input,
+        BCommand::Files(Files::Files(verbose, pattern.join(" "))),
+    )
+}
+
+fn parse_links(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    track(CLinks, preceded(nom_ws, nom_last_token))
+        .map(|v| BCommand::Find(Find::Links(v.fragment().to_string())))
+        .with_code(CLinksMatch)
+        .err_into()
+        .parse(input)
 }
 
 fn parse_find(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
-    track(CFind, many1(preceded(nom_ws, nom_last_token)))
-        .map(|spans| {
-            BCommand::Find(Find::Find(
-                spans
-                    .into_iter()
-                    .map(|v| v.fragment().to_string())
-                    .collect::<Vec<_>>(),
-            ))
-        })
+    Track.enter(CFind, input);
+
+    let (rest, spans) = many1(preceded(nom_ws, nom_last_token))
         .with_code(CFindMatch)
         .err_into()
         .parse(input)
+        .track()?;
+
+    match find_query(spans) {
+        Ok((_, terms)) if terms.is_empty() => Track.err(CParserError::new(CFindMatch, rest)),
+        Ok((flags, terms)) => Track.ok(rest, input, BCommand::Find(Find::Find(flags, terms))),
+        Err(span) => Track.err(CParserError::new(CFindFlag, span)),
+    }
+}
+
+fn parse_explain(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CExplain, input);
+
+    let (rest, spans) = many1(preceded(nom_ws, nom_last_token))
+        .with_code(CFindMatch)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    match find_query(spans) {
+        Ok((_, terms)) if terms.is_empty() => Track.err(CParserError::new(CFindMatch, rest)),
+        Ok((flags, terms)) => Track.ok(rest, input, BCommand::Find(Find::Explain(flags, terms))),
+        Err(span) => Track.err(CParserError::new(CFindFlag, span)),
+    }
+}
+
+/// Leading flags and the search-terms of a find.
+/// Returns an unknown flag as error.
+fn find_query(spans: Vec<CSpan<'_>>) -> Result<(FindFlags, Vec<String>), CSpan<'_>> {
+    let mut flags = FindFlags::default();
+    let mut terms = Vec::new();
+    for span in spans {
+        match *span.fragment() {
+            "--files-only" if terms.is_empty() => flags.files_only = true,
+            "--literal" if terms.is_empty() => flags.literal = true,
+            "--force" if terms.is_empty() => flags.force = true,
+            "-v" if terms.is_empty() => flags.verbose = 1,
+            "-vv" if terms.is_empty() => flags.verbose = 2,
+            v if v.starts_with("--") && terms.is_empty() => return Err(span),
+            v if flags.literal => terms.push(Pattern::escape(v)),
+            v => terms.push(v.to_string()),
+        }
+    }
+    Ok((flags, terms))
+}
+
+fn parse_stopwords(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CStopWords, input);
+
+    let (rest, v) = preceded(nom_ws, nom_last_token)
+        .with_code(COnOff)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    match *v.fragment() {
+        "on" => Track.ok(rest, input, BCommand::Set(Set::StopWords(true))),
+        "off" => Track.ok(rest, input, BCommand::Set(Set::StopWords(false))),
+        _ => Track.err(CParserError::new(COnOff, v)),
+    }
+}
+
+fn parse_attributes(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CAttributes, input);
+
+    let (rest, v) = preceded(nom_ws, nom_last_token)
+        .with_code(COnOff)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    match *v.fragment() {
+        "on" => Track.ok(rest, input, BCommand::Set(Set::Attributes(true))),
+        "off" => Track.ok(rest, input, BCommand::Set(Set::Attributes(false))),
+        _ => Track.err(CParserError::new(COnOff, v)),
+    }
+}
+
+fn parse_index_hidden(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CIndexHidden, input);
+
+    let (rest, v) = preceded(nom_ws, nom_last_token)
+        .with_code(COnOff)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    match *v.fragment() {
+        "on" => Track.ok(rest, input, BCommand::Set(Set::IndexHidden(true))),
+        "off" => Track.ok(rest, input, BCommand::Set(Set::IndexHidden(false))),
+        _ => Track.err(CParserError::new(COnOff, v)),
+    }
+}
+
+fn parse_index_empty(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CIndexEmpty, input);
+
+    let (rest, v) = preceded(nom_ws, nom_last_token)
+        .with_code(COnOff)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    match *v.fragment() {
+        "on" => Track.ok(rest, input, BCommand::Set(Set::IndexEmpty(true))),
+        "off" => Track.ok(rest, input, BCommand::Set(Set::IndexEmpty(false))),
+        _ => Track.err(CParserError::new(COnOff, v)),
+    }
+}
+
+fn parse_fold_case(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CFoldCase, input);
+
+    let (rest, v) = preceded(nom_ws, nom_last_token)
+        .with_code(COnOff)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    match *v.fragment() {
+        "on" => Track.ok(rest, input, BCommand::Set(Set::FoldCase(true))),
+        "off" => Track.ok(rest, input, BCommand::Set(Set::FoldCase(false))),
+        _ => Track.err(CParserError::new(COnOff, v)),
+    }
+}
+
+fn parse_skip_wordlists(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CSkipWordlists, input);
+
+    let (rest, v) = preceded(nom_ws, nom_last_token)
+        .with_code(COnOff)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    match *v.fragment() {
+        "on" => Track.ok(rest, input, BCommand::Set(Set::SkipWordlists(true))),
+        "off" => Track.ok(rest, input, BCommand::Set(Set::SkipWordlists(false))),
+        _ => Track.err(CParserError::new(COnOff, v)),
+    }
+}
+
+fn parse_force(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CForce, input);
+
+    let (rest, (glob, kind)) = pair(
+        preceded(nom_ws, nom_last_token),
+        preceded(nom_ws, nom_last_token),
+    )
+    .with_code(CFilterKind)
+    .err_into()
+    .parse(input)
+    .track()?;
+
+    let filter = match *kind.fragment() {
+        "off" => None,
+        v => match parse_filter(v) {
+            Some(v) => Some(v),
+            None => return Track.err(CParserError::new(CFilterKind, kind)),
+        },
+    };
+
+    Track.ok(
+        rest,
+        input,
+        BCommand::Filter(Filter::Force(glob.fragment().to_string(), filter)),
+    )
+}
+
+fn parse_why(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    track(CWhy, many1(preceded(nom_ws, nom_last_token)))
+        .map(|v| {
+            let path = v.iter().map(|v| *v.fragment()).collect::<Vec<_>>();
+            BCommand::Why(path.join(" "))
+        })
+        .with_code(CWhyPath)
+        .err_into()
+        .parse(input)
+}
+
+fn parse_collect(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CCollect, input);
+
+    let (rest, spans) = many1(preceded(nom_ws, nom_last_token))
+        .with_code(CCollectDir)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    // leading flag
+    let mut force = false;
+    let mut dir = Vec::new();
+    for span in spans {
+        match *span.fragment() {
+            "--force" if dir.is_empty() => force = true,
+            v if v.starts_with("--") && dir.is_empty() => {
+                return Track.err(CParserError::new(CCollectFlag, span));
+            }
+            v => dir.push(v),
+        }
+    }
+    if dir.is_empty() {
+        return Track.err(CParserError::new(CCollectDir, rest));
+    }
+
+    Track.ok(rest, input, BCommand::Collect(force, dir.join(" ")))
+}
+
+fn parse_index_file(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CIndexFile, input);
+
+    let (rest, spans) = many1(preceded(nom_ws, nom_last_token))
+        .with_code(CIndexFilePath)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    let path: Vec<_> = spans.iter().map(|v| *v.fragment()).collect();
+    let cmd = match path.as_slice() {
+        ["-"] => return Track.err(CParserError::new(CIndexFilePath, rest)),
+        ["-", name @ ..] => IndexFile::Stdin(name.join(" ")),
+        path => IndexFile::File(path.join(" ")),
+    };
+
+    Track.ok(rest, input, BCommand::IndexFile(cmd))
+}
+
+fn parse_backup(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CBackup, input);
+
+    let (rest, spans) = many1(preceded(nom_ws, nom_last_token))
+        .with_code(CBackupDir)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    if spans[0].fragment().starts_with("--") {
+        return Track.err(CParserError::new(CBackupDir, spans[0]));
+    }
+    let dir: Vec<_> = spans.iter().map(|v| *v.fragment()).collect();
+
+    Track.ok(rest, input, BCommand::Backup(dir.join(" ")))
+}
+
+fn parse_restore(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CRestore, input);
+
+    let (rest, spans) = many1(preceded(nom_ws, nom_last_token))
+        .with_code(CRestoreDir)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    // leading flag
+    let mut force = false;
+    let mut dir = Vec::new();
+    for span in spans {
+        match *span.fragment() {
+            "--force" if dir.is_empty() => force = true,
+            v if v.starts_with("--") && dir.is_empty() => {
+                return Track.err(CParserError::new(CRestoreFlag, span));
+            }
+            v => dir.push(v),
+        }
+    }
+    if dir.is_empty() {
+        return Track.err(CParserError::new(CRestoreDir, rest));
+    }
+
+    Track.ok(rest, input, BCommand::Restore(force, dir.join(" ")))
+}
+
+fn parse_merge_index(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CMergeIndex, input);
+
+    let (rest, spans) = many1(preceded(nom_ws, nom_last_token))
+        .with_code(CMergePath)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    // leading flag
+    let mut conflict = MergeConflict::default();
+    let mut path = Vec::new();
+    for span in spans {
+        match *span.fragment() {
+            "--skip" if path.is_empty() => conflict = MergeConflict::Skip,
+            "--replace" if path.is_empty() => conflict = MergeConflict::Replace,
+            "--error" if path.is_empty() => conflict = MergeConflict::Error,
+            v if v.starts_with("--") && path.is_empty() => {
+                return Track.err(CParserError::new(CMergeFlag, span));
+            }
+            v => path.push(v),
+        }
+    }
+    if path.is_empty() {
+        return Track.err(CParserError::new(CMergePath, rest));
+    }
+
+    Track.ok(rest, input, BCommand::MergeIndex(conflict, path.join(" ")))
+}
+
+fn parse_config_name(input: CSpan<'_>) -> CParserResult<'_, String> {
+    Track.enter(CConfigName, input);
+
+    let (rest, name) = preceded(nom_ws, nom_last_token)
+        .with_code(CConfigName)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    if CONFIGS.iter().any(|(v, _)| v == name.fragment()) {
+        Track.ok(rest, input, name.fragment().to_string())
+    } else {
+        Track.err(CParserError::new(CConfigName, name))
+    }
+}
+
+fn parse_embed(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CEmbed, input);
+
+    let (rest, name) = parse_config_name(input).track()?;
+
+    Track.ok(rest, input, BCommand::Config(Config::Embed(name)))
+}
+
+fn parse_extract(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CExtract, input);
+
+    let (rest, name) = parse_config_name(input).track()?;
+    let (rest, path) = preceded(nom_ws, nom_last_token)
+        .with_code(CConfigPath)
+        .err_into()
+        .parse(rest)
+        .track()?;
+
+    Track.ok(
+        rest,
+        input,
+        BCommand::Config(Config::Extract(name, path.fragment().to_string())),
+    )
+}
+
+fn parse_segments(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CSegments, input);
+
+    let (rest, v) = preceded(nom_ws, nom_last_token)
+        .with_code(CSegmentSize)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    match *v.fragment() {
+        "off" => Track.ok(rest, input, BCommand::Set(Set::Segments(0))),
+        mb => match mb.parse::<u32>() {
+            Ok(mb) => Track.ok(rest, input, BCommand::Set(Set::Segments(mb))),
+            Err(_) => Track.err(CParserError::new(CSegmentSize, v)),
+        },
+    }
+}
+
+fn parse_mem_limit(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CMemLimit, input);
+
+    let (rest, v) = preceded(nom_ws, nom_last_token)
+        .with_code(CMemLimitSize)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    match *v.fragment() {
+        "off" => Track.ok(rest, input, BCommand::Set(Set::MemLimit(0))),
+        mb => match mb.parse::<usize>() {
+            Ok(mb) => Track.ok(rest, input, BCommand::Set(Set::MemLimit(mb))),
+            Err(_) => Track.err(CParserError::new(CMemLimitSize, v)),
+        },
+    }
+}
+
+fn parse_prealloc(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CPrealloc, input);
+
+    let (rest, v) = preceded(nom_ws, nom_last_token)
+        .with_code(CPreallocSize)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    match *v.fragment() {
+        "off" => Track.ok(rest, input, BCommand::Set(Set::Prealloc(0))),
+        n => match n.parse::<usize>() {
+            Ok(n) => Track.ok(rest, input, BCommand::Set(Set::Prealloc(n))),
+            Err(_) => Track.err(CParserError::new(CPreallocSize, v)),
+        },
+    }
+}
+
+fn parse_wordlist_ratio(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CWordlistRatio, input);
+
+    let (rest, v) = preceded(nom_ws, nom_last_token)
+        .with_code(CWordlistRatioSize)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    match *v.fragment() {
+        "off" => Track.ok(rest, input, BCommand::Set(Set::WordlistRatio(0))),
+        n => match n.parse::<usize>() {
+            Ok(n) => Track.ok(rest, input, BCommand::Set(Set::WordlistRatio(n))),
+            Err(_) => Track.err(CParserError::new(CWordlistRatioSize, v)),
+        },
+    }
+}
+
+fn parse_match_limit(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CMatchLimit, input);
+
+    let (rest, v) = preceded(nom_ws, nom_last_token)
+        .with_code(CMatchLimitSize)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    match *v.fragment() {
+        "off" => Track.ok(rest, input, BCommand::Set(Set::MatchLimit(0))),
+        n => match n.parse::<usize>() {
+            Ok(n) => Track.ok(rest, input, BCommand::Set(Set::MatchLimit(n))),
+            Err(_) => Track.err(CParserError::new(CMatchLimitSize, v)),
+        },
+    }
+}
+
+fn parse_index_timeout(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
+    Track.enter(CIndexTimeout, input);
+
+    let (rest, v) = preceded(nom_ws, nom_last_token)
+        .with_code(CIndexTimeoutSecs)
+        .err_into()
+        .parse(input)
+        .track()?;
+
+    match *v.fragment() {
+        "off" => Track.ok(rest, input, BCommand::Set(Set::IndexTimeout(0))),
+        n => match n.parse::<u64>() {
+            Ok(n) => Track.ok(rest, input, BCommand::Set(Set::IndexTimeout(n))),
+            Err(_) => Track.err(CParserError::new(CIndexTimeoutSecs, v)),
+        },
+    }
 }
 
 fn parse_usize(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
@@ -321,3 +1206,171 @@ fn parse_usize(input: CSpan<'_>) -> CParserResult<'_, BCommand> {
         .err_into()
         .parse(input)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cmds::{parse_cmds, BCommand, CCode, Find, FindFlags, IndexFile};
+    use crate::index2::merge::MergeConflict;
+    use kparse::prelude::*;
+    use kparse::Track;
+
+    fn find(txt: &str) -> Option<(FindFlags, Vec<String>)> {
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, txt);
+        match parse_cmds(span) {
+            Ok((_, BCommand::Find(Find::Find(flags, terms)))) => Some((flags, terms)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_find_escaped() {
+        let (flags, terms) = find(r"find a\*b c*").expect("find");
+        assert!(!flags.literal);
+        assert_eq!(terms, vec![r"a\*b", "c*"]);
+    }
+
+    #[test]
+    fn test_verify_background() {
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, "verify --background");
+        assert!(matches!(
+            parse_cmds(span),
+            Ok((_, BCommand::VerifyBackground))
+        ));
+
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, "verify");
+        assert!(matches!(parse_cmds(span), Ok((_, BCommand::Verify))));
+    }
+
+    #[test]
+    fn test_collect() {
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, "collect --force out dir");
+        assert!(matches!(
+            parse_cmds(span),
+            Ok((_, BCommand::Collect(true, dir))) if dir == "out dir"
+        ));
+
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, "collect out");
+        assert!(matches!(
+            parse_cmds(span),
+            Ok((_, BCommand::Collect(false, dir))) if dir == "out"
+        ));
+
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, "collect --force");
+        assert!(parse_cmds(span).is_err());
+    }
+
+    #[test]
+    fn test_backup_restore() {
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, "backup bak dir");
+        assert!(matches!(
+            parse_cmds(span),
+            Ok((_, BCommand::Backup(dir))) if dir == "bak dir"
+        ));
+
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, "restore --force bak/backup-1");
+        assert!(matches!(
+            parse_cmds(span),
+            Ok((_, BCommand::Restore(true, dir))) if dir == "bak/backup-1"
+        ));
+
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, "restore --force");
+        assert!(parse_cmds(span).is_err());
+    }
+
+    #[test]
+    fn test_merge_index() {
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, "merge-index --replace other/stored.idx");
+        assert!(matches!(
+            parse_cmds(span),
+            Ok((_, BCommand::MergeIndex(MergeConflict::Replace, path))) if path == "other/stored.idx"
+        ));
+
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, "merge-index other/stored.idx");
+        assert!(matches!(
+            parse_cmds(span),
+            Ok((_, BCommand::MergeIndex(MergeConflict::Skip, _)))
+        ));
+
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, "merge-index --keep x.idx");
+        assert!(parse_cmds(span).is_err());
+    }
+
+    #[test]
+    fn test_find_literal() {
+        let (flags, terms) = find("find --literal a*b c?").expect("find");
+        assert!(flags.literal);
+        assert_eq!(terms, vec![r"a\*b", r"c\?"]);
+
+        let (flags, terms) = find(r"find --files-only --literal a\b").expect("find");
+        assert!(flags.files_only);
+        assert!(flags.literal);
+        assert_eq!(terms, vec![r"a\\b"]);
+
+        // only leading flags.
+        let (flags, terms) = find("find a --literal").expect("find");
+        assert!(!flags.literal);
+        assert_eq!(terms, vec!["a", "--literal"]);
+    }
+
+    #[test]
+    fn test_find_force() {
+        let (flags, terms) = find("find --force s*").expect("find");
+        assert!(flags.force);
+        assert_eq!(terms, vec!["s*"]);
+
+        let (flags, _) = find("find s* --force").expect("find");
+        assert!(!flags.force);
+    }
+
+    #[test]
+    fn test_index_file() {
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, "indexfile dir/new file.txt");
+        assert!(matches!(
+            parse_cmds(span),
+            Ok((_, BCommand::IndexFile(IndexFile::File(path)))) if path == "dir/new file.txt"
+        ));
+
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, "indexfile - piped.txt");
+        assert!(matches!(
+            parse_cmds(span),
+            Ok((_, BCommand::IndexFile(IndexFile::Stdin(name)))) if name == "piped.txt"
+        ));
+
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, "indexfile -");
+        assert!(parse_cmds(span).is_err());
+
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, "index");
+        assert!(matches!(parse_cmds(span), Ok((_, BCommand::Index()))));
+    }
+
+    #[test]
+    fn test_explain() {
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, "explain --literal a* b?");
+        assert!(matches!(
+            parse_cmds(span),
+            Ok((_, BCommand::Find(Find::Explain(flags, terms))))
+                if flags.literal && terms == vec![r"a\*", r"b\?"]
+        ));
+
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, "explain");
+        assert!(parse_cmds(span).is_err());
+    }
+}
@@ -1,26 +1,50 @@
 #![allow(dead_code)]
 
+pub mod backup;
+pub mod bags;
+pub mod block_pool;
+pub mod check;
+pub mod config;
+pub mod diagnostics;
+pub mod file_refs;
 pub mod files;
+pub mod header;
 pub mod ids;
+pub mod merge;
+pub mod pattern;
+pub mod plan;
+pub mod query_cache;
+pub mod recover;
+pub mod save;
+pub mod segments;
 pub mod tmp_index;
 pub mod word_map;
 pub mod words;
 
-use crate::index2::files::{FileData, FileList};
+use crate::index2::config::ConfigBlobs;
+use crate::index2::file_refs::RawFileRef;
+use crate::index2::files::{FileList, FILE_PARTIAL, FILE_SKIPPED, FILE_WORDLIST};
+use crate::index2::header::{
+    Header, RawHeader, FLAG_CLEAN, FLAG_NO_STOP_WORDS, FLAG_VERIFY, INDEX_VERSION,
+};
+use crate::index2::pattern::Pattern;
+use crate::index2::plan::{QueryPlan, TermPlan};
+use crate::index2::query_cache::QueryCache;
+use crate::index2::segments::{block_as, Segments};
 use crate::index2::tmp_index::TmpWords;
-use crate::index2::word_map::{RawBags, RawWordMap, WordMap, BAG_LEN};
-use crate::index2::words::{RawWord, WordData, WordList};
-use blockfile2::{BlockType, FileBlocks, UserBlockType};
+use crate::index2::word_map::{bag_for, IterFileId, RawBags, RawWordMap, WordMap, BAG_LEN};
+use crate::index2::words::{BadWord, RawWord, RawWordV1, WordData, WordList, LAZY_WORDS};
+use blockfile2::{BlockType, LogicalNr, UserBlockType};
 use ids::{BlkIdx, FIdx, FileId, WordId};
 use std::backtrace::Backtrace;
-use std::collections::{BTreeMap, BTreeSet};
+use std::cmp::max;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt::{Debug, Display, Formatter};
 use std::mem::align_of;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::from_utf8;
 use std::time::Instant;
-use std::{fs, io, string};
-use wildmatch::WildMatch;
+use std::{io, string};
 
 pub struct IndexError {
     pub kind: IndexKind,
@@ -31,8 +55,34 @@ pub struct IndexError {
 pub enum IndexKind {
     BlockFile(blockfile2::Error),
     Utf8Error(Vec<u8>),
+    /// A word of the word-list is not valid UTF-8.
+    BadWord(BadWord),
     FromUtf8Error(string::FromUtf8Error),
     IOError(io::Error),
+    /// A write failed because the disk is full.
+    DiskFull,
+    /// A file refers to a directory that isn't stored.
+    UnknownDir(FileId, u32),
+    /// Flags for a file that isn't stored.
+    UnknownFile(FileId),
+    /// The block has a different type than the one expected.
+    WrongBlockType(LogicalNr, Option<WordBlockType>, &'static [WordBlockType]),
+    /// Not a backup, or not one that can be restored.
+    InvalidBackup(PathBuf),
+    /// The index was opened with Words::read_only().
+    ReadOnly,
+    /// The file is in both indexes of a merge.
+    MergeConflict(String),
+    /// The block-nr refers to a segment that doesn't exist.
+    UnknownSegment(LogicalNr),
+    /// All segments are used up, no more blocks can be allocated.
+    SegmentsFull,
+    /// The files are closed, see Segments::close().
+    Closed,
+    /// A chain of the word-map points past the end of a block.
+    BrokenChain(LogicalNr, BlkIdx),
+    /// The configuration is too large to embed.
+    InvalidConfig(String),
 }
 
 impl Display for IndexKind {
@@ -40,8 +90,39 @@ impl Display for IndexKind {
         match self {
             IndexKind::BlockFile(e) => write!(f, "BlockFile {:?}", e),
             IndexKind::Utf8Error(v) => write!(f, "Utf8Error {:?}", v),
+            IndexKind::BadWord(v) => write!(f, "{}", v),
             IndexKind::IOError(v) => write!(f, "IOError {:?}", v),
             IndexKind::FromUtf8Error(v) => write!(f, "FromUtf8Error {:?}", v),
+            IndexKind::DiskFull => {
+                write!(f, "disk full — index not saved, free space and run store")
+            }
+            IndexKind::UnknownDir(file_id, dir) => {
+                write!(f, "file {} in unknown directory {}", file_id, dir)
+            }
+            IndexKind::UnknownFile(file_id) => {
+                write!(f, "flags for unknown file {}", file_id)
+            }
+            IndexKind::WrongBlockType(block_nr, ty, expected) => {
+                write!(f, "block {} is {:?}, expected {:?}", block_nr, ty, expected)
+            }
+            IndexKind::InvalidBackup(path) => {
+                write!(f, "{} is not a valid backup", path.display())
+            }
+            IndexKind::ReadOnly => write!(f, "index is opened read-only"),
+            IndexKind::MergeConflict(file) => {
+                write!(f, "{} is in both indexes, nothing merged", file)
+            }
+            IndexKind::UnknownSegment(block_nr) => {
+                write!(f, "block {} is in an unknown segment", block_nr)
+            }
+            IndexKind::SegmentsFull => write!(f, "no segment left for a new block"),
+            IndexKind::Closed => write!(f, "index is closed"),
+            IndexKind::BrokenChain(block_nr, block_idx) => {
+                write!(f, "chain points past the end of {}:{}", block_nr, block_idx)
+            }
+            IndexKind::InvalidConfig(name) => {
+                write!(f, "configuration {} is too large to embed", name)
+            }
         }
     }
 }
@@ -53,6 +134,52 @@ impl IndexError {
             backtrace: Backtrace::capture(),
         }
     }
+
+    /// ENOSPC or its equivalent.
+    pub fn is_disk_full(&self) -> bool {
+        match &self.kind {
+            IndexKind::DiskFull => true,
+            IndexKind::IOError(e) => io_disk_full(e),
+            IndexKind::BlockFile(e) => {
+                let mut source: Option<&(dyn std::error::Error + 'static)> = Some(e);
+                while let Some(err) = source {
+                    if let Some(e) = err.downcast_ref::<io::Error>() {
+                        if io_disk_full(e) {
+                            return true;
+                        }
+                    }
+                    source = err.source();
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Replaces an error caused by a full disk with DiskFull.
+    pub fn or_disk_full(self) -> Self {
+        if self.is_disk_full() {
+            IndexError::err(IndexKind::DiskFull)
+        } else {
+            self
+        }
+    }
+}
+
+/// Raw os errors for a full disk. ENOSPC, and ERROR_HANDLE_DISK_FULL
+/// and ERROR_DISK_FULL for windows.
+#[cfg(unix)]
+const DISK_FULL_ERRORS: &[i32] = &[28];
+#[cfg(windows)]
+const DISK_FULL_ERRORS: &[i32] = &[39, 112];
+#[cfg(not(any(unix, windows)))]
+const DISK_FULL_ERRORS: &[i32] = &[];
+
+/// StorageFull, or the raw os error where the kind isn't mapped.
+fn io_disk_full(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::StorageFull
+        || e.raw_os_error()
+            .is_some_and(|v| DISK_FULL_ERRORS.contains(&v))
 }
 
 impl Debug for IndexError {
@@ -93,18 +220,62 @@ impl std::error::Error for IndexError {}
 
 const BLOCK_SIZE: usize = 4096;
 
+/// Namespace for the link-targets of html files.
+pub const LINK_NS: &str = "link:";
+
+/// Words in a namespace are kept apart from the ordinary text-words.
+/// They are only found by a search-term with the same prefix.
+pub const NAMESPACES: &[&str] = &[LINK_NS];
+
+pub fn is_namespaced(word: &str) -> bool {
+    NAMESPACES.iter().any(|ns| word.starts_with(ns))
+}
+
+/// The part of a file pattern before the first wildcard.
+pub fn literal_prefix(txt: &str) -> &str {
+    match txt.find(['*', '?']) {
+        Some(idx) => &txt[..idx],
+        None => txt,
+    }
+}
+
+/// Slow path of find_file, matches a copy of the file names.
+pub fn match_file_names(names: Vec<String>, txt: &str) -> Vec<String> {
+    let find = Pattern::glob(txt);
+    names.into_iter().filter(|v| find.matches(v)).collect()
+}
+
 pub struct Words {
     pub db: WordFileBlocks,
+    header: Header,
+    /// The clean flag was set when opening.
+    was_clean: bool,
     words: WordList,
     word_count: usize,
     bag_stats: [usize; BAG_LEN],
     files: FileList,
     wordmap: WordMap,
+    /// Configuration stored with the index.
+    config: ConfigBlobs,
     auto_save: u32,
     save_time: Instant,
+    /// Incremented with every change to the index.
+    generation: u64,
+    query_cache: QueryCache,
+    /// Opened with read_only().
+    read_only: bool,
+}
+
+/// A word of a merge, looked up but not changed yet.
+struct StagedWord<'a> {
+    word: &'a str,
+    count: usize,
+    bag: usize,
+    /// The word-map entry of a known word, None for a new one.
+    chain: Option<(LogicalNr, BlkIdx)>,
 }
 
-pub type WordFileBlocks = FileBlocks<WordBlockType>;
+pub type WordFileBlocks = Segments;
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum WordBlockType {
@@ -113,19 +284,57 @@ pub enum WordBlockType {
     WordMapHead = BlockType::User3 as isize,
     WordMapTail = BlockType::User4 as isize,
     WordMapBags = BlockType::User5 as isize,
+    Header = BlockType::User6 as isize,
+    WordList2 = BlockType::User7 as isize,
+    Config = BlockType::User8 as isize,
+    FileList2 = BlockType::User9 as isize,
+    DirList = BlockType::User10 as isize,
+    FileFlags = BlockType::User11 as isize,
+    FileRefs = BlockType::User12 as isize,
+    FileLeaves = BlockType::User13 as isize,
+    /// Reserved for a map from the files to their words.
+    ReverseMap = BlockType::User14 as isize,
+    /// Reserved for words longer than the word-list holds.
+    LongWords = BlockType::User15 as isize,
+    /// First and last word of each word-list block.
+    WordDir = BlockType::User16 as isize,
+}
+
+impl WordBlockType {
+    /// All block types.
+    pub const ALL: [WordBlockType; 16] = [
+        WordBlockType::WordList,
+        WordBlockType::FileList,
+        WordBlockType::WordMapHead,
+        WordBlockType::WordMapTail,
+        WordBlockType::WordMapBags,
+        WordBlockType::Header,
+        WordBlockType::WordList2,
+        WordBlockType::Config,
+        WordBlockType::FileList2,
+        WordBlockType::DirList,
+        WordBlockType::FileFlags,
+        WordBlockType::FileRefs,
+        WordBlockType::FileLeaves,
+        WordBlockType::ReverseMap,
+        WordBlockType::LongWords,
+        WordBlockType::WordDir,
+    ];
+
+    /// Not written yet, a later version may use them.
+    pub fn is_reserved(self) -> bool {
+        matches!(self, WordBlockType::ReverseMap | WordBlockType::LongWords)
+    }
 }
 
+/// By the number of the BlockType.
 impl TryFrom<u32> for WordBlockType {
     type Error = u32;
     fn try_from(value: u32) -> Result<Self, Self::Error> {
-        match value {
-            16 => Ok(WordBlockType::WordList),
-            17 => Ok(WordBlockType::FileList),
-            18 => Ok(WordBlockType::WordMapHead),
-            19 => Ok(WordBlockType::WordMapTail),
-            20 => Ok(WordBlockType::WordMapBags),
-            _ => Err(value),
-        }
+        BlockType::try_from(value)
+            .ok()
+            .and_then(Self::user_type)
+            .ok_or(value)
     }
 }
 
@@ -143,6 +352,17 @@ impl Debug for WordBlockType {
             WordBlockType::WordMapHead => "WHD",
             WordBlockType::WordMapTail => "WTL",
             WordBlockType::WordMapBags => "WBG",
+            WordBlockType::Header => "HDR",
+            WordBlockType::WordList2 => "WR2",
+            WordBlockType::Config => "CFG",
+            WordBlockType::FileList2 => "FL2",
+            WordBlockType::DirList => "DIR",
+            WordBlockType::FileFlags => "FFL",
+            WordBlockType::FileRefs => "FRF",
+            WordBlockType::FileLeaves => "FLV",
+            WordBlockType::ReverseMap => "RVM",
+            WordBlockType::LongWords => "LWD",
+            WordBlockType::WordDir => "WDR",
         };
         write!(f, "{}", v)
     }
@@ -156,6 +376,17 @@ impl UserBlockType for WordBlockType {
             WordBlockType::WordMapHead => BlockType::User3,
             WordBlockType::WordMapTail => BlockType::User4,
             WordBlockType::WordMapBags => BlockType::User5,
+            WordBlockType::Header => BlockType::User6,
+            WordBlockType::WordList2 => BlockType::User7,
+            WordBlockType::Config => BlockType::User8,
+            WordBlockType::FileList2 => BlockType::User9,
+            WordBlockType::DirList => BlockType::User10,
+            WordBlockType::FileFlags => BlockType::User11,
+            WordBlockType::FileRefs => BlockType::User12,
+            WordBlockType::FileLeaves => BlockType::User13,
+            WordBlockType::ReverseMap => BlockType::User14,
+            WordBlockType::LongWords => BlockType::User15,
+            WordBlockType::WordDir => BlockType::User16,
         }
     }
 
@@ -166,23 +397,50 @@ impl UserBlockType for WordBlockType {
             BlockType::User3 => Some(Self::WordMapHead),
             BlockType::User4 => Some(Self::WordMapTail),
             BlockType::User5 => Some(Self::WordMapBags),
+            BlockType::User6 => Some(Self::Header),
+            BlockType::User7 => Some(Self::WordList2),
+            BlockType::User8 => Some(Self::Config),
+            BlockType::User9 => Some(Self::FileList2),
+            BlockType::User10 => Some(Self::DirList),
+            BlockType::User11 => Some(Self::FileFlags),
+            BlockType::User12 => Some(Self::FileRefs),
+            BlockType::User13 => Some(Self::FileLeaves),
+            BlockType::User14 => Some(Self::ReverseMap),
+            BlockType::User15 => Some(Self::LongWords),
+            BlockType::User16 => Some(Self::WordDir),
             _ => None,
         }
     }
 
     fn align(self) -> usize {
         match self {
-            WordBlockType::WordList => align_of::<[RawWord; 1]>(),
+            WordBlockType::WordList => align_of::<[RawWordV1; 1]>(),
+            WordBlockType::WordList2 => align_of::<[RawWord; 1]>(),
             WordBlockType::FileList => align_of::<[u8; 1]>(),
+            WordBlockType::Config => align_of::<[u8; 1]>(),
+            WordBlockType::FileList2 => align_of::<[u8; 1]>(),
+            WordBlockType::DirList => align_of::<[u8; 1]>(),
+            WordBlockType::FileFlags => align_of::<[u8; 1]>(),
+            WordBlockType::FileRefs => align_of::<[RawFileRef; 1]>(),
+            WordBlockType::FileLeaves => align_of::<[u8; 1]>(),
+            WordBlockType::ReverseMap => align_of::<[u8; 1]>(),
+            WordBlockType::LongWords => align_of::<[u8; 1]>(),
+            WordBlockType::WordDir => align_of::<[u8; 1]>(),
             WordBlockType::WordMapHead => align_of::<[RawWordMap; 1]>(),
             WordBlockType::WordMapTail => align_of::<[RawWordMap; 1]>(),
             WordBlockType::WordMapBags => align_of::<RawBags>(),
+            WordBlockType::Header => align_of::<RawHeader>(),
         }
     }
 
     fn is_stream(self) -> bool {
         match self {
             WordBlockType::FileList => true,
+            WordBlockType::FileList2 => true,
+            WordBlockType::DirList => true,
+            WordBlockType::FileFlags => true,
+            WordBlockType::WordDir => true,
+            WordBlockType::Config => true,
             _ => false,
         }
     }
@@ -235,7 +493,8 @@ impl Debug for Words {
         for block in self.db.iter_blocks() {
             match WordBlockType::user_type(block.block_type()) {
                 Some(WordBlockType::WordList) => {
-                    let data = unsafe { block.cast_array::<RawWord>() };
+                    let data = block_as::<RawWordV1>(block, block.block_nr())
+                        .map_err(|_| std::fmt::Error)?;
                     writeln!(f, "WordList {}", block.block_nr())?;
                     if f.width().unwrap_or(0) >= 1 {
                         for d in data.iter() {
@@ -250,14 +509,77 @@ impl Debug for Words {
                         }
                     }
                 }
+                Some(WordBlockType::WordList2) => {
+                    let data = block_as::<RawWord>(block, block.block_nr())
+                        .map_err(|_| std::fmt::Error)?;
+                    writeln!(f, "WordList2 {}", block.block_nr())?;
+                    if f.width().unwrap_or(0) >= 1 {
+                        for d in data.iter() {
+                            writeln!(f, "{:?}", d)?;
+                        }
+                    }
+                }
                 Some(WordBlockType::FileList) => {
                     writeln!(f, "FileList {}", block.block_nr())?;
                     if f.width().unwrap_or(0) >= 1 {
                         writeln!(f, "{:?}", block)?;
                     }
                 }
+                Some(WordBlockType::FileList2) => {
+                    writeln!(f, "FileList2 {}", block.block_nr())?;
+                    if f.width().unwrap_or(0) >= 1 {
+                        writeln!(f, "{:?}", block)?;
+                    }
+                }
+                Some(WordBlockType::DirList) => {
+                    writeln!(f, "DirList {}", block.block_nr())?;
+                    if f.width().unwrap_or(0) >= 1 {
+                        writeln!(f, "{:?}", block)?;
+                    }
+                }
+                Some(WordBlockType::FileFlags) => {
+                    writeln!(f, "FileFlags {}", block.block_nr())?;
+                    if f.width().unwrap_or(0) >= 1 {
+                        writeln!(f, "{:?}", block)?;
+                    }
+                }
+                Some(WordBlockType::WordDir) => {
+                    writeln!(f, "WordDir {}", block.block_nr())?;
+                    if f.width().unwrap_or(0) >= 1 {
+                        writeln!(f, "{:?}", block)?;
+                    }
+                }
+                Some(WordBlockType::FileRefs) => {
+                    let data = block_as::<RawFileRef>(block, block.block_nr())
+                        .map_err(|_| std::fmt::Error)?;
+                    writeln!(f, "FileRefs {}", block.block_nr())?;
+                    if f.width().unwrap_or(0) >= 1 {
+                        for d in data.iter().take_while(|v| v.file_id != 0) {
+                            writeln!(f, "{:?}", d)?;
+                        }
+                    }
+                }
+                Some(ty @ (WordBlockType::ReverseMap | WordBlockType::LongWords)) => {
+                    writeln!(f, "{} {} reserved", ty, block.block_nr())?;
+                    if f.width().unwrap_or(0) >= 1 {
+                        writeln!(f, "{:?}", block)?;
+                    }
+                }
+                Some(WordBlockType::FileLeaves) => {
+                    writeln!(f, "FileLeaves {}", block.block_nr())?;
+                    if f.width().unwrap_or(0) >= 1 {
+                        writeln!(f, "{:?}", block)?;
+                    }
+                }
+                Some(WordBlockType::Config) => {
+                    writeln!(f, "Config {}", block.block_nr())?;
+                    if f.width().unwrap_or(0) >= 1 {
+                        writeln!(f, "{:?}", block)?;
+                    }
+                }
                 Some(WordBlockType::WordMapHead) => {
-                    let data = unsafe { block.cast_array::<RawWordMap>() };
+                    let data = block_as::<RawWordMap>(block, block.block_nr())
+                        .map_err(|_| std::fmt::Error)?;
                     writeln!(f, "WordMapHead {}", block.block_nr())?;
                     if f.width().unwrap_or(0) >= 1 {
                         for d in data.iter() {
@@ -266,7 +588,8 @@ impl Debug for Words {
                     }
                 }
                 Some(WordBlockType::WordMapTail) => {
-                    let data = unsafe { block.cast_array::<RawWordMap>() };
+                    let data = block_as::<RawWordMap>(block, block.block_nr())
+                        .map_err(|_| std::fmt::Error)?;
                     writeln!(f, "WordMapTail {}", block.block_nr())?;
                     if f.width().unwrap_or(0) >= 1 {
                         for d in data.iter() {
@@ -275,7 +598,8 @@ impl Debug for Words {
                     }
                 }
                 Some(WordBlockType::WordMapBags) => {
-                    let data = unsafe { block.cast::<RawBags>() };
+                    let data = &block_as::<RawBags>(block, block.block_nr())
+                        .map_err(|_| std::fmt::Error)?[0];
                     writeln!(f, "WordMapBags {}", block.block_nr())?;
                     if f.width().unwrap_or(0) >= 1 {
                         for i in 0..BAG_LEN {
@@ -290,6 +614,22 @@ impl Debug for Words {
                         }
                     }
                 }
+                Some(WordBlockType::Header) => {
+                    let data = &block_as::<RawHeader>(block, block.block_nr())
+                        .map_err(|_| std::fmt::Error)?[0];
+                    writeln!(f, "Header {}", block.block_nr())?;
+                    if f.width().unwrap_or(0) >= 1 {
+                        writeln!(
+                            f,
+                            "version {} flags {:x} segment {}MB verify {} min-word-len {}",
+                            data.version,
+                            data.flags,
+                            data.segment_mb,
+                            data.verify_pos,
+                            data.min_word_len
+                        )?;
+                    }
+                }
                 None => {
                     writeln!(f, "{:?} {}", block.block_type(), block.block_nr())?;
                 }
@@ -300,6 +640,14 @@ impl Debug for Words {
     }
 }
 
+fn segment_limit(segment_mb: u32) -> Option<u64> {
+    if segment_mb > 0 {
+        Some(segment_mb as u64 * 1024 * 1024)
+    } else {
+        None
+    }
+}
+
 pub(crate) struct LastRef {
     pub id: u32,
     pub block_nr: u32,
@@ -308,49 +656,155 @@ pub(crate) struct LastRef {
 
 impl Words {
     pub fn create(file: &Path) -> Result<Self, IndexError> {
-        let _ = fs::remove_file(file);
+        Segments::remove(file);
         Self::read(file)
     }
 
     pub fn read(file: &Path) -> Result<Self, IndexError> {
+        Self::read_with(file, LAZY_WORDS)
+    }
+
+    /// Reads the index. With more than lazy_words words the word-list
+    /// is loaded lazily, 0 is always lazy.
+    pub fn read_with(file: &Path, lazy_words: usize) -> Result<Self, IndexError> {
+        Self::open(file, lazy_words, false)
+    }
+
+    /// Reads an index with broken words. Words that are not valid
+    /// UTF-8 are skipped, bad_words() lists them.
+    pub fn recover(file: &Path) -> Result<Self, IndexError> {
+        Self::open(file, LAZY_WORDS, true)
+    }
+
+    /// Opens the index for queries only. Nothing is written, the
+    /// word-list is lazy and of the file-list only the directories
+    /// are loaded. The found files are looked up by their id.
+    ///
+    /// No word block is read at open. The first lookup reads the
+    /// stored directory of the word-list, after that only the blocks
+    /// that may hold the word. Anything that would change a block
+    /// fails with IndexKind::ReadOnly.
+    pub fn read_only(file: &Path) -> Result<Self, IndexError> {
+        let mut db = Segments::load_read_only(file, BLOCK_SIZE)?;
+
+        let header = Header::load(&mut db)?;
+        let was_clean = header.block_nr == 0 || header.flag(FLAG_CLEAN);
+
+        let mut files = FileList::load_lookup(&mut db)?;
+        files.set_fold_case(header.fold_case());
+        let words = WordList::deferred(false);
+        let wordmap = WordMap::load(&mut db)?;
+        let config = ConfigBlobs::load(&mut db)?;
+
+        Ok(Self {
+            db,
+            header,
+            was_clean,
+            words,
+            word_count: 0,
+            bag_stats: [0usize; BAG_LEN],
+            files,
+            wordmap,
+            config,
+            auto_save: 0,
+            save_time: Instant::now(),
+            generation: 0,
+            query_cache: QueryCache::default(),
+            read_only: true,
+        })
+    }
+
+    /// Drops everything that is not written and reads the index
+    /// again. fold_case is kept.
+    ///
+    /// If the index can't be read, this one stays as it is.
+    pub fn reload(&mut self) -> Result<(), IndexError> {
+        let path = self.db.path(0);
+        let fold_case = self.fold_case();
+        *self = Words::read(&path)?;
+        self.set_fold_case(fold_case);
+        Ok(())
+    }
+
+    /// Opened with read_only().
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn open(file: &Path, lazy_words: usize, recover: bool) -> Result<Self, IndexError> {
         // 382_445 Dateien, 16_218 Ordner
         // 8,56 GB (9_194_861_782 Bytes)
 
-        let mut db = match FileBlocks::load(file, BLOCK_SIZE) {
+        let mut db = match Segments::load(file, BLOCK_SIZE) {
             Ok(db) => db,
             Err(err) => {
                 println!("{:?}", err);
-                return Err(err.into());
+                return Err(err);
             }
         };
 
-        println!("load files");
-        let files = FileList::load(&mut db)?;
-
-        println!("load words");
-        let words = WordList::load(&mut db)?;
-
-        println!("load wordmap");
+        let mut header = Header::load(&mut db)?;
+        // a new index has no header yet.
+        let was_clean = header.block_nr == 0 || header.flag(FLAG_CLEAN);
+        header.set_flag(FLAG_CLEAN, false);
+        // older word-lists are still read, but new words are
+        // stored with the current layout.
+        header.version = INDEX_VERSION;
+        header.store(&mut db)?;
+        db.store()?;
+        db.set_limit(segment_limit(header.segment_mb));
+
+        eprintln!("load files");
+        let mut files = FileList::load(&mut db)?;
+        files.set_fold_case(header.fold_case());
+
+        eprintln!("load words");
+        let words = WordList::load(&mut db, lazy_words, recover)?;
+
+        eprintln!("load wordmap");
         let wordmap = WordMap::load(&mut db)?;
 
+        let config = ConfigBlobs::load(&mut db)?;
+
         Self::cleanup(&mut db)?;
 
-        Ok(Self {
+        let mut words = Self {
             db,
+            header,
+            was_clean,
             words,
             word_count: 0,
             bag_stats: [0usize; BAG_LEN],
             files,
             wordmap,
+            config,
             auto_save: 0,
             save_time: Instant::now(),
-        })
+            generation: 0,
+            query_cache: QueryCache::default(),
+            read_only: false,
+        };
+        if words.fold_case() {
+            words.merge_case_duplicates();
+        }
+        Ok(words)
     }
 
+    /// Stores everything. A full disk is reported as IndexKind::DiskFull.
     pub fn write(&mut self) -> Result<(), IndexError> {
+        if self.is_read_only() {
+            return Err(IndexError::err(IndexKind::ReadOnly));
+        }
+        self.write_all().map_err(IndexError::or_disk_full)
+    }
+
+    fn write_all(&mut self) -> Result<(), IndexError> {
+        self.header.set_flag(FLAG_CLEAN, true);
+        self.header.store(&mut self.db)?;
         self.words.store(&mut self.db)?;
         self.files.store(&mut self.db)?;
         self.wordmap.store(&mut self.db)?;
+        self.config.store(&mut self.db)?;
 
         self.write_stats();
 
@@ -362,12 +816,25 @@ impl Words {
 
     fn cleanup(db: &mut WordFileBlocks) -> Result<(), IndexError> {
         // retain some datablocks in memory.
+        // blocks changed during a chunked write are still dirty.
         db.retain(|_k, v| match WordBlockType::user_type(v.block_type()) {
+            _ if v.is_dirty() => true,
             Some(WordBlockType::WordList) => false,
+            Some(WordBlockType::WordList2) => false,
             Some(WordBlockType::FileList) => false,
+            Some(WordBlockType::FileList2) => false,
+            Some(WordBlockType::DirList) => false,
+            Some(WordBlockType::FileFlags) => false,
+            Some(WordBlockType::WordDir) => false,
+            Some(WordBlockType::FileRefs) => false,
+            Some(WordBlockType::FileLeaves) => false,
+            Some(WordBlockType::ReverseMap) => false,
+            Some(WordBlockType::LongWords) => false,
+            Some(WordBlockType::Config) => false,
             Some(WordBlockType::WordMapHead) => false,
             Some(WordBlockType::WordMapTail) => false,
             Some(WordBlockType::WordMapBags) => true,
+            Some(WordBlockType::Header) => true,
             None => false, // doesn't matter
         });
         Ok(())
@@ -377,6 +844,61 @@ impl Words {
         // todo: self.db.compact_to()
     }
 
+    /// Allocates n blocks each for the word-map heads and tails and
+    /// the word-list ahead of a bulk load. They are used before any
+    /// new block is allocated. Returns the number of new blocks.
+    ///
+    /// The pools are only topped up to n. Unused blocks are kept for
+    /// the next walk, the blockfile can't free them. Only the blocks
+    /// left when the index is closed stay as empty blocks.
+    pub fn prealloc_blocks(&mut self, n: usize) -> Result<usize, IndexError> {
+        if self.is_read_only() {
+            return Err(IndexError::err(IndexKind::ReadOnly));
+        }
+        let mut count = 0;
+        count += self
+            .wordmap
+            .head_pool
+            .fill(&mut self.db, WordMap::TY_LISTHEAD, n)?;
+        count += self
+            .wordmap
+            .tail_pool
+            .fill(&mut self.db, WordMap::TY_LISTTAIL, n)?;
+        count += self.words.fill_pool(&mut self.db, n)?;
+        Ok(count)
+    }
+
+    /// Pre-allocated blocks not used yet.
+    pub fn pooled_blocks(&self) -> usize {
+        self.wordmap.head_pool.len() + self.wordmap.tail_pool.len() + self.words.pool_len()
+    }
+
+    /// Forgets the unused pre-allocated blocks and returns their
+    /// number. There is no free, they stay as empty blocks. A walk
+    /// keeps them for the next one instead.
+    pub fn release_blocks(&mut self) -> usize {
+        self.wordmap.head_pool.release()
+            + self.wordmap.tail_pool.release()
+            + self.words.release_pool()
+    }
+
+    /// The number of blocks of the type and the number of runs of
+    /// consecutive block-nrs they form. Fewer runs are better.
+    pub fn block_runs(&self, block_type: WordBlockType) -> (usize, usize) {
+        let mut nrs: Vec<u32> = self
+            .db
+            .iter_metadata_filter(|_nr, ty| ty == block_type)
+            .map(|(nr, _)| nr.0)
+            .collect();
+        nrs.sort_unstable();
+        let runs = nrs
+            .iter()
+            .enumerate()
+            .filter(|(i, nr)| *i == 0 || nrs[i - 1] + 1 != **nr)
+            .count();
+        (nrs.len(), runs)
+    }
+
     fn write_stats(&mut self) {
         let mut dirty = [0u32; 32];
         let mut clean = [0u32; 32];
@@ -411,6 +933,7 @@ impl Words {
                 print!(
                     "{} {}/{} ",
                     match WordBlockType::try_from(i as u32) {
+                        Ok(v) if v.is_reserved() => format!("{} reserved", v),
                         Ok(v) => v.to_string(),
                         Err(e) => match BlockType::try_from(e) {
                             Ok(v) => v.to_string(),
@@ -429,50 +952,236 @@ impl Words {
     /// It's not checked, if the same file was already added.
     /// Simply returns a new FileId.
     pub fn add_file(&mut self, file: String) -> FileId {
+        self.generation += 1;
         self.files.add(file)
     }
 
+    /// Is the file indexed. With fold_case a name that differs only in
+    /// case is the same file.
     pub fn have_file(&self, txt: &String) -> bool {
-        self.files.list().values().any(|v| &v.name == txt)
+        self.files.file_id(txt).is_some()
+    }
+
+    /// Names are compared ignoring case. Defaults to on for windows
+    /// and macos.
+    pub fn fold_case(&self) -> bool {
+        self.files.fold_case()
+    }
+
+    /// Stored with the next write. Turned on, the files indexed under
+    /// names that differ only in case are merged.
+    pub fn set_fold_case(&mut self, fold_case: bool) {
+        self.header.set_fold_case(fold_case);
+        self.files.set_fold_case(fold_case);
+        if fold_case {
+            self.merge_case_duplicates();
+        }
     }
 
-    pub fn files(&self) -> &BTreeMap<FileId, FileData> {
-        self.files.list()
+    /// Files indexed under names that differ only in case and not
+    /// merged yet. The first name of each group is the original.
+    pub fn case_duplicates(&self) -> Vec<Vec<String>> {
+        self.files
+            .case_duplicates()
+            .into_iter()
+            .map(|v| v.into_iter().flat_map(|id| self.file(id)).collect())
+            .collect()
+    }
+
+    /// Merges the files indexed under names that differ only in case
+    /// into the first of them, and returns their names. The words of
+    /// the merged files are found under the first name.
+    pub fn merge_case_duplicates(&mut self) -> Vec<Vec<String>> {
+        let duplicates = self.case_duplicates();
+        if !duplicates.is_empty() {
+            self.files.merge_case_duplicates();
+            self.generation += 1;
+        }
+        duplicates
+    }
+
+    pub fn files(&self) -> &FileList {
+        &self.files
     }
 
-    pub fn words(&self) -> &BTreeMap<String, WordData> {
-        self.words.list()
+    /// Data for the word. In lazy mode its block is loaded.
+    pub fn word(&mut self, word: &str) -> Result<Option<WordData>, IndexError> {
+        self.words.get(&mut self.db, word)
     }
 
+    /// Number of distinct words.
+    pub fn word_len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// The word-list is loaded lazily, see WordList.
+    pub fn lazy_words(&self) -> bool {
+        self.words.is_lazy()
+    }
+
+    /// All words starting with the prefix, sorted.
+    /// With an empty prefix these are all words.
+    pub fn prefix_words(&mut self, prefix: &str) -> Result<Vec<(String, WordData)>, IndexError> {
+        self.words.prefix(&mut self.db, prefix)
+    }
+
+    /// Files matching the pattern, in the order they were added.
     pub fn find_file(&self, txt: &str) -> Vec<String> {
-        let find = WildMatch::new(txt);
+        if let Some(found) = self.find_file_prefix(txt) {
+            return found;
+        }
+        let find = Pattern::glob(txt);
+        self.files
+            .iter()
+            .filter(|(_, name)| find.matches(name))
+            .map(|(_, name)| name.into_owned())
+            .collect()
+    }
+
+    /// Fast path of find_file for patterns with a literal prefix.
+    /// Only the names with this prefix are matched.
+    ///
+    /// Returns None if the pattern starts with a wildcard.
+    pub fn find_file_prefix(&self, txt: &str) -> Option<Vec<String>> {
+        let prefix = literal_prefix(txt);
+        if prefix.is_empty() {
+            return None;
+        }
+
+        let find = Pattern::glob(txt);
+        let found = self
+            .files
+            .with_prefix(prefix)
+            .into_iter()
+            .flat_map(|v| self.files.file_name(v))
+            .filter(|v| find.matches(v))
+            .map(|v| v.into_owned())
+            .collect();
+        Some(found)
+    }
+
+    /// Stored words that are not valid UTF-8, with their location.
+    pub fn bad_words(&mut self) -> Result<Vec<BadWord>, IndexError> {
+        WordList::bad_words(&mut self.db)
+    }
+
+    /// Files that ran out of time while indexing.
+    pub fn partial_files(&self) -> Vec<String> {
+        self.files
+            .with_flags(FILE_PARTIAL)
+            .into_iter()
+            .flat_map(|v| self.file(v))
+            .collect()
+    }
+
+    /// Files that look like word lists, and whether their words
+    /// were skipped.
+    pub fn wordlist_files(&self) -> Vec<(String, bool)> {
+        let skipped: BTreeSet<FileId> = self.files.with_flags(FILE_SKIPPED).into_iter().collect();
         self.files
-            .list()
-            .values()
-            .filter(|v| find.matches(v.name.as_str()))
-            .map(|v| &v.name)
-            .cloned()
+            .with_flags(FILE_WORDLIST)
+            .into_iter()
+            .flat_map(|v| Some((self.file(v)?, skipped.contains(&v))))
             .collect()
     }
 
+    /// Copy of all file names, in the order they were added.
+    /// For matching without holding on to the index.
+    pub fn file_name_list(&self) -> Vec<String> {
+        self.files.iter().map(|(_, v)| v.into_owned()).collect()
+    }
+
     pub fn file(&self, file_id: FileId) -> Option<String> {
-        self.files.list().get(&file_id).map(|v| v.name.clone())
+        self.files.file_name(file_id).map(|v| v.into_owned())
     }
 
     pub fn remove_file(&mut self, _name: String) {
         // todo: no removes
+        self.generation += 1;
+    }
+
+    /// Was the index closed cleanly last time.
+    pub fn was_clean(&self) -> bool {
+        self.was_clean
+    }
+
+    /// Are stop-words skipped when indexing.
+    pub fn stop_words(&self) -> bool {
+        !self.header.flag(FLAG_NO_STOP_WORDS)
+    }
+
+    /// Only sensible for an empty index.
+    pub fn set_stop_words(&mut self, stop_words: bool) {
+        self.header.set_flag(FLAG_NO_STOP_WORDS, !stop_words);
+    }
+
+    /// Shorter words are not indexed.
+    pub fn min_word_len(&self) -> usize {
+        max(self.header.min_word_len, 1) as usize
     }
 
-    /// Iterate words.
-    pub fn iter_words(&mut self) -> impl Iterator<Item = (&String, &WordData)> {
-        self.words.iter_words()
+    /// Applies to the files indexed from now on, the words
+    /// already in the index stay.
+    pub fn set_min_word_len(&mut self, min_word_len: usize) {
+        self.header.min_word_len = min_word_len as u32;
+    }
+
+    /// Segment size in MB, 0 keeps everything in one file.
+    pub fn segment_size(&self) -> u32 {
+        self.header.segment_mb
+    }
+
+    /// Start a new segment file when the current one exceeds
+    /// the size. Existing segments are kept as they are.
+    pub fn set_segment_size(&mut self, segment_mb: u32) {
+        self.header.segment_mb = segment_mb;
+        self.db.set_limit(segment_limit(segment_mb));
+    }
+
+    /// Configuration stored with the index.
+    pub fn config(&self) -> &ConfigBlobs {
+        &self.config
+    }
+
+    /// Embeds a configuration blob. It is saved with the next write.
+    pub fn set_config(&mut self, name: &str, data: Vec<u8>) -> Result<(), IndexError> {
+        self.config.set(name, data)?;
+        self.generation += 1;
+        Ok(())
+    }
+
+    /// Position of an unfinished background verify.
+    /// It is saved with the next write.
+    pub fn verify_pos(&self) -> Option<usize> {
+        if self.header.flag(FLAG_VERIFY) {
+            Some(self.header.verify_pos as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn set_verify_pos(&mut self, pos: Option<usize>) {
+        self.header.set_flag(FLAG_VERIFY, pos.is_some());
+        self.header.verify_pos = pos.unwrap_or(0) as u32;
+    }
+
+    /// Changes with every modification of the index.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Blocks in memory and their bytes.
+    pub fn cache_size(&self) -> (usize, usize) {
+        let blocks = self.db.iter_blocks().count();
+        (blocks, blocks * self.db.block_size())
+    }
+
+    pub fn query_cache(&self) -> &QueryCache {
+        &self.query_cache
     }
 
     /// Iterate all files for a word.
-    pub fn iter_word_files(
-        &mut self,
-        word_data: WordData,
-    ) -> impl Iterator<Item = Result<FileId, IndexError>> + '_ {
+    pub fn iter_word_files(&mut self, word_data: WordData) -> IterFileId<'_> {
         WordMap::iter_files(
             &mut self.db,
             word_data.file_map_block_nr,
@@ -480,6 +1189,11 @@ impl Words {
         )
     }
 
+    /// Words added per bag since the index was opened.
+    pub fn bag_stats(&self) -> &[usize; BAG_LEN] {
+        &self.bag_stats
+    }
+
     /// Total word count.
     pub fn add_word_count(&mut self, count: usize) {
         self.word_count += count;
@@ -494,20 +1208,16 @@ impl Words {
         count: usize,
         file_id: FileId,
     ) -> Result<(), IndexError> {
-        if let Some(data) = self.words.get_mut(word.as_ref()) {
+        self.generation += 1;
+
+        if let Some(data) = self.words.get_mut(&mut self.db, word.as_ref())? {
             data.count += count;
 
-            let bag = if self.word_count == 0 {
-                0
-            } else {
-                // a single word should hardly have more than 5% of total word count.
-                let v = (data.count * 256 * 20) / self.word_count;
-                clamp(0, 255, v)
-            };
+            let bag = bag_for(data.count, self.word_count);
             self.bag_stats[bag] += 1;
 
             // add second file-id. (and any further).
-            self.wordmap.add(
+            let added = self.wordmap.add(
                 &mut self.db,
                 word.as_ref(),
                 bag,
@@ -515,14 +1225,11 @@ impl Words {
                 data.file_map_idx,
                 file_id,
             )?;
+            if added {
+                self.words.count_file(&mut self.db, word.as_ref())?;
+            }
         } else {
-            let bag = if self.word_count == 0 {
-                0
-            } else {
-                // a single word should hardly have more than 5% of total word count.
-                let v = (count * 256 * 20) / self.word_count;
-                clamp(0, 255, v)
-            };
+            let bag = bag_for(count, self.word_count);
             self.bag_stats[bag] += 1;
 
             // Initial references get a special block.
@@ -531,54 +1238,193 @@ impl Words {
                     .add_initial(&mut self.db, bag, word.as_ref(), file_id)?;
 
             self.words
-                .insert(word, count, file_map_block_nr, file_map_idx);
+                .insert(word, count, file_map_block_nr, file_map_idx, file_id);
         };
         Ok(())
     }
 
     /// Append a temp buffer for a file.
+    ///
+    /// The words are looked up first, then the word-map entries are
+    /// written and only then the word-list is changed. If a write
+    /// fails the word-list is as before. The file is kept with the
+    /// references written so far and marked as partial, replace()
+    /// can merge it again.
     pub fn append(&mut self, other: TmpWords) -> Result<(), IndexError> {
+        let staged = self.stage_words(&other.words, other.count)?;
+
         let f_idx = self.add_file(other.file);
-        self.add_word_count(other.count);
-        for (a_txt, a_n) in other.words.iter() {
-            self.add_word(a_txt, *a_n, f_idx)?;
+        if other.partial {
+            self.files.set_flags(f_idx, FILE_PARTIAL);
+        }
+        if other.wordlist {
+            self.files.set_flags(f_idx, FILE_WORDLIST);
+        }
+        if other.skipped {
+            self.files.set_flags(f_idx, FILE_SKIPPED);
+        }
+        if let Err(e) = self.merge_staged(f_idx, other.count, staged) {
+            self.files.set_flags(f_idx, FILE_PARTIAL);
+            return Err(e);
         }
         Ok(())
     }
 
+    /// Append a temp buffer for a file that may be indexed already.
+    /// Returns true if it was.
+    ///
+    /// There are no removes, so a file that is indexed keeps its entry
+    /// and its flags and gets the words added. Words that are gone from
+    /// the file still find it. This only fits the same content again,
+    /// as after a failed append or a timeout, not a changed file.
+    /// If the file is complete now its partial flag is cleared.
+    pub fn replace(&mut self, other: TmpWords) -> Result<bool, IndexError> {
+        let Some(f_idx) = self.files.file_id(&other.file) else {
+            self.append(other)?;
+            return Ok(false);
+        };
+        let staged = self.stage_words(&other.words, other.count)?;
+        self.merge_staged(f_idx, other.count, staged)?;
+        if !other.partial {
+            self.files.clear_partial(f_idx);
+        }
+        Ok(true)
+    }
+
+    /// Looks up the words of a merge, nothing is changed yet.
+    fn stage_words<'a>(
+        &mut self,
+        words: &'a HashMap<String, usize>,
+        word_count: usize,
+    ) -> Result<Vec<StagedWord<'a>>, IndexError> {
+        let total = self.word_count + word_count;
+        let mut staged = Vec::with_capacity(words.len());
+        for (word, count) in words {
+            let (bag, chain) = match self.words.get(&mut self.db, word)? {
+                Some(data) => (
+                    bag_for(data.count + count, total),
+                    Some((data.file_map_block_nr, data.file_map_idx)),
+                ),
+                None => (bag_for(*count, total), None),
+            };
+            staged.push(StagedWord {
+                word,
+                count: *count,
+                bag,
+                chain,
+            });
+        }
+        Ok(staged)
+    }
+
+    /// Writes the word-map entries for the staged words, then adds
+    /// them to the word-list, which can't fail.
+    ///
+    /// If a write fails the references written so far stay, the
+    /// known words find the file. The head entries of the new words
+    /// are cleared and kept for reuse, no word points to them.
+    fn merge_staged(
+        &mut self,
+        file_id: FileId,
+        word_count: usize,
+        staged: Vec<StagedWord<'_>>,
+    ) -> Result<(), IndexError> {
+        self.generation += 1;
+
+        let mut heads = Vec::with_capacity(staged.len());
+        for v in &staged {
+            let written = match v.chain {
+                Some((blk_nr, blk_idx)) => self
+                    .wordmap
+                    .add(&mut self.db, v.word, v.bag, blk_nr, blk_idx, file_id)
+                    .and_then(|added| {
+                        if added {
+                            self.words.count_file(&mut self.db, v.word)
+                        } else {
+                            Ok(())
+                        }
+                    })
+                    .map(|_| None),
+                None => self
+                    .wordmap
+                    .add_initial(&mut self.db, v.bag, v.word, file_id)
+                    .map(Some),
+            };
+            match written {
+                Ok(head) => {
+                    self.bag_stats[v.bag] += 1;
+                    heads.push(head);
+                }
+                Err(e) => {
+                    for (blk_nr, blk_idx) in heads.into_iter().flatten() {
+                        self.wordmap.orphan(&mut self.db, blk_nr, blk_idx);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        self.add_word_count(word_count);
+        for (v, head) in staged.iter().zip(heads) {
+            match head {
+                Some((blk_nr, blk_idx)) => {
+                    self.words.insert(v.word, v.count, blk_nr, blk_idx, file_id)
+                }
+                None => self.words.add_count(v.word, v.count),
+            }
+        }
+        Ok(())
+    }
+
+    /// Word-map entries left behind by a failed merge.
+    pub fn orphaned_slots(&self) -> &[(LogicalNr, BlkIdx)] {
+        &self.wordmap.orphans
+    }
+
+    /// Makes the n-th next write of a file reference fail.
+    /// Only for testing the error handling.
+    #[cfg(feature = "test-hooks")]
+    pub fn fail_map_after(&mut self, n: Option<usize>) {
+        self.wordmap.fail_after(n);
+    }
+
     /// Find words.
     pub fn find(&mut self, terms: &[String]) -> Result<Vec<String>, IndexError> {
-        let mut collect = BTreeSet::<FileId>::new();
-        let mut first = true;
-
-        let terms: Vec<_> = terms.iter().map(|v| WildMatch::new(v)).collect();
-
-        // find the words and the files where they are contained.
-        // each consecutive search-term *reduces* the list of viable files.
-        for matcher in terms {
-            let words: Vec<_> = self
-                .iter_words()
-                .filter(|(k, _)| matcher.matches(k))
-                .map(|(_, v)| *v)
-                .collect();
-
-            let files = words
-                .into_iter()
-                .flat_map(|v| self.iter_word_files(v).flatten().collect::<Vec<FileId>>());
-
-            if first {
-                collect = files.collect();
-            } else {
-                collect = files.filter(|v| collect.contains(v)).collect();
-            }
+        let ids = self.find_ids(terms)?;
+        self.resolve_files(&ids)?;
+        Ok(self.file_names(&ids))
+    }
+
+    /// The words matching one search-term. Namespaced terms only match
+    /// namespaced words and the other way round.
+    pub fn match_words(&mut self, term: &str) -> Result<Vec<(String, WordData)>, IndexError> {
+        self.plan_words(&TermPlan::new(term), None)
+    }
 
-            first = false;
+    /// Find the files that contain all the terms.
+    /// Recent results are cached until the index changes.
+    pub fn find_ids(&mut self, terms: &[String]) -> Result<BTreeSet<FileId>, IndexError> {
+        let key = QueryCache::normalize(terms);
+        if let Some(ids) = self.query_cache.get(&key, self.generation) {
+            return Ok(ids.iter().copied().collect());
         }
 
-        // map the found file-id to the file-name.
-        let names = collect.iter().flat_map(|v| self.file(*v)).collect();
+        let collect = self.execute(&QueryPlan::new(terms), None)?;
 
-        Ok(names)
+        self.query_cache
+            .insert(key, self.generation, collect.iter().copied().collect());
+
+        Ok(collect)
+    }
+
+    /// Loads the found files, only needed with read_only().
+    pub fn resolve_files(&mut self, ids: &BTreeSet<FileId>) -> Result<(), IndexError> {
+        self.files.resolve(&mut self.db, ids)
+    }
+
+    /// Map the found file-ids to the file-names.
+    pub fn file_names(&self, ids: &BTreeSet<FileId>) -> Vec<String> {
+        ids.iter().flat_map(|v| self.file(*v)).collect()
     }
 
     pub fn set_save_time(&mut self) {
@@ -633,7 +1479,3 @@ fn byte_to_string<const N: usize>(src: &[u8; N]) -> String {
     let word = word.trim_end_matches('\0');
     word.to_string()
 }
-
-fn clamp(min: usize, max: usize, val: usize) -> usize {
-    usize::max(min, usize::min(val, max))
-}
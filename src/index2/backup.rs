@@ -0,0 +1,165 @@
+use crate::index2::header::{Header, FLAG_CLEAN, INDEX_VERSION};
+use crate::index2::segments::{segment_path, Segments};
+use crate::index2::{IndexError, IndexKind, Words, BLOCK_SIZE};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+impl Words {
+    /// Writes the index and copies it with all segments into the
+    /// directory. Returns the copied files.
+    ///
+    /// Nothing can be added while the caller holds the words lock,
+    /// so the copy is the state of this write.
+    pub fn backup(&mut self, dir: &Path) -> Result<Vec<PathBuf>, IndexError> {
+        self.write()?;
+
+        fs::create_dir_all(dir)?;
+        let mut copied = Vec::new();
+        for n in 0..self.db.len() {
+            let path = self.db.path(n);
+            let copy = dir.join(file_name(&path)?);
+            fs::copy(&path, &copy)?;
+            copied.push(copy);
+        }
+        Ok(copied)
+    }
+
+    /// Checks the backup in the directory without changing it.
+    /// Returns its index version.
+    pub fn check_backup(&self, dir: &Path) -> Result<u32, IndexError> {
+        let path = self.db.path(0);
+        read_backup_header(&dir.join(file_name(&path)?))
+    }
+
+    /// Replaces the index with the backup in the directory and
+    /// opens it again. Everything not written yet is lost.
+    ///
+    /// The backup is copied next to the index and checked there.
+    /// Only a complete copy replaces the index, a bad backup or a
+    /// failed copy leaves the index alone. The index files are moved
+    /// aside until the copy is opened, if anything fails they are
+    /// moved back and opened again.
+    pub fn restore(&mut self, dir: &Path) -> Result<(), IndexError> {
+        let path = self.db.path(0);
+        let backup = dir.join(file_name(&path)?);
+        read_backup_header(&backup)?;
+
+        let tmp = side_path(&path, "restore");
+        let mut copies = Vec::new();
+        if let Err(e) = copy_backup(&backup, &tmp, &mut copies) {
+            for copy in copies {
+                let _ = fs::remove_file(copy);
+            }
+            return Err(e);
+        }
+
+        let aside = side_path(&path, "old");
+        let mut moved = Vec::new();
+        self.db.close();
+        let result = replace_files(&path, &aside, &copies, &mut moved).and_then(|_| self.reload());
+        if let Err(e) = result {
+            for (from, to) in moved.iter().rev() {
+                let _ = fs::rename(to, from);
+            }
+            for copy in copies {
+                let _ = fs::remove_file(copy);
+            }
+            // without the old index every access fails with
+            // IndexKind::Closed.
+            let _ = self.reload();
+            return Err(e);
+        }
+
+        for n in 0.. {
+            let seg_path = segment_path(&aside, n);
+            if !seg_path.exists() {
+                break;
+            }
+            fs::remove_file(seg_path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Name of a file next to the index, for the copy of a backup
+/// and the index while it is restored.
+fn side_path(path: &Path, tag: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|v| v.to_string_lossy())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, tag, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, tag),
+    };
+    path.with_file_name(name)
+}
+
+/// Moves all segments of the index aside and the copies in their
+/// place. Each rename is added to moved.
+fn replace_files(
+    path: &Path,
+    aside: &Path,
+    copies: &[PathBuf],
+    moved: &mut Vec<(PathBuf, PathBuf)>,
+) -> Result<(), IndexError> {
+    for n in 0.. {
+        let seg_path = segment_path(path, n);
+        if !seg_path.exists() {
+            break;
+        }
+        let to = segment_path(aside, n);
+        fs::rename(&seg_path, &to)?;
+        moved.push((seg_path, to));
+    }
+    for (n, copy) in copies.iter().enumerate() {
+        let to = segment_path(path, n);
+        fs::rename(copy, &to)?;
+        moved.push((copy.clone(), to));
+    }
+    Ok(())
+}
+
+/// Copies the backup with all segments to tmp and checks the copy.
+/// The copied files are added to copies as they are written.
+fn copy_backup(backup: &Path, tmp: &Path, copies: &mut Vec<PathBuf>) -> Result<(), IndexError> {
+    for n in 0.. {
+        let from = segment_path(backup, n);
+        if !from.exists() {
+            break;
+        }
+        let to = segment_path(tmp, n);
+        copies.push(to.clone());
+        fs::copy(&from, &to)?;
+    }
+
+    read_backup_header(tmp)?;
+    let mut copy = Words::read_only(tmp)?;
+    if !copy.check(None).is_ok() {
+        return Err(IndexError::err(IndexKind::InvalidBackup(backup.into())));
+    }
+    Ok(())
+}
+
+/// Reads the header of a backup without changing it.
+/// Only a cleanly written index of a known version is accepted.
+///
+/// Returns the index version.
+pub fn read_backup_header(path: &Path) -> Result<u32, IndexError> {
+    if !path.is_file() {
+        return Err(IndexError::err(IndexKind::InvalidBackup(path.into())));
+    }
+
+    let mut db = Segments::load(path, BLOCK_SIZE)?;
+    let header = Header::load(&mut db)?;
+    if header.block_nr == 0 || header.version > INDEX_VERSION || !header.flag(FLAG_CLEAN) {
+        return Err(IndexError::err(IndexKind::InvalidBackup(path.into())));
+    }
+
+    Ok(header.version)
+}
+
+fn file_name(path: &Path) -> Result<&std::ffi::OsStr, IndexError> {
+    path.file_name()
+        .ok_or_else(|| IndexError::err(IndexKind::InvalidBackup(path.into())))
+}
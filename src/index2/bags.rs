@@ -0,0 +1,142 @@
+use crate::index2::ids::BlkIdx;
+use crate::index2::segments::block_as;
+use crate::index2::word_map::{RawWordMap, WordMap, BAG_LEN};
+use crate::index2::{IndexError, WordBlockType, WordFileBlocks, Words};
+use blockfile2::{Block, LogicalNr};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Result of rebuilding the bags.
+#[derive(Debug, Default)]
+pub struct BagsReport {
+    pub head_blocks: usize,
+    pub tail_blocks: usize,
+    /// One line per bag that changed.
+    pub changes: Vec<String>,
+    /// Blocks with free slots that no bag got.
+    pub stranded: usize,
+}
+
+impl Words {
+    /// There are word-map blocks but the bags don't point anywhere.
+    /// The next add would start new blocks for every bag.
+    pub fn bags_missing(&self) -> bool {
+        let empty = (0..BAG_LEN)
+            .all(|bag| self.wordmap.last_head_nr[bag] == 0 && self.wordmap.last_tail_nr[bag] == 0);
+        empty
+            && self
+                .db
+                .iter_metadata_filter(|_nr, ty| {
+                    ty == WordMap::TY_LISTHEAD || ty == WordMap::TY_LISTTAIL
+                })
+                .next()
+                .is_some()
+    }
+
+    /// Sets the bags from the word-map blocks.
+    ///
+    /// Each bag must point to the last used slot of a block, and a block
+    /// may only belong to one bag. Bags that already do so are kept.
+    /// The bag of a block isn't stored, so the other blocks with free
+    /// slots are handed to the free bags, the newest block first.
+    ///
+    /// The bags are stored with the next write.
+    pub fn rebuild_bags(&mut self) -> Result<BagsReport, IndexError> {
+        let mut report = BagsReport::default();
+
+        let map_len = Block::len_array::<RawWordMap>(self.db.block_size()) as u32;
+        let heads = last_used(&mut self.db, WordMap::TY_LISTHEAD)?;
+        let tails = last_used(&mut self.db, WordMap::TY_LISTTAIL)?;
+        report.head_blocks = heads.len();
+        report.tail_blocks = tails.len();
+
+        let map = &mut self.wordmap;
+        let (head_nr, head_idx) = (&mut map.last_head_nr, &mut map.last_head_idx);
+        rebuild(&heads, map_len, head_nr, head_idx, "head", &mut report);
+        let (tail_nr, tail_idx) = (&mut map.last_tail_nr, &mut map.last_tail_idx);
+        rebuild(&tails, map_len, tail_nr, tail_idx, "tail", &mut report);
+
+        Ok(report)
+    }
+}
+
+/// Last used slot for every block of this type.
+fn last_used(
+    db: &mut WordFileBlocks,
+    ty: WordBlockType,
+) -> Result<BTreeMap<u32, BlkIdx>, IndexError> {
+    let mut blocks = BTreeMap::new();
+    for (block_nr, _) in db.iter_metadata_filter(|_nr, t| t == ty) {
+        let (used, dirty) = {
+            let block = db.get(block_nr)?;
+            let maps = block_as::<RawWordMap>(block, block_nr)?;
+            // an empty block continues at 1.
+            let used = maps
+                .iter()
+                .rposition(|v| *v != RawWordMap::default())
+                .unwrap_or(0);
+            (used, block.is_dirty())
+        };
+        if !dirty {
+            db.discard(block_nr)?;
+        }
+
+        blocks.insert(block_nr.0, BlkIdx(used as u32));
+    }
+    Ok(blocks)
+}
+
+fn rebuild(
+    blocks: &BTreeMap<u32, BlkIdx>,
+    map_len: u32,
+    last_nr: &mut [LogicalNr; BAG_LEN],
+    last_idx: &mut [BlkIdx; BAG_LEN],
+    what: &str,
+    report: &mut BagsReport,
+) {
+    let mut taken = BTreeSet::new();
+
+    // keep or correct the current ones.
+    for bag in 0..BAG_LEN {
+        if last_nr[bag] == 0 {
+            continue;
+        }
+        let old = (last_nr[bag], last_idx[bag]);
+        match blocks.get(&last_nr[bag].0) {
+            Some(used) if !taken.contains(&last_nr[bag].0) => {
+                taken.insert(last_nr[bag].0);
+                last_idx[bag] = *used;
+            }
+            _ => {
+                // gone or already taken.
+                last_nr[bag] = LogicalNr(0);
+                last_idx[bag] = BlkIdx(0);
+            }
+        }
+        if old != (last_nr[bag], last_idx[bag]) {
+            report.changes.push(format!(
+                "bag {} {} {}:{} -> {}:{}",
+                bag, what, old.0, old.1, last_nr[bag], last_idx[bag]
+            ));
+        }
+    }
+
+    // the rest goes to the free bags.
+    let mut free = blocks
+        .iter()
+        .rev()
+        .filter(|(nr, used)| **used + 1 < map_len && !taken.contains(*nr));
+    for bag in 0..BAG_LEN {
+        if last_nr[bag] != 0 {
+            continue;
+        }
+        let Some((nr, used)) = free.next() else {
+            break;
+        };
+        last_nr[bag] = LogicalNr(*nr);
+        last_idx[bag] = *used;
+        report
+            .changes
+            .push(format!("bag {} {} -> {}:{}", bag, what, nr, used));
+    }
+    report.stranded += free.count();
+}
@@ -0,0 +1,58 @@
+use crate::index2::{IndexError, WordBlockType, WordFileBlocks};
+use blockfile2::LogicalNr;
+use std::collections::VecDeque;
+
+/// Blocks of one type allocated ahead in one batch, so they get
+/// neighbouring block-nrs instead of being interleaved with
+/// everything else allocated during a walk.
+#[derive(Debug, Default)]
+pub struct BlockPool {
+    free: VecDeque<LogicalNr>,
+}
+
+impl BlockPool {
+    /// Allocates blocks until the pool holds n.
+    pub fn fill(
+        &mut self,
+        db: &mut WordFileBlocks,
+        block_type: WordBlockType,
+        n: usize,
+    ) -> Result<usize, IndexError> {
+        let mut count = 0;
+        while self.free.len() < n {
+            self.free.push_back(db.alloc(block_type)?.0);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// The next block of the pool, a new one if the pool is empty.
+    pub fn alloc(
+        &mut self,
+        db: &mut WordFileBlocks,
+        block_type: WordBlockType,
+    ) -> Result<LogicalNr, IndexError> {
+        match self.free.pop_front() {
+            Some(block_nr) => Ok(block_nr),
+            None => Ok(db.alloc(block_type)?.0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.free.is_empty()
+    }
+
+    /// Forgets the unused blocks and returns their number.
+    ///
+    /// The blockfile can't free a block, they stay in the file as
+    /// empty blocks of their type.
+    pub fn release(&mut self) -> usize {
+        let n = self.free.len();
+        self.free.clear();
+        n
+    }
+}
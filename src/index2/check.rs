@@ -0,0 +1,352 @@
+use crate::cancel::is_cancelled;
+use crate::index2::files::FileList;
+use crate::index2::ids::{BlkIdx, FileId};
+use crate::index2::segments::block_as;
+use crate::index2::word_map::{RawWordMap, BAG_LEN};
+use crate::index2::words::{RawWord, WordList};
+use crate::index2::{WordBlockType, WordFileBlocks, Words};
+use blockfile2::{Block, LogicalNr};
+use std::cmp::min;
+use std::collections::BTreeMap;
+
+/// Number of words sampled by the quick check.
+pub const QUICK_CHECK_WORDS: usize = 1000;
+
+/// Words per step of a chunked check.
+pub const CHECK_CHUNK: usize = 500;
+
+/// Longer chains are taken for a cycle.
+pub(crate) const CHAIN_LIMIT: usize = 1_000_000;
+
+/// Result of an integrity check.
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    pub words: usize,
+    pub chain_entries: usize,
+    pub files: usize,
+    pub problems: Vec<String>,
+    /// Stopped with Ctrl-C, only part of the words were checked.
+    pub cancelled: bool,
+}
+
+/// State of a chunked check.
+///
+/// The words are checked in the order of the word-list, the position
+/// counts the words passed so far. A lazy word-list is walked in the
+/// order the words are stored.
+#[derive(Debug)]
+pub struct CheckScan {
+    /// Words passed, checked or skipped by the sample.
+    pub pos: usize,
+    /// Only every n-th word is checked.
+    step: usize,
+    /// Continue after this word.
+    last_word: Option<String>,
+    started: bool,
+    pub finished: bool,
+    pub report: CheckReport,
+    /// Block types, taken when the scan starts. They are taken again
+    /// when a chain reaches a block allocated since.
+    types: BTreeMap<u32, WordBlockType>,
+    /// Word blocks whose bytes are not checked yet. Each step takes
+    /// about as many as it passes words.
+    word_blocks: Vec<LogicalNr>,
+}
+
+impl CheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl Words {
+    /// Checks the bags block, the word chains and the file list.
+    ///
+    /// With a sample only every n-th word is checked, so that the
+    /// check stays fast even for a large index.
+    pub fn check(&mut self, sample: Option<usize>) -> CheckReport {
+        let mut scan = self.start_check(sample, 0);
+        while !self.check_step(&mut scan, CHECK_CHUNK) {
+            if is_cancelled() {
+                scan.report.cancelled = true;
+                break;
+            }
+        }
+        scan.report
+    }
+
+    /// Starts a chunked check at the n-th word.
+    pub fn start_check(&self, sample: Option<usize>, pos: usize) -> CheckScan {
+        let step = match sample {
+            Some(n) if n > 0 => usize::max(1, self.words.len() / n),
+            _ => 1,
+        };
+        let pos = if pos <= self.words.len() { pos } else { 0 };
+        let types = block_types(&self.db);
+        let mut word_blocks: Vec<LogicalNr> = types
+            .iter()
+            .filter(|(_, ty)| **ty == WordList::TY || **ty == WordList::TY_V1)
+            .map(|(nr, _)| LogicalNr(*nr))
+            .collect();
+        // a resumed check skips about as many blocks as words.
+        let skip = min(pos / words_per_block(&self.db), word_blocks.len());
+        word_blocks.drain(..skip);
+        CheckScan {
+            pos,
+            step,
+            last_word: None,
+            started: false,
+            finished: false,
+            report: Default::default(),
+            types,
+            word_blocks,
+        }
+    }
+
+    /// Checks the next n words. The bags are checked with the first
+    /// step, the bytes of the stored words along the way, and the
+    /// file list with the last step.
+    ///
+    /// The index may change between the steps. Words added before the
+    /// position are not checked. Returns true when the check is finished.
+    pub fn check_step(&mut self, scan: &mut CheckScan, n: usize) -> bool {
+        if scan.finished {
+            return true;
+        }
+
+        let map_len = Block::len_array::<RawWordMap>(self.db.block_size()) as u32;
+
+        if !scan.started {
+            scan.started = true;
+            self.check_bags(&scan.types, map_len, &mut scan.report);
+        }
+
+        // word chains
+        let last_file_id = self.files.last_file_id();
+        let after = scan.last_word.take();
+        let words = match self
+            .words
+            .chunk(&mut self.db, scan.pos, after.as_deref(), n * scan.step)
+        {
+            Ok(v) => v,
+            Err(e) => {
+                scan.report.problems.push(format!("word-list: {}", e.kind));
+                Vec::new()
+            }
+        };
+        let mut taken = 0;
+        let mut chains = Vec::new();
+        for (k, v) in words {
+            if (scan.pos + taken) % scan.step == 0 {
+                chains.push((k.clone(), v.file_map_block_nr, v.file_map_idx));
+            }
+            taken += 1;
+            scan.last_word = Some(k);
+        }
+        if taken == 0 {
+            scan.last_word = after;
+        }
+        scan.pos += taken;
+        for (word, nr, idx) in chains {
+            scan.report.words += 1;
+            check_chain(
+                &mut self.db,
+                &mut scan.types,
+                map_len,
+                last_file_id,
+                &word,
+                nr,
+                idx,
+                &mut scan.report,
+            );
+        }
+
+        let last = taken < n * scan.step;
+
+        // the rest of the word blocks with the last step.
+        let n_blocks = if last {
+            scan.word_blocks.len()
+        } else {
+            min(
+                (n * scan.step).div_ceil(words_per_block(&self.db)),
+                scan.word_blocks.len(),
+            )
+        };
+        let blocks: Vec<LogicalNr> = scan.word_blocks.drain(..n_blocks).collect();
+        self.check_bad_words(&blocks, &mut scan.report);
+
+        if last {
+            self.check_files(&mut scan.report);
+            scan.finished = true;
+        }
+
+        scan.finished
+    }
+
+    /// The words of the blocks that are not valid UTF-8, with their
+    /// location.
+    fn check_bad_words(&mut self, blocks: &[LogicalNr], report: &mut CheckReport) {
+        match WordList::bad_words_in(&mut self.db, blocks) {
+            Ok(bad) => {
+                for v in bad {
+                    report.problems.push(format!("word-list: {}", v));
+                }
+            }
+            Err(e) => report.problems.push(format!("word-list: {}", e.kind)),
+        }
+    }
+
+    fn check_bags(
+        &self,
+        types: &BTreeMap<u32, WordBlockType>,
+        map_len: u32,
+        report: &mut CheckReport,
+    ) {
+        if self.wordmap.bag_nr != 0
+            && types.get(&self.wordmap.bag_nr.0) != Some(&WordBlockType::WordMapBags)
+        {
+            report
+                .problems
+                .push(format!("bags block {} is missing", self.wordmap.bag_nr));
+        }
+        for bag in 0..BAG_LEN {
+            let head = (
+                self.wordmap.last_head_nr[bag],
+                self.wordmap.last_head_idx[bag],
+            );
+            let tail = (
+                self.wordmap.last_tail_nr[bag],
+                self.wordmap.last_tail_idx[bag],
+            );
+            for (ty, (nr, idx)) in [
+                (WordBlockType::WordMapHead, head),
+                (WordBlockType::WordMapTail, tail),
+            ] {
+                if nr == 0 {
+                    continue;
+                }
+                if types.get(&nr.0) != Some(&ty) {
+                    report
+                        .problems
+                        .push(format!("bag {} points to {} which is no {:?}", bag, nr, ty));
+                } else if idx >= map_len {
+                    report
+                        .problems
+                        .push(format!("bag {} points past the end of {}", bag, nr));
+                }
+            }
+        }
+    }
+
+    /// The file list must read back completely.
+    fn check_files(&mut self, report: &mut CheckReport) {
+        match FileList::load(&mut self.db) {
+            Ok(files) => {
+                report.files = files.len();
+                // merged files are records too.
+                if files.list().len()
+                    < self
+                        .files
+                        .list()
+                        .values()
+                        .filter(|v| v.block_nr != 0)
+                        .count()
+                {
+                    report.problems.push(format!(
+                        "file list ends after {} of {} files",
+                        files.list().len(),
+                        self.files.list().len()
+                    ));
+                }
+            }
+            Err(e) => {
+                report.problems.push(format!("file list {}", e.kind));
+            }
+        }
+    }
+}
+
+/// Entries of a word block.
+fn words_per_block(db: &WordFileBlocks) -> usize {
+    usize::max(1, Block::len_array::<RawWord>(db.block_size()))
+}
+
+/// The types of all blocks.
+fn block_types(db: &WordFileBlocks) -> BTreeMap<u32, WordBlockType> {
+    db.iter_metadata_filter(|_nr, _ty| true)
+        .map(|(nr, ty)| (nr.0, ty))
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn check_chain(
+    db: &mut WordFileBlocks,
+    types: &mut BTreeMap<u32, WordBlockType>,
+    map_len: u32,
+    last_file_id: FileId,
+    word: &str,
+    mut nr: LogicalNr,
+    mut idx: BlkIdx,
+    report: &mut CheckReport,
+) {
+    let mut steps = 0usize;
+    loop {
+        if nr != 0 && !types.contains_key(&nr.0) {
+            // allocated since the scan started.
+            *types = block_types(db);
+        }
+        match types.get(&nr.0) {
+            Some(WordBlockType::WordMapHead) | Some(WordBlockType::WordMapTail) => {}
+            ty => {
+                report
+                    .problems
+                    .push(format!("{}: chain points to {} {:?}", word, nr, ty));
+                return;
+            }
+        }
+        if idx >= map_len {
+            report
+                .problems
+                .push(format!("{}: chain points past the end of {}", word, nr));
+            return;
+        }
+
+        let (map, dirty) = match db.get(nr) {
+            Ok(block) => match block_as::<RawWordMap>(block, nr) {
+                Ok(maps) => (maps[idx.as_usize()], block.is_dirty()),
+                Err(e) => {
+                    report.problems.push(format!("{}: {}", word, e.kind));
+                    return;
+                }
+            },
+            Err(e) => {
+                report.problems.push(format!("{}: {}", word, e.kind));
+                return;
+            }
+        };
+        // the check may run while indexing, changes must stay.
+        // it was just read, the segment exists.
+        if !dirty {
+            let _ = db.discard(nr);
+        }
+
+        report.chain_entries += 1;
+        if let Some(file_id) = map.file_id.iter().find(|v| **v > last_file_id) {
+            report
+                .problems
+                .push(format!("{}: unknown file {}", word, file_id));
+        }
+
+        if map.next_block_nr == 0 {
+            return;
+        }
+        nr = map.next_block_nr;
+        idx = map.next_idx;
+
+        steps += 1;
+        if steps > CHAIN_LIMIT {
+            report.problems.push(format!("{}: chain has a cycle", word));
+            return;
+        }
+    }
+}
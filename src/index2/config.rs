@@ -0,0 +1,97 @@
+use crate::index2::{IndexError, IndexKind, WordBlockType, WordFileBlocks};
+use blockfile2::{BlockRead, BlockWrite};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+/// Named configuration blobs stored with the index.
+///
+/// The stream is append only, a changed blob is appended again
+/// and the last one wins when loading.
+#[derive(Debug, Default)]
+pub struct ConfigBlobs {
+    list: BTreeMap<String, Vec<u8>>,
+    /// Changed since the last store.
+    pending: Vec<String>,
+}
+
+impl ConfigBlobs {
+    pub(crate) const TY: WordBlockType = WordBlockType::Config;
+
+    pub(crate) fn load(db: &mut WordFileBlocks) -> Result<ConfigBlobs, IndexError> {
+        let mut list = BTreeMap::new();
+
+        let mut r = db.read_stream(Self::TY)?;
+        loop {
+            let mut buf_name_len = [0u8; 2];
+            if !r.read_maybe(&mut buf_name_len)? {
+                break;
+            }
+            let name_len = u16::from_ne_bytes(buf_name_len);
+            let mut buf_name = vec![0u8; name_len as usize];
+            r.read_exact(buf_name.as_mut())?;
+            let name = String::from_utf8(buf_name)?;
+
+            let mut buf_data_len = [0u8; 4];
+            r.read_exact(&mut buf_data_len)?;
+            let data_len = u32::from_ne_bytes(buf_data_len);
+            let mut data = vec![0u8; data_len as usize];
+            r.read_exact(data.as_mut())?;
+
+            list.insert(name, data);
+        }
+
+        Ok(Self {
+            list,
+            pending: Vec::new(),
+        })
+    }
+
+    pub(crate) fn store(&mut self, db: &mut WordFileBlocks) -> Result<(), IndexError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut w = db.append_stream(Self::TY)?;
+
+        let mut buf: Vec<u8> = Vec::new();
+        for name in self.pending.drain(..) {
+            let Some(data) = self.list.get(&name) else {
+                continue;
+            };
+
+            // checked by set().
+            debug_assert!(name.len() <= u16::MAX as usize);
+
+            buf.clear();
+            buf.extend((name.len() as u16).to_ne_bytes());
+            buf.extend(name.as_bytes());
+            buf.extend((data.len() as u32).to_ne_bytes());
+            buf.extend(data);
+
+            w.write_all(buf.as_slice())?;
+        }
+
+        Ok(())
+    }
+
+    /// Stored with the next write. The name can have up to 64KB,
+    /// the data up to 4GB.
+    pub fn set(&mut self, name: &str, data: Vec<u8>) -> Result<(), IndexError> {
+        if name.len() > u16::MAX as usize || data.len() > u32::MAX as usize {
+            return Err(IndexError::err(IndexKind::InvalidConfig(name.to_string())));
+        }
+        self.list.insert(name.to_string(), data);
+        if !self.pending.iter().any(|v| v == name) {
+            self.pending.push(name.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.list.get(name).map(|v| v.as_slice())
+    }
+
+    pub fn list(&self) -> &BTreeMap<String, Vec<u8>> {
+        &self.list
+    }
+}
@@ -0,0 +1,142 @@
+use crate::index2::plan::QueryPlan;
+use crate::index2::segments::BlockCounters;
+use crate::index2::words::WordData;
+use crate::index2::{is_namespaced, FileId, IndexError, IndexKind, Words};
+use std::collections::{BTreeSet, HashSet};
+use std::time::{Duration, Instant};
+
+/// Diagnostics for one matched word.
+#[derive(Debug, Default)]
+pub struct WordDiagnostics {
+    pub term: String,
+    pub word: String,
+    /// Distinct blocks of the word-map chain.
+    pub blocks: usize,
+    /// Entries of the chain visited.
+    pub entries: usize,
+    /// File ids scanned.
+    pub file_ids: usize,
+    /// The chain ends at an entry past the end of its block.
+    pub broken: bool,
+}
+
+/// Diagnostics for a find.
+#[derive(Debug, Default)]
+pub struct FindDiagnostics {
+    pub words: Vec<WordDiagnostics>,
+    /// Block cache for the whole query.
+    pub cache: BlockCounters,
+    pub duration: Duration,
+}
+
+impl FindDiagnostics {
+    /// Sums for one search-term: words, blocks, file ids.
+    pub fn term_sum(&self, term: &str) -> (usize, usize, usize) {
+        self.words
+            .iter()
+            .filter(|v| v.term == term)
+            .fold((0, 0, 0), |(w, b, f), v| {
+                (w + 1, b + v.blocks, f + v.file_ids)
+            })
+    }
+}
+
+/// Words sharing a prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixStats {
+    pub prefix: String,
+    /// Distinct words.
+    pub words: usize,
+    /// Sum of the word counts. The counts only cover what was
+    /// indexed since the index was opened.
+    pub count: usize,
+}
+
+impl Words {
+    /// Groups the word-list by the first len chars, words with fewer chars
+    /// are a group of their own. Namespaced words are skipped.
+    ///
+    /// Sorted by the number of distinct words, most first.
+    pub fn prefix_stats(&mut self, len: usize) -> Result<Vec<PrefixStats>, IndexError> {
+        let mut stats: Vec<PrefixStats> = Vec::new();
+
+        // the list is sorted, so each prefix is one contiguous range.
+        for (word, data) in self.prefix_words("")? {
+            if is_namespaced(&word) {
+                continue;
+            }
+            let prefix = match word.char_indices().nth(len) {
+                Some((idx, _)) => &word[..idx],
+                None => word.as_str(),
+            };
+            match stats.last_mut() {
+                Some(last) if last.prefix == prefix => {
+                    last.words += 1;
+                    last.count += data.count;
+                }
+                _ => stats.push(PrefixStats {
+                    prefix: prefix.to_string(),
+                    words: 1,
+                    count: data.count,
+                }),
+            }
+        }
+
+        stats.sort_by(|a, b| b.words.cmp(&a.words).then_with(|| a.prefix.cmp(&b.prefix)));
+        Ok(stats)
+    }
+
+    /// Finds the files like find_ids and collects the numbers per
+    /// word of the same walk. Doesn't use the query cache.
+    pub fn find_diagnostics(
+        &mut self,
+        terms: &[String],
+        diag: &mut FindDiagnostics,
+    ) -> Result<BTreeSet<FileId>, IndexError> {
+        let start = Instant::now();
+
+        self.db.start_counting();
+        let result = self.execute(&QueryPlan::new(terms), Some(&mut *diag));
+        diag.cache = self.db.stop_counting();
+        diag.duration = start.elapsed();
+
+        result
+    }
+
+    /// Walks the chain of one word and adds its files.
+    pub(crate) fn word_diagnostics(
+        &mut self,
+        term: &str,
+        word: String,
+        data: WordData,
+        files: &mut Vec<FileId>,
+    ) -> Result<WordDiagnostics, IndexError> {
+        let mut wd = WordDiagnostics {
+            term: term.to_string(),
+            word,
+            ..Default::default()
+        };
+
+        let mut blocks = HashSet::new();
+        let mut entries = HashSet::new();
+        let mut it = self.iter_word_files(data);
+        while let Some(file_id) = it.next() {
+            match file_id {
+                Ok(file_id) => files.push(file_id),
+                Err(e) if matches!(e.kind, IndexKind::BrokenChain(_, _)) => {
+                    wd.broken = true;
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+            let (nr, idx) = it.entry();
+            blocks.insert(nr.0);
+            entries.insert((nr.0, idx.0));
+            wd.file_ids += 1;
+        }
+        wd.blocks = blocks.len();
+        wd.entries = entries.len();
+
+        Ok(wd)
+    }
+}
@@ -0,0 +1,174 @@
+use crate::index2::files::FileData;
+use crate::index2::{BlkIdx, FileId, IndexError, WordBlockType, WordFileBlocks};
+use blockfile2::{Block, LogicalNr};
+use std::collections::BTreeMap;
+
+/// Lookup of single files without loading the file-list.
+///
+/// One entry per file, in the order of the file-ids. The leaf is
+/// copied to a block of its own, so a file can be read with two
+/// blocks. The entries are written with the file-list, an index
+/// from before gets them with its next write.
+#[derive(Debug, Default)]
+pub struct FileRefs {
+    /// Files up to this one have an entry.
+    last_file_id: FileId,
+    /// All blocks of entries, in the order of the file-ids.
+    blocks: Vec<LogicalNr>,
+    last_block_nr: LogicalNr,
+    /// Next free entry.
+    last_block_idx: BlkIdx,
+    leaf_block_nr: LogicalNr,
+    /// Next free byte.
+    leaf_block_idx: usize,
+}
+
+/// Entry of the lookup.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[repr(C)]
+pub struct RawFileRef {
+    pub file_id: FileId,
+    pub dir: u32,
+    pub leaf_nr: LogicalNr,
+    pub leaf_idx: u16,
+    /// LEAF_STREAM if the leaf is only in the file-list.
+    pub leaf_len: u16,
+}
+
+/// The leaf is longer than a block, use the file-list.
+pub const LEAF_STREAM: u16 = u16::MAX;
+
+impl FileRefs {
+    pub(crate) const TY: WordBlockType = WordBlockType::FileRefs;
+    pub(crate) const TY_LEAVES: WordBlockType = WordBlockType::FileLeaves;
+
+    /// Finds the end of the entries, only the last block is read.
+    ///
+    /// The metadata doesn't promise any order of the blocks. Blocks
+    /// are only ever allocated after all existing ones, so the order
+    /// of the block-nrs is the order of the entries.
+    pub(crate) fn load(db: &mut WordFileBlocks) -> Result<FileRefs, IndexError> {
+        let mut refs = Self::default();
+
+        refs.blocks = db
+            .iter_metadata_filter(|_nr, ty| ty == Self::TY)
+            .map(|(nr, _)| nr)
+            .collect();
+        refs.blocks.sort_by_key(|v| v.0);
+
+        if let Some(&block_nr) = refs.blocks.last() {
+            let block = db.get_as::<RawFileRef>(block_nr)?;
+            // a block is only allocated for an entry.
+            let used = block.iter().take_while(|v| v.file_id != 0).count();
+            if let Some(last) = block[..used].last() {
+                refs.last_file_id = last.file_id;
+                if last.leaf_len != LEAF_STREAM {
+                    refs.leaf_block_nr = last.leaf_nr;
+                    refs.leaf_block_idx = last.leaf_idx as usize + last.leaf_len as usize;
+                }
+            }
+            refs.last_block_nr = block_nr;
+            refs.last_block_idx = BlkIdx(used as u32);
+        }
+
+        Ok(refs)
+    }
+
+    /// Entries for the files after the last one.
+    pub(crate) fn store(
+        &mut self,
+        db: &mut WordFileBlocks,
+        list: &BTreeMap<FileId, FileData>,
+    ) -> Result<(), IndexError> {
+        let block_size = db.block_size();
+        let len = Block::len_array::<RawFileRef>(block_size) as u32;
+
+        for (file_id, file_data) in list.range(self.last_file_id + 1..) {
+            let leaf = file_data.leaf.as_bytes();
+            let (leaf_nr, leaf_idx, leaf_len) = if leaf.len() <= block_size {
+                if self.leaf_block_nr == 0 || self.leaf_block_idx + leaf.len() > block_size {
+                    self.leaf_block_nr = db.alloc(Self::TY_LEAVES)?.0;
+                    self.leaf_block_idx = 0;
+                }
+                let bytes = db.get_as_mut::<u8>(self.leaf_block_nr)?;
+                bytes[self.leaf_block_idx..self.leaf_block_idx + leaf.len()].copy_from_slice(leaf);
+                let pos = (
+                    self.leaf_block_nr,
+                    self.leaf_block_idx as u16,
+                    leaf.len() as u16,
+                );
+                self.leaf_block_idx += leaf.len();
+                pos
+            } else {
+                (LogicalNr(0), 0, LEAF_STREAM)
+            };
+
+            if self.last_block_nr == 0 || self.last_block_idx == len {
+                self.last_block_nr = db.alloc(Self::TY)?.0;
+                self.last_block_idx = BlkIdx(0);
+                self.blocks.push(self.last_block_nr);
+            }
+            let block = db.get_as_mut::<RawFileRef>(self.last_block_nr)?;
+            block[self.last_block_idx.as_usize()] = RawFileRef {
+                file_id: *file_id,
+                dir: file_data.dir,
+                leaf_nr,
+                leaf_idx,
+                leaf_len,
+            };
+            self.last_block_idx += 1;
+            self.last_file_id = *file_id;
+        }
+
+        Ok(())
+    }
+
+    /// The entry of the file. Searches the blocks binary, the entries
+    /// are stored in the order of the file-ids. Every block that is
+    /// looked at is read.
+    pub(crate) fn find(
+        &self,
+        db: &mut WordFileBlocks,
+        file_id: FileId,
+    ) -> Result<Option<RawFileRef>, IndexError> {
+        if file_id > self.last_file_id {
+            return Ok(None);
+        }
+
+        let mut lo = 0;
+        let mut hi = self.blocks.len();
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let block = db.get_as::<RawFileRef>(self.blocks[mid])?;
+            let used = block.iter().take_while(|v| v.file_id != 0).count();
+            let block = &block[..used];
+
+            match block.binary_search_by_key(&file_id, |v| v.file_id) {
+                Ok(idx) => return Ok(Some(block[idx])),
+                Err(0) => hi = mid,
+                Err(idx) if idx == block.len() => lo = mid + 1,
+                Err(_) => return Ok(None),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The leaf of the entry.
+    pub(crate) fn leaf(
+        &self,
+        db: &mut WordFileBlocks,
+        file_ref: &RawFileRef,
+    ) -> Result<String, IndexError> {
+        debug_assert!(file_ref.leaf_len != LEAF_STREAM);
+        let start = file_ref.leaf_idx as usize;
+        let end = start + file_ref.leaf_len as usize;
+        let bytes = db.get_as::<u8>(file_ref.leaf_nr)?;
+        Ok(String::from_utf8(bytes[start..end].to_vec())?)
+    }
+
+    /// Files up to this one have an entry.
+    pub fn last_file_id(&self) -> FileId {
+        self.last_file_id
+    }
+}
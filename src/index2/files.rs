@@ -1,137 +1,865 @@
-use crate::index2::{BlkIdx, FileId, IndexError, WordBlockType, WordFileBlocks};
+use crate::index2::file_refs::{FileRefs, LEAF_STREAM};
+use crate::index2::{BlkIdx, FileId, IndexError, IndexKind, WordBlockType, WordFileBlocks};
 use blockfile2::{BlockRead, BlockWrite, LogicalNr};
-use std::collections::BTreeMap;
+use std::borrow::Cow;
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Debug;
 use std::io::{Read, Write};
 
+/// Files of the index.
+///
+/// The names are kept as directory and leaf name, the directories
+/// are stored once in their own table. The full name is joined
+/// on demand.
 #[derive(Debug)]
 pub struct FileList {
     last_file_id: FileId,
     last_block_nr: LogicalNr,
     list: BTreeMap<FileId, FileData>,
+    /// The index is the directory id, 0 is the root.
+    dirs: Vec<DirData>,
+    dir_ids: BTreeMap<String, u32>,
+    /// Lower-case full name to the first file with it. Only with
+    /// fold_case.
+    folded: HashMap<String, FileId>,
+    /// Files whose name differs from an earlier one only in case.
+    /// Only with fold_case.
+    duplicates: BTreeSet<FileId>,
+    /// Duplicates merged into the first file with their name, and
+    /// that first file.
+    merged: BTreeMap<FileId, FileId>,
+    /// Replaced by a later file with the same name.
+    replaced: BTreeSet<FileId>,
+    /// Flags of stored files changed since the last store, they get
+    /// another record.
+    appended: Vec<(FileId, u32)>,
+    /// Names are compared ignoring case, they keep their case for
+    /// display.
+    fold_case: bool,
+    refs: FileRefs,
+    /// Only the directories are loaded, the files are looked up
+    /// with resolve().
+    lookup: bool,
 }
 
 #[derive(Debug)]
 pub struct FileData {
-    pub name: String,
+    pub dir: u32,
+    pub leaf: String,
     pub block_nr: LogicalNr,
     pub block_idx: BlkIdx,
+    /// FILE_PARTIAL, FILE_WORDLIST, FILE_SKIPPED, FILE_MERGED,
+    /// FILE_REPLACED. Stored
+    /// in their own stream.
+    pub flags: u32,
+}
+
+/// Default for comparing names ignoring case, the file systems of
+/// windows and macos are case-insensitive.
+pub const FOLD_CASE: bool = cfg!(any(windows, target_os = "macos"));
+
+/// The file ran out of time and is only partially indexed.
+pub const FILE_PARTIAL: u32 = 1;
+
+/// Too many distinct words for its size, it looks like a word list.
+pub const FILE_WORDLIST: u32 = 2;
+
+/// A word list whose words were dropped, only the name is indexed.
+pub const FILE_SKIPPED: u32 = 4;
+
+/// Merged into the first file whose name differs only in case.
+/// Its words are found under that file.
+pub const FILE_MERGED: u32 = 8;
+
+/// Replaced by a later file with the same name. Its words don't find
+/// it anymore.
+pub const FILE_REPLACED: u32 = 32;
+
+/// Indexed again in full. Only in a later record, it clears
+/// FILE_PARTIAL of the earlier ones.
+pub const FILE_COMPLETE: u32 = 16;
+
+#[derive(Debug)]
+pub struct DirData {
+    /// Path with the trailing separator, empty for the root.
+    /// Joined with the leaf this gives the file name.
+    pub prefix: String,
+    /// Files in this directory, in the order they were added.
+    pub files: Vec<FileId>,
+    /// Leaf name to the first file with it.
+    leaves: HashMap<String, FileId>,
+    pub block_nr: LogicalNr,
+    pub block_idx: BlkIdx,
+}
+
+/// Splits a file name into the directory with the trailing
+/// separator and the leaf.
+pub fn split_name(name: &str) -> (&str, &str) {
+    match name.rfind(['/', '\\']) {
+        Some(idx) => name.split_at(idx + 1),
+        None => ("", name),
+    }
 }
 
 impl FileList {
-    pub(crate) const TY: WordBlockType = WordBlockType::FileList;
+    /// Full names, up to index version 2.
+    pub(crate) const TY_V1: WordBlockType = WordBlockType::FileList;
+    /// Directory and leaf.
+    pub(crate) const TY: WordBlockType = WordBlockType::FileList2;
+    pub(crate) const TY_DIRS: WordBlockType = WordBlockType::DirList;
+    /// Flags of the files that have any.
+    pub(crate) const TY_FLAGS: WordBlockType = WordBlockType::FileFlags;
 
     pub(crate) fn recover(db: &mut WordFileBlocks) -> Result<FileList, IndexError> {
         Self::load(db)
     }
 
+    fn empty() -> Self {
+        Self {
+            last_file_id: FileId(0u32),
+            last_block_nr: LogicalNr(0u32),
+            list: BTreeMap::new(),
+            dirs: Vec::new(),
+            dir_ids: BTreeMap::new(),
+            folded: HashMap::new(),
+            duplicates: BTreeSet::new(),
+            merged: BTreeMap::new(),
+            replaced: BTreeSet::new(),
+            appended: Vec::new(),
+            fold_case: FOLD_CASE,
+            refs: FileRefs::default(),
+            lookup: false,
+        }
+    }
+
     pub(crate) fn load(db: &mut WordFileBlocks) -> Result<FileList, IndexError> {
-        let mut list = BTreeMap::new();
-        let mut last_file_id = FileId(0u32);
+        let mut files = Self::empty();
+
+        files.load_dirs(db)?;
+        if files.dirs.is_empty() {
+            files.intern_dir("");
+        }
+        files.refs = FileRefs::load(db)?;
+        files.load_v1(db)?;
+        files.load_files(db)?;
+        files.load_flags(db)?;
+
+        for dir in files.dirs.iter_mut() {
+            dir.files.sort();
+        }
+
+        Ok(files)
+    }
+
+    /// Only the directories, the files are looked up when needed.
+    /// For queries, such a file-list is never stored.
+    ///
+    /// With merged or replaced files everything is loaded, they are
+    /// found by their name.
+    pub(crate) fn load_lookup(db: &mut WordFileBlocks) -> Result<FileList, IndexError> {
+        if Self::read_flags(db)?
+            .iter()
+            .any(|(_, flags)| flags & (FILE_MERGED | FILE_REPLACED) != 0)
+        {
+            return Self::load(db);
+        }
+
+        let mut files = Self::empty();
+
+        files.load_dirs(db)?;
+        if files.dirs.is_empty() {
+            files.intern_dir("");
+        }
+        files.refs = FileRefs::load(db)?;
+        files.lookup = true;
+
+        Ok(files)
+    }
+
+    /// Looks up the files after load_lookup(). If one of them has no
+    /// entry the whole file-list is loaded.
+    pub(crate) fn resolve(
+        &mut self,
+        db: &mut WordFileBlocks,
+        file_ids: &BTreeSet<FileId>,
+    ) -> Result<(), IndexError> {
+        if !self.lookup {
+            return Ok(());
+        }
+
+        for file_id in file_ids {
+            if self.list.contains_key(file_id) {
+                continue;
+            }
+            let file_ref = match self.refs.find(db, *file_id)? {
+                Some(v) if v.leaf_len != LEAF_STREAM && (v.dir as usize) < self.dirs.len() => v,
+                _ => {
+                    let fold_case = self.fold_case;
+                    *self = Self::load(db)?;
+                    self.set_fold_case(fold_case);
+                    return Ok(());
+                }
+            };
+            let leaf = self.refs.leaf(db, &file_ref)?;
+            self.insert(*file_id, file_ref.dir, leaf, LogicalNr(0), BlkIdx(0));
+        }
+
+        Ok(())
+    }
+
+    /// Only the directories and the files looked up so far are loaded.
+    pub fn is_lookup(&self) -> bool {
+        self.lookup
+    }
+
+    fn load_dirs(&mut self, db: &mut WordFileBlocks) -> Result<(), IndexError> {
+        let mut r = db.read_stream(Self::TY_DIRS)?;
+        loop {
+            let mut buf_dir_id = [0u8; 4];
+            if !r.read_maybe(&mut buf_dir_id[..1])? {
+                break;
+            }
+            let (block_nr, block_idx) = record_pos(r.block_nr(), r.idx());
+            r.read_exact(&mut buf_dir_id[1..])?;
+            let dir_id = u32::from_ne_bytes(buf_dir_id);
+            let prefix = read_name(&mut r)?;
+
+            // stored in order.
+            debug_assert_eq!(dir_id as usize, self.dirs.len());
+
+            self.add_dir_id(&prefix, dir_id);
+            self.dirs.push(DirData {
+                prefix,
+                files: Vec::new(),
+                leaves: HashMap::new(),
+                block_nr,
+                block_idx,
+            });
+        }
+        Ok(())
+    }
+
+    /// Full names are split when loading. The records stay where
+    /// they are, only the new directories are stored.
+    fn load_v1(&mut self, db: &mut WordFileBlocks) -> Result<(), IndexError> {
+        let mut r = db.read_stream(Self::TY_V1)?;
+        loop {
+            let mut buf_file_id = [0u8; 4];
+            if !r.read_maybe(&mut buf_file_id[..1])? {
+                break;
+            }
+            let (block_nr, block_idx) = record_pos(r.block_nr(), r.idx());
+            r.read_exact(&mut buf_file_id[1..])?;
+            let file_id = FileId(u32::from_ne_bytes(buf_file_id));
+            let name = read_name(&mut r)?;
+
+            debug_assert!(
+                file_id != 0,
+                "zero file {} at {} {}",
+                name,
+                r.block_nr(),
+                r.idx()
+            );
+
+            let (prefix, leaf) = split_name(&name);
+            let dir = self.intern_dir(prefix);
+            self.insert(file_id, dir, leaf.to_string(), block_nr, block_idx);
+        }
+        Ok(())
+    }
+
+    fn load_files(&mut self, db: &mut WordFileBlocks) -> Result<(), IndexError> {
         #[allow(unused_assignments)]
         let mut last_block_nr = LogicalNr(0u32);
 
         let mut r = db.read_stream(Self::TY)?;
         loop {
-            let block_nr = r.block_nr();
-            let block_idx = BlkIdx(r.idx() as u32);
-
             let mut buf_file_id = [0u8; 4];
-            if !r.read_maybe(&mut buf_file_id)? {
-                last_block_nr = block_nr;
+            if !r.read_maybe(&mut buf_file_id[..1])? {
+                last_block_nr = r.block_nr();
                 break;
             }
+            let (block_nr, block_idx) = record_pos(r.block_nr(), r.idx());
+            r.read_exact(&mut buf_file_id[1..])?;
             let file_id = FileId(u32::from_ne_bytes(buf_file_id));
-            last_file_id = file_id;
 
-            let mut buf_name_len = [0u8; 2];
-            r.read_exact(&mut buf_name_len)?;
-            let name_len = u16::from_ne_bytes(buf_name_len);
+            let mut buf_dir_id = [0u8; 4];
+            r.read_exact(&mut buf_dir_id)?;
+            let dir = u32::from_ne_bytes(buf_dir_id);
 
-            let mut buf_name = Vec::with_capacity(name_len as usize);
-            buf_name.resize(name_len as usize, 0);
-            r.read_exact(buf_name.as_mut())?;
-            let name = String::from_utf8(buf_name)?;
+            let leaf = read_name(&mut r)?;
 
             debug_assert!(
                 file_id != 0,
                 "zero file {} at {} {}",
-                name,
+                leaf,
                 r.block_nr(),
                 r.idx()
             );
+            if dir as usize >= self.dirs.len() {
+                return Err(IndexError::err(IndexKind::UnknownDir(file_id, dir)));
+            }
 
-            list.insert(
-                file_id,
-                FileData {
-                    name,
-                    block_nr,
-                    block_idx,
-                },
-            );
+            self.insert(file_id, dir, leaf, block_nr, block_idx);
+        }
+        self.last_block_nr = last_block_nr;
+
+        Ok(())
+    }
+
+    /// A file can have more than one record, the flags add up.
+    /// FILE_COMPLETE clears FILE_PARTIAL.
+    fn load_flags(&mut self, db: &mut WordFileBlocks) -> Result<(), IndexError> {
+        let mut merged = BTreeSet::new();
+        for (file_id, flags) in Self::read_flags(db)? {
+            match self.list.get_mut(&file_id) {
+                Some(file_data) if flags & FILE_COMPLETE != 0 => {
+                    file_data.flags = (file_data.flags | flags) & !(FILE_PARTIAL | FILE_COMPLETE)
+                }
+                Some(file_data) => file_data.flags |= flags,
+                None => return Err(IndexError::err(IndexKind::UnknownFile(file_id))),
+            }
+            if flags & FILE_MERGED != 0 {
+                merged.insert(file_id);
+            }
+            if flags & FILE_REPLACED != 0 {
+                self.replaced.insert(file_id);
+            }
+        }
+        // replaced after they were merged.
+        merged.retain(|v| !self.replaced.contains(v));
+        if !merged.is_empty() {
+            self.link_merged(&merged);
+        }
+        if !self.replaced.is_empty() {
+            self.link_replaced();
+        }
+        Ok(())
+    }
+
+    /// The names find the first file with them that is not replaced.
+    fn link_replaced(&mut self) {
+        // ordered by id.
+        for (file_id, file_data) in self.list.iter() {
+            if self.replaced.contains(file_id) {
+                continue;
+            }
+            let dir_data = &mut self.dirs[file_data.dir as usize];
+            if let Some(first) = dir_data.leaves.get_mut(&file_data.leaf) {
+                if self.replaced.contains(first) {
+                    *first = *file_id;
+                }
+            }
+            if self.fold_case {
+                let name = folded_name(&dir_data.prefix, &file_data.leaf);
+                if let Some(first) = self.folded.get_mut(&name) {
+                    if self.replaced.contains(first) {
+                        *first = *file_id;
+                        self.duplicates.remove(file_id);
+                    }
+                }
+            }
         }
+    }
+
+    /// Finds the file each merged file was merged into. That is the
+    /// first one whose name differs only in case, it is never merged
+    /// or replaced.
+    fn link_merged(&mut self, merged: &BTreeSet<FileId>) {
+        let mut first: HashMap<String, Option<FileId>> = merged
+            .iter()
+            .filter_map(|v| Some((self.folded_name(self.list.get(v)?), None)))
+            .collect();
+        // ordered by id.
+        for (file_id, file_data) in self.list.iter() {
+            if merged.contains(file_id) || self.replaced.contains(file_id) {
+                continue;
+            }
+            if let Some(slot) = first.get_mut(&self.folded_name(file_data)) {
+                slot.get_or_insert(*file_id);
+            }
+        }
+        for file_id in merged {
+            let into = self
+                .list
+                .get(file_id)
+                .and_then(|v| first.get(&self.folded_name(v)).copied().flatten())
+                .unwrap_or(*file_id);
+            self.merged.insert(*file_id, into);
+        }
+    }
+
+    /// The lower-case full name.
+    fn folded_name(&self, file_data: &FileData) -> String {
+        folded_name(&self.dirs[file_data.dir as usize].prefix, &file_data.leaf)
+    }
+
+    fn read_flags(db: &mut WordFileBlocks) -> Result<Vec<(FileId, u32)>, IndexError> {
+        let mut records = Vec::new();
+        let mut r = db.read_stream(Self::TY_FLAGS)?;
+        loop {
+            let mut buf_file_id = [0u8; 4];
+            if !r.read_maybe(&mut buf_file_id)? {
+                break;
+            }
+            let file_id = FileId(u32::from_ne_bytes(buf_file_id));
+
+            let mut buf_flags = [0u8; 4];
+            r.read_exact(&mut buf_flags)?;
+            let flags = u32::from_ne_bytes(buf_flags);
+
+            records.push((file_id, flags));
+        }
+        Ok(records)
+    }
+
+    fn insert(
+        &mut self,
+        file_id: FileId,
+        dir: u32,
+        leaf: String,
+        block_nr: LogicalNr,
+        block_idx: BlkIdx,
+    ) {
+        self.last_file_id = self.last_file_id.max(file_id);
+        let dir_data = &mut self.dirs[dir as usize];
+        dir_data.files.push(file_id);
+        let first = dir_data.leaves.entry(leaf.clone()).or_insert(file_id);
+        *first = min(*first, file_id);
+
+        if self.fold_case {
+            let name = folded_name(&dir_data.prefix, &leaf);
+            add_folded(&mut self.folded, &mut self.duplicates, name, file_id);
+        }
+
+        self.list.insert(
+            file_id,
+            FileData {
+                dir,
+                leaf,
+                block_nr,
+                block_idx,
+                flags: 0,
+            },
+        );
+    }
 
-        Ok(Self {
-            last_file_id,
-            last_block_nr,
-            list,
-        })
+    fn add_dir_id(&mut self, prefix: &str, dir_id: u32) {
+        self.dir_ids.insert(prefix.to_string(), dir_id);
+    }
+
+    /// Id of the directory, new ones are stored with the next write.
+    fn intern_dir(&mut self, prefix: &str) -> u32 {
+        if let Some(dir_id) = self.dir_ids.get(prefix) {
+            return *dir_id;
+        }
+        let dir_id = self.dirs.len() as u32;
+        self.add_dir_id(prefix, dir_id);
+        self.dirs.push(DirData {
+            prefix: prefix.to_string(),
+            files: Vec::new(),
+            leaves: HashMap::new(),
+            block_nr: LogicalNr(0),
+            block_idx: BlkIdx(0),
+        });
+        dir_id
     }
 
     pub(crate) fn store(&mut self, db: &mut WordFileBlocks) -> Result<(), IndexError> {
+        debug_assert!(!self.lookup);
         // assume append only
-        let mut w = db.append_stream(Self::TY)?;
-
         let mut buf: Vec<u8> = Vec::new();
-        for (file_id, file_data) in self.list.iter_mut() {
-            if file_data.block_nr == 0 {
-                file_data.block_nr = w.block_nr();
-                file_data.block_idx = BlkIdx(w.idx() as u32);
 
-                assert!(file_data.name.len() < 65536);
+        // directories first, the files refer to them.
+        if self.dirs.iter().any(|v| v.block_nr == 0) {
+            let mut w = db.append_stream(Self::TY_DIRS)?;
+            for (dir_id, dir_data) in self.dirs.iter_mut().enumerate() {
+                if dir_data.block_nr == 0 {
+                    buf.clear();
+                    buf.extend((dir_id as u32).to_ne_bytes());
+                    write_name(&mut buf, &dir_data.prefix);
 
-                let file_name = file_data.name.as_bytes();
+                    (dir_data.block_nr, dir_data.block_idx) = write_record(&mut w, &buf)?;
+                }
+            }
+        }
+
+        // flags are set for new files, stored files get another record.
+        let mut flags: Vec<(FileId, u32)> = self.appended.drain(..).collect();
+
+        let mut w = db.append_stream(Self::TY)?;
+        for (file_id, file_data) in self.list.iter_mut() {
+            if file_data.block_nr == 0 {
+                if file_data.flags != 0 {
+                    flags.push((*file_id, file_data.flags));
+                }
 
                 buf.clear();
                 buf.extend(file_id.0.to_ne_bytes());
-                buf.extend((file_name.len() as u16).to_ne_bytes());
-                buf.extend(file_name);
+                buf.extend(file_data.dir.to_ne_bytes());
+                write_name(&mut buf, &file_data.leaf);
 
-                w.write_all(buf.as_slice())?;
+                (file_data.block_nr, file_data.block_idx) = write_record(&mut w, &buf)?;
             } else {
                 // no updates
             }
         }
+        drop(w);
+
+        if !flags.is_empty() {
+            let mut w = db.append_stream(Self::TY_FLAGS)?;
+            for (file_id, flags) in flags {
+                buf.clear();
+                buf.extend(file_id.0.to_ne_bytes());
+                buf.extend(flags.to_ne_bytes());
+                w.write_all(buf.as_slice())?;
+            }
+        }
+
+        self.refs.store(db, &self.list)?;
 
         Ok(())
     }
 
     pub fn add(&mut self, name: String) -> FileId {
-        self.last_file_id += 1;
-        self.list.insert(
-            self.last_file_id,
-            FileData {
-                name,
-                block_nr: LogicalNr(0),
-                block_idx: BlkIdx(0),
-            },
-        );
-        self.last_file_id
+        let (prefix, leaf) = split_name(&name);
+        let dir = self.intern_dir(prefix);
+        let file_id = self.last_file_id + 1;
+        self.insert(file_id, dir, leaf.to_string(), LogicalNr(0), BlkIdx(0));
+        file_id
+    }
+
+    /// Sets flags of a file that is not stored yet.
+    pub fn set_flags(&mut self, file_id: FileId, flags: u32) {
+        if let Some(file_data) = self.list.get_mut(&file_id) {
+            debug_assert!(file_data.block_nr == 0);
+            file_data.flags |= flags;
+        }
+    }
+
+    /// The file is indexed in full now. A stored file gets another
+    /// record with FILE_COMPLETE.
+    pub fn clear_partial(&mut self, file_id: FileId) {
+        if let Some(file_data) = self.list.get_mut(&file_id) {
+            if file_data.flags & FILE_PARTIAL != 0 {
+                file_data.flags &= !FILE_PARTIAL;
+                if file_data.block_nr != 0 {
+                    self.appended.push((file_id, FILE_COMPLETE));
+                }
+            }
+        }
+    }
+
+    /// Files with all of the flags, ordered by id. Replaced files are
+    /// left out.
+    pub fn with_flags(&self, flags: u32) -> Vec<FileId> {
+        self.list
+            .iter()
+            .filter(|(k, v)| v.flags & flags == flags && !self.replaced.contains(k))
+            .map(|(k, _)| *k)
+            .collect()
     }
 
     pub fn list(&self) -> &BTreeMap<FileId, FileData> {
         &self.list
     }
 
+    pub fn dirs(&self) -> &[DirData] {
+        &self.dirs
+    }
+
+    /// Full name of the file.
+    pub fn name<'a>(&'a self, file: &'a FileData) -> Cow<'a, str> {
+        let prefix = &self.dirs[file.dir as usize].prefix;
+        if prefix.is_empty() {
+            Cow::Borrowed(file.leaf.as_str())
+        } else {
+            let mut name = String::with_capacity(prefix.len() + file.leaf.len());
+            name.push_str(prefix);
+            name.push_str(&file.leaf);
+            Cow::Owned(name)
+        }
+    }
+
+    pub fn file_name(&self, file_id: FileId) -> Option<Cow<'_, str>> {
+        self.list.get(&file_id).map(|v| self.name(v))
+    }
+
+    /// All files with their full name, ordered by id. Merged and
+    /// replaced files are left out.
+    pub fn iter(&self) -> impl Iterator<Item = (FileId, Cow<'_, str>)> {
+        self.list
+            .iter()
+            .filter(|(k, _)| !self.merged.contains_key(k) && !self.replaced.contains(k))
+            .map(move |(k, v)| (*k, self.name(v)))
+    }
+
+    pub fn contains(&self, file_id: FileId) -> bool {
+        self.list.contains_key(&file_id)
+    }
+
+    /// Names are compared ignoring case.
+    pub fn fold_case(&self) -> bool {
+        self.fold_case
+    }
+
+    /// The lower-case names are only kept with fold_case.
+    pub fn set_fold_case(&mut self, fold_case: bool) {
+        if self.fold_case == fold_case {
+            return;
+        }
+        self.fold_case = fold_case;
+
+        if fold_case {
+            (self.folded, self.duplicates) = self.fold_names();
+        } else {
+            self.folded = HashMap::new();
+            self.duplicates = BTreeSet::new();
+        }
+    }
+
+    /// The lower-case names of all files and the duplicates among them.
+    fn fold_names(&self) -> (HashMap<String, FileId>, BTreeSet<FileId>) {
+        let mut folded = HashMap::new();
+        let mut duplicates = BTreeSet::new();
+        for (file_id, file_data) in self.list.iter() {
+            if self.replaced.contains(file_id) {
+                continue;
+            }
+            add_folded(
+                &mut folded,
+                &mut duplicates,
+                self.folded_name(file_data),
+                *file_id,
+            );
+        }
+        (folded, duplicates)
+    }
+
+    /// The name as it is compared.
+    pub fn fold_name<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        if self.fold_case {
+            Cow::Owned(name.to_lowercase())
+        } else {
+            Cow::Borrowed(name)
+        }
+    }
+
+    /// First file with this name. With fold_case the first one that
+    /// differs only in case.
+    pub fn file_id(&self, name: &str) -> Option<FileId> {
+        let (prefix, leaf) = split_name(name);
+        if self.fold_case {
+            return self.folded.get(&folded_name(prefix, leaf)).copied();
+        }
+
+        let dir_id = self.dir_ids.get(prefix)?;
+        self.dirs[*dir_id as usize].leaves.get(leaf).copied()
+    }
+
+    /// The file a merged file was merged into, otherwise the file itself.
+    /// None if that file is replaced.
+    pub fn canonical(&self, file_id: FileId) -> Option<FileId> {
+        let file_id = self.merged.get(&file_id).copied().unwrap_or(file_id);
+        (!self.replaced.contains(&file_id)).then_some(file_id)
+    }
+
+    /// The new file with the same name takes the place of the file.
+    /// The words of the file and of the files merged into it don't
+    /// find it anymore. Stored with the next write.
+    pub fn replace_file(&mut self, file_id: FileId, new_id: FileId) {
+        if !self.list.contains_key(&file_id) {
+            return;
+        }
+        let merged: Vec<FileId> = self
+            .merged
+            .iter()
+            .filter(|(_, v)| **v == file_id)
+            .map(|(k, _)| *k)
+            .collect();
+        for merged_id in merged {
+            self.merged.remove(&merged_id);
+            self.mark_replaced(merged_id);
+        }
+        self.mark_replaced(file_id);
+
+        let file_data = &self.list[&file_id];
+        let dir_data = &mut self.dirs[file_data.dir as usize];
+        if let Some(first) = dir_data.leaves.get_mut(&file_data.leaf) {
+            if *first == file_id {
+                *first = new_id;
+            }
+        }
+        if self.fold_case {
+            let name = folded_name(&dir_data.prefix, &file_data.leaf);
+            if let Some(first) = self.folded.get_mut(&name) {
+                if *first == file_id {
+                    *first = new_id;
+                    self.duplicates.remove(&new_id);
+                }
+            }
+        }
+    }
+
+    fn mark_replaced(&mut self, file_id: FileId) {
+        if let Some(file_data) = self.list.get_mut(&file_id) {
+            file_data.flags |= FILE_REPLACED;
+            // a new file gets its flags when it is stored.
+            if file_data.block_nr != 0 {
+                self.appended.push((file_id, FILE_REPLACED));
+            }
+            self.replaced.insert(file_id);
+        }
+    }
+
+    /// Files whose names differ only in case and that are not merged
+    /// yet. Each group is ordered by id, the first one is the original.
+    ///
+    /// Without fold_case the lower-case names are only collected for
+    /// this call.
+    pub fn case_duplicates(&self) -> Vec<Vec<FileId>> {
+        let names;
+        let (folded, duplicates) = if self.fold_case {
+            (&self.folded, &self.duplicates)
+        } else {
+            names = self.fold_names();
+            (&names.0, &names.1)
+        };
+
+        let mut groups: BTreeMap<FileId, Vec<FileId>> = BTreeMap::new();
+        for file_id in duplicates.iter() {
+            if self.merged.contains_key(file_id) || self.replaced.contains(file_id) {
+                continue;
+            }
+            let Some(file_data) = self.list.get(file_id) else {
+                continue;
+            };
+            let first = folded[&self.folded_name(file_data)];
+            groups
+                .entry(first)
+                .or_insert_with(|| vec![first])
+                .push(*file_id);
+        }
+        groups.into_values().collect()
+    }
+
+    /// Merges the files whose names differ only in case into the first
+    /// of them. From now on their words are found under that file.
+    /// Stored with the next write.
+    pub fn merge_case_duplicates(&mut self) -> Vec<Vec<FileId>> {
+        let duplicates = self.case_duplicates();
+        for group in duplicates.iter() {
+            for file_id in &group[1..] {
+                if let Some(file_data) = self.list.get_mut(file_id) {
+                    file_data.flags |= FILE_MERGED;
+                    // a new file gets its flags when it is stored.
+                    if file_data.block_nr != 0 {
+                        self.appended.push((*file_id, FILE_MERGED));
+                    }
+                    self.merged.insert(*file_id, group[0]);
+                }
+            }
+        }
+        duplicates
+    }
+
+    /// Files whose name starts with the prefix, ordered by id.
+    pub fn with_prefix(&self, prefix: &str) -> Vec<FileId> {
+        let mut found = Vec::new();
+
+        // inside the directory of the prefix, this one must match the leaf.
+        let (dir, rest) = split_name(prefix);
+        if !rest.is_empty() {
+            if let Some(dir_id) = self.dir_ids.get(dir) {
+                found.extend(
+                    self.dirs[*dir_id as usize]
+                        .files
+                        .iter()
+                        .filter(|v| self.list[*v].leaf.starts_with(rest)),
+                );
+            }
+        }
+        // everything in directories below.
+        for (_, dir_id) in self
+            .dir_ids
+            .range(prefix.to_string()..)
+            .take_while(|(v, _)| v.starts_with(prefix))
+        {
+            found.extend(&self.dirs[*dir_id as usize].files);
+        }
+
+        found.retain(|v| !self.merged.contains_key(v) && !self.replaced.contains(v));
+        found.sort();
+        found
+    }
+
+    /// Number of files, without the merged and replaced ones.
     pub fn len(&self) -> usize {
-        self.list.len()
+        self.list.len() - self.merged.len() - self.replaced.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.list.is_empty()
+        self.len() == 0
     }
 
     pub fn last_file_id(&self) -> FileId {
         self.last_file_id
     }
 }
+
+fn folded_name(prefix: &str, leaf: &str) -> String {
+    let mut name = prefix.to_lowercase();
+    name.push_str(&leaf.to_lowercase());
+    name
+}
+
+/// Adds the lower-case name of a file. A later file with the
+/// same name is a duplicate.
+fn add_folded(
+    folded: &mut HashMap<String, FileId>,
+    duplicates: &mut BTreeSet<FileId>,
+    name: String,
+    file_id: FileId,
+) {
+    let first = folded.entry(name).or_insert(file_id);
+    if *first != file_id {
+        duplicates.insert(max(*first, file_id));
+        *first = min(*first, file_id);
+    }
+}
+
+/// Position of a record, the block and index of its first byte.
+///
+/// Reader and writer take it after the first byte. Taken before the
+/// record, a record that starts right at the end of a block could get
+/// the end of this block from one side and the start of the next block
+/// from the other.
+fn record_pos(block_nr: LogicalNr, idx: usize) -> (LogicalNr, BlkIdx) {
+    debug_assert!(idx > 0);
+    (block_nr, BlkIdx(idx as u32 - 1))
+}
+
+/// Writes one record and returns its position.
+fn write_record(w: &mut impl BlockWrite, buf: &[u8]) -> Result<(LogicalNr, BlkIdx), IndexError> {
+    w.write_all(&buf[..1])?;
+    let pos = record_pos(w.block_nr(), w.idx());
+    w.write_all(&buf[1..])?;
+    Ok(pos)
+}
+
+fn read_name(r: &mut impl Read) -> Result<String, IndexError> {
+    let mut buf_name_len = [0u8; 2];
+    r.read_exact(&mut buf_name_len)?;
+    let name_len = u16::from_ne_bytes(buf_name_len);
+
+    let mut buf_name = vec![0u8; name_len as usize];
+    r.read_exact(buf_name.as_mut())?;
+    Ok(String::from_utf8(buf_name)?)
+}
+
+fn write_name(buf: &mut Vec<u8>, name: &str) {
+    assert!(name.len() < 65536);
+    buf.extend((name.len() as u16).to_ne_bytes());
+    buf.extend(name.as_bytes());
+}
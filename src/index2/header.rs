@@ -0,0 +1,121 @@
+use crate::index2::files::FOLD_CASE;
+use crate::index2::{IndexError, WordBlockType, WordFileBlocks};
+use blockfile2::LogicalNr;
+
+/// Version of the index format.
+///
+/// 2: the word-list records the file that introduced a word.
+/// 3: file names are stored as directory and leaf.
+pub const INDEX_VERSION: u32 = 3;
+
+/// Stop-words are not skipped, everything is indexed.
+pub const FLAG_NO_STOP_WORDS: u32 = 0x0001;
+/// Set by each write, cleared when the index is opened.
+/// If it is missing on open the last session didn't end cleanly.
+pub const FLAG_CLEAN: u32 = 0x0002;
+/// A background verify is running, it continues at verify_pos.
+pub const FLAG_VERIFY: u32 = 0x0004;
+/// Words were lost and replaced by placeholders.
+pub const FLAG_DEGRADED: u32 = 0x0008;
+/// File names are compared ignoring case.
+pub const FLAG_FOLD_CASE: u32 = 0x0010;
+/// File names are compared exactly. Without either flag the
+/// default of the platform applies.
+pub const FLAG_EXACT_CASE: u32 = 0x0020;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[repr(C)]
+pub struct RawHeader {
+    pub version: u32,
+    pub flags: u32,
+    /// Segment size in MB, 0 for a single file.
+    pub segment_mb: u32,
+    /// Words passed by the background verify.
+    pub verify_pos: u32,
+    /// Shorter words are not indexed. 0 for an index from before,
+    /// the same as 1.
+    pub min_word_len: u32,
+}
+
+/// Index-wide settings. These are fixed when the index is created.
+#[derive(Debug)]
+pub struct Header {
+    pub block_nr: LogicalNr,
+    pub version: u32,
+    pub flags: u32,
+    pub segment_mb: u32,
+    pub verify_pos: u32,
+    pub min_word_len: u32,
+}
+
+impl Header {
+    pub const TY: WordBlockType = WordBlockType::Header;
+
+    pub fn load(db: &mut WordFileBlocks) -> Result<Header, IndexError> {
+        for (block_nr, _block_type) in db.iter_metadata_filter(|_nr, ty| ty == Self::TY) {
+            let header = &db.get_as::<RawHeader>(block_nr)?[0];
+
+            return Ok(Self {
+                block_nr,
+                version: header.version,
+                flags: header.flags,
+                segment_mb: header.segment_mb,
+                verify_pos: header.verify_pos,
+                min_word_len: header.min_word_len,
+            });
+        }
+
+        Ok(Self {
+            block_nr: LogicalNr(0),
+            version: INDEX_VERSION,
+            flags: 0,
+            segment_mb: 0,
+            verify_pos: 0,
+            min_word_len: 0,
+        })
+    }
+
+    pub fn store(&mut self, db: &mut WordFileBlocks) -> Result<(), IndexError> {
+        if self.block_nr == 0 {
+            self.block_nr = db.alloc(Self::TY)?.0;
+        }
+        let header = &mut db.get_as_mut::<RawHeader>(self.block_nr)?[0];
+
+        header.version = self.version;
+        header.flags = self.flags;
+        header.segment_mb = self.segment_mb;
+        header.verify_pos = self.verify_pos;
+        header.min_word_len = self.min_word_len;
+
+        Ok(())
+    }
+
+    pub fn flag(&self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    pub fn set_flag(&mut self, flag: u32, value: bool) {
+        if value {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+    }
+
+    /// File names are compared ignoring case. Defaults to the platform
+    /// until it is set.
+    pub fn fold_case(&self) -> bool {
+        if self.flag(FLAG_FOLD_CASE) {
+            true
+        } else if self.flag(FLAG_EXACT_CASE) {
+            false
+        } else {
+            FOLD_CASE
+        }
+    }
+
+    pub fn set_fold_case(&mut self, fold_case: bool) {
+        self.set_flag(FLAG_FOLD_CASE, fold_case);
+        self.set_flag(FLAG_EXACT_CASE, !fold_case);
+    }
+}
@@ -0,0 +1,184 @@
+use crate::cancel::is_cancelled;
+use crate::index2::files::FileList;
+use crate::index2::{FileId, IndexError, IndexKind, Words};
+use std::collections::HashMap;
+
+/// Call the progress of merge() every n words.
+pub const MERGE_PROGRESS: usize = 10_000;
+
+/// Words of the other index read at once.
+const MERGE_CHUNK: usize = 1_000;
+
+/// A file of the other index that this index has too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergeConflict {
+    /// Keep the file as it is, its words of the other index are dropped.
+    #[default]
+    Skip,
+    /// The file of the other index takes its place. The file is kept
+    /// and flagged as replaced, its words don't find it anymore.
+    Replace,
+    /// Nothing is merged.
+    Error,
+}
+
+/// Result of merge().
+#[derive(Debug, Default)]
+pub struct MergeReport {
+    /// Files added, the replaced ones are not counted.
+    pub files: usize,
+    pub words: usize,
+    /// Files in both indexes.
+    pub conflicts: Vec<String>,
+    /// Stopped with Ctrl-C. The files are all added, the words only
+    /// up to there. Such an index must not be written, merge_index()
+    /// reads it again.
+    pub cancelled: bool,
+}
+
+impl Words {
+    /// Appends the files and words of the other index. The file-ids of
+    /// the other index are mapped to new ones, the names are compared
+    /// with forward slashes and with the fold_case of this index.
+    ///
+    /// With MergeConflict::Error the conflicts are checked first and
+    /// the index is left alone.
+    ///
+    /// Progress is called with the number of words looked at every
+    /// MERGE_PROGRESS words.
+    pub fn merge(
+        &mut self,
+        other: &mut Words,
+        conflict: MergeConflict,
+        mut progress: impl FnMut(usize),
+    ) -> Result<MergeReport, IndexError> {
+        // all the names are needed.
+        if other.files.is_lookup() {
+            other.files = FileList::load(&mut other.db)?;
+        }
+
+        let mut report = MergeReport::default();
+
+        let fold_case = self.files.fold_case();
+        let known: HashMap<String, FileId> = self
+            .files
+            .iter()
+            .map(|(file_id, name)| (normalize(&name, fold_case), file_id))
+            .collect();
+        let conflicts: Vec<(FileId, FileId)> = other
+            .files
+            .iter()
+            .filter_map(|(file_id, name)| {
+                Some((file_id, *known.get(&normalize(&name, fold_case))?))
+            })
+            .collect();
+        report.conflicts = conflicts
+            .iter()
+            .flat_map(|(file_id, _)| other.file(*file_id))
+            .collect();
+        if conflict == MergeConflict::Error && !report.conflicts.is_empty() {
+            return Err(IndexError::err(IndexKind::MergeConflict(
+                report.conflicts.swap_remove(0),
+            )));
+        }
+
+        // None for skipped files.
+        let mut map: HashMap<FileId, Option<FileId>> = HashMap::new();
+        let mut replace: HashMap<FileId, FileId> = HashMap::new();
+        for (file_id, own_id) in conflicts {
+            match conflict {
+                MergeConflict::Replace => replace.insert(file_id, own_id),
+                _ => map.insert(file_id, None),
+            };
+        }
+        let flags: HashMap<FileId, u32> = other
+            .files
+            .list()
+            .iter()
+            .filter(|(_, v)| v.flags != 0)
+            .map(|(file_id, v)| (*file_id, v.flags))
+            .collect();
+        let names: Vec<(FileId, String)> = other
+            .files
+            .iter()
+            .map(|(file_id, name)| (file_id, name.into_owned()))
+            .collect();
+        for (file_id, name) in names {
+            if map.contains_key(&file_id) {
+                continue;
+            }
+            let new_id = match replace.get(&file_id) {
+                Some(own_id) => {
+                    // it keeps the name it has here.
+                    let name = match self.files.file_name(*own_id) {
+                        Some(own_name) => own_name.into_owned(),
+                        None => name,
+                    };
+                    let new_id = self.add_file(name);
+                    self.files.replace_file(*own_id, new_id);
+                    new_id
+                }
+                None => {
+                    report.files += 1;
+                    self.add_file(name)
+                }
+            };
+            if let Some(flags) = flags.get(&file_id) {
+                self.files.set_flags(new_id, *flags);
+            }
+            map.insert(file_id, Some(new_id));
+        }
+
+        // block by block, the stored words have no count.
+        let mut n = 0;
+        let mut pos = 0;
+        let mut after: Option<String> = None;
+        'words: loop {
+            let words = other
+                .words
+                .chunk(&mut other.db, pos, after.as_deref(), MERGE_CHUNK)?;
+            pos += words.len();
+            let done = words.len() < MERGE_CHUNK;
+            for (word, data) in words {
+                n += 1;
+                if n % MERGE_PROGRESS == 0 {
+                    progress(n);
+                }
+                if is_cancelled() {
+                    report.cancelled = true;
+                    break 'words;
+                }
+
+                let mut file_ids = Vec::new();
+                for file_id in other.iter_word_files(data) {
+                    if let Some(Some(own_id)) = map.get(&file_id?) {
+                        file_ids.push(*own_id);
+                    }
+                }
+                if !file_ids.is_empty() {
+                    for own_id in file_ids {
+                        self.add_word(&word, 0, own_id)?;
+                    }
+                    report.words += 1;
+                }
+                after = Some(word);
+            }
+            if done {
+                break;
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// Names are compared with forward slashes, and in lower-case with
+/// fold_case.
+fn normalize(name: &str, fold_case: bool) -> String {
+    let name = name.replace('\\', "/");
+    if fold_case {
+        name.to_lowercase()
+    } else {
+        name
+    }
+}
@@ -0,0 +1,139 @@
+/// A search-term.
+///
+/// `*` matches any number of chars, `?` exactly one char. A backslash
+/// makes the next char literal: `\*`, `\?` and `\\`.
+///
+/// Matching works on chars, not bytes, so `?` matches an umlaut or
+/// a CJK char as well. Nothing is normalized, `ü` as `u` with a
+/// combining diaeresis is two chars.
+///
+/// The indexer keeps only letters, apostrophes and hyphens for a text-word,
+/// so a literal `*` or `?` can only be found in a namespaced word like
+/// the target of a link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    tok: Vec<Tok>,
+    /// The pattern has no wildcards.
+    literal: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tok {
+    Char(char),
+    One,
+    Any,
+}
+
+impl Pattern {
+    pub fn new(term: &str) -> Self {
+        Self::parse(term, true)
+    }
+
+    /// A file pattern. There are no escapes, a backslash is
+    /// a path separator.
+    pub fn glob(txt: &str) -> Self {
+        Self::parse(txt, false)
+    }
+
+    fn parse(term: &str, escapes: bool) -> Self {
+        let mut tok = Vec::new();
+        let mut it = term.chars();
+        while let Some(c) = it.next() {
+            match c {
+                // a trailing backslash is taken as is.
+                '\\' if escapes => tok.push(Tok::Char(it.next().unwrap_or('\\'))),
+                '*' => {
+                    if tok.last() != Some(&Tok::Any) {
+                        tok.push(Tok::Any);
+                    }
+                }
+                '?' => tok.push(Tok::One),
+                c => tok.push(Tok::Char(c)),
+            }
+        }
+
+        let literal = tok
+            .iter()
+            .map(|v| match v {
+                Tok::Char(c) => Some(*c),
+                _ => None,
+            })
+            .collect();
+
+        Self { tok, literal }
+    }
+
+    /// Escapes all wildcards, the term only matches itself.
+    pub fn escape(term: &str) -> String {
+        let mut escaped = String::with_capacity(term.len());
+        for c in term.chars() {
+            if matches!(c, '*' | '?' | '\\') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    }
+
+    /// The literal chars before the first wildcard, without escapes.
+    pub fn prefix(&self) -> String {
+        self.tok
+            .iter()
+            .map_while(|v| match v {
+                Tok::Char(c) => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Literal chars followed by a single trailing `*`.
+    pub fn is_prefix(&self) -> bool {
+        match self.tok.split_last() {
+            Some((Tok::Any, rest)) => rest.iter().all(|v| matches!(v, Tok::Char(_))),
+            _ => false,
+        }
+    }
+
+    /// The text without escapes, if there are no wildcards.
+    pub fn literal(&self) -> Option<&str> {
+        self.literal.as_deref()
+    }
+
+    pub fn matches(&self, word: &str) -> bool {
+        if let Some(literal) = &self.literal {
+            return literal == word;
+        }
+
+        // backtracks to the last *.
+        let mut p = 0;
+        let mut w = 0;
+        let mut star = None;
+        while let Some(c) = word[w..].chars().next() {
+            match self.tok.get(p) {
+                Some(Tok::Char(t)) if *t == c => {
+                    p += 1;
+                    w += c.len_utf8();
+                }
+                Some(Tok::One) => {
+                    p += 1;
+                    w += c.len_utf8();
+                }
+                Some(Tok::Any) => {
+                    star = Some((p, w));
+                    p += 1;
+                }
+                _ => match star {
+                    Some((star_p, star_w)) => {
+                        let skip = word[star_w..].chars().next().map_or(1, |v| v.len_utf8());
+                        p = star_p + 1;
+                        w = star_w + skip;
+                        star = Some((star_p, w));
+                    }
+                    None => return false,
+                },
+            }
+        }
+
+        self.tok[p..].iter().all(|v| *v == Tok::Any)
+    }
+}
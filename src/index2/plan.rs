@@ -0,0 +1,188 @@
+use crate::index2::diagnostics::FindDiagnostics;
+use crate::index2::pattern::Pattern;
+use crate::index2::query_cache::QueryCache;
+use crate::index2::words::WordData;
+use crate::index2::{is_namespaced, FileId, IndexError, Words};
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+
+/// How a search-term matches the words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchMode {
+    /// No wildcards.
+    Exact,
+    /// Only a trailing `*`.
+    Prefix,
+    /// Any other `*` or `?`.
+    Wildcard,
+}
+
+impl Display for MatchMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MatchMode::Exact => write!(f, "exact"),
+            MatchMode::Prefix => write!(f, "prefix"),
+            MatchMode::Wildcard => write!(f, "wildcard"),
+        }
+    }
+}
+
+/// One search-term of a find.
+#[derive(Debug, Clone)]
+pub struct TermPlan {
+    pub term: String,
+    pub mode: MatchMode,
+    /// The range of the word-list that is looked at. Empty is all of it.
+    pub prefix: String,
+    /// Only matches namespaced words, and no text-lines.
+    pub namespaced: bool,
+    pattern: Pattern,
+}
+
+impl TermPlan {
+    pub fn new(term: &str) -> Self {
+        let pattern = Pattern::new(term);
+        let mode = if pattern.literal().is_some() {
+            MatchMode::Exact
+        } else if pattern.is_prefix() {
+            MatchMode::Prefix
+        } else {
+            MatchMode::Wildcard
+        };
+        Self {
+            term: term.to_string(),
+            mode,
+            prefix: pattern.prefix(),
+            namespaced: is_namespaced(term),
+            pattern,
+        }
+    }
+
+    /// Namespaced terms only match namespaced words and the other
+    /// way round.
+    pub fn matches(&self, word: &str) -> bool {
+        is_namespaced(word) == self.namespaced && self.pattern.matches(word)
+    }
+}
+
+/// The steps of a find. Each term is matched against the word-list
+/// and the files of its words are collected. Each term after the
+/// first reduces the files of the ones before.
+#[derive(Debug, Clone)]
+pub struct QueryPlan {
+    pub terms: Vec<TermPlan>,
+}
+
+impl QueryPlan {
+    pub fn new(terms: &[String]) -> Self {
+        Self {
+            terms: terms.iter().map(|v| TermPlan::new(v)).collect(),
+        }
+    }
+}
+
+/// Matching words of one term, counted up to a cap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WordEstimate {
+    pub words: usize,
+    /// Counting stopped at the cap.
+    pub capped: bool,
+}
+
+/// The plan with the matching words per term. Nothing is executed.
+#[derive(Debug, Clone)]
+pub struct Explain {
+    pub plan: QueryPlan,
+    pub estimates: Vec<WordEstimate>,
+    /// The result is in the query cache, no chain would be walked.
+    pub cached: bool,
+}
+
+impl Words {
+    /// The words matching one planned term. With a limit it stops
+    /// when it has that many.
+    ///
+    /// An exact term is looked up directly, in lazy mode this only
+    /// reads the blocks that may hold the word.
+    pub fn plan_words(
+        &mut self,
+        term: &TermPlan,
+        limit: Option<usize>,
+    ) -> Result<Vec<(String, WordData)>, IndexError> {
+        if let Some(literal) = term.pattern.literal() {
+            if !term.matches(literal) || limit == Some(0) {
+                return Ok(Vec::new());
+            }
+            let found = self.words.get(&mut self.db, literal)?;
+            return Ok(found
+                .map(|v| (literal.to_string(), v))
+                .into_iter()
+                .collect());
+        }
+        self.words
+            .prefix_matching(&mut self.db, &term.prefix, |k| term.matches(k), limit)
+    }
+
+    /// Runs the plan. Doesn't use the query cache.
+    /// With diag the numbers per word are collected on the way.
+    pub fn execute(
+        &mut self,
+        plan: &QueryPlan,
+        mut diag: Option<&mut FindDiagnostics>,
+    ) -> Result<BTreeSet<FileId>, IndexError> {
+        let mut collect = BTreeSet::<FileId>::new();
+        let mut first = true;
+
+        // find the words and the files where they are contained.
+        // each consecutive search-term *reduces* the list of viable files.
+        for term in &plan.terms {
+            let mut files = Vec::new();
+            for (word, data) in self.plan_words(term, None)? {
+                match diag.as_deref_mut() {
+                    Some(diag) => {
+                        let wd = self.word_diagnostics(&term.term, word, data, &mut files)?;
+                        diag.words.push(wd);
+                    }
+                    None => files.extend(self.iter_word_files(data).flatten()),
+                }
+            }
+            // merged files count as the file they were merged into,
+            // replaced files not at all.
+            let files = files.into_iter().filter_map(|v| self.files.canonical(v));
+
+            if first {
+                collect = files.collect();
+            } else {
+                collect = files.filter(|v| collect.contains(v)).collect();
+            }
+
+            first = false;
+        }
+
+        Ok(collect)
+    }
+
+    /// The plan for the terms and the number of their words, counted
+    /// up to cap. Only the word-list is read, no file chain is walked.
+    pub fn explain(&mut self, terms: &[String], cap: usize) -> Result<Explain, IndexError> {
+        let plan = QueryPlan::new(terms);
+        let mut estimates = Vec::new();
+        for term in &plan.terms {
+            // one more tells that there are more.
+            let words = self.plan_words(term, Some(cap.saturating_add(1)))?.len();
+            estimates.push(WordEstimate {
+                words: words.min(cap),
+                capped: words > cap,
+            });
+        }
+        let cached = self
+            .query_cache
+            .contains(&QueryCache::normalize(terms), self.generation);
+
+        Ok(Explain {
+            plan,
+            estimates,
+            cached,
+        })
+    }
+}
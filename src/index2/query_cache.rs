@@ -0,0 +1,82 @@
+use crate::index2::ids::FileId;
+use std::collections::VecDeque;
+
+/// Number of cached queries.
+pub const QUERY_CACHE_LEN: usize = 8;
+
+/// LRU cache for the results of Words::find_ids.
+///
+/// Only the file-ids are kept, not the text-lines.
+#[derive(Debug, Default)]
+pub struct QueryCache {
+    /// Most recently used first.
+    entries: VecDeque<QueryEntry>,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug)]
+pub struct QueryEntry {
+    pub terms: Vec<String>,
+    pub generation: u64,
+    pub ids: Vec<FileId>,
+}
+
+impl QueryCache {
+    /// The order of the search-terms doesn't matter for the result.
+    pub fn normalize(terms: &[String]) -> Vec<String> {
+        let mut terms = terms
+            .iter()
+            .map(|v| v.trim().to_string())
+            .collect::<Vec<_>>();
+        terms.sort();
+        terms.dedup();
+        terms
+    }
+
+    /// Cached result for the normalized terms.
+    /// Entries from an older generation of the index are dropped.
+    pub fn get(&mut self, terms: &[String], generation: u64) -> Option<&[FileId]> {
+        self.entries.retain(|v| v.generation == generation);
+
+        if let Some(pos) = self.entries.iter().position(|v| v.terms == terms) {
+            self.hits += 1;
+            let entry = self.entries.remove(pos).expect("entry");
+            self.entries.push_front(entry);
+            self.entries.front().map(|v| v.ids.as_slice())
+        } else {
+            self.misses += 1;
+            None
+        }
+    }
+
+    /// Is there a result for the normalized terms. Doesn't count
+    /// as a hit or a miss.
+    pub fn contains(&self, terms: &[String], generation: u64) -> bool {
+        self.entries
+            .iter()
+            .any(|v| v.generation == generation && v.terms == terms)
+    }
+
+    pub fn insert(&mut self, terms: Vec<String>, generation: u64, ids: Vec<FileId>) {
+        self.entries.retain(|v| v.terms != terms);
+        self.entries.push_front(QueryEntry {
+            terms,
+            generation,
+            ids,
+        });
+        self.entries.truncate(QUERY_CACHE_LEN);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &QueryEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
@@ -0,0 +1,132 @@
+use crate::index2::check::{CHAIN_LIMIT, CHECK_CHUNK};
+use crate::index2::header::FLAG_DEGRADED;
+use crate::index2::ids::{BlkIdx, FileId};
+use crate::index2::segments::block_as;
+use crate::index2::word_map::{RawWordMap, WordMap};
+use crate::index2::{IndexError, Words};
+use std::collections::BTreeSet;
+
+/// Prefix of the placeholder words.
+pub const RECOVERED_PREFIX: &str = "recovered-";
+
+/// Result of recovering the words.
+#[derive(Debug, Default)]
+pub struct RecoverReport {
+    /// Used entries of the word-map head blocks.
+    pub chains: usize,
+    /// Chains with no word pointing to them.
+    pub orphans: usize,
+    /// Placeholder words added for them.
+    pub recovered: Vec<String>,
+    /// Distinct files referenced by the orphaned chains.
+    pub files: usize,
+    /// Referenced files that are not in the file-list.
+    pub missing_files: usize,
+}
+
+impl Words {
+    /// Chains of the word-map that no word points to get a
+    /// placeholder word `recovered-<n>`, so their files can be
+    /// found again.
+    ///
+    /// The text of the word is only stored in the word-list and
+    /// there is no map from the files back to their words, so
+    /// the original words can't be restored. The index is marked
+    /// as degraded if anything was recovered.
+    ///
+    /// The words are stored with the next write.
+    pub fn recover_words(&mut self) -> Result<RecoverReport, IndexError> {
+        let mut report = RecoverReport::default();
+
+        // chains known to the word-list.
+        let mut known = BTreeSet::new();
+        let mut pos = 0;
+        let mut after: Option<String> = None;
+        loop {
+            let words = self
+                .words
+                .chunk(&mut self.db, pos, after.as_deref(), CHECK_CHUNK)?;
+            pos += words.len();
+            let done = words.len() < CHECK_CHUNK;
+            for (k, v) in words {
+                known.insert((v.file_map_block_nr.0, v.file_map_idx.0));
+                after = Some(k);
+            }
+            if done {
+                break;
+            }
+        }
+
+        // left behind by a failed merge.
+        known.extend(self.wordmap.orphans.iter().map(|(nr, idx)| (nr.0, idx.0)));
+
+        // every used head entry starts a chain.
+        let blocks: Vec<_> = self
+            .db
+            .iter_metadata_filter(|_nr, ty| ty == WordMap::TY_LISTHEAD)
+            .map(|(nr, _)| nr)
+            .collect();
+        let mut orphans = Vec::new();
+        for block_nr in blocks {
+            let (used, dirty) = {
+                let block = self.db.get(block_nr)?;
+                let maps = block_as::<RawWordMap>(block, block_nr)?;
+                let used: Vec<_> = maps
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, v)| **v != RawWordMap::default())
+                    .map(|(idx, _)| BlkIdx(idx as u32))
+                    .collect();
+                (used, block.is_dirty())
+            };
+            for idx in used {
+                report.chains += 1;
+                if !known.contains(&(block_nr.0, idx.0)) {
+                    orphans.push((block_nr, idx));
+                }
+            }
+            if !dirty {
+                self.db.discard(block_nr)?;
+            }
+        }
+        report.orphans = orphans.len();
+
+        let last_file_id = self.files.last_file_id();
+        let mut files = BTreeSet::new();
+        let mut n = 0;
+        for (block_nr, block_idx) in orphans {
+            let file_ids: Vec<FileId> = WordMap::iter_files(&mut self.db, block_nr, block_idx)
+                .take(CHAIN_LIMIT)
+                .collect::<Result<_, _>>()?;
+            let Some(first_file_id) = file_ids.iter().copied().min() else {
+                continue;
+            };
+
+            let word = loop {
+                n += 1;
+                let word = format!("{}{}", RECOVERED_PREFIX, n);
+                if self.words.get(&mut self.db, &word)?.is_none() {
+                    break word;
+                }
+            };
+            self.words
+                .insert(&word, file_ids.len(), block_nr, block_idx, first_file_id);
+            report.recovered.push(word);
+            files.extend(file_ids);
+        }
+        report.files = files.len();
+        report.missing_files = files.iter().filter(|v| **v > last_file_id).count();
+
+        if !report.recovered.is_empty() {
+            self.generation += 1;
+            self.header.set_flag(FLAG_DEGRADED, true);
+        }
+
+        Ok(report)
+    }
+
+    /// Words were recovered as placeholders, see recover_words().
+    pub fn is_degraded(&self) -> bool {
+        self.header.flag(FLAG_DEGRADED)
+    }
+}
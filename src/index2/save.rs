@@ -0,0 +1,92 @@
+use crate::index2::header::FLAG_CLEAN;
+use crate::index2::{IndexError, IndexKind, Words};
+use blockfile2::LogicalNr;
+use std::collections::HashSet;
+
+/// State of a chunked write.
+#[derive(Debug, Default)]
+pub struct ChunkedWrite {
+    /// Blocks that were dirty at the start and are not written yet.
+    /// The last ones are written first.
+    pending: Vec<LogicalNr>,
+    /// Generation at the start.
+    pub generation: u64,
+    pub batches: usize,
+    pub blocks: usize,
+}
+
+impl ChunkedWrite {
+    /// Blocks still to write.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+impl Words {
+    /// Starts a chunked write.
+    ///
+    /// The word-list, file-list and wordmap are stored to their blocks
+    /// right away, that's quick. The blocks that are dirty now are written
+    /// with write_batch(). Blocks that get dirty later wait for the next write.
+    pub fn start_write(&mut self) -> Result<ChunkedWrite, IndexError> {
+        if self.is_read_only() {
+            return Err(IndexError::err(IndexKind::ReadOnly));
+        }
+        // the file is not consistent until the last batch.
+        self.header.set_flag(FLAG_CLEAN, false);
+        self.header.store(&mut self.db)?;
+        self.words.store(&mut self.db)?;
+        self.files.store(&mut self.db)?;
+        self.wordmap.store(&mut self.db)?;
+        self.config.store(&mut self.db)?;
+
+        self.write_stats();
+
+        let header_nr = self.header.block_nr;
+        let mut pending = self.db.dirty_blocks();
+        // header first.
+        pending.retain(|v| v.0 != header_nr.0);
+        pending.push(header_nr);
+
+        Ok(ChunkedWrite {
+            pending,
+            generation: self.generation,
+            batches: 0,
+            blocks: 0,
+        })
+    }
+
+    /// Writes the next n blocks of a chunked write.
+    /// Returns true when all blocks are written.
+    ///
+    /// The index is only marked clean if nothing changed since
+    /// start_write(), otherwise the next write has to do that.
+    pub fn write_batch(&mut self, state: &mut ChunkedWrite, n: usize) -> Result<bool, IndexError> {
+        self.write_next(state, n).map_err(IndexError::or_disk_full)
+    }
+
+    fn write_next(&mut self, state: &mut ChunkedWrite, n: usize) -> Result<bool, IndexError> {
+        if !state.pending.is_empty() {
+            let at = state.pending.len().saturating_sub(usize::max(1, n));
+            let batch: HashSet<u32> = state.pending.drain(at..).map(|v| v.0).collect();
+            self.db.store_only(&batch)?;
+            state.batches += 1;
+            state.blocks += batch.len();
+
+            if !state.pending.is_empty() {
+                return Ok(false);
+            }
+        }
+
+        if self.generation == state.generation {
+            self.header.set_flag(FLAG_CLEAN, true);
+            self.header.store(&mut self.db)?;
+            self.db
+                .store_only(&HashSet::from([self.header.block_nr.0]))?;
+        }
+        self.db.roll_over()?;
+
+        Self::cleanup(&mut self.db)?;
+        Ok(true)
+    }
+}
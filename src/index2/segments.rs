@@ -0,0 +1,662 @@
+use crate::index2::file_refs::RawFileRef;
+use crate::index2::header::RawHeader;
+use crate::index2::word_map::{RawBags, RawWordMap};
+use crate::index2::words::{RawWord, RawWordV1};
+use crate::index2::{IndexError, IndexKind, WordBlockType};
+use blockfile2::{Block, BlockRead, BlockWrite, FileBlocks, LogicalNr, UserBlockType};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The segment is encoded in the high bits of a LogicalNr.
+pub const SEGMENT_SHIFT: u32 = 24;
+/// Mask for the block-nr inside a segment.
+pub const SEGMENT_MASK: u32 = (1 << SEGMENT_SHIFT) - 1;
+/// Maximum number of segments.
+pub const MAX_SEGMENTS: usize = 1 << (32 - SEGMENT_SHIFT);
+
+/// The index split across multiple block files.
+///
+/// The first segment is the index file itself, the others are named
+/// stored.0001.idx, stored.0002.idx ...
+///
+/// New blocks are only allocated in the last segment. Blocks in the
+/// older segments are still modified in place, the chains of the
+/// wordmap reach back into them. The stream of the file-list always
+/// stays in the first segment.
+///
+/// Without a segment limit this behaves exactly like a single FileBlocks,
+/// block-nrs in the first segment are the same as before.
+pub struct Segments {
+    path: PathBuf,
+    block_size: usize,
+    /// Start a new segment when the last one exceeds this size.
+    limit: Option<u64>,
+    segments: Vec<FileBlocks<WordBlockType>>,
+    /// Highest block-nr inside a segment. Always SEGMENT_MASK
+    /// outside of the tests.
+    max_nr: u32,
+    /// The last segment handed out max_nr, new blocks go to
+    /// the next segment.
+    full: bool,
+    /// Only while diagnostics run.
+    counting: Option<Counting>,
+    /// Block accesses since the index was loaded.
+    reads: u64,
+    /// Loaded with load_read_only().
+    read_only: bool,
+}
+
+/// Layout of the blocks of some user types.
+///
+/// # Safety
+///
+/// Only for plain data with a fixed layout, where any content
+/// of the block is a valid value.
+pub unsafe trait BlockLayout: Copy {
+    /// The block types with this layout.
+    const TYPES: &'static [WordBlockType];
+}
+
+unsafe impl BlockLayout for RawWordMap {
+    const TYPES: &'static [WordBlockType] =
+        &[WordBlockType::WordMapHead, WordBlockType::WordMapTail];
+}
+
+unsafe impl BlockLayout for RawBags {
+    const TYPES: &'static [WordBlockType] = &[WordBlockType::WordMapBags];
+}
+
+unsafe impl BlockLayout for RawWord {
+    const TYPES: &'static [WordBlockType] = &[WordBlockType::WordList2];
+}
+
+unsafe impl BlockLayout for RawWordV1 {
+    const TYPES: &'static [WordBlockType] = &[WordBlockType::WordList];
+}
+
+unsafe impl BlockLayout for RawHeader {
+    const TYPES: &'static [WordBlockType] = &[WordBlockType::Header];
+}
+
+unsafe impl BlockLayout for RawFileRef {
+    const TYPES: &'static [WordBlockType] = &[WordBlockType::FileRefs];
+}
+
+unsafe impl BlockLayout for u8 {
+    const TYPES: &'static [WordBlockType] = &[WordBlockType::FileLeaves];
+}
+
+fn check_type<T: BlockLayout>(block: &Block, block_nr: LogicalNr) -> Result<(), IndexError> {
+    let ty = WordBlockType::user_type(block.block_type());
+    match ty {
+        Some(ty) if T::TYPES.contains(&ty) => Ok(()),
+        _ => Err(IndexError::err(IndexKind::WrongBlockType(
+            block_nr,
+            ty,
+            T::TYPES,
+        ))),
+    }
+}
+
+/// The block as an array of T. Fails if the type of the block
+/// doesn't have this layout.
+///
+/// block_nr is only used for the error.
+pub fn block_as<T: BlockLayout>(block: &Block, block_nr: LogicalNr) -> Result<&[T], IndexError> {
+    check_type::<T>(block, block_nr)?;
+    Ok(unsafe { block.cast_array::<T>() })
+}
+
+/// The block as an array of T. Fails if the type of the block
+/// doesn't have this layout.
+pub fn block_as_mut<T: BlockLayout>(
+    block: &mut Block,
+    block_nr: LogicalNr,
+) -> Result<&mut [T], IndexError> {
+    check_type::<T>(block, block_nr)?;
+    Ok(unsafe { block.cast_array_mut::<T>() })
+}
+
+/// Block cache counters.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BlockCounters {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+struct Counting {
+    counters: BlockCounters,
+    /// Blocks currently in memory.
+    resident: HashSet<u32>,
+}
+
+impl Segments {
+    /// Loads the index file and all existing segments.
+    pub fn load(path: &Path, block_size: usize) -> Result<Self, IndexError> {
+        let mut segments = vec![FileBlocks::load(path, block_size)?];
+        loop {
+            let seg_path = segment_path(path, segments.len());
+            if !seg_path.exists() {
+                break;
+            }
+            segments.push(FileBlocks::load(&seg_path, block_size)?);
+        }
+        let full = is_full(&segments[segments.len() - 1], SEGMENT_MASK);
+
+        Ok(Self {
+            path: path.into(),
+            block_size,
+            limit: None,
+            segments,
+            max_nr: SEGMENT_MASK,
+            full,
+            counting: None,
+            reads: 0,
+            read_only: false,
+        })
+    }
+
+    /// Loads the index for reading only. Everything that would change
+    /// a block fails with IndexKind::ReadOnly.
+    ///
+    /// FileBlocks has no read-only open, the files themselves are
+    /// still opened as for load().
+    pub fn load_read_only(path: &Path, block_size: usize) -> Result<Self, IndexError> {
+        let mut db = Self::load(path, block_size)?;
+        db.read_only = true;
+        Ok(db)
+    }
+
+    /// Loaded with load_read_only().
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn check_writable(&self) -> Result<(), IndexError> {
+        if self.read_only {
+            Err(IndexError::err(IndexKind::ReadOnly))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Closes all files. Nothing can be read or written until the
+    /// index is loaded again, everything fails with IndexKind::Closed.
+    pub fn close(&mut self) {
+        self.segments.clear();
+    }
+
+    /// Removes the index file and all segments.
+    pub fn remove(path: &Path) {
+        let _ = fs::remove_file(path);
+        for n in 1..MAX_SEGMENTS {
+            let seg_path = segment_path(path, n);
+            if !seg_path.exists() {
+                break;
+            }
+            let _ = fs::remove_file(seg_path);
+        }
+    }
+
+    /// Size limit for a segment. None keeps everything in one file.
+    pub fn set_limit(&mut self, limit: Option<u64>) {
+        self.limit = limit;
+    }
+
+    pub fn limit(&self) -> Option<u64> {
+        self.limit
+    }
+
+    /// Number of segment files.
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// File of the n-th segment.
+    pub fn path(&self, n: usize) -> PathBuf {
+        segment_path(&self.path, n)
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Start counting block cache hits and misses.
+    pub fn start_counting(&mut self) {
+        let mut resident = HashSet::new();
+        for (seg, segment) in self.segments.iter().enumerate() {
+            resident.extend(segment.iter_blocks().map(|v| join(seg, v.block_nr()).0));
+        }
+        self.counting = Some(Counting {
+            counters: BlockCounters::default(),
+            resident,
+        });
+    }
+
+    /// Stop counting and return the counters.
+    pub fn stop_counting(&mut self) -> BlockCounters {
+        match self.counting.take() {
+            Some(counting) => counting.counters,
+            None => BlockCounters::default(),
+        }
+    }
+
+    /// Block accesses since the index was loaded, cached or not.
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    fn touch(&mut self, block_nr: LogicalNr) {
+        self.reads += 1;
+        if let Some(counting) = &mut self.counting {
+            if counting.resident.insert(block_nr.0) {
+                counting.counters.misses += 1;
+            } else {
+                counting.counters.hits += 1;
+            }
+        }
+    }
+
+    /// The segment of the block-nr. A block-nr from a broken block
+    /// may point past the last one.
+    fn segment(
+        &mut self,
+        block_nr: LogicalNr,
+    ) -> Result<(&mut FileBlocks<WordBlockType>, LogicalNr), IndexError> {
+        let (seg, nr) = split(block_nr);
+        match self.segments.get_mut(seg) {
+            Some(segment) => Ok((segment, nr)),
+            None => Err(IndexError::err(IndexKind::UnknownSegment(block_nr))),
+        }
+    }
+
+    pub fn get(&mut self, block_nr: LogicalNr) -> Result<&Block, IndexError> {
+        self.touch(block_nr);
+        let (segment, nr) = self.segment(block_nr)?;
+        Ok(segment.get(nr)?)
+    }
+
+    pub fn get_mut(&mut self, block_nr: LogicalNr) -> Result<&mut Block, IndexError> {
+        self.check_writable()?;
+        self.touch(block_nr);
+        let (segment, nr) = self.segment(block_nr)?;
+        Ok(segment.get_mut(nr)?)
+    }
+
+    /// The block as an array of T, see block_as().
+    pub fn get_as<T: BlockLayout>(&mut self, block_nr: LogicalNr) -> Result<&[T], IndexError> {
+        let block = self.get(block_nr)?;
+        block_as(block, block_nr)
+    }
+
+    /// The block as an array of T, see block_as(). The block is
+    /// marked dirty, but only if it has the right type.
+    pub fn get_as_mut<T: BlockLayout>(
+        &mut self,
+        block_nr: LogicalNr,
+    ) -> Result<&mut [T], IndexError> {
+        let block = self.get_mut(block_nr)?;
+        check_type::<T>(block, block_nr)?;
+        block.set_dirty(true);
+        block_as_mut(block, block_nr)
+    }
+
+    /// Allocates a new block in the last segment. Starts a new
+    /// segment when the last one has no block-nr left.
+    ///
+    /// Block::block_nr() only knows the nr inside its segment,
+    /// the returned nr is the one to store.
+    pub fn alloc(
+        &mut self,
+        block_type: WordBlockType,
+    ) -> Result<(LogicalNr, &mut Block), IndexError> {
+        if self.segments.is_empty() {
+            return Err(IndexError::err(IndexKind::Closed));
+        }
+        self.check_writable()?;
+        if self.full {
+            // another block-nr would alias one of the next segment.
+            if self.segments.len() >= MAX_SEGMENTS {
+                return Err(IndexError::err(IndexKind::SegmentsFull));
+            }
+            self.push_segment()?;
+        }
+        let seg = self.segments.len() - 1;
+        let block = self.segments[seg].alloc(block_type)?;
+        if block.block_nr().0 >= self.max_nr {
+            self.full = true;
+        }
+        let block_nr = join(seg, block.block_nr());
+        if let Some(counting) = &mut self.counting {
+            counting.resident.insert(block_nr.0);
+        }
+        Ok((block_nr, block))
+    }
+
+    pub fn discard(&mut self, block_nr: LogicalNr) -> Result<(), IndexError> {
+        if let Some(counting) = &mut self.counting {
+            counting.resident.remove(&block_nr.0);
+        }
+        let (segment, nr) = self.segment(block_nr)?;
+        segment.discard(nr);
+        Ok(())
+    }
+
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&LogicalNr, &mut Block) -> bool,
+    {
+        let counting = &mut self.counting;
+        for (seg, segment) in self.segments.iter_mut().enumerate() {
+            segment.retain(|k, v| {
+                let block_nr = join(seg, *k);
+                let keep = f(&block_nr, v);
+                if !keep {
+                    if let Some(counting) = counting {
+                        counting.resident.remove(&block_nr.0);
+                    }
+                }
+                keep
+            });
+        }
+    }
+
+    /// All blocks of the given types, with the block-nrs of the segments.
+    pub fn iter_metadata_filter<F>(&self, f: F) -> std::vec::IntoIter<(LogicalNr, WordBlockType)>
+    where
+        F: Fn(LogicalNr, WordBlockType) -> bool,
+    {
+        let mut result = Vec::new();
+        for (seg, segment) in self.segments.iter().enumerate() {
+            result.extend(
+                segment
+                    .iter_metadata_filter(|nr, ty| f(join(seg, nr), ty))
+                    .map(|(nr, ty)| (join(seg, nr), ty)),
+            );
+        }
+        result.into_iter()
+    }
+
+    pub fn iter_blocks(&self) -> impl Iterator<Item = &Block> {
+        self.segments.iter().flat_map(|v| v.iter_blocks())
+    }
+
+    pub fn iter_physical(&self) -> impl Iterator<Item = &Block> {
+        self.segments.iter().flat_map(|v| v.iter_physical())
+    }
+
+    pub fn iter_types(&self) -> impl Iterator<Item = &Block> {
+        self.segments.iter().flat_map(|v| v.iter_types())
+    }
+
+    /// The first segment, it holds the streams.
+    fn first(&mut self) -> Result<&mut FileBlocks<WordBlockType>, IndexError> {
+        self.segments
+            .first_mut()
+            .ok_or_else(|| IndexError::err(IndexKind::Closed))
+    }
+
+    /// Streams live in the first segment.
+    pub fn read_stream(
+        &mut self,
+        block_type: WordBlockType,
+    ) -> Result<impl BlockRead + '_, IndexError> {
+        Ok(self.first()?.read_stream(block_type)?)
+    }
+
+    /// Streams live in the first segment.
+    pub fn append_stream(
+        &mut self,
+        block_type: WordBlockType,
+    ) -> Result<impl BlockWrite + '_, IndexError> {
+        self.check_writable()?;
+        Ok(self.first()?.append_stream(block_type)?)
+    }
+
+    /// Stores all segments. Starts a new segment if the last
+    /// one exceeds the limit.
+    pub fn store(&mut self) -> Result<(), IndexError> {
+        self.check_writable()?;
+        for segment in self.segments.iter_mut() {
+            segment.store()?;
+        }
+        self.roll_over()
+    }
+
+    /// All dirty blocks.
+    pub fn dirty_blocks(&self) -> Vec<LogicalNr> {
+        let mut result = Vec::new();
+        for (seg, segment) in self.segments.iter().enumerate() {
+            result.extend(
+                segment
+                    .iter_blocks()
+                    .filter(|v| v.is_dirty())
+                    .map(|v| join(seg, v.block_nr())),
+            );
+        }
+        result
+    }
+
+    /// Stores only the given blocks. All other dirty blocks stay
+    /// dirty and are written by a later store.
+    ///
+    /// blockfile2 can't store a subset of the blocks. This clears the
+    /// dirty flag of the other blocks for the store() and sets it again
+    /// afterwards. It relies on FileBlocks::store() writing exactly the
+    /// blocks flagged dirty and clearing their flag, test_store_only
+    /// checks that.
+    ///
+    /// Doesn't start a new segment, call roll_over() when done.
+    pub(crate) fn store_only(&mut self, blocks: &HashSet<u32>) -> Result<(), IndexError> {
+        self.check_writable()?;
+        let mut held = HashSet::new();
+        self.retain(|nr, block| {
+            if block.is_dirty() && !blocks.contains(&nr.0) {
+                block.set_dirty(false);
+                held.insert(nr.0);
+            }
+            true
+        });
+
+        let mut result = Ok(());
+        for segment in self.segments.iter_mut() {
+            if let Err(e) = segment.store() {
+                result = Err(e.into());
+                break;
+            }
+        }
+
+        self.retain(|nr, block| {
+            if held.contains(&nr.0) {
+                block.set_dirty(true);
+            }
+            true
+        });
+
+        result
+    }
+
+    /// Starts a new segment if the last one exceeds the limit.
+    pub fn roll_over(&mut self) -> Result<(), IndexError> {
+        if self.segments.is_empty() {
+            return Err(IndexError::err(IndexKind::Closed));
+        }
+        if let Some(limit) = self.limit {
+            let last = self.segments.len() - 1;
+            let size = fs::metadata(self.path(last))?.len();
+            if size > limit && self.segments.len() < MAX_SEGMENTS {
+                self.push_segment()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn push_segment(&mut self) -> Result<(), IndexError> {
+        let seg_path = self.path(self.segments.len());
+        self.segments
+            .push(FileBlocks::load(&seg_path, self.block_size)?);
+        self.full = false;
+        Ok(())
+    }
+}
+
+/// The segment has handed out the highest block-nr.
+fn is_full(segment: &FileBlocks<WordBlockType>, max_nr: u32) -> bool {
+    segment
+        .iter_metadata_filter(|nr, _| nr.0 >= max_nr)
+        .next()
+        .is_some()
+}
+
+/// Name of the n-th segment. The 0th is the index file itself.
+pub fn segment_path(path: &Path, n: usize) -> PathBuf {
+    if n == 0 {
+        return path.into();
+    }
+    let stem = path
+        .file_stem()
+        .map(|v| v.to_string_lossy())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}.{:04}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}.{:04}", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+/// Segment and block-nr inside the segment.
+pub fn split(block_nr: LogicalNr) -> (usize, LogicalNr) {
+    (
+        (block_nr.0 >> SEGMENT_SHIFT) as usize,
+        LogicalNr(block_nr.0 & SEGMENT_MASK),
+    )
+}
+
+/// Block-nr across segments.
+pub fn join(seg: usize, block_nr: LogicalNr) -> LogicalNr {
+    LogicalNr(((seg as u32) << SEGMENT_SHIFT) | (block_nr.0 & SEGMENT_MASK))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::index2::segments::{split, Segments};
+    use crate::index2::{IndexError, IndexKind, WordBlockType};
+    use std::collections::HashSet;
+    use std::path::Path;
+
+    #[test]
+    fn test_closed() {
+        let path = Path::new("tmp/segments_closed.idx");
+        Segments::remove(path);
+
+        let mut db = Segments::load(path, 4096).expect("load");
+        let (block_nr, _) = db.alloc(WordBlockType::FileLeaves).expect("alloc");
+        db.store().expect("store");
+        db.close();
+
+        // errors, no panics.
+        assert!(db.alloc(WordBlockType::FileLeaves).is_err());
+        assert!(db.get(block_nr).is_err());
+        assert!(db.read_stream(WordBlockType::FileList2).is_err());
+        assert!(db.append_stream(WordBlockType::FileList2).is_err());
+        assert!(db.roll_over().is_err());
+        assert!(db.store().is_err());
+
+        Segments::remove(path);
+    }
+
+    #[test]
+    fn test_read_only() {
+        let path = Path::new("tmp/segments_read_only.idx");
+        Segments::remove(path);
+
+        let mut db = Segments::load(path, 4096).expect("load");
+        let (block_nr, _) = db.alloc(WordBlockType::FileLeaves).expect("alloc");
+        db.store().expect("store");
+        drop(db);
+
+        let mut db = Segments::load_read_only(path, 4096).expect("load");
+        assert!(db.is_read_only());
+        assert!(db.get(block_nr).is_ok());
+        assert!(db.read_stream(WordBlockType::FileList2).is_ok());
+
+        let read_only = |e: IndexError| matches!(e.kind, IndexKind::ReadOnly);
+        assert!(db.alloc(WordBlockType::FileLeaves).is_err_and(read_only));
+        assert!(db.get_mut(block_nr).is_err_and(read_only));
+        assert!(db
+            .append_stream(WordBlockType::FileList2)
+            .is_err_and(read_only));
+        assert!(db.store().is_err_and(read_only));
+        assert!(db.store_only(&HashSet::new()).is_err_and(read_only));
+
+        Segments::remove(path);
+    }
+
+    #[test]
+    fn test_store_only() {
+        let path = Path::new("tmp/segments_store_only.idx");
+        Segments::remove(path);
+
+        let mut db = Segments::load(path, 4096).expect("load");
+        let (a, _) = db.alloc(WordBlockType::FileLeaves).expect("alloc");
+        let (b, _) = db.alloc(WordBlockType::FileLeaves).expect("alloc");
+        db.store().expect("store");
+        assert!(db.dirty_blocks().is_empty());
+
+        db.get_as_mut::<u8>(a).expect("a")[0] = 1;
+        db.get_as_mut::<u8>(b).expect("b")[0] = 2;
+        db.store_only(&HashSet::from([a.0])).expect("store_only");
+        // only b waits for the next store.
+        assert_eq!(db.dirty_blocks(), vec![b]);
+
+        let mut stored = Segments::load(path, 4096).expect("load");
+        assert_eq!(stored.get_as::<u8>(a).expect("a")[0], 1);
+        assert_eq!(stored.get_as::<u8>(b).expect("b")[0], 0);
+        drop(stored);
+
+        db.store().expect("store");
+        assert!(db.dirty_blocks().is_empty());
+        let mut stored = Segments::load(path, 4096).expect("load");
+        assert_eq!(stored.get_as::<u8>(b).expect("b")[0], 2);
+        drop(stored);
+
+        drop(db);
+        Segments::remove(path);
+    }
+
+    #[test]
+    fn test_alloc_last_nr() {
+        let path = Path::new("tmp/segments_last_nr.idx");
+        Segments::remove(path);
+
+        let mut db = Segments::load(path, 4096).expect("load");
+        let (first, _) = db.alloc(WordBlockType::WordList2).expect("alloc");
+        // two more block-nrs fit into the first segment.
+        db.max_nr = split(first).1 .0 + 2;
+
+        let mut seen = HashSet::new();
+        seen.insert(first.0);
+        for _ in 0..2 {
+            let (block_nr, block) = db.alloc(WordBlockType::WordList2).expect("alloc");
+            assert_eq!(split(block_nr), (0, block.block_nr()));
+            assert!(seen.insert(block_nr.0));
+        }
+        assert!(db.full);
+
+        let (block_nr, block) = db.alloc(WordBlockType::WordList2).expect("alloc");
+        assert_eq!(split(block_nr), (1, block.block_nr()));
+        assert!(seen.insert(block_nr.0));
+        assert!(!db.full);
+        assert_eq!(db.len(), 2);
+
+        db.store().expect("store");
+        drop(db);
+        Segments::remove(path);
+    }
+}
@@ -1,11 +1,47 @@
-use std::collections::{BTreeMap, HashMap};
+use crate::index2::is_namespaced;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Debug;
+use std::mem::size_of;
+use std::sync::Arc;
+use std::time::Instant;
 
 #[derive(Debug)]
 pub struct TmpWords {
     pub file: String,
     pub words: HashMap<String, usize>,
     pub count: usize,
+    /// Skip stop-words.
+    pub stop_words: bool,
+    /// Replaces the built-in stop-words.
+    pub stop_list: Option<Arc<HashSet<String>>>,
+    /// All words found, including skipped ones.
+    pub total: usize,
+    /// Stop-words found, whether skipped or not.
+    pub stop_count: usize,
+    /// Shorter words are not added.
+    pub min_len: usize,
+    /// Words not added, they are shorter than min_len.
+    pub short_count: usize,
+    /// Side channel for the bigram profiler.
+    pub bigrams: Option<Bigrams>,
+    /// Index the alt and title attributes of html tags.
+    pub attributes: bool,
+    /// The text was decoded from quoted-printable.
+    pub decoded: bool,
+    /// Indexing stops at this time, the words so far are kept.
+    pub deadline: Option<Instant>,
+    /// Called before each look at the deadline. Tests use it
+    /// to slow the parser down.
+    pub check_hook: Option<fn()>,
+    /// The deadline was hit, the file is only partially indexed.
+    pub partial: bool,
+    /// Too many distinct words for its size.
+    pub wordlist: bool,
+    /// The words of a word list were dropped.
+    pub skipped: bool,
+    /// Length of the text the words come from. For html this is
+    /// the extracted text without the markup.
+    pub text_len: usize,
 }
 
 impl TmpWords {
@@ -14,10 +50,63 @@ impl TmpWords {
             file: path.into(),
             words: Default::default(),
             count: 0,
+            stop_words: true,
+            stop_list: None,
+            total: 0,
+            stop_count: 0,
+            min_len: 1,
+            short_count: 0,
+            bigrams: None,
+            attributes: false,
+            decoded: false,
+            deadline: None,
+            check_hook: None,
+            partial: false,
+            wordlist: false,
+            skipped: false,
+            text_len: 0,
         }
     }
 
+    /// Distinct words per KB of the indexed text.
+    pub fn words_per_kb(&self) -> usize {
+        if self.text_len == 0 {
+            0
+        } else {
+            self.words.len() * 1024 / self.text_len
+        }
+    }
+
+    /// Drops the words of a word list, the file is still added.
+    pub fn skip_words(&mut self) {
+        self.words.clear();
+        self.count = 0;
+        self.skipped = true;
+    }
+
+    /// Is the deadline reached. Marks the words as partial.
+    pub fn out_of_time(&mut self) -> bool {
+        if let Some(hook) = self.check_hook {
+            hook();
+        }
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                self.partial = true;
+            }
+        }
+        self.partial
+    }
+
     pub fn add_word<S: AsRef<str>>(&mut self, word: S) {
+        // links are always kept.
+        if self.min_len > 1
+            && word.as_ref().chars().count() < self.min_len
+            && !is_namespaced(word.as_ref())
+        {
+            self.short_count += 1;
+            return;
+        }
+
         if self.words.contains_key(word.as_ref()) {
             *self.words.get_mut(word.as_ref()).expect("word") += 1;
         } else {
@@ -27,6 +116,14 @@ impl TmpWords {
         self.count += 1;
     }
 
+    /// Estimated heap bytes, for the accounting of the words in flight.
+    /// The same as long as nothing is added.
+    pub fn mem_size(&self) -> usize {
+        self.file.capacity()
+            + self.words.capacity() * (size_of::<String>() + size_of::<usize>())
+            + self.words.keys().map(|v| v.capacity()).sum::<usize>()
+    }
+
     pub fn invert(&self) -> BTreeMap<usize, Vec<String>> {
         let mut r = BTreeMap::new();
         for (k, v) in &self.words {
@@ -37,3 +134,113 @@ impl TmpWords {
         r
     }
 }
+
+/// Maximum number of pairs kept by Bigrams.
+pub const BIGRAM_MAP_LEN: usize = 50_000;
+
+/// Counts adjacent word pairs in a bounded map.
+///
+/// When the map is full the rare pairs are evicted, so the counts of the
+/// frequent pairs are a lower bound.
+#[derive(Debug)]
+pub struct Bigrams {
+    counts: HashMap<(String, String), usize>,
+    max_len: usize,
+    prev: Option<(String, bool)>,
+    /// Pairs counted.
+    pub pairs: usize,
+    /// Pairs of two stop-words, not counted.
+    pub stop_pairs: usize,
+    /// Evicted entries.
+    pub evicted: usize,
+}
+
+impl Default for Bigrams {
+    fn default() -> Self {
+        Self::new(BIGRAM_MAP_LEN)
+    }
+}
+
+impl Bigrams {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            counts: Default::default(),
+            max_len,
+            prev: None,
+            pairs: 0,
+            stop_pairs: 0,
+            evicted: 0,
+        }
+    }
+
+    /// Next word of the text.
+    pub fn add(&mut self, word: &str, stop_word: bool) {
+        if let Some((prev, prev_stop)) = self.prev.take() {
+            if prev_stop && stop_word {
+                self.stop_pairs += 1;
+            } else {
+                self.pairs += 1;
+                *self.counts.entry((prev, word.to_string())).or_insert(0) += 1;
+                if self.counts.len() > self.max_len {
+                    self.evict();
+                }
+            }
+        }
+        self.prev = Some((word.to_string(), stop_word));
+    }
+
+    /// Something else than a word, the next one starts a new pair.
+    pub fn reset(&mut self) {
+        self.prev = None;
+    }
+
+    /// Adds the counts of another one.
+    pub fn merge(&mut self, other: Bigrams) {
+        self.pairs += other.pairs;
+        self.stop_pairs += other.stop_pairs;
+        self.evicted += other.evicted;
+        for (k, v) in other.counts {
+            *self.counts.entry(k).or_insert(0) += v;
+        }
+        if self.counts.len() > self.max_len {
+            self.evict();
+        }
+    }
+
+    /// Drop the rarest pairs until a quarter of the space is free.
+    fn evict(&mut self) {
+        let target = self.max_len - self.max_len / 4;
+        let mut threshold = 1;
+        while self.counts.len() > target {
+            let len = self.counts.len();
+            self.counts.retain(|_, v| *v > threshold);
+            self.evicted += len - self.counts.len();
+            threshold += 1;
+        }
+    }
+
+    /// Number of distinct pairs.
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// The n most common pairs.
+    pub fn top(&self, n: usize) -> Vec<(&str, &str, usize)> {
+        let mut top: Vec<_> = self
+            .counts
+            .iter()
+            .map(|((a, b), v)| (a.as_str(), b.as_str(), *v))
+            .collect();
+        top.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(b.0)).then(a.1.cmp(b.1)));
+        top.truncate(n);
+        top
+    }
+}
@@ -1,5 +1,7 @@
-use crate::index2::{BlkIdx, FIdx, FileId, IndexError, WordBlockType, WordFileBlocks};
+use crate::index2::block_pool::BlockPool;
+use crate::index2::{BlkIdx, FIdx, FileId, IndexError, IndexKind, WordBlockType, WordFileBlocks};
 use blockfile2::{Block, LogicalNr};
+use std::cmp::min;
 use std::fmt::{Debug, Formatter};
 
 pub struct WordMap {
@@ -8,6 +10,16 @@ pub struct WordMap {
     pub last_head_idx: [BlkIdx; BAG_LEN],
     pub last_tail_nr: [LogicalNr; BAG_LEN],
     pub last_tail_idx: [BlkIdx; BAG_LEN],
+    /// Pre-allocated head blocks.
+    pub head_pool: BlockPool,
+    /// Pre-allocated tail blocks.
+    pub tail_pool: BlockPool,
+    /// Head entries written for words that never made it to the
+    /// word-list. They are cleared and can be reused.
+    pub orphans: Vec<(LogicalNr, BlkIdx)>,
+    /// The n-th next add fails. For testing.
+    #[cfg(feature = "test-hooks")]
+    fail_after: Option<usize>,
 }
 
 pub const FILE_ID_LEN: usize = 6;
@@ -22,6 +34,29 @@ pub struct RawWordMap {
 
 pub const BAG_LEN: usize = 256;
 
+/// A word with 1/BAG_SCALE of the total word count goes to the last bag.
+/// A single word should hardly have more than 5% of all words.
+pub const BAG_SCALE: usize = 20;
+
+/// The bag for a word with count of total words.
+///
+/// The shares from 0 to 1/BAG_SCALE are split evenly across the
+/// bags, anything above goes to the last one. Bag 0 without a total.
+/// The product doesn't fit a 32-bit usize for large counts, so it's
+/// computed with u128.
+pub fn bag_for(count: usize, total: usize) -> usize {
+    if total == 0 {
+        return 0;
+    }
+    let bag = (count as u128 * (BAG_LEN * BAG_SCALE) as u128) / total as u128;
+    min(bag, (BAG_LEN - 1) as u128) as usize
+}
+
+/// The smallest share of the total word count in the bag, in percent.
+pub fn bag_share(bag: usize) -> f64 {
+    (bag as f64 * 100.0) / (BAG_LEN * BAG_SCALE) as f64
+}
+
 #[derive(Clone, Copy, PartialEq)]
 #[repr(C)]
 pub struct RawBags {
@@ -64,8 +99,7 @@ impl WordMap {
 
     pub fn load(db: &mut WordFileBlocks) -> Result<WordMap, IndexError> {
         for (block_nr, _block_type) in db.iter_metadata_filter(|_nr, ty| ty == Self::TY_BAGS) {
-            let block = db.get(block_nr)?;
-            let bags = unsafe { block.cast::<RawBags>() };
+            let bags = &db.get_as::<RawBags>(block_nr)?[0];
 
             return Ok(Self {
                 bag_nr: block_nr,
@@ -73,6 +107,11 @@ impl WordMap {
                 last_head_idx: bags.head_idx,
                 last_tail_nr: bags.tail_nr,
                 last_tail_idx: bags.tail_idx,
+                head_pool: BlockPool::default(),
+                tail_pool: BlockPool::default(),
+                orphans: Vec::new(),
+                #[cfg(feature = "test-hooks")]
+                fail_after: None,
             });
         }
 
@@ -82,19 +121,19 @@ impl WordMap {
             last_head_idx: [BlkIdx(0); BAG_LEN],
             last_tail_nr: [LogicalNr(0); BAG_LEN],
             last_tail_idx: [BlkIdx(0); BAG_LEN],
+            head_pool: BlockPool::default(),
+            tail_pool: BlockPool::default(),
+            orphans: Vec::new(),
+            #[cfg(feature = "test-hooks")]
+            fail_after: None,
         })
     }
 
     pub fn store(&mut self, db: &mut WordFileBlocks) -> Result<(), IndexError> {
-        let block = if self.bag_nr != 0 {
-            db.get_mut(self.bag_nr)?
-        } else {
-            let block = db.alloc(Self::TY_BAGS)?;
-            self.bag_nr = block.block_nr();
-            block
-        };
-        block.set_dirty(true);
-        let bags = unsafe { block.cast_mut::<RawBags>() };
+        if self.bag_nr == 0 {
+            self.bag_nr = db.alloc(Self::TY_BAGS)?.0;
+        }
+        let bags = &mut db.get_as_mut::<RawBags>(self.bag_nr)?[0];
 
         bags.head_nr = self.last_head_nr;
         bags.head_idx = self.last_head_idx;
@@ -117,7 +156,7 @@ impl WordMap {
     ) -> Result<(LogicalNr, BlkIdx), IndexError> {
         #[allow(clippy::collapsible_else_if)]
         let v = if self.last_head_nr[bag] == 0 {
-            let new_block_nr = db.alloc(Self::TY_LISTHEAD)?.block_nr();
+            let new_block_nr = self.head_pool.alloc(db, Self::TY_LISTHEAD)?;
 
             self.last_head_nr[bag] = new_block_nr;
             self.last_head_idx[bag] = BlkIdx(0);
@@ -126,7 +165,7 @@ impl WordMap {
         } else {
             if self.last_head_idx[bag] + 1 >= Block::len_array::<RawWordMap>(db.block_size()) as u32
             {
-                let new_block_nr = db.alloc(Self::TY_LISTHEAD)?.block_nr();
+                let new_block_nr = self.head_pool.alloc(db, Self::TY_LISTHEAD)?;
 
                 self.last_head_nr[bag] = new_block_nr;
                 self.last_head_idx[bag] = BlkIdx(0);
@@ -153,7 +192,7 @@ impl WordMap {
     ) -> Result<(LogicalNr, BlkIdx), IndexError> {
         #[allow(clippy::collapsible_else_if)]
         let v = if self.last_tail_nr[bag] == 0 {
-            let new_block_nr = db.alloc(Self::TY_LISTTAIL)?.block_nr();
+            let new_block_nr = self.tail_pool.alloc(db, Self::TY_LISTTAIL)?;
 
             self.last_tail_nr[bag] = new_block_nr;
             self.last_tail_idx[bag] = BlkIdx(0);
@@ -162,7 +201,7 @@ impl WordMap {
         } else {
             if self.last_tail_idx[bag] + 1 >= Block::len_array::<RawWordMap>(db.block_size()) as u32
             {
-                let new_block_nr = db.alloc(Self::TY_LISTTAIL)?.block_nr();
+                let new_block_nr = self.tail_pool.alloc(db, Self::TY_LISTTAIL)?;
 
                 self.last_tail_nr[bag] = new_block_nr;
                 self.last_tail_idx[bag] = BlkIdx(0);
@@ -176,6 +215,39 @@ impl WordMap {
         Ok(v)
     }
 
+    /// Makes the n-th next add fail, 1 is the next one.
+    #[cfg(feature = "test-hooks")]
+    pub fn fail_after(&mut self, n: Option<usize>) {
+        self.fail_after = n;
+    }
+
+    #[cfg(feature = "test-hooks")]
+    fn injected_failure(&mut self) -> Result<(), IndexError> {
+        match self.fail_after {
+            Some(n) if n <= 1 => {
+                self.fail_after = None;
+                Err(IndexError::err(IndexKind::IOError(std::io::Error::other(
+                    "injected failure",
+                ))))
+            }
+            Some(n) => {
+                self.fail_after = Some(n - 1);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// A head entry from add_initial() that no word points to.
+    /// It's cleared, so it doesn't look like a lost chain, and kept
+    /// in orphans.
+    pub fn orphan(&mut self, db: &mut WordFileBlocks, blk_nr: LogicalNr, blk_idx: BlkIdx) {
+        if let Ok(word_map_list) = db.get_as_mut::<RawWordMap>(blk_nr) {
+            word_map_list[blk_idx.as_usize()] = RawWordMap::default();
+        }
+        self.orphans.push((blk_nr, blk_idx));
+    }
+
     /// Add first reference for a new word.
     pub fn add_initial(
         &mut self,
@@ -184,12 +256,12 @@ impl WordMap {
         _word: &str,
         file_id: FileId,
     ) -> Result<(LogicalNr, BlkIdx), IndexError> {
-        let (new_blk_nr, new_idx) = self.ensure_add_head(db, bag)?;
+        #[cfg(feature = "test-hooks")]
+        self.injected_failure()?;
 
-        let block = db.get_mut(new_blk_nr)?;
-        block.set_dirty(true);
+        let (new_blk_nr, new_idx) = self.ensure_add_head(db, bag)?;
 
-        let word_map_list = unsafe { block.cast_array_mut::<RawWordMap>() };
+        let word_map_list = db.get_as_mut::<RawWordMap>(new_blk_nr)?;
         let word_map = &mut word_map_list[new_idx.as_usize()];
 
         word_map.file_id[0] = file_id;
@@ -200,6 +272,13 @@ impl WordMap {
     }
 
     /// Add one more file reference for a word.
+    ///
+    /// The newest references are in the head entry. A file that is
+    /// there already is not added again, as when a failed merge is
+    /// repeated. Returns false then.
+    ///
+    /// A full entry is copied to the tail before the head is changed,
+    /// so the chain stays intact if any of the writes fails.
     pub fn add(
         &mut self,
         db: &mut WordFileBlocks,
@@ -208,44 +287,39 @@ impl WordMap {
         blk_nr: LogicalNr,
         blk_idx: BlkIdx,
         file_id: FileId,
-    ) -> Result<(), IndexError> {
+    ) -> Result<bool, IndexError> {
+        #[cfg(feature = "test-hooks")]
+        self.injected_failure()?;
+
         // append to given region list.
         {
             let (retire_block_nr, retire_idx) = self.ensure_add_tail(db, bag)?;
 
-            let block = db.get_mut(blk_nr)?;
-            block.set_dirty(true);
-            let word_map_list = unsafe { block.cast_array_mut::<RawWordMap>() };
-            let word_map = &mut word_map_list[blk_idx.as_usize()];
+            let word_map = db.get_as::<RawWordMap>(blk_nr)?[blk_idx.as_usize()];
 
+            if word_map.file_id.contains(&file_id) {
+                return Ok(false);
+            }
             if let Some(insert_pos) = word_map.file_id.iter().position(|v| *v == 0) {
-                word_map.file_id[insert_pos] = file_id;
+                let word_map_list = db.get_as_mut::<RawWordMap>(blk_nr)?;
+                word_map_list[blk_idx.as_usize()].file_id[insert_pos] = file_id;
             } else {
-                // move out of current
-                let retire_file_id = word_map.file_id;
-                let retire_next_block_nr = word_map.next_block_nr;
-                let retire_next_idx = word_map.next_idx;
+                // retire first, nothing points there yet.
+                let retire_map_list = db.get_as_mut::<RawWordMap>(retire_block_nr)?;
+                retire_map_list[retire_idx.as_usize()] = word_map;
 
                 // re-init and write
+                let word_map_list = db.get_as_mut::<RawWordMap>(blk_nr)?;
+                let word_map = &mut word_map_list[blk_idx.as_usize()];
                 word_map.file_id = [FileId(0u32); FILE_ID_LEN];
                 word_map.next_block_nr = retire_block_nr;
                 word_map.next_idx = retire_idx;
                 word_map.file_id[0] = file_id;
 
-                // retire
-                let retire_block = db.get_mut(self.last_tail_nr[bag])?;
-                retire_block.set_dirty(true);
-                let retire_map_list = unsafe { retire_block.cast_array_mut::<RawWordMap>() };
-                let retire_map = &mut retire_map_list[retire_idx.as_usize()];
-
-                retire_map.file_id = retire_file_id;
-                retire_map.next_block_nr = retire_next_block_nr;
-                retire_map.next_idx = retire_next_idx;
-
                 self.confirm_add_tail(bag, retire_block_nr, retire_idx);
             }
         }
-        Ok(())
+        Ok(true)
     }
 
     pub fn iter_files(
@@ -258,6 +332,7 @@ impl WordMap {
             map_block_nr: block_nr,
             map_idx: block_idx,
             file_idx: FIdx(0),
+            entry: (LogicalNr(0), BlkIdx(0)),
         }
     }
 }
@@ -267,9 +342,16 @@ pub struct IterFileId<'a> {
     map_block_nr: LogicalNr,
     map_idx: BlkIdx,
     file_idx: FIdx,
+    /// Entry of the last file-id.
+    entry: (LogicalNr, BlkIdx),
 }
 
 impl<'a> IterFileId<'a> {
+    /// The chain entry of the last file-id.
+    pub fn entry(&self) -> (LogicalNr, BlkIdx) {
+        self.entry
+    }
+
     fn is_clear(&self) -> bool {
         self.map_block_nr == 0
     }
@@ -291,15 +373,20 @@ impl<'a> Iterator for IterFileId<'a> {
 
         let mut to_discard = LogicalNr(0);
         let file_id = 'it: loop {
-            let map_list = match self.db.get(self.map_block_nr) {
-                Ok(block) => unsafe { block.cast_array::<RawWordMap>() },
-                Err(err) => return Some(Err(err.into())),
+            let map_list = match self.db.get_as::<RawWordMap>(self.map_block_nr) {
+                Ok(map_list) => map_list,
+                Err(err) => return Some(Err(err)),
+            };
+            let Some(map) = map_list.get(self.map_idx.as_usize()) else {
+                let err = IndexError::err(IndexKind::BrokenChain(self.map_block_nr, self.map_idx));
+                self.clear();
+                return Some(Err(err));
             };
-            let map = &map_list[self.map_idx.as_usize()];
             let file_id = map.file_id[self.file_idx.as_usize()];
 
             #[allow(clippy::collapsible_else_if)]
             if file_id != 0 {
+                self.entry = (self.map_block_nr, self.map_idx);
                 // next
                 self.file_idx += 1;
                 if self.file_idx >= map.file_id.len() as u32 {
@@ -324,13 +411,17 @@ impl<'a> Iterator for IterFileId<'a> {
             }
 
             if to_discard != 0 {
-                self.db.discard(to_discard);
+                if let Err(err) = self.db.discard(to_discard) {
+                    return Some(Err(err));
+                }
                 to_discard = LogicalNr(0);
             }
         };
 
         if to_discard != 0 {
-            self.db.discard(to_discard);
+            if let Err(err) = self.db.discard(to_discard) {
+                return Some(Err(err));
+            }
         }
 
         file_id.map(Ok)
@@ -1,18 +1,94 @@
+use crate::index2::block_pool::BlockPool;
+use crate::index2::segments::{block_as, block_as_mut};
 use crate::index2::{
-    byte_to_str, copy_fix, BlkIdx, IndexError, WordBlockType, WordFileBlocks, WordId,
+    byte_to_str, copy_fix, BlkIdx, FileId, IndexError, IndexKind, WordBlockType, WordFileBlocks,
+    WordId,
 };
-use blockfile2::{Block, LogicalNr, UserBlock};
-use std::collections::BTreeMap;
-use std::fmt::{Debug, Formatter};
-use std::marker::PhantomData;
+use blockfile2::{Block, BlockRead, BlockWrite, LogicalNr, UserBlockType};
+use std::cmp::max;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt::{Debug, Display, Formatter};
+use std::io::{Read, Write};
+use std::ops::Bound::{Excluded, Included, Unbounded};
 use std::str::from_utf8;
 
+/// Above this many words the word-list is loaded lazily.
+pub const LAZY_WORDS: usize = 500_000;
+
+/// Word blocks kept in memory in lazy mode.
+pub const LAZY_BLOCKS: usize = 256;
+
+/// Bits of the bloom filter of a word block.
+const BLOOM_BITS: usize = 1024;
+
 #[derive(Debug)]
 pub struct WordList {
     last_block_nr: LogicalNr,
     last_block_idx: BlkIdx,
     last_word_id: WordId,
+    /// All words. In lazy mode only the words of the loaded blocks
+    /// and the words that are not stored yet.
     list: BTreeMap<String, WordData>,
+    lazy: Option<LazyDir>,
+    /// Words that are not valid UTF-8 are skipped instead of failing.
+    recover: bool,
+    /// Pre-allocated blocks.
+    pool: BlockPool,
+    /// Nothing is read until the first lookup builds the directory.
+    deferred: bool,
+}
+
+/// A stored word that is not valid UTF-8.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BadWord {
+    pub block_nr: LogicalNr,
+    pub block_idx: BlkIdx,
+    pub bytes: Vec<u8>,
+}
+
+impl Display for BadWord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "word {}:{} is not UTF-8 {:?}",
+            self.block_nr, self.block_idx, self.bytes
+        )
+    }
+}
+
+/// Directory of the stored word blocks for the lazy mode.
+#[derive(Debug, Default)]
+struct LazyDir {
+    /// By block-nr.
+    blocks: BTreeMap<u32, BlockDir>,
+    /// The block of each stored word, by the hash of the word.
+    /// A word that is not stored costs no block read.
+    by_hash: HashMap<u64, u32>,
+    /// More blocks for a hash shared by different words.
+    collisions: HashMap<u64, Vec<u32>>,
+    /// Blocks from the stored directory. Their words are not in
+    /// by_hash, the bloom filter of the block stands in.
+    filtered: Vec<u32>,
+    /// Words in the blocks.
+    stored: usize,
+    /// Words not stored yet.
+    new_words: usize,
+    /// Loaded blocks with their words, the most recent last.
+    lru: VecDeque<(LogicalNr, Vec<String>)>,
+}
+
+/// Summary of one word block.
+///
+/// The blocks are filled in the order the words are stored,
+/// so the ranges of the blocks overlap.
+#[derive(Debug)]
+struct BlockDir {
+    first: String,
+    last: String,
+    len: usize,
+    /// Bloom filter of the words.
+    bloom: [u64; BLOOM_BITS / 64],
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -23,8 +99,30 @@ pub struct WordData {
     pub block_idx: BlkIdx,
     pub file_map_block_nr: LogicalNr,
     pub file_map_idx: BlkIdx,
+    /// The file that introduced the word. 0 for words from
+    /// an index before version 2.
+    pub first_file_id: FileId,
+    /// Files that refer to the word. Stored with the word, an index
+    /// from before only counts the files added since.
+    pub files: u32,
 }
 
+impl WordData {
+    fn stored(block_nr: LogicalNr, block_idx: BlkIdx, raw: &RawWord) -> Self {
+        Self {
+            id: raw.id,
+            count: 0,
+            block_nr,
+            block_idx,
+            file_map_block_nr: raw.file_map_block_nr,
+            file_map_idx: raw.file_map_idx,
+            first_file_id: raw.first_file_id,
+            files: raw.files,
+        }
+    }
+}
+
+/// Word-list entry since index version 2.
 #[derive(Clone, Copy, PartialEq)]
 #[repr(C)]
 pub struct RawWord {
@@ -32,6 +130,22 @@ pub struct RawWord {
     pub id: WordId,
     pub file_map_block_nr: LogicalNr,
     pub file_map_idx: BlkIdx,
+    pub first_file_id: FileId,
+    /// Files that refer to the word, 0 before it was counted.
+    pub files: u32,
+    /// Keeps the size a divisor of the block size.
+    pub reserved: [u32; 6],
+}
+
+/// Word-list entry of index version 1. These blocks are still read,
+/// but new words are always stored as RawWord.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct RawWordV1 {
+    pub word: [u8; 20],
+    pub id: WordId,
+    pub file_map_block_nr: LogicalNr,
+    pub file_map_idx: BlkIdx,
 }
 
 impl Debug for RawWord {
@@ -39,8 +153,8 @@ impl Debug for RawWord {
         let w = from_utf8(&self.word).unwrap_or("");
         write!(
             f,
-            "{} {} -> {} {}",
-            w, self.id, self.file_map_block_nr, self.file_map_idx
+            "{} {} -> {} {} first {} files {}",
+            w, self.id, self.file_map_block_nr, self.file_map_idx, self.first_file_id, self.files
         )
     }
 }
@@ -52,61 +166,87 @@ impl Default for RawWord {
             id: WordId(0),
             file_map_block_nr: LogicalNr(0),
             file_map_idx: BlkIdx(0),
+            first_file_id: FileId(0),
+            files: 0,
+            reserved: [0; 6],
+        }
+    }
+}
+
+impl From<RawWordV1> for RawWord {
+    fn from(value: RawWordV1) -> Self {
+        Self {
+            word: value.word,
+            id: value.id,
+            file_map_block_nr: value.file_map_block_nr,
+            file_map_idx: value.file_map_idx,
+            first_file_id: FileId(0),
+            files: 0,
+            reserved: [0; 6],
         }
     }
 }
 
 impl WordList {
-    pub const TY: WordBlockType = WordBlockType::WordList;
+    pub const TY: WordBlockType = WordBlockType::WordList2;
+    /// Blocks of index version 1.
+    pub const TY_V1: WordBlockType = WordBlockType::WordList;
+    /// Directory of the blocks, for the read-only index.
+    pub const TY_DIR: WordBlockType = WordBlockType::WordDir;
 
-    pub(crate) fn load(db: &mut WordFileBlocks) -> Result<WordList, IndexError> {
+    /// Loads the word-list. With more than lazy_words words only a
+    /// directory of the blocks is kept, 0 is always lazy.
+    ///
+    /// A word that is not valid UTF-8 fails with IndexKind::BadWord,
+    /// with recover it is skipped.
+    pub(crate) fn load(
+        db: &mut WordFileBlocks,
+        lazy_words: usize,
+        recover: bool,
+    ) -> Result<WordList, IndexError> {
         let mut list = BTreeMap::new();
+        let mut lazy = LazyDir::default();
+        let mut is_lazy = lazy_words == 0;
 
         let mut last_block_nr = LogicalNr(0u32);
         let mut last_block_idx = BlkIdx(0u32);
         let mut last_word_id = WordId(0u32);
 
         let blocks: Vec<_> = db
-            .iter_metadata_filter(|_nr, ty| ty == Self::TY)
-            .map(|v| v.0)
+            .iter_metadata_filter(|_nr, ty| ty == Self::TY || ty == Self::TY_V1)
             .collect();
-        let empty = RawWord::default();
-        for block_nr in blocks {
-            let block = db.get(block_nr)?;
-            let raw = unsafe { block.cast_array::<RawWord>() };
-            for (i, r) in raw.iter().enumerate() {
-                if r.word != empty.word {
-                    let word = byte_to_str(&r.word)
-                        .or_else(|v| {
-                            eprintln!("{:2?}", UserBlock::<WordBlockType>(block, PhantomData));
-                            Err(v)
-                        })?
-                        .to_string();
-
-                    // remember
-                    last_word_id = r.id;
+        for (block_nr, block_type) in blocks {
+            let (raw, _) = Self::read_raw(db, block_nr)?;
+            for (block_idx, r) in raw {
+                // remember, a skipped word keeps its id too.
+                last_word_id = max(last_word_id, r.id);
+                let Some(word) = Self::entry_word(block_nr, block_idx, &r, recover)? else {
+                    continue;
+                };
+                // only append to the current layout.
+                if block_type == Self::TY {
                     last_block_nr = block_nr;
-                    last_block_idx = BlkIdx(i as u32 + 1);
-
-                    list.insert(
-                        word,
-                        WordData {
-                            id: r.id,
-                            count: 0,
-                            block_nr,
-                            block_idx: BlkIdx(i as u32),
-                            file_map_block_nr: r.file_map_block_nr,
-                            file_map_idx: r.file_map_idx,
-                        },
-                    );
+                    last_block_idx = block_idx + 1;
+                }
+
+                lazy.add(block_nr, &word);
+                if !is_lazy {
+                    list.insert(word, WordData::stored(block_nr, block_idx, &r));
+                    if list.len() > lazy_words {
+                        is_lazy = true;
+                        list.clear();
+                    }
                 }
             }
+            if is_lazy {
+                db.discard(block_nr)?;
+            }
         }
 
         // Check overflow
         if last_block_nr > 0 {
             if last_block_idx >= Block::len_array::<RawWord>(db.block_size()) as u32 {
-                last_block_nr = db.alloc(Self::TY)?.block_nr();
+                last_block_nr = db.alloc(Self::TY)?.0;
                 last_block_idx = BlkIdx(0);
             }
         }
@@ -116,88 +256,609 @@ impl WordList {
             last_block_idx,
             last_word_id,
             list,
+            lazy: if is_lazy { Some(lazy) } else { None },
+            recover,
+            pool: BlockPool::default(),
+            deferred: false,
+        })
+    }
+
+    /// A lazy word-list that reads no block until the first lookup,
+    /// which builds the directory. Until then len() is 0.
+    pub(crate) fn deferred(recover: bool) -> WordList {
+        Self {
+            last_block_nr: LogicalNr(0),
+            last_block_idx: BlkIdx(0),
+            last_word_id: WordId(0),
+            list: BTreeMap::new(),
+            lazy: Some(LazyDir::default()),
+            recover,
+            pool: BlockPool::default(),
+            deferred: true,
+        }
+    }
+
+    /// Builds the directory of a deferred word-list.
+    fn build_dir(&mut self, db: &mut WordFileBlocks) -> Result<(), IndexError> {
+        if self.deferred {
+            *self = Self::load_dir(db, self.recover)?;
+        }
+        Ok(())
+    }
+
+    /// A lazy word-list from the stored directory of the blocks. Only
+    /// the blocks missing there are read, the ones of version 1 and
+    /// the ones stored before there was a directory.
+    ///
+    /// It knows no last word-id, nothing can be inserted.
+    fn load_dir(db: &mut WordFileBlocks, recover: bool) -> Result<WordList, IndexError> {
+        let mut stored = Self::read_dir(db)?;
+        let mut lazy = LazyDir::default();
+
+        let blocks: Vec<_> = db
+            .iter_metadata_filter(|_nr, ty| ty == Self::TY || ty == Self::TY_V1)
+            .map(|(nr, _)| nr)
+            .collect();
+        for block_nr in blocks {
+            if let Some(dir) = stored.remove(&block_nr.0) {
+                lazy.add_dir(block_nr, dir);
+                continue;
+            }
+            let (raw, dirty) = Self::read_block(db, block_nr, recover)?;
+            if !dirty {
+                db.discard(block_nr)?;
+            }
+            for (_, word, _) in raw {
+                lazy.add(block_nr, &word);
+            }
+        }
+
+        Ok(Self {
+            last_block_nr: LogicalNr(0),
+            last_block_idx: BlkIdx(0),
+            last_word_id: WordId(0),
+            list: BTreeMap::new(),
+            lazy: Some(lazy),
+            recover,
+            pool: BlockPool::default(),
+            deferred: false,
         })
     }
 
+    /// The stored directory by block-nr. A later record for a block
+    /// replaces the earlier ones.
+    fn read_dir(db: &mut WordFileBlocks) -> Result<HashMap<u32, BlockDir>, IndexError> {
+        let mut dir = HashMap::new();
+        let mut r = db.read_stream(Self::TY_DIR)?;
+        loop {
+            let mut buf_block_nr = [0u8; 4];
+            if !r.read_maybe(&mut buf_block_nr)? {
+                break;
+            }
+            let block_nr = u32::from_ne_bytes(buf_block_nr);
+
+            let mut buf_len = [0u8; 4];
+            r.read_exact(&mut buf_len)?;
+            let len = u32::from_ne_bytes(buf_len) as usize;
+
+            let mut bloom = [0u64; BLOOM_BITS / 64];
+            for v in bloom.iter_mut() {
+                let mut buf_bits = [0u8; 8];
+                r.read_exact(&mut buf_bits)?;
+                *v = u64::from_ne_bytes(buf_bits);
+            }
+
+            let first = read_word(&mut r)?;
+            let last = read_word(&mut r)?;
+
+            dir.insert(
+                block_nr,
+                BlockDir {
+                    first,
+                    last,
+                    len,
+                    bloom,
+                },
+            );
+        }
+        Ok(dir)
+    }
+
+    /// Appends a directory record for each of the blocks.
+    fn store_dir(db: &mut WordFileBlocks, blocks: &BTreeSet<u32>) -> Result<(), IndexError> {
+        let mut records = Vec::new();
+        for block_nr in blocks.iter().copied() {
+            let (raw, _) = Self::read_block(db, LogicalNr(block_nr), true)?;
+            let mut words = raw.into_iter().map(|(_, word, _)| word);
+            let Some(first) = words.next() else {
+                continue;
+            };
+            let mut dir = BlockDir::new(&first);
+            for word in words {
+                dir.add(&word);
+            }
+            records.push((block_nr, dir));
+        }
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let mut buf: Vec<u8> = Vec::new();
+        let mut w = db.append_stream(Self::TY_DIR)?;
+        for (block_nr, dir) in records {
+            buf.clear();
+            buf.extend(block_nr.to_ne_bytes());
+            buf.extend((dir.len as u32).to_ne_bytes());
+            for v in dir.bloom {
+                buf.extend(v.to_ne_bytes());
+            }
+            write_word(&mut buf, &dir.first);
+            write_word(&mut buf, &dir.last);
+            w.write_all(buf.as_slice())?;
+        }
+        Ok(())
+    }
+
+    /// The used entries of one block with their index.
+    /// Returns true as well if the block is dirty.
+    fn read_raw(
+        db: &mut WordFileBlocks,
+        block_nr: LogicalNr,
+    ) -> Result<(Vec<(BlkIdx, RawWord)>, bool), IndexError> {
+        let block = db.get(block_nr)?;
+        let raw: Vec<RawWord> = if WordBlockType::user_type(block.block_type()) == Some(Self::TY_V1)
+        {
+            let raw = block_as::<RawWordV1>(block, block_nr)?;
+            raw.iter().map(|v| RawWord::from(*v)).collect()
+        } else {
+            block_as::<RawWord>(block, block_nr)?.to_vec()
+        };
+
+        let empty = RawWord::default();
+        let used = raw
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.word != empty.word)
+            .map(|(i, r)| (BlkIdx(i as u32), *r))
+            .collect();
+
+        Ok((used, block.is_dirty()))
+    }
+
+    /// The words of one block with their index.
+    /// Returns true as well if the block is dirty.
+    ///
+    /// A word that is not valid UTF-8 fails with its location,
+    /// with recover it is skipped.
+    fn read_block(
+        db: &mut WordFileBlocks,
+        block_nr: LogicalNr,
+        recover: bool,
+    ) -> Result<(Vec<(BlkIdx, String, RawWord)>, bool), IndexError> {
+        let (raw, dirty) = Self::read_raw(db, block_nr)?;
+
+        let mut words = Vec::new();
+        for (block_idx, r) in raw {
+            if let Some(word) = Self::entry_word(block_nr, block_idx, &r, recover)? {
+                words.push((block_idx, word, r));
+            }
+        }
+
+        Ok((words, dirty))
+    }
+
+    /// The word of one entry. A word that is not valid UTF-8 fails
+    /// with its location, with recover it is None.
+    fn entry_word(
+        block_nr: LogicalNr,
+        block_idx: BlkIdx,
+        r: &RawWord,
+        recover: bool,
+    ) -> Result<Option<String>, IndexError> {
+        match byte_to_str(&r.word) {
+            Ok(word) => Ok(Some(word.to_string())),
+            Err(_) if recover => Ok(None),
+            Err(_) => Err(IndexError::err(IndexKind::BadWord(BadWord {
+                block_nr,
+                block_idx,
+                bytes: r.word.to_vec(),
+            }))),
+        }
+    }
+
+    /// All stored words that are not valid UTF-8.
+    pub(crate) fn bad_words(db: &mut WordFileBlocks) -> Result<Vec<BadWord>, IndexError> {
+        let blocks: Vec<_> = db
+            .iter_metadata_filter(|_nr, ty| ty == Self::TY || ty == Self::TY_V1)
+            .map(|(nr, _)| nr)
+            .collect();
+        Self::bad_words_in(db, &blocks)
+    }
+
+    /// The words of these word blocks that are not valid UTF-8.
+    pub(crate) fn bad_words_in(
+        db: &mut WordFileBlocks,
+        blocks: &[LogicalNr],
+    ) -> Result<Vec<BadWord>, IndexError> {
+        let mut bad = Vec::new();
+        for block_nr in blocks.iter().copied() {
+            let (raw, dirty) = Self::read_raw(db, block_nr)?;
+            if !dirty {
+                db.discard(block_nr)?;
+            }
+            for (block_idx, r) in raw {
+                if byte_to_str(&r.word).is_err() {
+                    bad.push(BadWord {
+                        block_nr,
+                        block_idx,
+                        bytes: r.word.to_vec(),
+                    });
+                }
+            }
+        }
+        Ok(bad)
+    }
+
+    /// Allocates blocks ahead until the pool holds n.
+    pub(crate) fn fill_pool(
+        &mut self,
+        db: &mut WordFileBlocks,
+        n: usize,
+    ) -> Result<usize, IndexError> {
+        self.pool.fill(db, Self::TY, n)
+    }
+
+    pub(crate) fn pool_len(&self) -> usize {
+        self.pool.len()
+    }
+
+    /// Forgets the unused pre-allocated blocks.
+    pub(crate) fn release_pool(&mut self) -> usize {
+        self.pool.release()
+    }
+
     pub(crate) fn store(&mut self, db: &mut WordFileBlocks) -> Result<(), IndexError> {
         // assume append only
-        for (word, word_data) in self.list.iter_mut() {
+        let new_words: Vec<String> = self
+            .list
+            .iter()
+            .filter(|(_, v)| v.block_nr == 0)
+            .map(|(k, _)| k.clone())
+            .collect();
+
+        // the directory gets a new record for each written block.
+        let mut written = BTreeSet::new();
+        for word in new_words {
+            if self.last_block_nr == 0 {
+                self.last_block_nr = self.pool.alloc(db, Self::TY)?;
+                self.last_block_idx = BlkIdx(0);
+            }
+
+            let word_data = self.list.get_mut(&word).expect("word");
             let w = RawWord {
                 word: copy_fix::<20>(word.as_bytes()),
                 id: word_data.id,
                 file_map_block_nr: word_data.file_map_block_nr,
                 file_map_idx: word_data.file_map_idx,
+                first_file_id: word_data.first_file_id,
+                files: word_data.files,
+                reserved: [0; 6],
             };
 
-            if word_data.block_nr != 0 {
-                // no updates necessary
+            let word_list = db.get_as_mut::<RawWord>(self.last_block_nr)?;
+            word_list[self.last_block_idx.as_usize()] = w;
+            word_data.block_nr = self.last_block_nr;
+            word_data.block_idx = self.last_block_idx;
+            written.insert(self.last_block_nr.0);
 
-                // let block = db.get_mut(word_data.block_nr)?;
-                // let word_list = block.cast_array_mut::<RawWord>();
-                //
-                // if word_list[word_data.block_idx.as_usize()] != w {
-                //     word_list[word_data.block_idx.as_usize()] = w;
-                //     block.set_dirty(true);
-                // }
+            if self.lazy.is_some() {
+                self.stored_lazy(db, &word)?;
+            }
+
+            if self.last_block_idx + 1 == Block::len_array::<RawWord>(db.block_size()) as u32 {
+                self.last_block_nr = self.pool.alloc(db, Self::TY)?;
+                self.last_block_idx = BlkIdx(0);
             } else {
-                if self.last_block_nr == 0 {
-                    self.last_block_nr = db.alloc(Self::TY)?.block_nr();
-                    self.last_block_idx = BlkIdx(0);
-                }
+                self.last_block_idx += 1;
+            }
+        }
 
-                let block = db.get_mut(self.last_block_nr)?;
-                block.set_dirty(true);
+        Self::store_dir(db, &written)
+    }
 
-                let word_list = unsafe { block.cast_array_mut::<RawWord>() };
-                word_list[self.last_block_idx.as_usize()] = w;
-                word_data.block_nr = self.last_block_nr;
-                word_data.block_idx = self.last_block_idx;
+    /// The word was just written to the last block. From now on it
+    /// belongs to that block and goes when the block is unloaded.
+    fn stored_lazy(&mut self, db: &mut WordFileBlocks, word: &str) -> Result<(), IndexError> {
+        let block_nr = self.last_block_nr;
+        let Some(lazy) = &mut self.lazy else {
+            return Ok(());
+        };
+        lazy.add(block_nr, word);
+        lazy.new_words -= 1;
 
-                if self.last_block_idx + 1 == Block::len_array::<RawWord>(db.block_size()) as u32 {
-                    self.last_block_nr = db.alloc(Self::TY)?.block_nr();
-                    self.last_block_idx = BlkIdx(0);
-                } else {
-                    self.last_block_idx += 1;
+        if let Some((_, words)) = lazy.lru.iter_mut().find(|(nr, _)| nr.0 == block_nr.0) {
+            words.push(word.to_string());
+            return Ok(());
+        }
+        // the block must be loaded completely, the older words too.
+        self.load_block(db, block_nr)
+    }
+
+    /// Loads the words of the block into the list. The least recently
+    /// used block is unloaded, the counts of its words are lost.
+    fn load_block(
+        &mut self,
+        db: &mut WordFileBlocks,
+        block_nr: LogicalNr,
+    ) -> Result<(), IndexError> {
+        let (raw, dirty) = Self::read_block(db, block_nr, self.recover)?;
+        if !dirty {
+            db.discard(block_nr)?;
+        }
+
+        let mut words = Vec::with_capacity(raw.len());
+        for (block_idx, word, r) in raw {
+            self.list
+                .entry(word.clone())
+                .or_insert_with(|| WordData::stored(block_nr, block_idx, &r));
+            words.push(word);
+        }
+
+        let Some(lazy) = &mut self.lazy else {
+            return Ok(());
+        };
+        lazy.lru.push_back((block_nr, words));
+        while lazy.lru.len() > LAZY_BLOCKS {
+            if let Some((nr, words)) = lazy.lru.pop_front() {
+                for word in words {
+                    if matches!(self.list.get(&word), Some(v) if v.block_nr.0 == nr.0) {
+                        self.list.remove(&word);
+                    }
                 }
             }
         }
+        Ok(())
+    }
 
+    /// Loads the block of the word, if it is stored. Only words with
+    /// the same hash may cause more reads.
+    fn load_word(&mut self, db: &mut WordFileBlocks, word: &str) -> Result<(), IndexError> {
+        self.build_dir(db)?;
+        let Some(lazy) = &self.lazy else {
+            return Ok(());
+        };
+        if self.list.contains_key(word) {
+            return Ok(());
+        }
+
+        let candidates: Vec<_> = lazy
+            .blocks_for(word)
+            .filter(|nr| !lazy.lru.iter().any(|(v, _)| v.0 == nr.0))
+            .collect();
+        for block_nr in candidates {
+            self.load_block(db, block_nr)?;
+            if self.list.contains_key(word) {
+                break;
+            }
+        }
         Ok(())
     }
 
-    /// Iterate words.
-    pub fn iter_words(&mut self) -> impl Iterator<Item = (&String, &WordData)> {
-        self.list.iter()
+    /// All words starting with the prefix, sorted. In lazy mode the
+    /// blocks are read but not kept.
+    pub(crate) fn prefix(
+        &mut self,
+        db: &mut WordFileBlocks,
+        prefix: &str,
+    ) -> Result<Vec<(String, WordData)>, IndexError> {
+        self.prefix_matching(db, prefix, |_| true, None)
+    }
+
+    /// The words starting with the prefix that match, sorted.
+    ///
+    /// With a limit it stops when it has that many. In lazy mode
+    /// these are not the first ones, only the blocks up to there
+    /// are read.
+    pub(crate) fn prefix_matching(
+        &mut self,
+        db: &mut WordFileBlocks,
+        prefix: &str,
+        matches: impl Fn(&str) -> bool,
+        limit: Option<usize>,
+    ) -> Result<Vec<(String, WordData)>, IndexError> {
+        self.build_dir(db)?;
+        let limit = limit.unwrap_or(usize::MAX);
+        let mut found: BTreeMap<String, WordData> = self
+            .list
+            .range::<str, _>((Included(prefix), Unbounded))
+            .take_while(|(k, _)| k.starts_with(prefix))
+            .filter(|(k, _)| matches(k))
+            .take(limit)
+            .map(|(k, v)| (k.clone(), *v))
+            .collect();
+
+        if let Some(lazy) = &self.lazy {
+            let candidates: Vec<_> = lazy
+                .blocks
+                .iter()
+                .filter(|(_, v)| v.overlaps(prefix))
+                .map(|(nr, _)| LogicalNr(*nr))
+                .collect();
+            for block_nr in candidates {
+                if found.len() >= limit {
+                    break;
+                }
+                let (raw, dirty) = Self::read_block(db, block_nr, self.recover)?;
+                if !dirty {
+                    db.discard(block_nr)?;
+                }
+                for (block_idx, word, r) in raw {
+                    if found.len() >= limit {
+                        break;
+                    }
+                    if word.starts_with(prefix) && matches(&word) {
+                        // the loaded ones have the current count.
+                        found
+                            .entry(word)
+                            .or_insert_with(|| WordData::stored(block_nr, block_idx, &r));
+                    }
+                }
+            }
+        }
+
+        Ok(found.into_iter().collect())
     }
 
+    /// The next n words for a walk over all words.
+    ///
+    /// Sorted, continuing after the given word or else at the pos-th
+    /// word. In lazy mode the words come in the order they are stored,
+    /// starting at the pos-th, and the words not stored yet come last.
+    pub(crate) fn chunk(
+        &mut self,
+        db: &mut WordFileBlocks,
+        pos: usize,
+        after: Option<&str>,
+        n: usize,
+    ) -> Result<Vec<(String, WordData)>, IndexError> {
+        self.build_dir(db)?;
+        let Some(lazy) = &self.lazy else {
+            let words: Vec<_> = match after {
+                Some(word) => self
+                    .list
+                    .range::<str, _>((Excluded(word), Unbounded))
+                    .take(n)
+                    .map(|(k, v)| (k.clone(), *v))
+                    .collect(),
+                None => self
+                    .list
+                    .iter()
+                    .skip(pos)
+                    .take(n)
+                    .map(|(k, v)| (k.clone(), *v))
+                    .collect(),
+            };
+            return Ok(words);
+        };
+
+        let blocks: Vec<_> = lazy
+            .blocks
+            .iter()
+            .map(|(nr, v)| (LogicalNr(*nr), v.len))
+            .collect();
+        let mut words = Vec::new();
+        let mut skip = pos;
+        for (block_nr, len) in blocks {
+            if words.len() >= n {
+                break;
+            }
+            if skip >= len {
+                skip -= len;
+                continue;
+            }
+            let (raw, dirty) = Self::read_block(db, block_nr, self.recover)?;
+            if !dirty {
+                db.discard(block_nr)?;
+            }
+            for (block_idx, word, r) in raw.into_iter().skip(skip).take(n - words.len()) {
+                words.push((word, WordData::stored(block_nr, block_idx, &r)));
+            }
+            skip = 0;
+        }
+        if words.len() < n {
+            let rest = n - words.len();
+            words.extend(
+                self.list
+                    .iter()
+                    .filter(|(_, v)| v.block_nr == 0)
+                    .skip(skip)
+                    .take(rest)
+                    .map(|(k, v)| (k.clone(), *v)),
+            );
+        }
+        Ok(words)
+    }
+
+    /// Only a directory of the blocks is kept in memory.
+    pub fn is_lazy(&self) -> bool {
+        self.lazy.is_some()
+    }
+
+    /// Number of words. 0 for a deferred list that had no lookup yet.
     pub fn len(&self) -> usize {
-        self.list.len()
+        match &self.lazy {
+            Some(lazy) => lazy.stored + lazy.new_words,
+            None => self.list.len(),
+        }
     }
 
     pub fn is_empty(&self) -> bool {
-        self.list.is_empty()
+        self.len() == 0
     }
 
-    pub fn list(&self) -> &BTreeMap<String, WordData> {
-        &self.list
+    pub fn get(
+        &mut self,
+        db: &mut WordFileBlocks,
+        word: &str,
+    ) -> Result<Option<WordData>, IndexError> {
+        self.load_word(db, word)?;
+        Ok(self.list.get(word).copied())
     }
 
-    pub fn list_mut(&mut self) -> &mut BTreeMap<String, WordData> {
-        &mut self.list
+    pub fn get_mut(
+        &mut self,
+        db: &mut WordFileBlocks,
+        word: &str,
+    ) -> Result<Option<&mut WordData>, IndexError> {
+        self.load_word(db, word)?;
+        Ok(self.list.get_mut(word))
+    }
+
+    /// Adds to the count of a word in memory. Counts are not stored,
+    /// a word that is not loaded starts with 0 anyway.
+    pub(crate) fn add_count(&mut self, word: &str, count: usize) {
+        if let Some(data) = self.list.get_mut(word) {
+            data.count += count;
+        }
     }
 
-    pub fn get_mut(&mut self, word: &str) -> Option<&mut WordData> {
-        self.list.get_mut(word)
+    /// One more file refers to the word. A stored word is changed
+    /// in its block right away, a block of version 1 has no room.
+    pub(crate) fn count_file(
+        &mut self,
+        db: &mut WordFileBlocks,
+        word: &str,
+    ) -> Result<(), IndexError> {
+        let Some(data) = self.get_mut(db, word)? else {
+            return Ok(());
+        };
+        data.files += 1;
+        if data.block_nr == 0 {
+            return Ok(());
+        }
+
+        let block = db.get_mut(data.block_nr)?;
+        if WordBlockType::user_type(block.block_type()) == Some(Self::TY) {
+            block.set_dirty(true);
+            let raw = block_as_mut::<RawWord>(block, data.block_nr)?;
+            raw[data.block_idx.as_usize()].files = data.files;
+        }
+        Ok(())
     }
 
+    /// Adds a new word, get() must have failed before.
     pub fn insert<S: AsRef<str>>(
         &mut self,
         word: S,
         count: usize,
         file_map_block_nr: LogicalNr,
         file_map_idx: BlkIdx,
+        first_file_id: FileId,
     ) {
         self.last_word_id += 1;
+        if let Some(lazy) = &mut self.lazy {
+            lazy.new_words += 1;
+        }
         self.list.insert(
             word.as_ref().into(),
             WordData {
@@ -207,7 +868,132 @@ impl WordList {
                 block_idx: BlkIdx(0),
                 file_map_block_nr,
                 file_map_idx,
+                first_file_id,
+                files: 1,
             },
         );
     }
 }
+
+impl LazyDir {
+    fn add(&mut self, block_nr: LogicalNr, word: &str) {
+        self.blocks
+            .entry(block_nr.0)
+            .and_modify(|v| v.add(word))
+            .or_insert_with(|| BlockDir::new(word));
+        let hash = word_hash(word);
+        match self.by_hash.entry(hash) {
+            Entry::Vacant(e) => {
+                e.insert(block_nr.0);
+            }
+            Entry::Occupied(e) => {
+                if *e.get() != block_nr.0 {
+                    self.collisions.entry(hash).or_default().push(block_nr.0);
+                }
+            }
+        }
+        self.stored += 1;
+    }
+
+    /// A block from the stored directory, its words are not read.
+    fn add_dir(&mut self, block_nr: LogicalNr, dir: BlockDir) {
+        self.stored += dir.len;
+        self.blocks.insert(block_nr.0, dir);
+        self.filtered.push(block_nr.0);
+    }
+
+    /// The blocks that may contain the word.
+    fn blocks_for<'a>(&'a self, word: &'a str) -> impl Iterator<Item = LogicalNr> + 'a {
+        let hash = word_hash(word);
+        self.by_hash
+            .get(&hash)
+            .into_iter()
+            .chain(self.collisions.get(&hash).into_iter().flatten())
+            .chain(
+                self.filtered.iter().filter(
+                    move |nr| matches!(self.blocks.get(*nr), Some(v) if v.may_contain(word)),
+                ),
+            )
+            .map(|nr| LogicalNr(*nr))
+    }
+}
+
+impl BlockDir {
+    fn new(word: &str) -> Self {
+        let mut dir = Self {
+            first: word.to_string(),
+            last: word.to_string(),
+            len: 1,
+            bloom: [0; BLOOM_BITS / 64],
+        };
+        dir.set_bits(word);
+        dir
+    }
+
+    fn add(&mut self, word: &str) {
+        self.set_bits(word);
+        if word < self.first.as_str() {
+            self.first = word.to_string();
+        }
+        if word > self.last.as_str() {
+            self.last = word.to_string();
+        }
+        self.len += 1;
+    }
+
+    fn set_bits(&mut self, word: &str) {
+        for bit in bloom_bits(word) {
+            self.bloom[bit / 64] |= 1u64 << (bit % 64);
+        }
+    }
+
+    /// The block may contain the word.
+    fn may_contain(&self, word: &str) -> bool {
+        word >= self.first.as_str()
+            && word <= self.last.as_str()
+            && bloom_bits(word)
+                .iter()
+                .all(|bit| self.bloom[bit / 64] & (1u64 << (bit % 64)) != 0)
+    }
+
+    /// Some word of the block may start with the prefix.
+    fn overlaps(&self, prefix: &str) -> bool {
+        self.last.as_str() >= prefix
+            && (self.first.starts_with(prefix) || self.first.as_str() < prefix)
+    }
+}
+
+/// The bits of the word in the bloom filter of a block.
+fn bloom_bits(word: &str) -> [usize; 3] {
+    let h = word_hash(word);
+    [
+        (h % BLOOM_BITS as u64) as usize,
+        ((h >> 21) % BLOOM_BITS as u64) as usize,
+        ((h >> 42) % BLOOM_BITS as u64) as usize,
+    ]
+}
+
+fn read_word(r: &mut impl Read) -> Result<String, IndexError> {
+    let mut buf_len = [0u8; 1];
+    r.read_exact(&mut buf_len)?;
+
+    let mut buf_word = vec![0u8; buf_len[0] as usize];
+    r.read_exact(buf_word.as_mut())?;
+    Ok(String::from_utf8(buf_word)?)
+}
+
+fn write_word(buf: &mut Vec<u8>, word: &str) {
+    assert!(word.len() < 256);
+    buf.extend((word.len() as u8).to_ne_bytes());
+    buf.extend(word.as_bytes());
+}
+
+/// FNV-1a.
+fn word_hash(word: &str) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in word.as_bytes() {
+        h ^= *b as u64;
+        h = h.wrapping_mul(0x0100_0000_01b3);
+    }
+    h
+}
@@ -1,3 +1,4 @@
+pub mod cancel;
 pub mod error;
 pub mod index2;
 pub mod proc3;
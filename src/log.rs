@@ -1,5 +1,6 @@
 use crate::cmdlib::{CParserError, CSpan};
 use crate::cmds::CCode;
+use crate::proc3::events::json_str;
 use kparse::parser_error::SpanAndCode;
 use kparse::prelude::*;
 use kparse::provider::TrackedDataVec;
@@ -33,35 +34,93 @@ pub fn log_trace(trace: &TrackedDataVec<CCode, &str>) {
 pub fn dump_diagnostics(str: &str, err: &CParserError<'_>, msg: &str, is_err: bool) {
     let txt = Track::source_str(str);
 
-    println!();
+    eprintln!();
     if !msg.is_empty() {
-        println!("{}: {:?}", if is_err { "FEHLER" } else { "WARNUNG" }, msg);
+        eprintln!("{}: {:?}", if is_err { "FEHLER" } else { "WARNUNG" }, msg);
     } else {
-        println!(
+        eprintln!(
             "{}: {:?} ",
             if is_err { "FEHLER" } else { "WARNUNG" },
             err.code,
         );
     }
 
-    println!("{}", str);
+    eprintln!("{}", str);
 
-    println!("{}^", " ".repeat(txt.column(err.span)));
+    eprintln!("{}^", " ".repeat(txt.column(err.span)));
     if !msg.is_empty() {
-        println!("Erwarted war: {}", msg);
+        eprintln!("Erwarted war: {}", msg);
     } else {
-        println!("Erwarted war: '{:?}'", err.code);
+        eprintln!("Erwarted war: '{:?}'", err.code);
     }
 
     let ex = dedup_spans(err.code, err.iter_expected());
     for exp in ex {
-        println!("{}^", " ".repeat(txt.column(err.span)));
-        println!("Erwarted war: '{:?}'", exp.code);
+        eprintln!("{}^", " ".repeat(txt.column(err.span)));
+        eprintln!("Erwarted war: '{:?}'", exp.code);
     }
 
     let sg = dedup_spans(err.code, err.iter_suggested());
     for sug in sg {
-        println!("Hinweis: '{:?}'", sug.code);
+        eprintln!("Hinweis: '{:?}'", sug.code);
+    }
+}
+
+/// A parse error for batch mode, without the spans.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseErrorReport {
+    pub command: String,
+    /// Byte offset in the command.
+    pub offset: usize,
+    pub code: String,
+    /// Expected tokens.
+    pub expected: Vec<String>,
+    pub suggested: Vec<String>,
+}
+
+impl ParseErrorReport {
+    pub fn new(str: &str, err: &CParserError<'_>) -> Self {
+        Self {
+            command: str.to_string(),
+            offset: err.span.location_offset(),
+            code: format!("{:?}", err.code),
+            expected: dedup_spans(err.code, err.iter_expected())
+                .iter()
+                .map(|v| token_text(v.code))
+                .collect(),
+            suggested: dedup_spans(err.code, err.iter_suggested())
+                .iter()
+                .map(|v| token_text(v.code))
+                .collect(),
+        }
+    }
+
+    /// One line of JSON.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"command\":{},\"offset\":{},\"code\":{},\"expected\":[{}],\"suggested\":[{}]}}",
+            json_str(&self.command),
+            self.offset,
+            json_str(&self.code),
+            self.expected
+                .iter()
+                .map(|v| json_str(v))
+                .collect::<Vec<_>>()
+                .join(","),
+            self.suggested
+                .iter()
+                .map(|v| json_str(v))
+                .collect::<Vec<_>>()
+                .join(","),
+        )
+    }
+}
+
+/// The token, or the name of the code if it has none.
+fn token_text(code: CCode) -> String {
+    match code.token().trim() {
+        "" => format!("{:?}", code),
+        v => v.to_string(),
     }
 }
 
@@ -73,3 +132,58 @@ fn dedup_spans<'a>(
     c.dedup_by(|v, w| v.code == w.code);
     c
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cmds::{parse_cmds, CCode};
+    use crate::log::ParseErrorReport;
+    use kparse::prelude::*;
+    use kparse::Track;
+
+    fn report(txt: &str) -> ParseErrorReport {
+        let trk = Track::new_tracker::<CCode, _>();
+        let span = Track::new_span(&trk, txt);
+        match parse_cmds(span) {
+            Err(nom::Err::Error(e)) => ParseErrorReport::new(txt, &e),
+            _ => panic!("no error for {}", txt),
+        }
+    }
+
+    #[test]
+    fn test_flag_error() {
+        let r = report("find --bogus x");
+        assert_eq!(r.offset, 5);
+        assert_eq!(r.code, "CFindFlag");
+        assert_eq!(
+            r.to_json(),
+            r#"{"command":"find --bogus x","offset":5,"code":"CFindFlag","expected":[],"suggested":[]}"#
+        );
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        let r = report("frobnicate");
+        assert_eq!(r.offset, 0);
+        assert_eq!(r.code, "CCommand");
+        assert!(r.suggested.iter().any(|v| v == "find"));
+        assert!(r.suggested.iter().any(|v| v == "collect"));
+        assert!(r.to_json().starts_with(
+            r#"{"command":"frobnicate","offset":0,"code":"CCommand","expected":[],"suggested":["#
+        ));
+    }
+
+    #[test]
+    fn test_json_escape() {
+        let r = ParseErrorReport {
+            command: "why \"a\\b\"\t".to_string(),
+            offset: 4,
+            code: "CWhyPath".to_string(),
+            expected: vec!["<path>".to_string()],
+            suggested: Vec::new(),
+        };
+        assert_eq!(
+            r.to_json(),
+            r#"{"command":"why \"a\\b\"\t","offset":4,"code":"CWhyPath","expected":["<path>"],"suggested":[]}"#
+        );
+    }
+}
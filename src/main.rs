@@ -1,28 +1,49 @@
-use crate::cmds::{parse_cmds, BCommand, CCode, Cmds, Delete, Next, Stats, Summary};
+use crate::browse::browse;
+use crate::cmds::{
+    parse_cmds, BCommand, CCode, Cmds, Config, Delete, Filter, IndexFile, Next, Set, Stats, Summary,
+};
 use crate::cmds::{Files, Find};
 use crate::error::AppError;
-use crate::log::dump_diagnostics;
+use crate::index2::check::{CheckReport, QUICK_CHECK_WORDS};
+use crate::index2::diagnostics::FindDiagnostics;
+use crate::index2::merge::MergeConflict;
+use crate::index2::word_map::{bag_share, BAG_LEN, BAG_SCALE};
+use crate::index2::{match_file_names, WordBlockType, Words, LINK_NS};
+use crate::log::{dump_diagnostics, ParseErrorReport};
+use crate::proc3::filter::FilterConfig;
+use crate::proc3::stop_words::{format_stop_words, STOP_WORDS};
 use crate::proc3::threads::{init_work, Msg, Work};
 #[allow(unused_imports)]
 use crate::proc3::{
-    auto_save, find_matched_lines, indexing, load_file, shut_down, Data, FileFilter,
+    auto_save, backup, broad_terms, collect_files, collect_target_used, explain_query, find,
+    find_matched_lines, index_file, indexing, load_file, merge_index, read_line, reindex_partial,
+    restore, sample_bigrams, short_terms, shut_down, BroadTerm, Data, FileFilter, FoundSnapshot,
+    BIGRAM_SAMPLE, CONFIGS, CONFIG_STOP_WORDS, PREFIX_TOP,
 };
 use blockfile2::LogicalNr;
 use kparse::prelude::*;
 use kparse::Track;
 use rustyline::error::ReadlineError;
 use rustyline::history::FileHistory;
-use rustyline::Editor;
+use rustyline::{Editor, ExternalPrinter};
 #[cfg(feature = "allocator")]
 use std::alloc::System;
-use std::io::Write;
-use std::path::PathBuf;
+use std::cmp::max;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::exit;
 #[cfg(feature = "allocator")]
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 #[cfg(feature = "allocator")]
 use tracking_allocator::{AllocationGroupId, AllocationRegistry, AllocationTracker, Allocator};
 
+mod browse;
+pub mod cancel;
 mod cmdlib;
 mod cmds;
 mod error;
@@ -80,6 +101,47 @@ impl AllocationTracker for StdoutTracker {
     }
 }
 
+/// Files per page of find results.
+const PAGE_LEN: usize = 20;
+
+/// Number of indexing threads.
+const INDEX_WORKERS: usize = 4;
+
+/// Exit code for one-shot and batch mode: success.
+const EXIT_OK: i32 = 0;
+/// Exit code for one-shot and batch mode: the command found nothing.
+const EXIT_NO_MATCH: i32 = 1;
+/// Exit code for one-shot and batch mode: invalid command or runtime error.
+const EXIT_ERROR: i32 = 2;
+
+/// Outcome of one command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Done,
+    NoMatch,
+    Invalid,
+}
+
+/// How parse errors are reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorFormat {
+    /// Diagnostics with the position marked.
+    Text,
+    /// One JSON line per failed command on stderr.
+    Json,
+}
+
+/// Printer for the one-shot and batch mode. Writes to stderr,
+/// stdout is reserved for the results.
+struct ConsolePrinter;
+
+impl ExternalPrinter for ConsolePrinter {
+    fn print(&mut self, msg: String) -> rustyline::Result<()> {
+        eprintln!("{}", msg);
+        Ok(())
+    }
+}
+
 fn main() -> Result<(), AppError> {
     #[cfg(feature = "allocator")]
     let trk = StdoutTracker {
@@ -110,39 +172,93 @@ fn main() -> Result<(), AppError> {
     #[cfg(feature = "allocator")]
     let _ = AllocationRegistry::set_global_tracker(trk).expect("global-tracker");
 
-    println!("loading");
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
     let stored = PathBuf::from("stored.idx");
+    if args.first().map(|v| v.as_str()) == Some("--query") {
+        exit(run_query(&stored, &args[1..]));
+    }
+
+    eprintln!("loading");
     let data = match Data::read(&stored) {
         Ok(v) => v,
         Err(e) => {
-            println!("{:?}", e);
+            eprintln!("{:?}", e);
             exit(1234);
         }
     };
 
+    {
+        let mut words = data.words.lock()?;
+        if !words.was_clean() {
+            eprintln!("index was not shut down cleanly, quick check ...");
+            let report = words.check(Some(QUICK_CHECK_WORDS));
+            print_check(&report);
+            if !report.is_ok() {
+                eprintln!("run `verify` for a full check, rebuild the index if it fails too.");
+            }
+        }
+        if words.bags_missing() {
+            eprintln!("the bags block is missing, run `rebuild-bags` before indexing.");
+        }
+        if words.is_degraded() {
+            eprintln!("words were lost, `find recovered-*` lists their placeholders.");
+        }
+    }
+
+    if !args.is_empty() {
+        exit(run_args(data, &args));
+    }
+
     let mut rl = Editor::<Cmds, FileHistory>::new()?;
     rl.set_helper(Some(Cmds));
     let _ = rl.load_history("history.txt");
 
-    println!("spinup");
-    let work: &'static Work = Box::leak(Box::new(init_work(rl.create_external_printer()?, data)));
+    eprintln!("spinup");
+    let work: &'static Work = Box::leak(Box::new(init_work(
+        rl.create_external_printer()?,
+        data,
+        INDEX_WORKERS,
+    )));
 
-    println!("enable_tracking");
+    if data.words.lock()?.verify_pos().is_some() {
+        work.send.send(Msg::Verify)?;
+    }
+
+    eprintln!("enable_tracking");
     #[cfg(feature = "allocator")]
     AllocationRegistry::enable_tracking();
 
+    // Ctrl-C outside of readline cancels the running command.
+    cancel::install()?;
+
+    let mut paging = Paging::default();
     let mut break_flag = false;
     loop {
         match rl.readline("> ") {
             Ok(txt_input) if txt_input.len() > 0 => {
                 break_flag = false;
                 rl.add_history_entry(txt_input.as_str())?;
-                match parse_cmd(data, work, &txt_input, &mut rl) {
+                cancel::reset();
+                match parse_cmd(
+                    data,
+                    work,
+                    &mut paging,
+                    ErrorFormat::Text,
+                    &txt_input,
+                    Some(&mut rl),
+                ) {
                     Ok(_) => {}
                     Err(e) => {
                         eprintln!("parse_cmd {:#?}", e);
                     }
                 }
+                if cancel::reset() {
+                    eprintln!("cancelled");
+                }
+                for (id, incarnation) in work.respawn_stopped() {
+                    eprintln!("{} stopped, restarted ({})", id, incarnation);
+                }
             }
             Ok(_) => {}
             Err(ReadlineError::Interrupted) => {
@@ -164,122 +280,469 @@ fn main() -> Result<(), AppError> {
     }
 
     shut_down(work);
-    auto_save(&work.printer.clone(), data)?;
+    // the terminal printer is done with the REPL.
+    let stderr: Arc<Mutex<dyn ExternalPrinter + Send>> = Arc::new(Mutex::new(ConsolePrinter));
+    if let Err(e) = auto_save(&stderr, data) {
+        eprintln!("autosave failed {:?}", e);
+    }
+
+    // not worth failing for.
+    if let Err(e) = rl.save_history("history.txt") {
+        eprintln!("history not saved: {}", e);
+    }
+
+    Ok(())
+}
+
+/// Paging through the found files. Kept by the REPL,
+/// not shared with anything that reads the found files.
+#[derive(Debug, Default)]
+struct Paging {
+    /// Next file to print.
+    lines_idx: usize,
+}
+
+/// Print the next page of the found files and advance.
+fn print_page(found: &FoundSnapshot, paging: &mut Paging, data: &Data) -> Result<(), AppError> {
+    let idx = paging.lines_idx;
+    let page = found.page(&data.root, idx, PAGE_LEN)?;
+    for (n, (file, lines)) in page.iter().enumerate() {
+        if found.is_missing(idx + n)? {
+            println!("  {}:{} [missing]", idx + n, file);
+        } else {
+            println!("  {}:{}", idx + n, file);
+        }
+        for (m, line) in lines.iter().enumerate() {
+            if line.is_truncated() {
+                println!(
+                    "    {}: {} ({} bytes, view {} {})",
+                    line.nr + 1,
+                    line,
+                    line.len,
+                    idx + n,
+                    m
+                );
+            } else {
+                println!("    {}: {}", line.nr + 1, line);
+            }
+        }
+    }
+
+    // shorter if cancelled.
+    let len = page.len();
+    paging.lines_idx += len;
 
-    rl.save_history("history.txt")?;
+    if paging.lines_idx < found.files.len() {
+        println!("...");
+    }
+
+    let (missing, checked) = found.missing_count()?;
+    if missing > 0 {
+        println!(
+            "{} of {} listed files no longer exist, the index is out of date",
+            missing, checked
+        );
+    }
+
+    let limit = data.settings.get().mem_limit;
+    if limit > 0 && found.line_bytes() > limit {
+        eprintln!(
+            "found lines use {} bytes, over the mem-limit. the next find frees them",
+            found.line_bytes()
+        );
+    }
 
     Ok(())
 }
 
+fn print_diagnostics(terms: &[String], diag: &FindDiagnostics, verbose: u8) {
+    for term in terms {
+        let (words, blocks, file_ids) = diag.term_sum(term);
+        eprintln!(
+            "{}: {} words, {} blocks, {} file ids",
+            term, words, blocks, file_ids
+        );
+        if verbose > 1 {
+            for wd in diag.words.iter().filter(|v| &v.term == term) {
+                eprintln!(
+                    "    {}: {} blocks, {} entries, {} file ids{}",
+                    wd.word,
+                    wd.blocks,
+                    wd.entries,
+                    wd.file_ids,
+                    if wd.broken { ", broken chain" } else { "" }
+                );
+            }
+        }
+    }
+    if verbose > 1 {
+        eprintln!(
+            "block cache: {} hits, {} misses",
+            diag.cache.hits, diag.cache.misses
+        );
+    }
+    eprintln!("in {:?}", diag.duration);
+}
+
+fn print_broad_terms(broad: &[BroadTerm], limit: usize) {
+    for term in broad {
+        eprintln!(
+            "{}: {} words, more than the match-limit {}",
+            term.term, term.words, limit
+        );
+        for (word, files) in &term.top {
+            eprintln!("    {} in {} files", word, files);
+        }
+        if term.words > term.top.len() {
+            eprintln!("    ...");
+        }
+    }
+}
+
+/// Asks on the terminal, only y or yes confirm.
+fn confirm(rl: &mut Editor<Cmds, FileHistory>, prompt: &str) -> Result<bool, AppError> {
+    match rl.readline(prompt) {
+        Ok(v) => Ok(matches!(v.trim(), "y" | "yes")),
+        Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn print_check(report: &CheckReport) {
+    eprintln!(
+        "checked {} words, {} chain entries, {} files: {}",
+        report.words,
+        report.chain_entries,
+        report.files,
+        if report.is_ok() {
+            "ok".to_string()
+        } else {
+            format!("{} problems", report.problems.len())
+        }
+    );
+    if report.cancelled {
+        eprintln!("cancelled, not all words were checked");
+    }
+    for problem in report.problems.iter().take(20) {
+        eprintln!("  {}", problem);
+    }
+    if report.problems.len() > 20 {
+        eprintln!("  ...");
+    }
+}
+
+/// `--query <terms>` prints the files that contain all the terms,
+/// one per line. The index is opened read-only and only the blocks
+/// the query needs are read. Returns the exit code.
+fn run_query(path: &Path, terms: &[String]) -> i32 {
+    if terms.is_empty() {
+        eprintln!("--query <terms>");
+        return EXIT_ERROR;
+    }
+
+    let found = Words::read_only(path).and_then(|mut words| words.find(terms));
+    match found {
+        Ok(files) if files.is_empty() => EXIT_NO_MATCH,
+        Ok(files) => {
+            for file in files {
+                println!("{}", file);
+            }
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("{:?}", e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// Runs the command given on the command line, or with --batch
+/// all the commands read from stdin. Returns the exit code.
+///
+/// `--batch --json-errors` reports parse errors as JSON.
+/// `--batch --progress-file <path>` writes progress events as JSON lines.
+fn run_args(data: &'static Data, args: &[String]) -> i32 {
+    let mut errors = ErrorFormat::Text;
+    if args[0] == "--batch" {
+        let mut flags = args[1..].iter();
+        while let Some(flag) = flags.next() {
+            match flag.as_str() {
+                "--json-errors" => errors = ErrorFormat::Json,
+                "--progress-file" => {
+                    let Some(path) = flags.next() else {
+                        eprintln!("--progress-file <path>");
+                        return EXIT_ERROR;
+                    };
+                    if let Err(e) = data.events.open(Path::new(path)) {
+                        eprintln!("progress-file {} {:?}", path, e);
+                        return EXIT_ERROR;
+                    }
+                }
+                v => {
+                    eprintln!("unknown flag {}", v);
+                    return EXIT_ERROR;
+                }
+            }
+        }
+    }
+
+    let work: &'static Work = Box::leak(Box::new(init_work(ConsolePrinter, data, INDEX_WORKERS)));
+
+    let mut paging = Paging::default();
+    let code = if args[0] == "--batch" {
+        let mut code = EXIT_OK;
+        for line in io::stdin().lines() {
+            let line = match line {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!("stdin {:?}", e);
+                    code = EXIT_ERROR;
+                    break;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            code = max(code, run_once(data, work, &mut paging, errors, &line));
+        }
+        code
+    } else {
+        run_once(data, work, &mut paging, ErrorFormat::Text, &args.join(" "))
+    };
+
+    shut_down(work);
+    data.events.close();
+
+    code
+}
+
+fn run_once(
+    data: &'static Data,
+    work: &'static Work,
+    paging: &mut Paging,
+    errors: ErrorFormat,
+    txt: &str,
+) -> i32 {
+    match parse_cmd(data, work, paging, errors, txt, None) {
+        Ok(Outcome::Done) => EXIT_OK,
+        Ok(Outcome::NoMatch) => EXIT_NO_MATCH,
+        Ok(Outcome::Invalid) => EXIT_ERROR,
+        Err(e) => {
+            eprintln!("{:?}", e);
+            EXIT_ERROR
+        }
+    }
+}
+
 fn parse_cmd(
     data: &'static Data,
     work: &'static Work,
+    paging: &mut Paging,
+    errors: ErrorFormat,
     txt: &str,
-    _rl: &mut Editor<Cmds, FileHistory>,
-) -> Result<(), AppError> {
+    rl: Option<&mut Editor<Cmds, FileHistory>>,
+) -> Result<Outcome, AppError> {
+    // the background verify waits.
+    data.activity.touch();
+
     let trk = Track::new_tracker::<CCode, _>();
     let span = Track::new_span(&trk, txt);
 
     let bcmd = match parse_cmds(span) {
         Ok((_, bcmd)) => bcmd,
+        Err(nom::Err::Error(e)) if errors == ErrorFormat::Json => {
+            eprintln!("{}", ParseErrorReport::new(txt, &e).to_json());
+            return Ok(Outcome::Invalid);
+        }
         Err(nom::Err::Error(e)) => {
-            println!("{:?}", trk.results());
+            eprintln!("{:?}", trk.results());
             dump_diagnostics(txt, &e, "", true);
-            return Ok(());
+            return Ok(Outcome::Invalid);
         }
         Err(e) => {
-            println!("{:?}", e);
-            return Ok(());
+            eprintln!("{:?}", e);
+            return Ok(Outcome::Invalid);
         }
     };
 
+    let mut outcome = Outcome::Done;
+
     match bcmd {
         BCommand::Index() => {
             let path = PathBuf::from(".");
             work.send.send(Msg::WalkTree(path))?;
         }
-        BCommand::Find(Find::Find(v)) => {
-            let mut words = data.words.lock()?;
-
-            let find_terms = v.iter().map(|v| v.clone()).collect::<Vec<_>>();
-            let found = words.find(find_terms.as_slice())?;
-            let found_lines = find_matched_lines(find_terms.as_slice(), &found)?;
-            for (idx, (file, lines)) in found_lines.iter().take(20).enumerate() {
-                println!("  {}:{}", idx, file);
-                for line in lines {
-                    println!("    {}", line);
+        BCommand::Find(Find::Find(flags, terms)) => {
+            let settings = data.settings.get();
+            if !flags.force {
+                // only the word-list, before walking the chains.
+                let broad = broad_terms(&mut data.words.lock()?, &terms, settings.match_limit)?;
+                if !broad.is_empty() {
+                    print_broad_terms(&broad, settings.match_limit);
+                    let run = match rl {
+                        Some(rl) => confirm(rl, "run anyway? y/n ")?,
+                        None => {
+                            eprintln!("use find --force to run anyway");
+                            false
+                        }
+                    };
+                    if !run {
+                        return Ok(Outcome::Invalid);
+                    }
+                }
+            }
+            let found = {
+                let mut words = data.words.lock()?;
+                if words.stop_words() {
+                    for term in &terms {
+                        if settings.is_stop_word(&term.to_lowercase()) {
+                            eprintln!("{} is a stop-word and not indexed", term);
+                        }
+                    }
+                }
+                for term in short_terms(&terms, words.min_word_len()) {
+                    eprintln!(
+                        "{} is shorter than min-word-len {} and not indexed",
+                        term,
+                        words.min_word_len()
+                    );
                 }
+                // the numbers of the same walk.
+                let mut diag = FindDiagnostics::default();
+                let verbose = flags.verbose > 0;
+                let found = find(
+                    &mut words,
+                    &data.root,
+                    &terms,
+                    true,
+                    verbose.then_some(&mut diag),
+                )?;
+                if verbose {
+                    print_diagnostics(&terms, &diag, flags.verbose);
+                }
+                found
+            };
+            if found.files.is_empty() {
+                outcome = Outcome::NoMatch;
             }
 
-            let mut found_guard = data.found.lock()?;
-            found_guard.terms = find_terms;
-            found_guard.files = found;
-            found_guard.lines_idx = 20;
-            found_guard.lines = found_lines;
+            let line_width = data.found.get()?.line_width;
+            let mut snapshot = FoundSnapshot::new(terms, found.files, line_width);
+            // the lines from the same text as indexed.
+            snapshot.attributes = settings.attributes;
+            let found = data.found.set(snapshot)?;
+            paging.lines_idx = 0;
+
+            if flags.files_only {
+                // one per line, suitable for xargs.
+                for file in &found.files {
+                    println!("{}", file);
+                }
+            } else {
+                print_page(&found, paging, data)?;
+            }
         }
-        BCommand::Files(Files::Files(v)) => {
-            let words = data.words.lock()?;
-            let found = words.find_file(v.as_str());
-            for (idx, file) in found.iter().enumerate() {
-                println!("  {}:{}", idx, file);
+        BCommand::Find(Find::Explain(_flags, terms)) => {
+            let settings = data.settings.get();
+            let lines = explain_query(&mut data.words.lock()?, &settings, &terms, PAGE_LEN)?;
+            for line in lines {
+                println!("{}", line);
             }
-
-            let mut found_guard = data.found.lock()?;
-            found_guard.terms.clear();
-            found_guard.files = found;
-            found_guard.lines_idx = 0;
-            found_guard.lines.clear();
         }
-        BCommand::Next(Next::First) => {
-            let mut found_guard = data.found.lock()?;
-            found_guard.lines_idx = 0;
-
-            for (idx, (file, lines)) in found_guard
-                .lines
-                .iter()
-                .enumerate()
-                .skip(found_guard.lines_idx)
-                .take(20)
-            {
+        BCommand::Find(Find::Links(v)) => {
+            let found = {
+                let mut words = data.words.lock()?;
+                find(
+                    &mut words,
+                    &data.root,
+                    &[format!("{}{}", LINK_NS, v)],
+                    true,
+                    None,
+                )?
+            };
+            for (idx, file) in found.files.iter().enumerate() {
                 println!("  {}:{}", idx, file);
-                for line in lines {
-                    println!("    {}", line);
-                }
             }
-
-            found_guard.lines_idx += 20;
-
-            if found_guard.lines_idx <= found_guard.lines.len() {
-                println!("...");
+            if found.files.is_empty() {
+                outcome = Outcome::NoMatch;
             }
+
+            let line_width = data.found.get()?.line_width;
+            data.found
+                .set(FoundSnapshot::new(Vec::new(), found.files, line_width))?;
+            paging.lines_idx = 0;
         }
-        BCommand::Next(Next::Next) => {
-            let mut found_guard = data.found.lock()?;
-            for (idx, (file, lines)) in found_guard
-                .lines
-                .iter()
-                .enumerate()
-                .skip(found_guard.lines_idx)
-                .take(20)
-            {
-                println!("  {}:{}", idx, file);
-                for line in lines {
-                    println!("    {}", line);
+        BCommand::Files(Files::Files(verbose, v)) => {
+            let timer = Instant::now();
+            // without a literal prefix match a copy, the index stays unlocked.
+            let (found, path) = {
+                let words = data.words.lock()?;
+                match words.find_file_prefix(v.as_str()) {
+                    Some(found) => (found, "prefix"),
+                    None => {
+                        let names = words.file_name_list();
+                        drop(words);
+                        (match_file_names(names, v.as_str()), "scan")
+                    }
                 }
+            };
+            if verbose {
+                eprintln!("{} files by {} in {:?}", found.len(), path, timer.elapsed());
             }
-
-            found_guard.lines_idx += 20;
-
-            if found_guard.lines_idx <= found_guard.lines.len() {
-                println!("...");
+            for (idx, file) in found.iter().enumerate() {
+                println!("  {}:{}", idx, file);
+            }
+            if found.is_empty() {
+                outcome = Outcome::NoMatch;
             }
+
+            let line_width = data.found.get()?.line_width;
+            data.found
+                .set(FoundSnapshot::new(Vec::new(), found, line_width))?;
+            paging.lines_idx = 0;
+        }
+        BCommand::Next(Next::First) => {
+            paging.lines_idx = 0;
+            print_page(&data.found.get()?, paging, data)?;
+        }
+        BCommand::Next(Next::Next) => {
+            print_page(&data.found.get()?, paging, data)?;
         }
         BCommand::Summary(Summary::Files(_v)) => {}
         BCommand::Delete(Delete::Delete(v)) => {
-            let words = data.words.lock()?;
-
-            for file in words.find_file(v.as_str()) {
-                work.send.send(Msg::DeleteFile(file.clone()))?;
+            let files = data.words.lock()?.find_file(v.as_str());
+            if !files.is_empty() {
+                work.send.send(Msg::DeleteFiles(files))?;
+            }
+        }
+        BCommand::IndexFile(cmd) => {
+            let (name, txt) = match cmd {
+                IndexFile::File(path) => (path, None),
+                IndexFile::Stdin(_) if rl.is_some() => {
+                    println!("stdin only as a command-line argument or in --batch");
+                    return Ok(Outcome::Invalid);
+                }
+                IndexFile::Stdin(name) => {
+                    let mut txt = Vec::new();
+                    io::stdin().read_to_end(&mut txt)?;
+                    (name, Some(txt))
+                }
+            };
+            let report = index_file(data, &name, txt)?;
+            if report.listed {
+                println!(
+                    "{}: indexed already. files can't be removed from the index, so it is not indexed again",
+                    name
+                );
+                outcome = Outcome::NoMatch;
+            } else if report.filter == FileFilter::Ignore {
+                println!("{}: filtered out, see why", name);
+                outcome = Outcome::NoMatch;
+            } else if report.empty {
+                println!("{}: no words, not indexed. see set index-empty", name);
+                outcome = Outcome::NoMatch;
+            } else {
+                println!("{}: {} words", name, report.words);
             }
         }
         BCommand::Stats(Stats::Base) => {
@@ -306,48 +769,638 @@ fn parse_cmd(
             );
             println!("recv terminal: {}", work.recv.len());
 
-            for i in 0..8 {
-                let w = &work.workers[i];
+            for w in work.workers.lock()?.iter() {
                 let s = w.state.lock().unwrap();
                 println!(
-                    "thread[{}]: {} state={} msg={} thread={}",
-                    i,
-                    w.name,
+                    "thread[{}]: incarnation={} state={} msg={} thread={}",
+                    w.id,
+                    w.incarnation,
                     s.state,
                     s.msg,
                     if w.handle.is_finished() {
                         "finished"
+                    } else if s.paused {
+                        "paused"
                     } else {
                         "running"
                     }
                 );
             }
+            if work.is_paused() {
+                println!("pipeline paused");
+            }
 
             let words = data.words.lock()?;
-            println!("words: {}", words.words().len());
+            println!(
+                "words: {}{}",
+                words.word_len(),
+                if words.lazy_words() { " (lazy)" } else { "" }
+            );
             println!("files: {}", words.files().len());
 
-            work.send.send(Msg::Debug)?;
+            let id = work.debug()?;
+            println!("debug {} sent", id);
+        }
+        BCommand::Stats(Stats::Cache) => {
+            let words = data.words.lock()?;
+            let cache = words.query_cache();
+            println!(
+                "query cache: hits={} misses={} generation={}",
+                cache.hits,
+                cache.misses,
+                words.generation()
+            );
+            for entry in cache.iter() {
+                println!(
+                    "  {:?} generation={} files={}",
+                    entry.terms,
+                    entry.generation,
+                    entry.ids.len()
+                );
+            }
         }
         BCommand::Stats(Stats::Word(txt)) => {
-            let block_nr = txt.parse::<u32>()?;
             let mut words = data.words.lock()?;
-            let block = words.db.get(LogicalNr(block_nr))?;
+            if let Some(word_data) = words.word(&txt)? {
+                println!(
+                    "{} id {} -> {}:{}",
+                    txt, word_data.id, word_data.file_map_block_nr, word_data.file_map_idx
+                );
+                if word_data.first_file_id == 0 {
+                    println!("first seen unknown, indexed before version 2");
+                } else {
+                    match words.file(word_data.first_file_id) {
+                        Some(path) => println!("first seen in {}", path),
+                        None => println!("first seen in file {}", word_data.first_file_id),
+                    }
+                }
+            } else {
+                let block_nr = txt.parse::<u32>()?;
+                let block = words.db.get(LogicalNr(block_nr))?;
 
-            println!("{:2?}", block);
+                println!("{:2?}", block);
+            }
         }
-        BCommand::Stats(Stats::Debug) => {
+        BCommand::Stats(Stats::Errors) => {
+            let failures = data.log.failures();
+            if failures > 0 {
+                println!(
+                    "{} log writes failed, last: {}",
+                    failures,
+                    data.log.last_failure().unwrap_or_default()
+                );
+            }
+            for msg in data.log.recent() {
+                println!("{}", msg);
+            }
+            let similar = data.log.similar();
+            if !similar.is_empty() {
+                println!("repeated messages:");
+                for v in similar {
+                    let dir = if v.dir.is_empty() { "." } else { &v.dir };
+                    println!("    {:>8} {} in {}", v.count, v.template, dir);
+                }
+            }
+            let partial = data.words.lock()?.partial_files();
+            if !partial.is_empty() {
+                println!("{} files partially indexed, out of time:", partial.len());
+                for file in partial {
+                    println!("    {}", file);
+                }
+            }
+        }
+        BCommand::Stats(Stats::Mem) => {
+            let found = data.found.get()?;
+            println!(
+                "found lines: {} bytes for {} files",
+                found.line_bytes(),
+                found.files.len()
+            );
+            println!(
+                "words in flight: {} bytes, max {}",
+                data.mem.tmp_words(),
+                data.mem.max_tmp_words()
+            );
+            let (blocks, bytes) = data.words.lock()?.cache_size();
+            println!("block cache: {} blocks, {} bytes", blocks, bytes);
+            match data.settings.get().mem_limit {
+                0 => println!("mem-limit: off"),
+                v => println!("mem-limit: {}MB", v / 1024 / 1024),
+            }
+        }
+        BCommand::Stats(Stats::Blocks) => {
             let words = data.words.lock()?;
+            for ty in [
+                WordBlockType::WordList2,
+                WordBlockType::WordMapHead,
+                WordBlockType::WordMapTail,
+                WordBlockType::FileList2,
+                WordBlockType::FileRefs,
+            ] {
+                let (blocks, runs) = words.block_runs(ty);
+                println!("{:?}: {} blocks in {} runs", ty, blocks, runs);
+            }
+            if words.pooled_blocks() > 0 {
+                println!("pre-allocated: {} blocks unused", words.pooled_blocks());
+            }
+        }
+        BCommand::Stats(Stats::Files) => {
+            let words = data.words.lock()?;
+            let partial = words.partial_files();
+            if !partial.is_empty() {
+                println!("{} files partially indexed, out of time:", partial.len());
+                for file in partial {
+                    println!("    {}", file);
+                }
+            }
+            let wordlists = words.wordlist_files();
+            if !wordlists.is_empty() {
+                println!("{} files look like word lists:", wordlists.len());
+                for (file, skipped) in wordlists {
+                    println!("    {}{}", file, if skipped { " (skipped)" } else { "" });
+                }
+            }
+            let duplicates = words.case_duplicates();
+            if !duplicates.is_empty() {
+                println!("{} files indexed under several names:", duplicates.len());
+                for names in duplicates {
+                    println!("    {}", names.join(" = "));
+                }
+            }
+        }
+        BCommand::Stats(Stats::Histogram) => {
+            let words = data.words.lock()?;
+            println!(
+                "bag scale 1/{}, the last bag from {:.2}% of all words",
+                BAG_SCALE,
+                bag_share(BAG_LEN - 1)
+            );
+            let total: usize = words.bag_stats().iter().sum();
+            println!("words added since open: {}", total);
+            for (bag, n) in words.bag_stats().iter().enumerate() {
+                if *n > 0 {
+                    println!("  bag {:3} from {:.3}%: {}", bag, bag_share(bag), n);
+                }
+            }
+        }
+        BCommand::Stats(Stats::Verify) => {
+            let verify = data.verify.lock()?;
+            match &verify.scan {
+                None => println!("no background verify"),
+                Some(scan) => {
+                    let total = data.words.lock()?.word_len();
+                    if let Some(duration) = verify.duration {
+                        println!("finished in {:?}", duration);
+                    } else {
+                        println!("running, waited {} times for other work", verify.paused);
+                    }
+                    if verify.start_pos > 0 {
+                        println!("continued after {} words", verify.start_pos);
+                    }
+                    println!("at word {} of {}", scan.pos, total);
+                    print_check(&scan.report);
+                }
+            }
+        }
+        BCommand::Stats(Stats::Bigrams(n)) => {
+            let files = data.words.lock()?.file_name_list();
+            let settings = data.settings.get();
+            let seed = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|v| v.as_nanos() as u64)
+                .unwrap_or(1);
 
-            let mut log = data.log.try_clone()?;
+            let mut log = data.log.writer();
+            let sample =
+                sample_bigrams(&mut log, &files, &data.root, &settings, BIGRAM_SAMPLE, seed)?;
+
+            println!(
+                "sampled {} of {} files, {} pairs, {} stop-word pairs skipped",
+                sample.files, sample.total_files, sample.bigrams.pairs, sample.bigrams.stop_pairs
+            );
+            println!(
+                "{} distinct pairs kept, at most {}, {} evicted",
+                sample.bigrams.len(),
+                sample.bigrams.max_len(),
+                sample.bigrams.evicted
+            );
+            for (a, b, count) in sample.bigrams.top(n) {
+                println!("  {} {}: {}", a, b, count);
+            }
+        }
+        BCommand::Stats(Stats::Prefixes(n)) => {
+            if n == 0 {
+                eprintln!("prefix length must be at least 1");
+                outcome = Outcome::Invalid;
+            } else {
+                let stats = data.words.lock()?.prefix_stats(n)?;
+                println!("{} prefixes of {} chars", stats.len(), n);
+                for v in stats.iter().take(PREFIX_TOP) {
+                    println!("  {}: {} words, count {}", v.prefix, v.words, v.count);
+                }
+            }
+        }
+        BCommand::Stats(Stats::Debug) => {
+            let mut words = data.words.lock()?;
+
+            let mut log = data.log.writer();
             writeln!(log, "{:#?}", *words)?;
-            for (word, data) in words.words().iter() {
+            for (word, data) in words.prefix_words("")? {
+                if cancel::is_cancelled() {
+                    break;
+                }
                 writeln!(log, "{}: [{}] n={}", word, data.id, data.count)?;
             }
         }
+        BCommand::Set(Set::StopWords(v)) => {
+            let mut words = data.words.lock()?;
+            if words.files().is_empty() {
+                words.set_stop_words(v);
+                words.write()?;
+                data.settings.update(|s| s.stop_words = v);
+            } else {
+                eprintln!("stopwords can only be changed for an empty index");
+                outcome = Outcome::Invalid;
+            }
+        }
+        BCommand::Set(Set::Attributes(v)) => {
+            data.settings.update(|s| s.attributes = v);
+            if v {
+                println!("alt and title are indexed for new files");
+            }
+        }
+        BCommand::Set(Set::IndexHidden(v)) => {
+            data.settings.update(|s| s.index_hidden = v);
+            if v {
+                println!("hidden files are indexed from the next walk");
+            } else {
+                println!("hidden files are skipped from the next walk");
+            }
+        }
+        BCommand::Set(Set::IndexEmpty(v)) => {
+            data.settings.update(|s| s.index_empty = v);
+            if v {
+                println!("files without words are indexed from the next walk");
+            } else {
+                println!("files without words are skipped from the next walk");
+            }
+        }
+        BCommand::Set(Set::FoldCase(v)) => {
+            let mut words = data.words.lock()?;
+            let duplicates = words.case_duplicates();
+            words.set_fold_case(v);
+            if v {
+                println!("file names are compared ignoring case");
+                for names in duplicates {
+                    println!("merged {}", names.join(" = "));
+                }
+            } else {
+                println!("file names are compared exactly");
+            }
+        }
+        BCommand::Set(Set::Segments(v)) => {
+            let mut words = data.words.lock()?;
+            words.set_segment_size(v);
+            words.write()?;
+            if v > 0 {
+                println!("new segment after {}MB", v);
+            } else {
+                println!("no new segments");
+            }
+        }
+        BCommand::Set(Set::MatchLimit(v)) => {
+            data.settings.update(|s| s.match_limit = v);
+            if v > 0 {
+                println!("find asks for more than {} words per term", v);
+            } else {
+                println!("no match-limit");
+            }
+        }
+        BCommand::Set(Set::IndexTimeout(v)) => {
+            data.settings
+                .update(|s| s.index_timeout = Duration::from_secs(v));
+            if v > 0 {
+                println!("files stop indexing after {}s", v);
+            } else {
+                println!("no index-timeout");
+            }
+        }
+        BCommand::Set(Set::MinWordLen(v)) => {
+            let v = max(v, 1);
+            let mut words = data.words.lock()?;
+            words.set_min_word_len(v);
+            words.write()?;
+            data.settings.update(|s| s.min_word_len = v);
+            println!("words shorter than {} chars are not indexed", v);
+            if !words.files().is_empty() {
+                eprintln!("only new files, the indexed ones keep their words until a rebuild");
+            }
+        }
+        BCommand::Set(Set::MemLimit(v)) => {
+            data.settings.update(|s| s.mem_limit = v * 1024 * 1024);
+            if v > 0 {
+                println!("loading waits above {}MB of words in flight", v);
+            } else {
+                println!("no mem-limit");
+            }
+        }
+        BCommand::Set(Set::Prealloc(v)) => {
+            data.settings.update(|s| s.prealloc = v);
+            if v > 0 {
+                println!("a walk allocates {} blocks per word-map type ahead", v);
+            } else {
+                println!("no pre-allocation");
+            }
+        }
+        BCommand::Set(Set::SkipWordlists(v)) => {
+            data.settings.update(|s| s.skip_wordlists = v);
+            if v {
+                println!("the words of word lists are skipped");
+            } else {
+                println!("word lists are indexed and flagged");
+            }
+        }
+        BCommand::Set(Set::WordlistRatio(v)) => {
+            data.settings.update(|s| s.wordlist_ratio = v);
+            if v > 0 {
+                println!("files above {} distinct words per KB are word lists", v);
+            } else {
+                println!("no word list check");
+            }
+        }
+        BCommand::Set(Set::LineWidth(v)) => {
+            // extract again with the new width.
+            let found = data.found.get()?.with_line_width(max(v, 1));
+            data.found.set(found)?;
+        }
+        BCommand::View(file_nr, line_nr) => match data.found.get()?.line(file_nr, line_nr)? {
+            Some((file, line)) => match read_line(&data.root, &file, line.nr)? {
+                Some(text) => println!("{}", text),
+                None => {
+                    eprintln!("{} changed, line {} is gone", file, line.nr + 1);
+                    outcome = Outcome::Invalid;
+                }
+            },
+            None => {
+                eprintln!("no line {} {}", file_nr, line_nr);
+                outcome = Outcome::Invalid;
+            }
+        },
         BCommand::Store() => {
             work.send.send(Msg::AutoSave)?;
         }
+        BCommand::Verify => {
+            let mut words = data.words.lock()?;
+            let report = words.check(None);
+            print_check(&report);
+            if !report.is_ok() {
+                outcome = Outcome::Invalid;
+            }
+        }
+        BCommand::Collect(force, dir) => {
+            let target = PathBuf::from(dir);
+            if !force && collect_target_used(&target)? {
+                eprintln!(
+                    "{} is not empty, use collect --force to copy into it",
+                    target.display()
+                );
+                outcome = Outcome::Invalid;
+            } else {
+                let found = data.found.get()?;
+                let report = collect_files(&data.root, &found.files, &target, |n| {
+                    println!("copied {} of {}", n, found.files.len())
+                })?;
+                for file in &report.missing {
+                    println!("  {} is gone", file);
+                }
+                println!(
+                    "{} files copied, {} bytes, {} missing",
+                    report.files,
+                    report.bytes,
+                    report.missing.len()
+                );
+            }
+        }
+        BCommand::Backup(dir) => {
+            let target = backup(data, Path::new(&dir))?;
+            println!("backup in {}", target.display());
+        }
+        BCommand::Restore(force, dir) => {
+            let dir = PathBuf::from(dir);
+            let check = data.words.lock()?.check_backup(&dir);
+            if data.walk.lock()?.active || data.verify.lock()?.is_running() {
+                eprintln!("indexing or verify is running, restore when it is finished");
+                outcome = Outcome::Invalid;
+            } else if let Err(e) = check {
+                eprintln!("{}", e.kind);
+                outcome = Outcome::Invalid;
+            } else {
+                let run = match (force, rl) {
+                    (true, _) => true,
+                    (false, Some(rl)) => {
+                        confirm(rl, "replace the index, unsaved changes are lost? y/n ")?
+                    }
+                    (false, None) => {
+                        eprintln!("use restore --force to replace the index");
+                        false
+                    }
+                };
+                if run {
+                    restore(data, &dir)?;
+                    let words = data.words.lock()?;
+                    println!(
+                        "restored {} files, {} words",
+                        words.files().len(),
+                        words.word_len()
+                    );
+                } else {
+                    outcome = Outcome::Invalid;
+                }
+            }
+        }
+        BCommand::MergeIndex(conflict, path) => {
+            let report = merge_index(data, Path::new(&path), conflict, |n| {
+                println!("merged {} words", n)
+            })?;
+            let what = match conflict {
+                MergeConflict::Replace => "replaced",
+                _ => "skipped",
+            };
+            for file in &report.conflicts {
+                println!("  {} {}", what, file);
+            }
+            if report.cancelled {
+                println!("cancelled, not all words are merged");
+            }
+            println!(
+                "{} files, {} words merged, {} conflicts",
+                report.files,
+                report.words,
+                report.conflicts.len()
+            );
+        }
+        BCommand::Pause => {
+            if work.pause()? {
+                println!("pausing, store still works");
+            } else {
+                println!("already paused");
+            }
+        }
+        BCommand::Resume => {
+            if work.resume()? {
+                println!("resumed");
+            } else {
+                println!("not paused");
+            }
+        }
+        BCommand::RebuildBags => {
+            let mut words = data.words.lock()?;
+            let report = words.rebuild_bags()?;
+            println!(
+                "{} head blocks, {} tail blocks",
+                report.head_blocks, report.tail_blocks
+            );
+            if report.changes.is_empty() {
+                println!("bags unchanged");
+            }
+            for change in &report.changes {
+                println!("  {}", change);
+            }
+            if report.stranded > 0 {
+                println!("{} blocks with free slots got no bag", report.stranded);
+            }
+            words.write()?;
+        }
+        BCommand::RecoverWords => {
+            let mut words = data.words.lock()?;
+            let report = words.recover_words()?;
+            println!(
+                "{} chains, {} without a word",
+                report.chains, report.orphans
+            );
+            if !report.recovered.is_empty() {
+                println!(
+                    "{} placeholder words {}..{} for {} files",
+                    report.recovered.len(),
+                    report.recovered[0],
+                    report.recovered[report.recovered.len() - 1],
+                    report.files
+                );
+                println!("the index is marked as degraded");
+            }
+            if report.missing_files > 0 {
+                println!("{} files are not in the file-list", report.missing_files);
+            }
+            words.write()?;
+        }
+        BCommand::ReindexPartial => {
+            let report = reindex_partial(data)?;
+            println!(
+                "{} files complete, {} still partial",
+                report.complete.len(),
+                report.partial.len()
+            );
+            for file in &report.partial {
+                println!("  {}", file);
+            }
+            for (file, reason) in &report.failed {
+                eprintln!("{}: {}", file, reason);
+            }
+        }
+        BCommand::Filter(Filter::List) => {
+            let filter = data.filter.lock()?;
+            if filter.force.is_empty() {
+                println!("no directory rules");
+            }
+            for rule in &filter.force {
+                println!("force {} {}", rule.glob, rule.filter);
+            }
+        }
+        BCommand::Filter(Filter::Force(glob, filter)) => {
+            let mut config = data.filter.lock()?;
+            config.set_force(&glob, filter);
+            config.store(Path::new(FilterConfig::FILE))?;
+            match filter {
+                Some(filter) => println!("{} is {} for new files", glob, filter),
+                None => println!("rule for {} removed", glob),
+            }
+        }
+        BCommand::Config(Config::List) => {
+            let words = data.words.lock()?;
+            for (name, file) in CONFIGS {
+                let embedded = words.config().get(name).map(|v| v.len());
+                let external = Path::new(file).exists();
+                match (embedded, external) {
+                    (Some(len), true) => {
+                        println!("{}: {} bytes embedded, overridden by {}", name, len, file)
+                    }
+                    (Some(len), false) => println!("{}: {} bytes embedded", name, len),
+                    (None, true) => println!("{}: from {}", name, file),
+                    (None, false) => println!("{}: default", name),
+                }
+            }
+        }
+        BCommand::Config(Config::Embed(name)) => {
+            // the configuration in use.
+            let txt = if name == CONFIG_STOP_WORDS {
+                match &data.settings.get().stop_list {
+                    Some(stop_list) => format_stop_words(stop_list.iter().map(|v| v.as_str())),
+                    None => format_stop_words(STOP_WORDS.iter().copied()),
+                }
+            } else {
+                data.filter.lock()?.to_text()
+            };
+            data.words.lock()?.set_config(&name, txt.into_bytes())?;
+            work.send.send(Msg::AutoSave)?;
+            println!("{} embedded in the index", name);
+        }
+        BCommand::Config(Config::Extract(name, path)) => {
+            let txt = data.words.lock()?.config().get(&name).map(|v| v.to_vec());
+            match txt {
+                Some(txt) => {
+                    fs::write(&path, txt)?;
+                    println!("{} written to {}", name, path);
+                }
+                None => {
+                    eprintln!("no {} embedded in the index", name);
+                    outcome = Outcome::Invalid;
+                }
+            }
+        }
+        BCommand::Why(path) => {
+            let absolute = data.root.join(&path);
+            let (filter, reason) = data.filter.lock()?.classify(&path, &absolute);
+            if filter == FileFilter::Inspect {
+                match load_file(filter, &absolute) {
+                    Ok((filter, _)) => println!("{}: {} {}", path, filter, reason),
+                    Err(e) => {
+                        println!("{}: decided {}, not readable {:?}", path, reason, e);
+                        outcome = Outcome::Invalid;
+                    }
+                }
+            } else {
+                println!("{}: {} {}", path, filter, reason);
+            }
+            if data.words.lock()?.have_file(&path) {
+                println!("is indexed");
+            }
+        }
+        BCommand::VerifyBackground => {
+            work.send.send(Msg::Verify)?;
+            println!("verify runs when idle, see stats verify");
+        }
+        BCommand::Browse => {
+            let before = data.found.get()?;
+            let marked = browse(data)?;
+            // refined in the browser.
+            if !Arc::ptr_eq(&before, &data.found.get()?) {
+                paging.lines_idx = 0;
+            }
+            if !marked.is_empty() {
+                println!("delete {} files", marked.len());
+                work.send.send(Msg::DeleteFiles(marked))?;
+            }
+        }
         BCommand::None => {
             //
         }
@@ -355,17 +1408,48 @@ fn parse_cmd(
             eprintln!(
                 "
 index
-stats base | debug | <word>
-find <match>
-files <match>
+indexfile <path> | - <name>
+stats base | debug | cache | errors | verify | histogram | mem | blocks | files | bigrams <n> | prefixes <len> | <word>
+find [--files-only] [--force] [-v | -vv] <match>
+explain [--literal] <match>
+links <match>
+files [-v] <match>
 summary <nr>
+set stopwords on | off
+set index-attributes on | off
+set index-hidden on | off
+set fold-case on | off
+set index-empty on | off
+set segments <mb> | off
+set linewidth <bytes>
+set mem-limit <mb> | off
+set prealloc <blocks> | off
+set skip-wordlists on | off
+set wordlist-ratio <words-per-kb> | off
+set min-word-len <chars>
+set match-limit <words> | off
+set index-timeout <secs> | off
+view <file-nr> <line-nr>
+filter [force <glob> text | html | ignore | off]
+config [embed stopwords | filter] [extract stopwords | filter <path>]
+why <path>
 delete <file-match>
 store
+verify [--background]
+rebuild-bags
+recover-words
+reindex-partial
+pause | resume
+collect [--force] <dir>
+backup <dir>
+restore [--force] <backup-dir>
+merge-index [--skip | --replace | --error] <path>
+browse
 help | ?
 "
             );
         }
     }
 
-    Ok(())
+    Ok(outcome)
 }
@@ -1,26 +1,49 @@
+use crate::cancel::is_cancelled;
 use crate::error::AppError;
-use crate::index2::tmp_index::TmpWords;
-use crate::index2::Words;
-use crate::proc3::indexer::{index_html2, index_txt2};
+use crate::index2::check::CheckScan;
+use crate::index2::diagnostics::FindDiagnostics;
+use crate::index2::merge::{MergeConflict, MergeReport};
+use crate::index2::pattern::Pattern;
+use crate::index2::plan::MatchMode;
+use crate::index2::tmp_index::{Bigrams, TmpWords};
+use crate::index2::{is_namespaced, IndexKind, Words};
+use crate::proc3::events::{Events, Value, EVENT_FILES};
+use crate::proc3::filter::{FilterConfig, FilterReason};
+use crate::proc3::indexer::{extract_html, index_html2, index_txt2};
+use crate::proc3::logger::Logger;
+use crate::proc3::quoted_printable::decode_text;
+use crate::proc3::stop_words::{is_stop_word, parse_stop_words, STOP_WORDS_FILE};
 use crate::proc3::threads::{Msg, Work, WorkerState};
+use crate::proc3::txt_parse::{normalize_word, split_words};
+use crossbeam::channel::Sender;
 use rustyline::ExternalPrinter;
 use std::borrow::Cow;
-use std::cmp::min;
-use std::fs::{File, OpenOptions};
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::fs::File;
 use std::io;
+use std::io::ErrorKind;
 use std::io::{Read, Seek, SeekFrom, Write};
+use std::mem::size_of;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
-use std::thread::sleep;
-use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::yield_now;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 #[cfg(feature = "allocator")]
 use tracking_allocator::AllocationGroupToken;
-use wildmatch::WildMatch;
 
+pub mod events;
+pub mod filter;
 pub mod html_parse;
 mod html_parse2;
 pub mod indexer;
+pub mod logger;
 mod named_char;
+pub mod quoted_printable;
 pub mod stop_words;
 pub mod threads;
 pub mod txt_parse;
@@ -33,23 +56,740 @@ pub enum FileFilter {
     Html,
 }
 
-#[derive(Default)]
-pub struct Found {
+impl Display for FileFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FileFilter::Ignore => write!(f, "ignore"),
+            FileFilter::Inspect => write!(f, "inspect"),
+            FileFilter::Text => write!(f, "text"),
+            FileFilter::Html => write!(f, "html"),
+        }
+    }
+}
+
+/// Captured lines are cut to this many bytes around the first match.
+pub const DEFAULT_LINE_WIDTH: usize = 500;
+
+/// A text-line that matched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchedLine {
+    /// Line in the file, 0 based. For html the line in the html.
+    pub nr: usize,
+    /// Length of the original line in bytes. For html of the line
+    /// of the text.
+    pub len: usize,
+    /// The line, maybe truncated.
+    pub text: String,
+}
+
+impl MatchedLine {
+    pub fn is_truncated(&self) -> bool {
+        self.text.len() != self.len
+    }
+}
+
+impl Display for MatchedLine {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl PartialEq<&str> for MatchedLine {
+    fn eq(&self, other: &&str) -> bool {
+        self.text == *other
+    }
+}
+
+/// Result of a find, files-, links- or browse-command.
+///
+/// Immutable once published. A new result replaces it as a whole,
+/// whoever still holds the Arc can finish with the old one.
+#[derive(Debug)]
+pub struct FoundSnapshot {
     pub terms: Vec<String>,
+    pub files: Vec<String>,
+    /// Width of the captured lines.
+    pub line_width: usize,
+    /// The lines of html files include the alt and title attributes.
+    pub attributes: bool,
+    /// Text-lines for the first files. Extracted page by page.
+    lines: Mutex<Vec<(String, Vec<MatchedLine>)>>,
+    /// Estimated bytes of the lines.
+    line_bytes: AtomicUsize,
+    /// By the index of the file, true if it doesn't exist any longer.
+    /// Each file is looked at once, when its page is extracted.
+    missing: Mutex<BTreeMap<usize, bool>>,
+}
+
+impl Default for FoundSnapshot {
+    fn default() -> Self {
+        Self::new(Vec::new(), Vec::new(), DEFAULT_LINE_WIDTH)
+    }
+}
+
+impl FoundSnapshot {
+    pub fn new(terms: Vec<String>, files: Vec<String>, line_width: usize) -> Self {
+        Self {
+            terms,
+            files,
+            line_width,
+            attributes: false,
+            lines: Default::default(),
+            line_bytes: Default::default(),
+            missing: Default::default(),
+        }
+    }
+
+    /// Same files, the lines are extracted again with the new width.
+    pub fn with_line_width(&self, line_width: usize) -> Self {
+        let mut found = Self::new(self.terms.clone(), self.files.clone(), line_width);
+        found.attributes = self.attributes;
+        found
+    }
+
+    /// Text-lines for the files starting at idx.
+    /// Extracts the lines of the files not extracted yet. Files that
+    /// don't exist any longer are skipped, they have no lines.
+    ///
+    /// The files are read without holding the lock. Two pages that
+    /// extract the same files at once both read them, the first one
+    /// back keeps its lines.
+    pub fn page(
+        &self,
+        root: &Path,
+        idx: usize,
+        len: usize,
+    ) -> Result<Vec<(String, Vec<MatchedLine>)>, AppError> {
+        let end = min(idx + len, self.files.len());
+        let start = self.lines.lock().map_err(poisoned)?.len();
+
+        if start < end {
+            let more = self.extract(root, start, end)?;
+
+            let mut lines = self.lines.lock().map_err(poisoned)?;
+            // lines only grow, skip what was added meanwhile.
+            let skip = lines.len() - start;
+            for (file, matched) in more.into_iter().skip(skip) {
+                let bytes = file.len()
+                    + matched
+                        .iter()
+                        .map(|v| size_of::<MatchedLine>() + v.text.len())
+                        .sum::<usize>();
+                self.line_bytes.fetch_add(bytes, Ordering::Relaxed);
+                lines.push((file, matched));
+            }
+        }
+
+        let lines = self.lines.lock().map_err(poisoned)?;
+        // cancelled extraction leaves it short.
+        let end = min(end, lines.len());
+        Ok(lines[min(idx, end)..end].to_vec())
+    }
+
+    /// The lines of the files from start to end.
+    fn extract(
+        &self,
+        root: &Path,
+        start: usize,
+        end: usize,
+    ) -> Result<Vec<(String, Vec<MatchedLine>)>, AppError> {
+        let files = &self.files[start..end];
+        let missing = self.stat(root, start, end)?;
+        if self.terms.is_empty() {
+            return Ok(files.iter().map(|v| (v.clone(), Vec::new())).collect());
+        }
+
+        let present: Vec<_> = files
+            .iter()
+            .zip(missing.iter())
+            .filter(|(_, missing)| !**missing)
+            .map(|(v, _)| v.clone())
+            .collect();
+        let more = find_matched_lines(
+            root,
+            &self.terms,
+            &present,
+            self.line_width,
+            self.attributes,
+        )?;
+
+        // cancelled extraction is short, it stops there.
+        let mut lines = Vec::new();
+        let mut more = more.into_iter();
+        for (file, missing) in files.iter().zip(missing) {
+            if missing {
+                lines.push((file.clone(), Vec::new()));
+            } else if let Some(v) = more.next() {
+                lines.push(v);
+            } else {
+                break;
+            }
+        }
+        Ok(lines)
+    }
+
+    /// For the files from start to end, true if one doesn't exist any
+    /// longer. Files looked at before are not looked at again.
+    fn stat(&self, root: &Path, start: usize, end: usize) -> Result<Vec<bool>, AppError> {
+        let known: Vec<_> = {
+            let missing = self.missing.lock().map_err(poisoned)?;
+            (start..end).map(|nr| missing.get(&nr).copied()).collect()
+        };
+        let found: Vec<_> = (start..end)
+            .zip(known)
+            .map(|(nr, known)| known.unwrap_or_else(|| !root.join(&self.files[nr]).exists()))
+            .collect();
+
+        let mut missing = self.missing.lock().map_err(poisoned)?;
+        Ok((start..end)
+            .zip(found)
+            .map(|(nr, found)| *missing.entry(nr).or_insert(found))
+            .collect())
+    }
+
+    /// The file doesn't exist any longer. Only known for the files
+    /// of the pages extracted so far.
+    pub fn is_missing(&self, file_nr: usize) -> Result<bool, AppError> {
+        let missing = self.missing.lock().map_err(poisoned)?;
+        Ok(missing.get(&file_nr).copied().unwrap_or(false))
+    }
+
+    /// Files that don't exist any longer, and the files looked at.
+    pub fn missing_count(&self) -> Result<(usize, usize), AppError> {
+        let missing = self.missing.lock().map_err(poisoned)?;
+        Ok((missing.values().filter(|v| **v).count(), missing.len()))
+    }
+
+    /// Estimated bytes of the extracted lines.
+    pub fn line_bytes(&self) -> usize {
+        self.line_bytes.load(Ordering::Relaxed)
+    }
+
+    /// An extracted line.
+    pub fn line(
+        &self,
+        file_nr: usize,
+        line_nr: usize,
+    ) -> Result<Option<(String, MatchedLine)>, AppError> {
+        let lines = self.lines.lock().map_err(poisoned)?;
+        Ok(lines
+            .get(file_nr)
+            .and_then(|(file, lines)| lines.get(line_nr).map(|v| (file.clone(), v.clone()))))
+    }
+}
+
+/// A poisoned lock. The error holds the guard, which borrows the
+/// lock, so only the fact goes into the AppError.
+fn poisoned<T>(_: PoisonError<T>) -> AppError {
+    AppError::from(PoisonError::new(()))
+}
+
+/// The current FoundSnapshot.
+///
+/// The lock is only held to swap the Arc, so a slow reader
+/// never blocks the next find.
+#[derive(Debug, Default)]
+pub struct Found {
+    current: Mutex<Arc<FoundSnapshot>>,
+}
+
+impl Found {
+    pub fn get(&self) -> Result<Arc<FoundSnapshot>, AppError> {
+        Ok(Arc::clone(&*self.current.lock().map_err(poisoned)?))
+    }
+
+    /// Publishes a new result and returns it.
+    pub fn set(&self, snapshot: FoundSnapshot) -> Result<Arc<FoundSnapshot>, AppError> {
+        let snapshot = Arc::new(snapshot);
+        *self.current.lock().map_err(poisoned)? = Arc::clone(&snapshot);
+        Ok(snapshot)
+    }
+}
+
+/// Settings for indexing.
+///
+/// A worker takes a snapshot for every file, a change applies to the
+/// files loaded after it. A file that is indexed right now keeps the
+/// values it started with. index_hidden is taken when a walk starts,
+/// match_limit is used by the next find.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Skip stop-words. Taken from the index header.
+    pub stop_words: bool,
+    /// Replaces the built-in stop-words.
+    pub stop_list: Option<Arc<HashSet<String>>>,
+    /// Count bigrams as a side channel.
+    pub bigrams: bool,
+    /// Index the alt and title attributes of html tags.
+    pub attributes: bool,
+    /// Shorter words are not indexed. Taken from the index header.
+    pub min_word_len: usize,
+    /// Walk hidden files and dot-directories. The root of the walk is
+    /// always walked.
+    pub index_hidden: bool,
+    /// find asks before running a search-term that matches more
+    /// words than this. 0 is off.
+    pub match_limit: usize,
+    /// Time budget for indexing one file. Zero is off.
+    pub index_timeout: Duration,
+    /// Soft limit in bytes for the words between indexer and merge,
+    /// the loader waits above it. The found lines only warn. 0 is off.
+    pub mem_limit: usize,
+    /// Blocks of each word-map and word-list type allocated ahead
+    /// when a walk starts. 0 is off.
+    pub prealloc: usize,
+    /// Distinct words per KB above which a file looks like a word
+    /// list. 0 is off.
+    pub wordlist_ratio: usize,
+    /// Drop the words of files that look like word lists.
+    pub skip_wordlists: bool,
+    /// Add files without any words to the index too.
+    pub index_empty: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            stop_words: true,
+            stop_list: None,
+            bigrams: false,
+            attributes: false,
+            min_word_len: 1,
+            index_hidden: false,
+            match_limit: MATCH_LIMIT,
+            index_timeout: INDEX_TIMEOUT,
+            mem_limit: 0,
+            prealloc: 0,
+            wordlist_ratio: WORDLIST_RATIO,
+            skip_wordlists: false,
+            index_empty: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Expects a lowercase word.
+    pub fn is_stop_word(&self, word: &str) -> bool {
+        match &self.stop_list {
+            Some(stop_list) => stop_list.contains(word),
+            None => is_stop_word(word),
+        }
+    }
+}
+
+/// The settings shared with the worker threads.
+///
+/// A change replaces the settings as a whole. The workers check the
+/// generation per file with one atomic load and take the new settings
+/// only after a change.
+#[derive(Debug, Default)]
+pub struct SharedSettings {
+    current: Mutex<Arc<Settings>>,
+    generation: AtomicUsize,
+}
+
+/// The snapshot a worker uses, see SharedSettings::refresh.
+#[derive(Debug, Default, Clone)]
+pub struct SettingsView {
+    generation: usize,
+    settings: Arc<Settings>,
+}
+
+impl SharedSettings {
+    pub fn new(settings: Settings) -> Self {
+        Self {
+            current: Mutex::new(Arc::new(settings)),
+            generation: AtomicUsize::new(0),
+        }
+    }
+
+    /// The current settings.
+    ///
+    /// The settings are only ever replaced as a whole, after a panic
+    /// the lock still holds a complete snapshot and is used as is.
+    pub fn get(&self) -> Arc<Settings> {
+        Arc::clone(&self.current.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Changes a copy of the settings and publishes it.
+    pub fn update(&self, f: impl FnOnce(&mut Settings)) {
+        let mut current = self.current.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut settings = Settings::clone(&current);
+        f(&mut settings);
+        *current = Arc::new(settings);
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+
+    /// A snapshot of the current settings.
+    pub fn view(&self) -> SettingsView {
+        let generation = self.generation.load(Ordering::Acquire);
+        SettingsView {
+            generation,
+            settings: self.get(),
+        }
+    }
+
+    /// The settings of the view, renewed if they changed since.
+    pub fn refresh(&self, view: &mut SettingsView) -> Arc<Settings> {
+        let generation = self.generation.load(Ordering::Acquire);
+        if generation != view.generation {
+            *view = self.view();
+        }
+        Arc::clone(&view.settings)
+    }
+}
+
+/// Embedded configuration: the stop-words.
+pub const CONFIG_STOP_WORDS: &str = "stopwords";
+/// Embedded configuration: the directory rules for the file-types.
+pub const CONFIG_FILTER: &str = "filter";
+
+/// Configurations that can be embedded in the index,
+/// with the external file that overrides them.
+pub const CONFIGS: &[(&str, &str)] = &[
+    (CONFIG_STOP_WORDS, STOP_WORDS_FILE),
+    (CONFIG_FILTER, FilterConfig::FILE),
+];
+
+/// Where a configuration was taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigSource {
+    File,
+    /// The external file overrides the embedded configuration.
+    FileOverEmbedded,
+    Embedded,
+}
+
+/// Number of files sampled for the bigrams.
+pub const BIGRAM_SAMPLE: usize = 200;
+
+/// Prefixes shown by stats prefixes.
+pub const PREFIX_TOP: usize = 50;
+
+/// Default for the matched words of one search-term before find asks.
+pub const MATCH_LIMIT: usize = 1000;
+
+/// Words shown for a search-term over the limit.
+pub const MATCH_TOP: usize = 10;
+
+/// explain counts the matching words of a term up to this.
+pub const EXPLAIN_CAP: usize = 100_000;
+
+/// Default for the distinct words per KB above which a file looks
+/// like a word list. A word list with one word of ~9 letters per line
+/// has ~100 per KB, 16KB of prose stays below ~60.
+pub const WORDLIST_RATIO: usize = 70;
+
+/// Smaller texts are not checked for word lists, short texts have
+/// mostly distinct words. Counts the text without markup.
+pub const WORDLIST_MIN_BYTES: usize = 16 * 1024;
+
+/// Default time budget for indexing one file.
+pub const INDEX_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// reindex_partial gives each file this many times the time budget.
+pub const REINDEX_TIMEOUT_FACTOR: u32 = 10;
+
+/// Result of sample_bigrams.
+#[derive(Debug, Default)]
+pub struct BigramSample {
+    /// Files read.
+    pub files: usize,
+    /// Files in the index.
+    pub total_files: usize,
+    pub bigrams: Bigrams,
+}
+
+/// Counters for the running tree walk.
+#[derive(Debug, Default)]
+pub struct WalkStats {
+    /// A tree walk is running.
+    pub active: bool,
+    pub files: usize,
+    /// All words, including stop-words.
+    pub words: usize,
+    pub stop_words: usize,
+    /// Words shorter than min-word-len, not indexed.
+    pub short_words: usize,
+    /// Files decoded from quoted-printable.
+    pub decoded: usize,
+    /// Files only partially indexed, they ran out of time.
+    pub partial: usize,
+    /// Files that look like word lists.
+    pub wordlists: usize,
+    /// Word lists whose words were dropped.
+    pub skipped: usize,
+    /// Files without any words, not added to the index.
+    pub empty: usize,
+    /// Files deleted while the walk is running. The walker still sees them
+    /// on disk and would index them again.
+    pub excluded: HashSet<String>,
+    /// Longest wait of a merge for the words lock.
+    pub max_stall: Duration,
+}
+
+/// Print progress for batch deletes every n files.
+pub const DELETE_PROGRESS: usize = 1000;
+
+/// Print progress for collect every n files.
+pub const COLLECT_PROGRESS: usize = 100;
+
+/// Blocks written by one batch of the auto-save.
+/// The words lock is released between the batches.
+pub const SAVE_BATCH: usize = 256;
+
+/// Words checked by one step of the background verify.
+pub const VERIFY_CHUNK: usize = 200;
 
+/// The background verify waits until there was no command and
+/// no indexing for this long.
+pub const VERIFY_IDLE: Duration = Duration::from_secs(2);
+
+/// Pause between two steps of the background verify.
+pub const VERIFY_TICK: Duration = Duration::from_millis(50);
+
+/// Time of the last command or indexed file.
+#[derive(Debug)]
+pub struct Activity {
+    last: Mutex<Instant>,
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self {
+            last: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+impl Activity {
+    pub fn touch(&self) {
+        if let Ok(mut last) = self.last.lock() {
+            *last = Instant::now();
+        }
+    }
+
+    /// Time since the last activity.
+    pub fn idle(&self) -> Duration {
+        self.last.lock().map(|v| v.elapsed()).unwrap_or_default()
+    }
+}
+
+/// Bytes of the TmpWords between the indexer and the merge.
+///
+/// Counted when the indexer sends them and when the merge is done
+/// with them, see MemTicket. An estimate of the heap that works
+/// without the allocator feature.
+#[derive(Debug, Default)]
+pub struct MemUsage {
+    tmp_words: AtomicUsize,
+    max_tmp_words: AtomicUsize,
+    /// Warned about the limit, cleared when below again.
+    warned: AtomicBool,
+}
+
+/// The bytes of one TmpWords in MemUsage. They are subtracted when
+/// the ticket is dropped, whether the words were merged or not.
+#[derive(Debug)]
+pub struct MemTicket {
+    mem: &'static MemUsage,
+    bytes: usize,
+}
+
+impl Drop for MemTicket {
+    fn drop(&mut self) {
+        self.mem.sub_tmp_words(self.bytes);
+    }
+}
+
+impl MemUsage {
+    /// Counts the bytes until the ticket is dropped.
+    pub fn track(&'static self, bytes: usize) -> MemTicket {
+        self.add_tmp_words(bytes);
+        MemTicket { mem: self, bytes }
+    }
+
+    pub fn add_tmp_words(&self, bytes: usize) {
+        let now = self.tmp_words.fetch_add(bytes, Ordering::AcqRel) + bytes;
+        self.max_tmp_words.fetch_max(now, Ordering::AcqRel);
+    }
+
+    pub fn sub_tmp_words(&self, bytes: usize) {
+        let _ = self
+            .tmp_words
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |v| {
+                Some(v.saturating_sub(bytes))
+            });
+    }
+
+    pub fn tmp_words(&self) -> usize {
+        self.tmp_words.load(Ordering::Acquire)
+    }
+
+    /// Highest value since the start.
+    pub fn max_tmp_words(&self) -> usize {
+        self.max_tmp_words.load(Ordering::Acquire)
+    }
+
+    /// The loader waits. A limit of 0 is off.
+    pub fn should_pause(&self, limit: usize) -> bool {
+        limit > 0 && self.tmp_words() > limit
+    }
+
+    /// True the first time after crossing the limit, for a
+    /// single warning.
+    pub fn warn_once(&self, over: bool) -> bool {
+        if over {
+            !self.warned.swap(true, Ordering::AcqRel)
+        } else {
+            self.warned.store(false, Ordering::Release);
+            false
+        }
+    }
+}
+
+/// Files in the pipeline and finished tree walks.
+///
+/// A file counts from the walker until it is merged or dropped
+/// by one of the stages.
+#[derive(Debug, Default)]
+pub struct Progress {
+    state: Mutex<ProgressState>,
+    cond: Condvar,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct ProgressState {
+    in_flight: usize,
+    done: usize,
+    walks: usize,
+}
+
+impl Progress {
+    /// The walker sent a file.
+    pub fn dispatched(&self) {
+        self.update(|v| v.in_flight += 1);
+    }
+
+    /// Merged or dropped.
+    pub fn done(&self) {
+        self.update(|v| {
+            v.in_flight = v.in_flight.saturating_sub(1);
+            v.done += 1;
+        });
+    }
+
+    /// The final store of a tree walk is finished.
+    pub fn walk_finished(&self) {
+        self.update(|v| v.walks += 1);
+    }
+
+    /// Files that went through the pipeline.
+    pub fn files_done(&self) -> usize {
+        self.state.lock().map(|v| v.done).unwrap_or(0)
+    }
+
+    /// Waits until n tree walks are finished and no file is left
+    /// in the pipeline. Returns false after the timeout.
+    pub fn wait_idle(&self, walks: usize, timeout: Duration) -> bool {
+        let Ok(state) = self.state.lock() else {
+            return false;
+        };
+        match self
+            .cond
+            .wait_timeout_while(state, timeout, |v| v.walks < walks || v.in_flight > 0)
+        {
+            Ok((_, result)) => !result.timed_out(),
+            Err(_) => false,
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut ProgressState)) {
+        if let Ok(mut state) = self.state.lock() {
+            f(&mut state);
+        }
+        self.cond.notify_all();
+    }
+}
+
+/// Verify that runs in the idle time of the terminal worker.
+#[derive(Debug, Default)]
+pub struct BackgroundVerify {
+    pub scan: Option<CheckScan>,
+    /// Words passed by an earlier session.
+    pub start_pos: usize,
+    /// Steps skipped because something else was running.
+    pub paused: usize,
+    pub started: Option<Instant>,
+    /// Duration of the finished verify.
+    pub duration: Option<Duration>,
+}
+
+impl BackgroundVerify {
+    pub fn is_running(&self) -> bool {
+        matches!(&self.scan, Some(scan) if !scan.finished)
+    }
+}
+
+/// Result of one find.
+#[derive(Debug, Default)]
+pub struct FindResult {
     pub files: Vec<String>,
+    pub lines: Vec<(String, Vec<MatchedLine>)>,
+}
 
-    pub lines_idx: usize,
-    pub lines: Vec<(String, Vec<String>)>,
+/// A search-term that matches more words than the limit.
+#[derive(Debug)]
+pub struct BroadTerm {
+    pub term: String,
+    /// Distinct words matched.
+    pub words: usize,
+    /// The words in the most files, with the number of files.
+    pub top: Vec<(String, usize)>,
 }
 
 pub struct Data {
+    /// Root directory of the indexed files.
+    pub root: PathBuf,
     pub words: Mutex<Words>,
-    pub found: Mutex<Found>,
-    pub log: File,
+    pub settings: SharedSettings,
+    pub walk: Mutex<WalkStats>,
+    pub found: Found,
+    pub log: Logger,
+    pub activity: Activity,
+    pub verify: Mutex<BackgroundVerify>,
+    /// Directory rules for the file-types.
+    pub filter: Mutex<FilterConfig>,
+    pub progress: Progress,
+    pub mem: MemUsage,
+    /// Progress events for --progress-file.
+    pub events: Events,
 }
 
 impl Data {
+    /// Default settings and no directory rules.
+    pub fn new(root: PathBuf, words: Words, log: Logger) -> Self {
+        let settings = Settings {
+            stop_words: words.stop_words(),
+            min_word_len: words.min_word_len(),
+            ..Default::default()
+        };
+        Self {
+            root,
+            words: Mutex::new(words),
+            settings: SharedSettings::new(settings),
+            walk: Default::default(),
+            found: Default::default(),
+            log,
+            activity: Default::default(),
+            verify: Default::default(),
+            filter: Default::default(),
+            progress: Default::default(),
+            mem: Default::default(),
+            events: Default::default(),
+        }
+    }
+
     pub fn write(&'static self) -> Result<(), AppError> {
         if let Ok(mut wrl) = self.words.lock() {
             wrl.write()?;
@@ -60,41 +800,103 @@ impl Data {
     }
 
     pub fn read(path: &Path) -> Result<&'static Data, AppError> {
-        let log = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open("log.txt")?;
+        let log = Logger::open(Path::new("log.txt"));
 
-        let words = Words::read(path)?;
+        let words = match Words::read(path) {
+            Ok(v) => v,
+            Err(e) if matches!(e.kind, IndexKind::BadWord(_)) => {
+                eprintln!("{}", e.kind);
+                eprintln!("skipping the broken words, `verify` lists them.");
+                let mut words = Words::recover(path)?;
+                for bad in words.bad_words()? {
+                    log.log(format!("skipped {}", bad));
+                }
+                words
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let filter = match load_config(&words, CONFIG_FILTER, Path::new(FilterConfig::FILE))? {
+            Some(txt) => FilterConfig::parse(&txt),
+            None => FilterConfig::default(),
+        };
+        let stop_list = load_stop_list(&words, Path::new(STOP_WORDS_FILE))?;
+        let settings = Settings {
+            stop_words: words.stop_words(),
+            stop_list,
+            min_word_len: words.min_word_len(),
+            ..Default::default()
+        };
 
         let data: &'static Data = Box::leak(Box::new(Data {
-            words: Mutex::new(words),
-            found: Default::default(),
-            log,
+            settings: SharedSettings::new(settings),
+            filter: Mutex::new(filter),
+            ..Data::new(PathBuf::from("."), words, log)
         }));
 
         Ok(data)
     }
 }
 
-pub fn shut_down(work: &Work) {
-    println!("sending shutdown!");
-    if let Err(e) = work.send.send(Msg::Quit) {
-        if let Ok(mut print) = work.printer.lock() {
-            let _ = print.print(format!("shutdown {:?}", e));
+/// The external file, or else the configuration embedded in the index.
+pub fn config_text(
+    words: &Words,
+    name: &str,
+    path: &Path,
+) -> Result<Option<(String, ConfigSource)>, AppError> {
+    let embedded = words.config().get(name);
+    match fs::read_to_string(path) {
+        Ok(txt) if embedded.is_some() => Ok(Some((txt, ConfigSource::FileOverEmbedded))),
+        Ok(txt) => Ok(Some((txt, ConfigSource::File))),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(embedded.map(|v| {
+            (
+                String::from_utf8_lossy(v).into_owned(),
+                ConfigSource::Embedded,
+            )
+        })),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Like config_text, with a notice where the configuration comes from.
+fn load_config(words: &Words, name: &str, path: &Path) -> Result<Option<String>, AppError> {
+    let config = config_text(words, name, path)?;
+    match &config {
+        Some((_, ConfigSource::FileOverEmbedded)) => {
+            eprintln!(
+                "{} overrides the {} embedded in the index",
+                path.display(),
+                name
+            );
+        }
+        Some((_, ConfigSource::Embedded)) => {
+            eprintln!("{} embedded in the index", name);
         }
+        _ => {}
     }
+    Ok(config.map(|(txt, _)| txt))
+}
 
-    if let Ok(mut print) = work.printer.lock() {
-        let _ = print.print("wait on shutdown".into());
+/// The stop-list from the file or the index, None for the built-in one.
+/// A broken stop-list fails.
+fn load_stop_list(words: &Words, path: &Path) -> Result<Option<Arc<HashSet<String>>>, AppError> {
+    match load_config(words, CONFIG_STOP_WORDS, path)? {
+        Some(txt) => Ok(Some(Arc::new(parse_stop_words(&txt)?))),
+        None => Ok(None),
     }
+}
 
-    sleep(Duration::from_millis(100));
+/// Stops the workers. Reports on stderr, the terminal may be gone already.
+pub fn shut_down(work: &Work) {
+    eprintln!("sending shutdown!");
+    if let Err(e) = work.quit() {
+        eprintln!("shutdown {:?}", e);
+    }
 
-    for w in work.workers.iter() {
-        if !w.handle.is_finished() {
-            continue;
-        }
+    eprintln!("wait on shutdown");
+
+    // give the workers some time to finish their queues.
+    if !work.wait_finished(Duration::from_secs(10)) {
+        eprintln!("not all workers finished");
     }
 }
 
@@ -124,16 +926,28 @@ pub fn load_file(filter: FileFilter, absolute: &Path) -> Result<(FileFilter, Vec
 }
 
 pub fn indexing(
-    log: &mut File,
+    log: &mut dyn Write,
     #[cfg(feature = "allocator")] tok_txt: &mut AllocationGroupToken,
     #[cfg(feature = "allocator")] tok_html: &mut AllocationGroupToken,
     #[cfg(feature = "allocator")] tok_tmpwords: &mut AllocationGroupToken,
+    settings: &Settings,
     filter: FileFilter,
     relative: &str,
     txt: &Vec<u8>,
 ) -> Result<(FileFilter, TmpWords), io::Error> {
     let mut words = TmpWords::new(relative);
-    let txt = String::from_utf8_lossy(txt.as_ref());
+    words.stop_words = settings.stop_words;
+    words.stop_list = settings.stop_list.clone();
+    if settings.bigrams {
+        words.bigrams = Some(Bigrams::default());
+    }
+    words.attributes = settings.attributes;
+    words.min_len = settings.min_word_len;
+    if !settings.index_timeout.is_zero() {
+        words.deadline = Some(Instant::now() + settings.index_timeout);
+    }
+    let (txt, decoded) = decode_text(txt.as_ref());
+    words.decoded = decoded;
 
     match filter {
         FileFilter::Text => {
@@ -166,6 +980,23 @@ pub fn indexing(
         FileFilter::Inspect => {}
     }
 
+    if settings.wordlist_ratio > 0 && words.text_len >= WORDLIST_MIN_BYTES {
+        let ratio = words.words_per_kb();
+        if ratio > settings.wordlist_ratio {
+            words.wordlist = true;
+            if settings.skip_wordlists {
+                words.skip_words();
+            }
+            writeln!(
+                log,
+                "{}: {} distinct words per KB, looks like a word list{}",
+                relative,
+                ratio,
+                if words.skipped { ", words skipped" } else { "" }
+            )?;
+        }
+    }
+
     Ok((filter, words))
 }
 
@@ -173,11 +1004,52 @@ pub fn merge_words(
     data: &'static Data,
     state: &Arc<Mutex<WorkerState>>,
     words_buffer: TmpWords,
+    send: &Sender<Msg>,
     printer: &Arc<Mutex<dyn ExternalPrinter + Send>>,
 ) -> Result<(), AppError> {
-    let do_auto_save = {
+    {
+        let mut walk = data.walk.lock()?;
+        // deleted while still in the pipeline.
+        if walk.excluded.contains(&words_buffer.file) {
+            return Ok(());
+        }
+        walk.files += 1;
+        if walk.files % EVENT_FILES == 0 {
+            data.events
+                .emit("files", &[("files", Value::Num(walk.files as u64))]);
+        }
+        walk.words += words_buffer.total;
+        walk.stop_words += words_buffer.stop_count;
+        walk.short_words += words_buffer.short_count;
+        if words_buffer.decoded {
+            walk.decoded += 1;
+        }
+        if words_buffer.partial {
+            walk.partial += 1;
+        }
+        if words_buffer.wordlist {
+            walk.wordlists += 1;
+        }
+        if words_buffer.skipped {
+            walk.skipped += 1;
+        }
+        // they can never match. skipped word lists and partial files
+        // are added for their flags.
+        if words_buffer.words.is_empty()
+            && !words_buffer.skipped
+            && !words_buffer.partial
+            && !data.settings.get().index_empty
+        {
+            walk.empty += 1;
+            return Ok(());
+        }
+    }
+
+    let (do_auto_save, stall) = {
         state.lock().unwrap().state = 100;
+        let wait = Instant::now();
         let mut write = data.words.lock()?;
+        let stall = wait.elapsed();
         state.lock().unwrap().state = 101;
 
         timing(printer, "merge", 100, || write.append(words_buffer))?;
@@ -189,18 +1061,216 @@ pub fn merge_words(
             print_(printer, format!("loop-time {:?}", last.elapsed()));
             write.set_save_time();
         }
-        auto_save
+        (auto_save, stall)
     };
 
+    {
+        let mut walk = data.walk.lock()?;
+        walk.max_stall = max(walk.max_stall, stall);
+    }
+
     if do_auto_save {
         state.lock().unwrap().state = 200;
-        timing(printer, "autosave", 1, || auto_save(printer, data))?;
+        // the terminal thread saves, the merge can go on.
+        send.send(Msg::AutoSave)?;
     }
 
     Ok(())
 }
 
+/// Result of indexing a single file.
+#[derive(Debug)]
+pub struct IndexFileReport {
+    /// Ignore if the file was filtered out.
+    pub filter: FileFilter,
+    /// Distinct words.
+    pub words: usize,
+    /// The file is indexed already and was left alone. Files can't be
+    /// removed from the index, so it can't be indexed again.
+    pub listed: bool,
+    /// Not indexed, no words and index-empty is off.
+    pub empty: bool,
+}
+
+/// Indexes a single file on the calling thread, without a walk.
+/// A file that is indexed already is not indexed again.
+///
+/// Without txt the file is read below the root, else txt is indexed
+/// under the given name. The filters are the same as for a walk.
+pub fn index_file(
+    data: &Data,
+    relative: &str,
+    txt: Option<Vec<u8>>,
+) -> Result<IndexFileReport, AppError> {
+    #[cfg(feature = "allocator")]
+    let mut tok_txt = AllocationGroupToken::register().expect("token");
+    #[cfg(feature = "allocator")]
+    let mut tok_html = AllocationGroupToken::register().expect("token");
+    #[cfg(feature = "allocator")]
+    let mut tok_tmpwords = AllocationGroupToken::register().expect("token");
+
+    let mut report = IndexFileReport {
+        filter: FileFilter::Ignore,
+        words: 0,
+        listed: false,
+        empty: false,
+    };
+
+    if data.words.lock()?.have_file(&relative.to_string()) {
+        report.listed = true;
+        return Ok(report);
+    }
+
+    let (filter, txt) = match txt {
+        Some(txt) => {
+            let filter = data
+                .filter
+                .lock()?
+                .classify(relative, Path::new(relative))
+                .0;
+            match filter {
+                FileFilter::Inspect => (content_filter(&txt[..min(txt.len(), 256)]), txt),
+                filter => (filter, txt),
+            }
+        }
+        None => {
+            let absolute = data.root.join(relative);
+            let filter = data.filter.lock()?.classify(relative, &absolute).0;
+            if filter == FileFilter::Ignore {
+                return Ok(report);
+            }
+            load_file(filter, &absolute)?
+        }
+    };
+    if filter == FileFilter::Ignore {
+        return Ok(report);
+    }
+
+    let settings = data.settings.get();
+    let mut log = data.log.writer();
+    let (filter, words) = indexing(
+        &mut log,
+        #[cfg(feature = "allocator")]
+        &mut tok_txt,
+        #[cfg(feature = "allocator")]
+        &mut tok_html,
+        #[cfg(feature = "allocator")]
+        &mut tok_tmpwords,
+        &settings,
+        filter,
+        relative,
+        &txt,
+    )?;
+    report.filter = filter;
+    report.words = words.words.len();
+    if filter == FileFilter::Ignore {
+        return Ok(report);
+    }
+    // the same as for a walk.
+    if words.words.is_empty() && !words.skipped && !words.partial && !settings.index_empty {
+        report.empty = true;
+        return Ok(report);
+    }
+
+    let mut write = data.words.lock()?;
+    // it may have been added by a walk meanwhile.
+    if write.have_file(&words.file) {
+        report.listed = true;
+        return Ok(report);
+    }
+    write.append(words)?;
+    write.write()?;
+
+    Ok(report)
+}
+
+/// Result of reindex_partial.
+#[derive(Debug, Default)]
+pub struct ReindexReport {
+    /// Indexed in full now, the partial flag is cleared.
+    pub complete: Vec<String>,
+    /// Ran out of time again, they stay partial.
+    pub partial: Vec<String>,
+    /// Could not be read or are filtered out now, with the reason.
+    pub failed: Vec<(String, String)>,
+}
+
+/// Indexes the partially indexed files again on the calling thread,
+/// with REINDEX_TIMEOUT_FACTOR times the time budget. The words are
+/// merged with replace(), a file that is complete now loses its
+/// partial flag.
+pub fn reindex_partial(data: &Data) -> Result<ReindexReport, AppError> {
+    #[cfg(feature = "allocator")]
+    let mut tok_txt = AllocationGroupToken::register().expect("token");
+    #[cfg(feature = "allocator")]
+    let mut tok_html = AllocationGroupToken::register().expect("token");
+    #[cfg(feature = "allocator")]
+    let mut tok_tmpwords = AllocationGroupToken::register().expect("token");
+
+    let settings = data.settings.get();
+    let settings = Settings {
+        index_timeout: settings.index_timeout * REINDEX_TIMEOUT_FACTOR,
+        ..(*settings).clone()
+    };
+    let mut log = data.log.writer();
+
+    let mut report = ReindexReport::default();
+    let partial = data.words.lock()?.partial_files();
+    for relative in partial {
+        if is_cancelled() {
+            break;
+        }
+
+        let absolute = data.root.join(&relative);
+        let filter = data.filter.lock()?.classify(&relative, &absolute).0;
+        if filter == FileFilter::Ignore {
+            report.failed.push((relative, "filtered out".into()));
+            continue;
+        }
+        let (filter, txt) = match load_file(filter, &absolute) {
+            Ok(v) => v,
+            Err(e) => {
+                report.failed.push((relative, format!("{:?}", e)));
+                continue;
+            }
+        };
+        if filter == FileFilter::Ignore {
+            report.failed.push((relative, "filtered out".into()));
+            continue;
+        }
+
+        let (_, words) = indexing(
+            &mut log,
+            #[cfg(feature = "allocator")]
+            &mut tok_txt,
+            #[cfg(feature = "allocator")]
+            &mut tok_html,
+            #[cfg(feature = "allocator")]
+            &mut tok_tmpwords,
+            &settings,
+            filter,
+            &relative,
+            &txt,
+        )?;
+        let partial = words.partial;
+        data.words.lock()?.replace(words)?;
+        if partial {
+            report.partial.push(relative);
+        } else {
+            report.complete.push(relative);
+        }
+    }
+    data.words.lock()?.write()?;
+
+    Ok(report)
+}
+
 pub fn name_filter(path: &Path) -> FileFilter {
+    name_filter_reason(path).0
+}
+
+/// Filter by the extension and the file name.
+pub fn name_filter_reason(path: &Path) -> (FileFilter, FilterReason) {
     let ext = path
         .extension()
         .map(|v| v.to_string_lossy())
@@ -223,6 +1293,7 @@ pub fn name_filter(path: &Path) -> FileFilter {
         "history.txt",
         "stored.idx",
         "log.txt",
+        "filter.txt",
         "thumbs.db",
         // "jan.html",
         // "feb.html",
@@ -291,10 +1362,12 @@ pub fn name_filter(path: &Path) -> FileFilter {
         // "week53.html",
     ];
 
-    if EXT_IGNORE.contains(&ext.as_str()) || NAME_IGNORE.contains(&name.as_str()) {
-        FileFilter::Ignore
+    if EXT_IGNORE.contains(&ext.as_str()) {
+        (FileFilter::Ignore, FilterReason::Extension)
+    } else if NAME_IGNORE.contains(&name.as_str()) {
+        (FileFilter::Ignore, FilterReason::Name)
     } else {
-        FileFilter::Inspect
+        (FileFilter::Inspect, FilterReason::Content)
     }
 }
 
@@ -336,10 +1409,113 @@ pub fn content_filter(txt: &[u8]) -> FileFilter {
 }
 
 pub fn auto_save(
-    _printer: &Arc<Mutex<dyn ExternalPrinter + Send>>,
+    printer: &Arc<Mutex<dyn ExternalPrinter + Send>>,
+    data: &'static Data,
+) -> Result<(), AppError> {
+    let now = Instant::now();
+
+    let mut state = data.words.lock()?.start_write()?;
+    loop {
+        let done = data.words.lock()?.write_batch(&mut state, SAVE_BATCH)?;
+        if done {
+            break;
+        }
+        // let the merge in.
+        yield_now();
+    }
+
+    print_(
+        printer,
+        format!(
+            "autosave {} blocks in {} batches {:?}",
+            state.blocks,
+            state.batches,
+            now.elapsed()
+        ),
+    );
+    data.events.emit(
+        "auto_save",
+        &[
+            ("blocks", Value::Num(state.blocks as u64)),
+            ("batches", Value::Num(state.batches as u64)),
+            ("millis", Value::Num(now.elapsed().as_millis() as u64)),
+        ],
+    );
+    Ok(())
+}
+
+/// Starts the background verify. An unfinished verify of the last
+/// session continues where it stopped.
+pub fn start_verify(
+    printer: &Arc<Mutex<dyn ExternalPrinter + Send>>,
+    data: &'static Data,
+) -> Result<(), AppError> {
+    let mut verify = data.verify.lock()?;
+    if verify.is_running() {
+        print_(printer, "background verify is already running");
+        return Ok(());
+    }
+
+    let mut words = data.words.lock()?;
+    let scan = words.start_check(None, words.verify_pos().unwrap_or(0));
+    words.set_verify_pos(Some(scan.pos));
+    if scan.pos > 0 {
+        print_(
+            printer,
+            format!("background verify continues after {} words", scan.pos),
+        );
+    }
+
+    *verify = BackgroundVerify {
+        start_pos: scan.pos,
+        scan: Some(scan),
+        started: Some(Instant::now()),
+        ..Default::default()
+    };
+
+    Ok(())
+}
+
+/// Checks the next few words, but only if nothing else is running.
+pub fn verify_step(
+    printer: &Arc<Mutex<dyn ExternalPrinter + Send>>,
     data: &'static Data,
 ) -> Result<(), AppError> {
-    data.write()?;
+    let mut verify = data.verify.lock()?;
+    if !verify.is_running() {
+        return Ok(());
+    }
+    if data.activity.idle() < VERIFY_IDLE || data.walk.lock()?.active {
+        verify.paused += 1;
+        return Ok(());
+    }
+
+    let (finished, problems) = {
+        let Some(scan) = verify.scan.as_mut() else {
+            return Ok(());
+        };
+        let mut words = data.words.lock()?;
+        let finished = words.check_step(scan, VERIFY_CHUNK);
+        // saved with the next auto-save.
+        words.set_verify_pos(if finished { None } else { Some(scan.pos) });
+        (finished, scan.report.problems.len())
+    };
+
+    if finished {
+        verify.duration = verify.started.map(|v| v.elapsed());
+        if problems == 0 {
+            print_(printer, "background verify finished: ok");
+        } else {
+            print_(
+                printer,
+                format!(
+                    "background verify finished: {} problems, see stats verify",
+                    problems
+                ),
+            );
+        }
+    }
+
     Ok(())
 }
 
@@ -354,6 +1530,341 @@ fn delete_file(
     Ok(())
 }
 
+/// Deletes all the files while holding the lock once.
+fn delete_files(
+    printer: &Arc<Mutex<dyn ExternalPrinter + Send>>,
+    data: &'static Data,
+    files: Vec<String>,
+) -> Result<(), AppError> {
+    let mut write = data.words.lock()?;
+
+    let len = files.len();
+    for (n, file) in files.into_iter().enumerate() {
+        write.remove_file(file);
+        if (n + 1) % DELETE_PROGRESS == 0 {
+            print_(printer, format!("deleted {} of {}", n + 1, len));
+        }
+    }
+    print_(printer, format!("{} files deleted", len));
+
+    Ok(())
+}
+
+/// Result of copying the found files.
+#[derive(Debug, Default)]
+pub struct CollectReport {
+    pub files: usize,
+    pub bytes: u64,
+    /// Files that don't exist any longer.
+    pub missing: Vec<String>,
+    /// Stopped with Ctrl-C.
+    pub cancelled: bool,
+}
+
+/// The target of collect exists and has something in it.
+pub fn collect_target_used(target: &Path) -> Result<bool, AppError> {
+    match fs::read_dir(target) {
+        Ok(mut dir) => Ok(dir.next().is_some()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Copies the files below root to the target directory,
+/// keeping the relative paths. Existing files are overwritten.
+///
+/// Progress is called with the number of copied files every
+/// COLLECT_PROGRESS files.
+pub fn collect_files(
+    root: &Path,
+    files: &[String],
+    target: &Path,
+    mut progress: impl FnMut(usize),
+) -> Result<CollectReport, AppError> {
+    let mut report = CollectReport::default();
+
+    for file in files {
+        if is_cancelled() {
+            report.cancelled = true;
+            break;
+        }
+
+        let absolute = root.join(file);
+        if !absolute.is_file() {
+            report.missing.push(file.clone());
+            continue;
+        }
+
+        let copy = target.join(file);
+        if let Some(parent) = copy.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        report.bytes += fs::copy(&absolute, &copy)?;
+        report.files += 1;
+
+        if report.files % COLLECT_PROGRESS == 0 {
+            progress(report.files);
+        }
+    }
+
+    Ok(report)
+}
+
+/// Keeps a running walk from indexing the files again.
+fn exclude_files(data: &'static Data, files: &[String]) -> Result<(), AppError> {
+    let mut walk = data.walk.lock()?;
+    if walk.active {
+        walk.excluded.extend(files.iter().cloned());
+    }
+    Ok(())
+}
+
+/// Copies the index and the external configurations into a new
+/// directory below dir and returns it.
+///
+/// Indexing waits for the copy of the index, a running walk
+/// continues afterwards.
+pub fn backup(data: &Data, dir: &Path) -> Result<PathBuf, AppError> {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let target = dir.join(format!("backup-{}", secs));
+
+    data.words.lock()?.backup(&target)?;
+
+    for (_, file) in CONFIGS {
+        let path = data.root.join(file);
+        if path.is_file() {
+            fs::copy(&path, target.join(file))?;
+        }
+    }
+
+    Ok(target)
+}
+
+/// Merges the index at path into this one. The other index is
+/// opened read-only and stays as it is.
+///
+/// Indexing waits for the merge. The file-ids of this index don't
+/// change, the last find result stays valid.
+///
+/// The index is written before the merge. A cancelled or failed merge
+/// goes back to that, a partial merge would make the files of the
+/// other index conflicts the next time.
+pub fn merge_index(
+    data: &Data,
+    path: &Path,
+    conflict: MergeConflict,
+    progress: impl FnMut(usize),
+) -> Result<MergeReport, AppError> {
+    let mut other = Words::read_only(path)?;
+
+    let mut words = data.words.lock()?;
+    words.write()?;
+    let report = match words.merge(&mut other, conflict, progress) {
+        Ok(report) => report,
+        Err(e) => {
+            words.reload()?;
+            return Err(e.into());
+        }
+    };
+    if report.cancelled {
+        words.reload()?;
+    } else {
+        words.write()?;
+    }
+
+    Ok(report)
+}
+
+/// Replaces the index with a backup from backup() and loads the
+/// configurations again. External configurations are only replaced
+/// if the backup has them.
+///
+/// The last find result is cleared, the file-ids are different now.
+pub fn restore(data: &Data, dir: &Path) -> Result<(), AppError> {
+    let mut words = data.words.lock()?;
+    words.restore(dir)?;
+
+    for (_, file) in CONFIGS {
+        let path = dir.join(file);
+        if path.is_file() {
+            fs::copy(&path, data.root.join(file))?;
+        }
+    }
+
+    let filter = match load_config(&words, CONFIG_FILTER, &data.root.join(FilterConfig::FILE))? {
+        Some(txt) => FilterConfig::parse(&txt),
+        None => FilterConfig::default(),
+    };
+    *data.filter.lock()? = filter;
+
+    let stop_list = load_stop_list(&words, &data.root.join(STOP_WORDS_FILE))?;
+    data.settings.update(|settings| {
+        settings.stop_words = words.stop_words();
+        settings.min_word_len = words.min_word_len();
+        settings.stop_list = stop_list;
+    });
+
+    let line_width = data.found.get()?.line_width;
+    data.found
+        .set(FoundSnapshot::new(Vec::new(), Vec::new(), line_width))?;
+
+    Ok(())
+}
+
+/// Cuts the line to width bytes around pos, with "…" where something
+/// was left out. Always on char boundaries.
+pub fn truncate_line(line: &str, pos: usize, width: usize) -> String {
+    if line.len() <= width {
+        return line.to_string();
+    }
+
+    let pos = min(pos, line.len());
+    let mut start = pos.saturating_sub(width / 2);
+    let mut end = min(start + width, line.len());
+    start = end.saturating_sub(width);
+
+    while !line.is_char_boundary(start) {
+        start += 1;
+    }
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    // no char fits.
+    if start > end {
+        end = start;
+    }
+
+    let mut buf = String::with_capacity(end - start + 6);
+    if start > 0 {
+        buf.push('…');
+    }
+    buf.push_str(&line[start..end]);
+    if end < line.len() {
+        buf.push('…');
+    }
+    buf
+}
+
+/// Reads one line of the file in full.
+pub fn read_line(root: &Path, file: &str, nr: usize) -> Result<Option<String>, AppError> {
+    let mut txt = Vec::new();
+    File::open(root.join(file))?.read_to_end(&mut txt)?;
+    let (txt, _) = decode_text(txt.as_ref());
+    Ok(txt.split('\n').nth(nr).map(|v| v.to_string()))
+}
+
+/// Counts the bigrams over a random sample of the indexed files.
+///
+/// The files are read again, nothing is stored in the index.
+pub fn sample_bigrams(
+    log: &mut dyn Write,
+    files: &[String],
+    root: &Path,
+    settings: &Settings,
+    sample: usize,
+    seed: u64,
+) -> Result<BigramSample, AppError> {
+    #[cfg(feature = "allocator")]
+    let mut tok_txt = AllocationGroupToken::register().expect("token");
+    #[cfg(feature = "allocator")]
+    let mut tok_html = AllocationGroupToken::register().expect("token");
+    #[cfg(feature = "allocator")]
+    let mut tok_tmpwords = AllocationGroupToken::register().expect("token");
+
+    let settings = Settings {
+        bigrams: true,
+        ..settings.clone()
+    };
+
+    let mut result = BigramSample {
+        total_files: files.len(),
+        ..Default::default()
+    };
+    for relative in reservoir_sample(files.iter(), sample, seed) {
+        if is_cancelled() {
+            break;
+        }
+
+        let absolute = root.join(relative);
+        let filter = name_filter(&absolute);
+        if filter == FileFilter::Ignore {
+            continue;
+        }
+        let (filter, txt) = match load_file(filter, &absolute) {
+            Ok(v) => v,
+            Err(e) => {
+                writeln!(log, "bigrams {}: {:?}", relative, e)?;
+                continue;
+            }
+        };
+
+        let (_, words) = indexing(
+            log,
+            #[cfg(feature = "allocator")]
+            &mut tok_txt,
+            #[cfg(feature = "allocator")]
+            &mut tok_html,
+            #[cfg(feature = "allocator")]
+            &mut tok_tmpwords,
+            &settings,
+            filter,
+            relative,
+            &txt,
+        )?;
+        if let Some(bigrams) = words.bigrams {
+            result.files += 1;
+            result.bigrams.merge(bigrams);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Picks n items with equal probability (reservoir sampling).
+pub fn reservoir_sample<T>(items: impl Iterator<Item = T>, n: usize, seed: u64) -> Vec<T> {
+    // xorshift, good enough for sampling.
+    let mut state = seed | 1;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    let mut sample = Vec::with_capacity(n);
+    for (i, item) in items.enumerate() {
+        if i < n {
+            sample.push(item);
+        } else {
+            let j = (next() % (i as u64 + 1)) as usize;
+            if j < n {
+                sample[j] = item;
+            }
+        }
+    }
+    sample
+}
+
+/// Summary for the stop-words of the last walk.
+pub fn stop_word_summary(stop_words: bool, walk: &WalkStats) -> String {
+    let share = if walk.words > 0 {
+        walk.stop_words as f64 * 100.0 / walk.words as f64
+    } else {
+        0.0
+    };
+    format!(
+        "stop-words: {} of {} words ({:.1}%) {}",
+        walk.stop_words,
+        walk.words,
+        share,
+        if stop_words {
+            "skipped"
+        } else {
+            "would have been skipped"
+        }
+    )
+}
+
 fn print_<S: Into<String>>(printer: &Arc<Mutex<dyn ExternalPrinter + Send>>, msg: S) {
     if let Ok(mut print) = printer.lock() {
         let _ = print.print(msg.into());
@@ -362,14 +1873,27 @@ fn print_<S: Into<String>>(printer: &Arc<Mutex<dyn ExternalPrinter + Send>>, msg
 
 fn print_err_(
     printer: &Arc<Mutex<dyn ExternalPrinter + Send>>,
-    mut log: File,
+    data: &Data,
     task: &str,
     res: Result<(), AppError>,
 ) {
     if let Err(err) = res {
-        let _ = writeln!(log, "{} {:#?}", task, err);
-        if let Ok(mut print) = printer.lock() {
-            let _ = print.print(format!("{} {:?}", task, err));
+        data.log.log(format!("{} {:#?}", task, err));
+        data.events.emit(
+            "error",
+            &[
+                ("task", Value::Str(task)),
+                ("msg", Value::Str(&format!("{:?}", err))),
+            ],
+        );
+        // errors go to stderr when the terminal is gone.
+        let msg = format!("{} {:?}", task, err);
+        let printed = match printer.lock() {
+            Ok(mut print) => print.print(msg.clone()).is_ok(),
+            Err(_) => false,
+        };
+        if !printed {
+            eprintln!("{}", msg);
         }
     }
 }
@@ -392,39 +1916,233 @@ fn timing<S: AsRef<str>, R>(
     result
 }
 
+/// Checks the search-terms before a find. Only matches the words,
+/// the chains are not touched. A limit of 0 is off.
+pub fn broad_terms(
+    words: &mut Words,
+    terms: &[String],
+    limit: usize,
+) -> Result<Vec<BroadTerm>, AppError> {
+    let mut broad = Vec::new();
+    if limit == 0 {
+        return Ok(broad);
+    }
+    for term in terms {
+        let mut matched = words.match_words(term)?;
+        if matched.len() <= limit {
+            continue;
+        }
+        matched.sort_by(|a, b| b.1.files.cmp(&a.1.files).then_with(|| a.0.cmp(&b.0)));
+        broad.push(BroadTerm {
+            term: term.clone(),
+            words: matched.len(),
+            top: matched
+                .iter()
+                .take(MATCH_TOP)
+                .map(|(k, v)| (k.clone(), v.files as usize))
+                .collect(),
+        });
+    }
+    Ok(broad)
+}
+
+/// Search-terms without wildcards that are shorter than min_len.
+/// They can't match anything in the index. Namespaced terms are
+/// not affected.
+pub fn short_terms(terms: &[String], min_len: usize) -> Vec<&String> {
+    terms
+        .iter()
+        .filter(|v| !is_namespaced(v))
+        .filter(|v| match Pattern::new(v).literal() {
+            Some(literal) => literal.chars().count() < min_len,
+            None => false,
+        })
+        .collect()
+}
+
+/// The plan of a find for the terms, one line each step. Only the
+/// word-list is read.
+pub fn explain_query(
+    words: &mut Words,
+    settings: &Settings,
+    terms: &[String],
+    page_len: usize,
+) -> Result<Vec<String>, AppError> {
+    let explain = words.explain(terms, EXPLAIN_CAP)?;
+    let short = short_terms(terms, words.min_word_len());
+
+    let mut lines = Vec::new();
+    let mut chains = 0;
+    for (n, (term, estimate)) in explain
+        .plan
+        .terms
+        .iter()
+        .zip(explain.estimates.iter())
+        .enumerate()
+    {
+        let mut head = format!("term {}: {} - {}", n + 1, term.term, term.mode);
+        if term.namespaced {
+            head.push_str(", namespaced");
+        }
+        match term.mode {
+            MatchMode::Exact => {}
+            _ if term.prefix.is_empty() => head.push_str(", all words"),
+            _ => head.push_str(&format!(", prefix \"{}\"", term.prefix)),
+        }
+        head.push_str(&format!(
+            ", {}{} {}",
+            estimate.words,
+            if estimate.capped { "+" } else { "" },
+            if estimate.words == 1 { "word" } else { "words" }
+        ));
+        lines.push(head);
+        chains += estimate.words;
+
+        if words.stop_words() && settings.is_stop_word(&term.term.to_lowercase()) {
+            lines.push("    is a stop-word and not indexed".into());
+        }
+        if short.contains(&&term.term) {
+            lines.push(format!(
+                "    is shorter than min-word-len {} and not indexed",
+                words.min_word_len()
+            ));
+        }
+        if settings.match_limit > 0 && estimate.words > settings.match_limit {
+            lines.push(format!(
+                "    matches more than match-limit {} words, find asks first",
+                settings.match_limit
+            ));
+        }
+        if n > 0 {
+            lines.push("    reduces the files of the terms before".into());
+        }
+    }
+
+    if explain.cached {
+        lines.push("files: from the query cache".into());
+    } else {
+        lines.push(format!("files: walks the file chains of {} words", chains));
+    }
+    lines.push(format!(
+        "order: as the files were added, {} files per page",
+        page_len
+    ));
+    let text: Vec<_> = explain
+        .plan
+        .terms
+        .iter()
+        .filter(|v| !v.namespaced)
+        .map(|v| v.term.as_str())
+        .collect();
+    if text.is_empty() {
+        lines.push("lines: none, only namespaced terms".into());
+    } else {
+        lines.push(format!(
+            "lines: read from the files of each page, matching {}",
+            text.join(" ")
+        ));
+    }
+
+    Ok(lines)
+}
+
+/// Find the files for the search-terms.
+///
+/// This runs in three stages: search-terms to file-ids, file-ids to file-names
+/// and file-names to the matching text-lines. Only the last stage reads the files
+/// themselves, it is skipped for files_only.
+pub fn find(
+    words: &mut Words,
+    root: &Path,
+    terms: &[String],
+    files_only: bool,
+    diag: Option<&mut FindDiagnostics>,
+) -> Result<FindResult, AppError> {
+    let ids = match diag {
+        Some(diag) => words.find_diagnostics(terms, diag)?,
+        None => words.find_ids(terms)?,
+    };
+    words.resolve_files(&ids)?;
+    let files = words.file_names(&ids);
+
+    let lines = if files_only {
+        Vec::new()
+    } else {
+        find_matched_lines(root, terms, &files, DEFAULT_LINE_WIDTH, false)?
+    };
+
+    Ok(FindResult { files, lines })
+}
+
 // Search the result files and return matching text-lines.
+// The terms are matched like in the index, escaped wildcards literally.
+// Stops early when cancelled, the result is shorter then.
+// Long lines are truncated to line_width bytes around the first match.
+// Html files are matched in their text, the lines are those of the html.
+// The alt and title attributes are part of the text with attributes,
+// the same as when indexing.
 pub fn find_matched_lines(
+    root: &Path,
     terms: &[String],
-    files: &Vec<String>,
-) -> Result<Vec<(String, Vec<String>)>, AppError> {
-    let terms: Vec<_> = terms.iter().map(|v| WildMatch::new(v)).collect();
+    files: &[String],
+    line_width: usize,
+    attributes: bool,
+) -> Result<Vec<(String, Vec<MatchedLine>)>, AppError> {
+    // namespaced words are not part of the text.
+    let terms: Vec<_> = terms
+        .iter()
+        .filter(|v| !is_namespaced(v))
+        .map(|v| Pattern::new(v))
+        .collect();
 
     // get the text-lines that contain any of the search-terms.
     let mut result = Vec::new();
     for file in files {
-        let path = PathBuf::from(".");
-        let path = path.join(&file);
+        if is_cancelled() {
+            break;
+        }
+
+        let path = root.join(&file);
 
         let mut txt = Vec::new();
         File::open(&path)?.read_to_end(&mut txt)?;
 
-        let txt = String::from_utf8_lossy(txt.as_ref());
+        // the same text as indexed.
+        let (txt, _) = decode_text(txt.as_ref());
+        let (txt, html_lines) = if text_filter(&path, txt.as_bytes()) == FileFilter::Html {
+            let html = extract_html(&txt, attributes, |_| Ok::<_, AppError>(false))?;
+            (Cow::Owned(html.text), Some(html.lines))
+        } else {
+            (txt, None)
+        };
 
         let mut text_lines = Vec::new();
-        for line in txt.split('\n') {
-            let mut print_line = false;
+        for (nr, line) in txt.split('\n').enumerate() {
+            let mut match_pos = None;
 
-            'line: for word in line.split(' ') {
+            'line: for word in split_words(line) {
+                let norm = normalize_word(word);
                 for term in &terms {
-                    if term.matches(word) {
-                        print_line = true;
+                    if term.matches(&norm) {
+                        match_pos = Some(word.as_ptr() as usize - line.as_ptr() as usize);
                         break 'line;
                     }
                 }
             }
 
-            if print_line {
-                text_lines.push(line.to_string());
+            if let Some(pos) = match_pos {
+                let nr = match &html_lines {
+                    Some(lines) => {
+                        let offset = line.as_ptr() as usize - txt.as_ptr() as usize;
+                        lines.line(&txt, offset + pos)
+                    }
+                    None => nr,
+                };
+                text_lines.push(MatchedLine {
+                    nr,
+                    len: line.len(),
+                    text: truncate_line(line, pos, line_width),
+                });
             }
         }
 
@@ -433,3 +2151,12 @@ pub fn find_matched_lines(
 
     Ok(result)
 }
+
+/// Text or html by the name, or else by the start of the text.
+/// The forced filters are not looked at.
+fn text_filter(path: &Path, txt: &[u8]) -> FileFilter {
+    match name_filter(path) {
+        FileFilter::Inspect => content_filter(&txt[..min(txt.len(), 256)]),
+        v => v,
+    }
+}
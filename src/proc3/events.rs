@@ -0,0 +1,132 @@
+use crate::error::AppError;
+use crossbeam::channel::{bounded, Sender, TrySendError};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Events waiting for the writer. More are dropped.
+pub const EVENT_QUEUE: usize = 1000;
+
+/// A files event every n files indexed.
+pub const EVENT_FILES: usize = 1000;
+
+/// Progress events for another process, one JSON object per line.
+///
+/// The lines are written by their own thread and flushed one by one.
+/// A slow file never blocks the pipeline, when the queue is full the
+/// event is dropped and counted.
+#[derive(Default)]
+pub struct Events {
+    send: Mutex<Option<Sender<String>>>,
+    writer: Mutex<Option<JoinHandle<()>>>,
+    dropped: AtomicUsize,
+}
+
+/// Value of an event field.
+#[derive(Debug, Clone, Copy)]
+pub enum Value<'a> {
+    Str(&'a str),
+    Num(u64),
+}
+
+impl Events {
+    /// Starts writing the events to the file.
+    pub fn open(&self, path: &Path) -> Result<(), AppError> {
+        let mut file = File::create(path)?;
+        let (send, recv) = bounded::<String>(EVENT_QUEUE);
+        let writer = thread::Builder::new()
+            .name("events".into())
+            .spawn(move || {
+                for line in recv {
+                    // a failed write is just one lost event.
+                    let _ = file.write_all(line.as_bytes()).and_then(|_| file.flush());
+                }
+            })?;
+
+        self.close();
+        if let Ok(mut v) = self.send.lock() {
+            *v = Some(send);
+        }
+        if let Ok(mut v) = self.writer.lock() {
+            *v = Some(writer);
+        }
+        Ok(())
+    }
+
+    /// Writes the queued events and closes the file.
+    pub fn close(&self) {
+        if let Ok(mut send) = self.send.lock() {
+            send.take();
+        }
+        let writer = self.writer.lock().ok().and_then(|mut v| v.take());
+        if let Some(writer) = writer {
+            let _ = writer.join();
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.send.lock().map(|v| v.is_some()).unwrap_or(false)
+    }
+
+    /// Events lost because the writer didn't keep up.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Queues one event with a timestamp in milliseconds.
+    /// Does nothing without a file.
+    pub fn emit(&self, event: &str, fields: &[(&str, Value<'_>)]) {
+        let Ok(send) = self.send.lock() else {
+            return;
+        };
+        let Some(send) = send.as_ref() else {
+            return;
+        };
+
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|v| v.as_millis())
+            .unwrap_or(0);
+        let mut line = format!("{{\"ts\":{},\"event\":{}", ts, json_str(event));
+        for (name, value) in fields {
+            line.push(',');
+            line.push_str(&json_str(name));
+            line.push(':');
+            match value {
+                Value::Str(v) => line.push_str(&json_str(v)),
+                Value::Num(v) => line.push_str(&v.to_string()),
+            }
+        }
+        line.push_str("}\n");
+
+        match send.try_send(line) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// A JSON string with quotes.
+pub fn json_str(v: &str) -> String {
+    let mut buf = String::with_capacity(v.len() + 2);
+    buf.push('"');
+    for c in v.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if (c as u32) < 0x20 => buf.push_str(&format!("\\u{:04x}", c as u32)),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+    buf
+}
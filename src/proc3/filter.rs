@@ -0,0 +1,164 @@
+use crate::error::AppError;
+use crate::index2::pattern::Pattern;
+use crate::proc3::{name_filter_reason, FileFilter};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Why a file got its filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterReason {
+    /// Forced by the directory rule with this glob.
+    Forced(String),
+    /// The extension is on the ignore-list.
+    Extension,
+    /// The file name is on the ignore-list.
+    Name,
+    /// Decided by the first bytes of the content.
+    Content,
+}
+
+impl Display for FilterReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FilterReason::Forced(glob) => write!(f, "forced by the directory rule {}", glob),
+            FilterReason::Extension => write!(f, "by the extension"),
+            FilterReason::Name => write!(f, "by the file name"),
+            FilterReason::Content => write!(f, "by the content"),
+        }
+    }
+}
+
+/// Forces the filter for all files below the directories matching the glob.
+#[derive(Debug, Clone)]
+pub struct ForceRule {
+    pub glob: String,
+    pub filter: FileFilter,
+    pattern: Pattern,
+}
+
+impl ForceRule {
+    pub fn new<S: Into<String>>(glob: S, filter: FileFilter) -> Self {
+        let glob = glob.into();
+        let pattern = Pattern::new(&glob);
+        Self {
+            glob,
+            filter,
+            pattern,
+        }
+    }
+
+    /// Length of the longest directory that matches. If the glob
+    /// matches the path itself, this counts for the directory of the file.
+    fn matched_len(&self, relative: &str) -> Option<usize> {
+        if self.pattern.matches(relative) {
+            return Some(relative.rfind('/').unwrap_or(0));
+        }
+        relative
+            .char_indices()
+            .filter(|(idx, c)| *c == '/' && self.pattern.matches(&relative[..*idx]))
+            .map(|(idx, _)| idx)
+            .last()
+    }
+}
+
+/// Rules that override the file-type detection.
+///
+/// The rules are consulted before the extension tables. If more than one
+/// rule matches, the one matching the longest path-prefix decides.
+#[derive(Debug, Clone, Default)]
+pub struct FilterConfig {
+    pub force: Vec<ForceRule>,
+}
+
+impl FilterConfig {
+    /// Kept next to the index.
+    pub const FILE: &'static str = "filter.txt";
+
+    /// One rule per line: `text|html|ignore <glob>`.
+    /// A missing file is an empty config.
+    pub fn load(path: &Path) -> Result<Self, AppError> {
+        match fs::read_to_string(path) {
+            Ok(txt) => Ok(Self::parse(&txt)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Unknown lines are skipped.
+    pub fn parse(txt: &str) -> Self {
+        let mut config = Self::default();
+        for line in txt.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((filter, glob)) = line.split_once(' ') {
+                if let Some(filter) = parse_filter(filter) {
+                    config.set_force(glob.trim(), Some(filter));
+                }
+            }
+        }
+        config
+    }
+
+    pub fn store(&self, path: &Path) -> Result<(), AppError> {
+        fs::write(path, self.to_text())?;
+        Ok(())
+    }
+
+    /// In the format of the file.
+    pub fn to_text(&self) -> String {
+        let mut txt = String::new();
+        for rule in &self.force {
+            txt.push_str(&format!("{} {}\n", rule.filter, rule.glob));
+        }
+        txt
+    }
+
+    /// Adds or replaces the rule for the glob. None removes it.
+    pub fn set_force(&mut self, glob: &str, filter: Option<FileFilter>) {
+        // no normalize, a backslash escapes in the glob.
+        let glob = glob.trim_matches('/').to_string();
+        self.force.retain(|v| v.glob != glob);
+        if let Some(filter) = filter {
+            self.force.push(ForceRule::new(glob, filter));
+        }
+    }
+
+    /// The rule deciding for this file. The longest matching prefix wins,
+    /// with the same prefix the longer glob.
+    pub fn rule(&self, relative: &str) -> Option<&ForceRule> {
+        let relative = normalize(relative);
+        self.force
+            .iter()
+            .filter_map(|v| v.matched_len(&relative).map(|len| (len, v)))
+            .max_by_key(|(len, v)| (*len, v.glob.len()))
+            .map(|(_, v)| v)
+    }
+
+    /// Filter by the rules or else by the name. Inspect means the
+    /// content must decide.
+    pub fn classify(&self, relative: &str, path: &Path) -> (FileFilter, FilterReason) {
+        match self.rule(relative) {
+            Some(rule) => (rule.filter, FilterReason::Forced(rule.glob.clone())),
+            None => name_filter_reason(path),
+        }
+    }
+}
+
+/// The filters that can be forced.
+pub fn parse_filter(txt: &str) -> Option<FileFilter> {
+    match txt {
+        "text" => Some(FileFilter::Text),
+        "html" => Some(FileFilter::Html),
+        "ignore" => Some(FileFilter::Ignore),
+        _ => None,
+    }
+}
+
+/// Paths are matched with forward slashes.
+fn normalize(path: &str) -> String {
+    path.replace('\\', "/").trim_matches('/').to_string()
+}
@@ -3,17 +3,19 @@
 use crate::proc3::named_char::{NAMED_CHAR, NAMED_CHAR_VAL};
 use kparse::combinators::{fchar, fsense, pchar, track};
 use kparse::spans::SpanFragment;
-use kparse::KParseError;
 use kparse::ParseSpan;
 use kparse::{define_span, Code, Track};
+use kparse::{KParseError, TrackedSpan};
 use nom::branch::alt;
 use nom::bytes::complete::{tag, tag_no_case, take_while1};
 use nom::combinator::{opt, recognize};
 use nom::error::ParseError;
 use nom::sequence::{preceded, terminated, tuple};
-use nom::{AsChar, IResult, InputIter, Slice};
-use std::fmt::{Display, Formatter};
-use std::ops::{RangeFrom, RangeTo};
+use nom::InputTakeAtPosition;
+use nom::{AsBytes, AsChar, Compare, IResult, InputIter, InputLength, InputTake, Offset, Slice};
+use std::borrow::Cow;
+use std::fmt::{Debug, Display, Formatter};
+use std::ops::{Range, RangeFrom, RangeTo};
 use std::str::from_utf8_unchecked;
 
 #[derive(Debug, PartialEq, Clone, Copy, Eq)]
@@ -45,28 +47,69 @@ impl Code for HtmlCode {
 
 #[derive(Debug)]
 pub enum HtmlPart<'s> {
-    ParseError(Span<'s>),
-    Text(Span<'s>),
-    StartTag(Span<'s>),
-    EndTag(Span<'s>),
+    ParseError(&'s str),
+    Text(&'s str),
+    StartTag(&'s str),
+    EndTag(&'s str),
     CharRef(char),
     CharRefStr(&'static str),
-    Comment(Span<'s>),
-    DocType(Span<'s>),
-    CData(Span<'s>),
+    Comment(&'s str),
+    DocType(&'s str),
+    CData(&'s str),
     Eof,
 }
 
 define_span!(pub Span = HtmlCode, str);
-// pub type Span<'a> = &'a str;
-pub type ParserResult<'s, O> = kparse::ParserResult<HtmlCode, Span<'s>, O>;
-pub type TokenizerResult<'s> = kparse::TokenizerResult<HtmlCode, Span<'s>, Span<'s>>;
-pub type NomResult<'s> = kparse::ParserResult<HtmlCode, Span<'s>, Span<'s>>;
-pub type ParserError<'s> = kparse::ParserError<HtmlCode, Span<'s>>;
+/// Span with tracking, independent of debug_assertions.
+pub type TrackSpan<'s> = ParseSpan<'s, HtmlCode, &'s str>;
+pub type ParserResult<I, O> = kparse::ParserResult<HtmlCode, I, O>;
+pub type TokenizerResult<I> = kparse::TokenizerResult<HtmlCode, I, I>;
+pub type NomResult<I> = kparse::ParserResult<HtmlCode, I, I>;
+pub type ParserError<I> = kparse::ParserError<HtmlCode, I>;
+
+/// Input for the parser.
+///
+/// This is implemented for &str and for the tracking spans. The tracking
+/// costs, so the indexer uses &str and only tracks when it needs diagnostics.
+pub trait HtmlSpan<'s>:
+    Copy
+    + Debug
+    + SpanFragment<Result = &'s str>
+    + TrackedSpan<HtmlCode>
+    + AsBytes
+    + Offset
+    + InputTake
+    + InputLength
+    + InputIter<Item = char>
+    + InputTakeAtPosition<Item = char>
+    + Compare<&'static str>
+    + Slice<Range<usize>>
+    + Slice<RangeFrom<usize>>
+    + Slice<RangeTo<usize>>
+{
+}
+
+impl<'s, T> HtmlSpan<'s> for T where
+    T: Copy
+        + Debug
+        + SpanFragment<Result = &'s str>
+        + TrackedSpan<HtmlCode>
+        + AsBytes
+        + Offset
+        + InputTake
+        + InputLength
+        + InputIter<Item = char>
+        + InputTakeAtPosition<Item = char>
+        + Compare<&'static str>
+        + Slice<Range<usize>>
+        + Slice<RangeFrom<usize>>
+        + Slice<RangeTo<usize>>
+{
+}
 
 // todo: bom / wide-char recognition.
 
-pub fn parse_html(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
+pub fn parse_html<'s, I: HtmlSpan<'s>>(input: I) -> ParserResult<I, HtmlPart<'s>> {
     track(
         HtmlCode::Html,
         alt((
@@ -85,25 +128,25 @@ pub fn parse_html(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
     .with_code(HtmlCode::Html)
 }
 
-fn parse_lt_amp(input: Span<'_>) -> ParserResult<'_, HtmlPart<'_>> {
+fn parse_lt_amp<'s, I: HtmlSpan<'s>>(input: I) -> ParserResult<I, HtmlPart<'s>> {
     let (rest, v) = track(HtmlCode::TextX, recognize(alt((pchar('<'), pchar('&')))))(input)
         .with_code(HtmlCode::TextX)?;
 
-    Ok((rest, HtmlPart::Text(v)))
+    Ok((rest, HtmlPart::Text(*v.fragment())))
 }
 
-fn parse_cdata(input: Span<'_>) -> ParserResult<'_, HtmlPart<'_>> {
+fn parse_cdata<'s, I: HtmlSpan<'s>>(input: I) -> ParserResult<I, HtmlPart<'s>> {
     let (rest, v) = track(
         HtmlCode::CData,
         recognize(tuple((tag("<!"), tag_no_case("[cdata["), parse_cdata_rest))),
     )(input)
     .with_code(HtmlCode::CData)?;
 
-    Ok((rest, HtmlPart::CData(v)))
+    Ok((rest, HtmlPart::CData(*v.fragment())))
 }
 
 #[inline]
-fn parse_cdata_rest(input: Span<'_>) -> ParserResult<'_, Span<'_>> {
+fn parse_cdata_rest<'s, I: HtmlSpan<'s>>(input: I) -> ParserResult<I, I> {
     let mut idx = 0usize;
 
     let mut it = input.iter_elements();
@@ -131,14 +174,14 @@ fn parse_cdata_rest(input: Span<'_>) -> ParserResult<'_, Span<'_>> {
     Ok((input.slice(idx..), input.slice(..idx)))
 }
 
-fn parse_bogus(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
+fn parse_bogus<'s, I: HtmlSpan<'s>>(input: I) -> ParserResult<I, HtmlPart<'s>> {
     let (rest, v) = track(HtmlCode::Bogus, recognize(tuple((tag("<?"), unto('>')))))(input)
         .with_code(HtmlCode::Bogus)?;
 
-    Ok((rest, HtmlPart::Comment(v)))
+    Ok((rest, HtmlPart::Comment(*v.fragment())))
 }
 
-fn parse_starttag(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
+fn parse_starttag<'s, I: HtmlSpan<'s>>(input: I) -> ParserResult<I, HtmlPart<'s>> {
     let (rest, v) = track(
         HtmlCode::StartTag,
         recognize(tuple((
@@ -149,11 +192,11 @@ fn parse_starttag(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
     )(input)
     .with_code(HtmlCode::StartTag)?;
 
-    Ok((rest, HtmlPart::StartTag(v)))
+    Ok((rest, HtmlPart::StartTag(*v.fragment())))
 }
 
 #[inline]
-fn parse_endtag(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
+fn parse_endtag<'s, I: HtmlSpan<'s>>(input: I) -> ParserResult<I, HtmlPart<'s>> {
     let (rest, v) = track(
         HtmlCode::EndTag,
         recognize(tuple((
@@ -164,32 +207,32 @@ fn parse_endtag(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
     )(input)
     .with_code(HtmlCode::EndTag)?;
 
-    Ok((rest, HtmlPart::EndTag(v)))
+    Ok((rest, HtmlPart::EndTag(*v.fragment())))
 }
 
 #[inline]
-fn parse_doctype(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
+fn parse_doctype<'s, I: HtmlSpan<'s>>(input: I) -> ParserResult<I, HtmlPart<'s>> {
     let (rest, v) = track(
         HtmlCode::DocType,
         recognize(preceded(tag_no_case("<!doctype"), unto('>'))),
     )(input)
     .with_code(HtmlCode::DocType)?;
 
-    Ok((rest, HtmlPart::DocType(v)))
+    Ok((rest, HtmlPart::DocType(*v.fragment())))
 }
 
 #[inline]
-fn parse_comment(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
+fn parse_comment<'s, I: HtmlSpan<'s>>(input: I) -> ParserResult<I, HtmlPart<'s>> {
     let (rest, v) = track(
         HtmlCode::Comment,
         recognize(preceded(tag("<!--"), parse_comment_rest)),
     )(input)?;
 
-    Ok((rest, HtmlPart::Comment(v)))
+    Ok((rest, HtmlPart::Comment(*v.fragment())))
 }
 
 #[inline]
-fn parse_comment_rest(input: Span<'_>) -> ParserResult<'_, Span<'_>> {
+fn parse_comment_rest<'s, I: HtmlSpan<'s>>(input: I) -> ParserResult<I, I> {
     let mut idx = 0usize;
 
     let mut it = input.iter_elements();
@@ -255,20 +298,20 @@ fn parse_comment_rest(input: Span<'_>) -> ParserResult<'_, Span<'_>> {
 }
 
 #[inline]
-fn parse_text(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
+fn parse_text<'s, I: HtmlSpan<'s>>(input: I) -> ParserResult<I, HtmlPart<'s>> {
     let (rest, v) = track(
         HtmlCode::Text,
         recognize(take_while1(|c: char| c != '<' && c != '&' && c != '\0')),
     )(input)
     .with_code(HtmlCode::Text)?;
 
-    Ok((rest, HtmlPart::Text(v)))
+    Ok((rest, HtmlPart::Text(*v.fragment())))
 }
 
 #[inline]
-fn parse_eof(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
+fn parse_eof<'s, I: HtmlSpan<'s>>(input: I) -> ParserResult<I, HtmlPart<'s>> {
     Track.enter(HtmlCode::Eof, input);
-    if input.len() == 0 {
+    if input.input_len() == 0 {
         Track.ok(input, input, HtmlPart::Eof)
     } else {
         Track.err(ParserError::new(HtmlCode::Eof, input))
@@ -276,7 +319,7 @@ fn parse_eof(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
 }
 
 #[inline]
-fn parse_charref(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
+fn parse_charref<'s, I: HtmlSpan<'s>>(input: I) -> ParserResult<I, HtmlPart<'s>> {
     let (rest, (_, v)) = track(
         HtmlCode::CharRef,
         tuple((
@@ -295,7 +338,7 @@ fn parse_charref(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
 }
 
 #[inline]
-fn tok_dec_charref(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
+fn tok_dec_charref<'s, I: HtmlSpan<'s>>(input: I) -> ParserResult<I, HtmlPart<'s>> {
     let (rest, v) = terminated(take_while1(|c: char| c.is_ascii_digit()), opt(pchar(';')))(input)
         .with_code(HtmlCode::CharRef)?;
 
@@ -309,7 +352,7 @@ fn tok_dec_charref(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
 }
 
 #[inline]
-fn tok_hex_charref(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
+fn tok_hex_charref<'s, I: HtmlSpan<'s>>(input: I) -> ParserResult<I, HtmlPart<'s>> {
     let (rest, v) = terminated(
         take_while1(|c: char| c.is_ascii_hexdigit()),
         opt(pchar(';')),
@@ -326,7 +369,7 @@ fn tok_hex_charref(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
 }
 
 #[inline]
-fn tok_named_charref(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
+fn tok_named_charref<'s, I: HtmlSpan<'s>>(input: I) -> ParserResult<I, HtmlPart<'s>> {
     let mut name = [0u8; 32];
     let mut ins = 0usize;
 
@@ -359,6 +402,102 @@ fn tok_named_charref(input: Span<'_>) -> ParserResult<'_, HtmlPart> {
     Err(nom::Err::Error(ParserError::new(HtmlCode::CharRef, input)))
 }
 
+/// Name of a start-tag in lowercase.
+pub fn tag_name(tag: &str) -> String {
+    let tag = tag.strip_prefix('<').unwrap_or(tag);
+    let end = tag
+        .find(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>')
+        .unwrap_or(tag.len());
+    tag[..end].to_ascii_lowercase()
+}
+
+/// Attributes of a start-tag as (name, value).
+/// The values are not unescaped, an attribute without value gives "".
+pub fn tag_attrs(tag: &str) -> Vec<(&str, &str)> {
+    let mut attrs = Vec::new();
+
+    let tag = tag.strip_prefix('<').unwrap_or(tag);
+    let tag = tag.strip_suffix('>').unwrap_or(tag);
+
+    // skip the name
+    let mut rest = tag.trim_start_matches(|c: char| !(c.is_ascii_whitespace() || c == '/'));
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == '/');
+        if rest.is_empty() {
+            break;
+        }
+
+        let end = rest
+            .find(|c: char| c.is_ascii_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let name = &rest[..end];
+        rest = rest[end..].trim_start_matches(|c: char| c.is_ascii_whitespace());
+
+        if let Some(v) = rest.strip_prefix('=') {
+            let v = v.trim_start_matches(|c: char| c.is_ascii_whitespace());
+            let (value, r) = match v.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    let v = &v[1..];
+                    match v.find(q) {
+                        Some(end) => (&v[..end], &v[end + 1..]),
+                        None => (v, ""),
+                    }
+                }
+                _ => {
+                    let end = v.find(|c: char| c.is_ascii_whitespace()).unwrap_or(v.len());
+                    (&v[..end], &v[end..])
+                }
+            };
+            attrs.push((name, value));
+            rest = r;
+        } else {
+            attrs.push((name, ""));
+        }
+    }
+
+    attrs
+}
+
+/// Replaces the character references in an attribute value.
+/// An '&' that starts no valid reference is kept as is.
+pub fn decode_entities(value: &str) -> Cow<'_, str> {
+    if !value.contains('&') {
+        return Cow::Borrowed(value);
+    }
+
+    let mut buf = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(idx) = rest.find('&') {
+        buf.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+        match parse_charref(rest) {
+            Ok((r, HtmlPart::CharRef(c))) => {
+                buf.push(c);
+                rest = r;
+            }
+            Ok((r, HtmlPart::CharRefStr(s))) => {
+                buf.push_str(s);
+                rest = r;
+            }
+            _ => {
+                buf.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    buf.push_str(rest);
+
+    Cow::Owned(buf)
+}
+
+/// Value of the first attribute with this name. Case-insensitive.
+pub fn tag_attr<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    tag_attrs(tag)
+        .into_iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v)
+}
+
 // parse up to and including the character. consumes the whole input if no such character is found.
 #[inline]
 fn unto<I, Error: ParseError<I>>(cc: <I as InputIter>::Item) -> impl Fn(I) -> IResult<I, I, Error>
@@ -391,8 +530,8 @@ where
 #[cfg(test)]
 mod tests {
     use crate::proc3::html_parse2::{
-        parse_bogus, parse_cdata, parse_doctype, parse_endtag, parse_html, parse_starttag,
-        parse_text, HtmlPart, Span,
+        decode_entities, parse_bogus, parse_cdata, parse_doctype, parse_endtag, parse_html,
+        parse_starttag, parse_text, tag_attr, tag_attrs, tag_name, HtmlPart,
     };
     use kparse::test::{str_parse, CheckTrace, Trace};
 
@@ -487,7 +626,7 @@ mod tests {
     fn test_text() {
         fn eq(p: &HtmlPart<'_>, t: &'static str) -> bool {
             match p {
-                HtmlPart::Text(v) => *v.fragment() == t,
+                HtmlPart::Text(v) => *v == t,
                 _ => false,
             }
         }
@@ -514,4 +653,38 @@ mod tests {
         .q(R);
         str_parse(&mut None, "<:)---*", parse_html).ok_any().q(R);
     }
+
+    #[test]
+    fn test_attrs() {
+        assert_eq!(tag_name("<a href=x>"), "a");
+        assert_eq!(tag_name("<IMG/>"), "img");
+        assert_eq!(tag_name("<br"), "br");
+
+        assert_eq!(
+            tag_attrs("<a href=\"x y\" target='_top' download class=c>"),
+            vec![
+                ("href", "x y"),
+                ("target", "_top"),
+                ("download", ""),
+                ("class", "c")
+            ]
+        );
+        assert_eq!(tag_attrs("<a href = \"x\"/>"), vec![("href", "x")]);
+        assert_eq!(
+            tag_attrs("<a href=\"unterminated"),
+            vec![("href", "unterminated")]
+        );
+        assert!(tag_attrs("<a>").is_empty());
+
+        assert_eq!(tag_attr("<A HREF=\"x\">", "href"), Some("x"));
+        assert_eq!(tag_attr("<a name=\"x\">", "href"), None);
+    }
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(decode_entities("plain"), "plain");
+        assert_eq!(decode_entities("Fish &amp; Chips"), "Fish & Chips");
+        assert_eq!(decode_entities("caf&eacute; &#65;&#x42;"), "café AB");
+        assert_eq!(decode_entities("a & b &nosuch; &"), "a & b &nosuch; &");
+    }
 }
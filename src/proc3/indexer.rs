@@ -1,6 +1,7 @@
 use crate::index2::tmp_index::TmpWords;
-use crate::proc3::html_parse2::{HtmlCode, HtmlPart};
-use crate::proc3::stop_words::STOP_WORDS;
+use crate::index2::LINK_NS;
+use crate::proc3::html_parse2::{HtmlCode, HtmlPart, TrackSpan};
+use crate::proc3::stop_words::is_stop_word;
 use crate::proc3::txt_parse::TxtPart;
 use crate::proc3::{html_parse2, txt_parse};
 #[allow(unused_imports)]
@@ -8,13 +9,15 @@ use kparse::prelude::TrackProvider;
 #[allow(unused_imports)]
 use kparse::spans::SpanFragment;
 use kparse::Track;
-use std::fs::File;
 use std::io;
 use std::io::Write;
 use std::time::{Duration, Instant};
 #[cfg(feature = "allocator")]
 use tracking_allocator::AllocationGroupToken;
 
+/// Parser steps between two looks at the deadline.
+pub const TIME_CHECK_STEPS: usize = 1000;
+
 pub fn timingr<R>(dur: &mut Duration, fun: impl FnOnce() -> R) -> R {
     let now = Instant::now();
     let result = fun();
@@ -23,7 +26,7 @@ pub fn timingr<R>(dur: &mut Duration, fun: impl FnOnce() -> R) -> R {
 }
 
 pub fn index_txt2(
-    log: &mut File,
+    log: &mut dyn Write,
     #[cfg(feature = "allocator")] tok_txt: &mut AllocationGroupToken,
     #[cfg(feature = "allocator")] tok_tmpwords: &mut AllocationGroupToken,
     relative: &str,
@@ -31,6 +34,7 @@ pub fn index_txt2(
     text: &str,
 ) -> Result<usize, io::Error> {
     let mut n_words = 0usize;
+    tmp_words.text_len += text.len();
 
     #[cfg(feature = "allocator")]
     let guard = tok_txt.enter();
@@ -38,7 +42,13 @@ pub fn index_txt2(
     // let tracker = Track::new_tracker::<TxtCode, _>();
     // let mut input = Track::new_span(&tracker, text);
     let mut input = text;
+    let mut steps = 0usize;
     'l: loop {
+        steps += 1;
+        if out_of_time(log, relative, tmp_words, steps)? {
+            break 'l;
+        }
+
         match txt_parse::parse_txt(input) {
             Ok((rest, v)) => {
                 input = rest;
@@ -49,12 +59,20 @@ pub fn index_txt2(
                 match v {
                     TxtPart::Text(v) => {
                         n_words += 1;
-                        let word = v.to_lowercase();
-                        if STOP_WORDS
-                            .binary_search_by(|probe| (*probe).cmp(word.as_ref()))
-                            .is_ok()
-                        {
-                            continue 'l;
+                        tmp_words.total += 1;
+                        let word = txt_parse::normalize_word(v);
+                        let stop_word = match &tmp_words.stop_list {
+                            Some(stop_list) => stop_list.contains(&word),
+                            None => is_stop_word(&word),
+                        };
+                        if let Some(bigrams) = &mut tmp_words.bigrams {
+                            bigrams.add(&word, stop_word);
+                        }
+                        if stop_word {
+                            tmp_words.stop_count += 1;
+                            if tmp_words.stop_words {
+                                continue 'l;
+                            }
                         }
                         #[cfg(feature = "allocator")]
                         let guard = tok_tmpwords.enter();
@@ -65,12 +83,16 @@ pub fn index_txt2(
                     TxtPart::Eof => {
                         break 'l;
                     }
-                    TxtPart::Tag => {}
-                    TxtPart::Pgp => {}
-                    TxtPart::Base64 => {}
-                    TxtPart::KeyValue => {}
-                    TxtPart::NonText => {}
                     TxtPart::NewLine => {}
+                    TxtPart::Tag
+                    | TxtPart::Pgp
+                    | TxtPart::Base64
+                    | TxtPart::KeyValue
+                    | TxtPart::NonText => {
+                        if let Some(bigrams) = &mut tmp_words.bigrams {
+                            bigrams.reset();
+                        }
+                    }
                 }
             }
             Err(e) => {
@@ -95,7 +117,7 @@ pub fn index_txt2(
 }
 
 pub fn index_html2(
-    log: &mut File,
+    log: &mut dyn Write,
     #[cfg(feature = "allocator")] tok_txt: &mut AllocationGroupToken,
     #[cfg(feature = "allocator")] tok_html: &mut AllocationGroupToken,
     #[cfg(feature = "allocator")] tok_tmpwords: &mut AllocationGroupToken,
@@ -106,68 +128,272 @@ pub fn index_html2(
     #[cfg(feature = "allocator")]
     let guard = tok_html.enter();
 
-    let mut buf = String::with_capacity(text.len());
+    // index the text so far when out of time.
+    let html = extract_html(text, words.attributes, |steps| {
+        out_of_time(log, relative, words, steps)
+    })?;
+    if let Some(offset) = html.failed {
+        println!("{}", relative);
+        // no tracking in the extraction, see html_diagnostics().
+        html_diagnostics(log, relative, text, &text[offset..])?;
+    }
 
-    let tracker = Track::new_tracker::<HtmlCode, _>();
-    let mut input = Track::new_span(&tracker, text);
-    // let mut input = text;
-    'l: loop {
+    for href in &html.links {
+        // links bypass the stop-words.
+        for link in link_words(href) {
+            words.add_word(format!("{}{}", LINK_NS, link));
+        }
+    }
+
+    #[cfg(feature = "allocator")]
+    drop(guard);
+
+    index_txt2(
+        log,
+        #[cfg(feature = "allocator")]
+        tok_txt,
+        #[cfg(feature = "allocator")]
+        tok_tmpwords,
+        relative,
+        words,
+        html.text.as_str(),
+    )?;
+
+    Ok(())
+}
+
+/// Lines of the html for the offsets of the text from extract_html().
+#[derive(Debug, Default)]
+pub struct LineMap {
+    /// Offset in the text and line in the html where a part starts.
+    parts: Vec<(usize, usize)>,
+}
+
+impl LineMap {
+    fn mark(&mut self, offset: usize, line: usize) {
+        self.parts.push((offset, line));
+    }
+
+    /// Line in the html, 0 based. A part keeps its line-breaks, the
+    /// lines after its start are counted in the text.
+    pub fn line(&self, text: &str, offset: usize) -> usize {
+        let idx = self.parts.partition_point(|v| v.0 <= offset);
+        let (start, line) = if idx > 0 { self.parts[idx - 1] } else { (0, 0) };
+        line + text.as_bytes()[start..offset]
+            .iter()
+            .filter(|v| **v == b'\n')
+            .count()
+    }
+}
+
+/// The text of an html file. The same text is indexed and matched
+/// for the found lines.
+#[derive(Debug, Default)]
+pub struct HtmlText {
+    pub text: String,
+    /// Lines in the html.
+    pub lines: LineMap,
+    /// Targets of the links.
+    pub links: Vec<String>,
+    /// Offset where the parser failed, the text stops there.
+    pub failed: Option<usize>,
+}
+
+/// Extracts the text of an html file, for indexing and for the
+/// found lines. With attributes the alt and title attributes are
+/// part of the text, apart from the surrounding text. A word found
+/// there gets its line too.
+///
+/// stop is asked with the number of steps so far, if it says so
+/// the text up to there is returned.
+pub fn extract_html<E>(
+    text: &str,
+    attributes: bool,
+    mut stop: impl FnMut(usize) -> Result<bool, E>,
+) -> Result<HtmlText, E> {
+    let mut html = HtmlText {
+        text: String::with_capacity(text.len()),
+        ..Default::default()
+    };
+    let buf = &mut html.text;
+    let map = &mut html.lines;
+
+    // lines up to line_pos.
+    let mut line = 0usize;
+    let mut line_pos = 0usize;
+    let mut line_at = |pos: usize| {
+        line += text.as_bytes()[line_pos..pos]
+            .iter()
+            .filter(|v| **v == b'\n')
+            .count();
+        line_pos = pos;
+        line
+    };
+
+    let mut input = text;
+    let mut steps = 0usize;
+    loop {
+        steps += 1;
+        if stop(steps)? {
+            break;
+        }
+
+        let pos = text.len() - input.len();
         match html_parse2::parse_html(input) {
             Ok((rest, v)) => {
                 input = rest;
 
-                // let r = tracker.results();
-                // writeln!(log, "{:#?}", r)?;
-
                 match v {
                     HtmlPart::Text(v) => {
-                        buf.push_str(*v.fragment());
+                        map.mark(buf.len(), line_at(pos));
+                        buf.push_str(v);
                     }
-                    HtmlPart::StartTag(_)
-                    | HtmlPart::EndTag(_)
-                    | HtmlPart::DocType(_)
-                    | HtmlPart::Comment(_)
-                    | HtmlPart::CData(_) => {
-                        // ignore
+                    HtmlPart::StartTag(v) => {
+                        if html_parse2::tag_name(v) == "a" {
+                            if let Some(href) = html_parse2::tag_attr(v, "href") {
+                                html.links.push(href.to_string());
+                            }
+                        }
+                        if attributes {
+                            for (name, value) in html_parse2::tag_attrs(v) {
+                                if name.eq_ignore_ascii_case("alt")
+                                    || name.eq_ignore_ascii_case("title")
+                                {
+                                    let value_pos =
+                                        value.as_ptr() as usize - text.as_ptr() as usize;
+                                    buf.push(' ');
+                                    map.mark(buf.len(), line_at(value_pos));
+                                    buf.push_str(&html_parse2::decode_entities(value));
+                                    buf.push(' ');
+                                }
+                            }
+                        }
                     }
                     HtmlPart::CharRef(v) => {
+                        map.mark(buf.len(), line_at(pos));
                         buf.push(v);
                     }
                     HtmlPart::CharRefStr(v) => {
+                        map.mark(buf.len(), line_at(pos));
                         buf.push_str(v);
                     }
-                    HtmlPart::Eof => {
-                        break 'l;
-                    }
-                    HtmlPart::ParseError(_) => {}
+                    HtmlPart::EndTag(_)
+                    | HtmlPart::DocType(_)
+                    | HtmlPart::Comment(_)
+                    | HtmlPart::CData(_)
+                    | HtmlPart::ParseError(_) => {}
+                    HtmlPart::Eof => break,
                 }
             }
-            Err(e) => {
-                println!("{}", relative);
-                println!("{:#?}", e);
-
-                writeln!(log, "{}", relative)?;
-                writeln!(log, "{:#?}", e)?;
+            Err(_) => {
+                html.failed = Some(pos);
+                break;
+            }
+        }
+    }
 
-                let r = tracker.results();
-                // println!("{:#?}", r);
-                writeln!(log, "{:#?}", r)?;
+    Ok(html)
+}
 
-                break 'l;
-            }
+/// Looks at the deadline every TIME_CHECK_STEPS steps.
+/// The first time it's reached the file is logged.
+fn out_of_time(
+    log: &mut dyn Write,
+    relative: &str,
+    words: &mut TmpWords,
+    steps: usize,
+) -> Result<bool, io::Error> {
+    if steps % TIME_CHECK_STEPS != 0 {
+        return Ok(false);
+    }
+    let partial = words.partial;
+    if words.out_of_time() {
+        if !partial {
+            writeln!(log, "{} partially indexed, out of time", relative)?;
         }
+        Ok(true)
+    } else {
+        Ok(false)
     }
+}
 
-    index_txt2(
-        log,
-        #[cfg(feature = "allocator")]
-        tok_txt,
-        #[cfg(feature = "allocator")]
-        tok_tmpwords,
-        relative,
-        words,
-        buf.as_str(),
-    )?;
+/// Parses the failing part again, this time with tracking,
+/// and writes the error and the tracks to the log.
+///
+/// The parser works step by step without state, so the step that
+/// failed is enough to reproduce the error.
+pub fn html_diagnostics(
+    log: &mut dyn Write,
+    relative: &str,
+    text: &str,
+    failed: &str,
+) -> Result<(), io::Error> {
+    let offset = text.len() - failed.len();
+
+    let tracker = Track::new_tracker::<HtmlCode, _>();
+    let span: TrackSpan<'_> = tracker.track_span(failed);
+    let result = html_parse2::parse_html(span);
+
+    writeln!(log, "{} at {}", relative, offset)?;
+    match result {
+        Ok(_) => writeln!(log, "no error with tracking")?,
+        Err(e) => writeln!(log, "{:#?}", e)?,
+    }
+    writeln!(log, "{:#?}", tracker.results())?;
 
     Ok(())
 }
+
+/// Splits a link-target into the host and the path segments.
+/// Query and fragment are dropped, and only web-links are used.
+///
+/// "https://www.example.com/docs/page.html?q=1" gives
+/// "example.com", "www.example.com", "docs", "page.html".
+pub fn link_words(href: &str) -> Vec<String> {
+    let mut words = Vec::new();
+
+    let href = href.trim();
+    let href = href.split(['?', '#']).next().unwrap_or("");
+
+    let (host, path) = if let Some(idx) = href.find("://") {
+        let scheme = href[..idx].to_ascii_lowercase();
+        if !matches!(scheme.as_str(), "http" | "https" | "ftp") {
+            return words;
+        }
+        split_host(&href[idx + 3..])
+    } else if let Some(rest) = href.strip_prefix("//") {
+        split_host(rest)
+    } else if href.split('/').next().unwrap_or("").contains(':') {
+        // mailto:, javascript: ...
+        return words;
+    } else {
+        ("", href)
+    };
+
+    // no user, no port
+    let host = host.rsplit('@').next().unwrap_or("");
+    let host = host.split(':').next().unwrap_or("");
+    let host = host.trim_end_matches('.').to_lowercase();
+    if !host.is_empty() {
+        if let Some(short) = host.strip_prefix("www.") {
+            words.push(short.to_string());
+        }
+        words.push(host);
+    }
+
+    for segment in path.split('/') {
+        match segment {
+            "" | "." | ".." => {}
+            v => words.push(v.to_lowercase()),
+        }
+    }
+
+    words
+}
+
+fn split_host(v: &str) -> (&str, &str) {
+    match v.find('/') {
+        Some(idx) => (&v[..idx], &v[idx..]),
+        None => (v, ""),
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Messages kept in memory.
+pub const LOG_RING_LEN: usize = 100;
+
+/// Similar messages written as they are, the rest is only counted.
+pub const LOG_SIMILAR: usize = 3;
+
+/// A summary line for every this many suppressed messages.
+pub const LOG_SIMILAR_EVERY: usize = 100;
+
+/// The log-file, with the last messages kept in memory.
+///
+/// Logging never fails. If the file can't be opened or written, with a
+/// full disk for instance, the messages are only kept in memory and the
+/// failures are counted.
+///
+/// Messages about a file are grouped by the message with the file
+/// and the numbers taken out, and by the directory of the file. Of
+/// each group only the first few are written, the rest is summarized.
+pub struct Logger {
+    inner: Mutex<LogInner>,
+}
+
+struct LogInner {
+    out: Option<Box<dyn Write + Send>>,
+    ring: VecDeque<String>,
+    failures: usize,
+    last_failure: Option<String>,
+    /// By template and directory.
+    similar: HashMap<(String, String), Similar>,
+}
+
+/// Messages of one group.
+#[derive(Debug, Default)]
+struct Similar {
+    count: usize,
+    /// Suppressed since the last summary.
+    pending: usize,
+}
+
+/// A group of similar messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimilarCount {
+    /// The message with `{}` for the file and `#` for numbers.
+    pub template: String,
+    pub dir: String,
+    pub count: usize,
+}
+
+impl Logger {
+    /// Appends to the log-file. Without the file only the memory is used.
+    pub fn open(path: &Path) -> Self {
+        match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(f) => Self::new(Some(Box::new(f))),
+            Err(e) => {
+                let log = Self::new(None);
+                log.fail(&e);
+                log
+            }
+        }
+    }
+
+    pub fn new(out: Option<Box<dyn Write + Send>>) -> Self {
+        Self {
+            inner: Mutex::new(LogInner {
+                out,
+                ring: VecDeque::with_capacity(LOG_RING_LEN),
+                failures: 0,
+                last_failure: None,
+                similar: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Writes one message.
+    pub fn log<S: Into<String>>(&self, msg: S) {
+        let mut suppressed = false;
+        self.log_similar(msg.into(), &mut suppressed);
+    }
+
+    /// Writes the message unless there were too many similar ones.
+    ///
+    /// A message without a file continues the one before, and is
+    /// dropped if that one was suppressed.
+    fn log_similar(&self, msg: String, suppressed: &mut bool) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+
+        let Some(key) = similar_key(&msg) else {
+            if !*suppressed {
+                inner.write(msg);
+            }
+            return;
+        };
+
+        let group = inner.similar.entry(key.clone()).or_default();
+        group.count += 1;
+        *suppressed = group.count > LOG_SIMILAR;
+        if *suppressed {
+            group.pending += 1;
+            if group.pending >= LOG_SIMILAR_EVERY {
+                group.pending = 0;
+                inner.write(summary(&key.1, LOG_SIMILAR_EVERY));
+            }
+        } else {
+            inner.write(msg);
+        }
+    }
+
+    /// Writes the summary for the messages suppressed since the
+    /// last one.
+    pub fn flush_similar(&self) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        let mut pending: Vec<_> = inner
+            .similar
+            .iter_mut()
+            .filter(|(_, v)| v.pending > 0)
+            .map(|((_, dir), v)| (dir.clone(), std::mem::take(&mut v.pending)))
+            .collect();
+        pending.sort();
+        for (dir, n) in pending {
+            inner.write(summary(&dir, n));
+        }
+    }
+
+    /// The groups with suppressed messages, the largest first.
+    pub fn similar(&self) -> Vec<SimilarCount> {
+        let Ok(inner) = self.inner.lock() else {
+            return Vec::new();
+        };
+        let mut similar: Vec<_> = inner
+            .similar
+            .iter()
+            .filter(|(_, v)| v.count > LOG_SIMILAR)
+            .map(|((template, dir), v)| SimilarCount {
+                template: template.clone(),
+                dir: dir.clone(),
+                count: v.count,
+            })
+            .collect();
+        similar.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.dir.cmp(&b.dir))
+                .then_with(|| a.template.cmp(&b.template))
+        });
+        similar
+    }
+
+    /// For write!(). Each line is one message.
+    pub fn writer(&self) -> LogWriter<'_> {
+        LogWriter {
+            log: self,
+            buf: String::new(),
+            suppressed: false,
+        }
+    }
+
+    /// The last messages, oldest first.
+    pub fn recent(&self) -> Vec<String> {
+        match self.inner.lock() {
+            Ok(inner) => inner.ring.iter().cloned().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Failed writes to the log-file.
+    pub fn failures(&self) -> usize {
+        self.inner.lock().map(|v| v.failures).unwrap_or(0)
+    }
+
+    pub fn last_failure(&self) -> Option<String> {
+        self.inner.lock().ok().and_then(|v| v.last_failure.clone())
+    }
+
+    fn fail(&self, e: &io::Error) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.failures += 1;
+            inner.last_failure = Some(e.to_string());
+        }
+    }
+}
+
+impl LogInner {
+    fn write(&mut self, msg: String) {
+        let written = match &mut self.out {
+            Some(out) => writeln!(out, "{}", msg).and_then(|_| out.flush()),
+            None => Ok(()),
+        };
+        if let Err(e) = written {
+            self.failures += 1;
+            self.last_failure = Some(e.to_string());
+        }
+
+        if self.ring.len() >= LOG_RING_LEN {
+            self.ring.pop_front();
+        }
+        self.ring.push_back(msg);
+    }
+}
+
+fn summary(dir: &str, n: usize) -> String {
+    let dir = if dir.is_empty() { "." } else { dir };
+    format!("... and {} similar in {}", n, dir)
+}
+
+/// The group of a message about a file, None for any other message.
+///
+/// The first word that looks like a file is replaced by `{}` and runs
+/// of digits by `#`. The directory of the file is the second part.
+/// Only the first line of a message counts.
+fn similar_key(msg: &str) -> Option<(String, String)> {
+    let first = msg.lines().next().unwrap_or_default();
+    let path = first.split_whitespace().find(|v| is_path(v))?;
+    let start = path.as_ptr() as usize - first.as_ptr() as usize;
+    let end = start + path.len();
+
+    let dir = match path.rfind(['/', '\\']) {
+        Some(idx) => &path[..idx],
+        None => "",
+    };
+
+    let mut template = String::new();
+    let mut digits = false;
+    for (idx, c) in first.char_indices() {
+        if idx == start {
+            template.push_str("{}");
+            digits = false;
+        }
+        if idx >= start && idx < end {
+            continue;
+        }
+        if c.is_ascii_digit() {
+            if !digits {
+                template.push('#');
+            }
+            digits = true;
+        } else {
+            template.push(c);
+            digits = false;
+        }
+    }
+
+    Some((template, dir.to_string()))
+}
+
+/// Contains a path separator or ends with a file extension.
+fn is_path(word: &str) -> bool {
+    if word.contains(['/', '\\']) {
+        return true;
+    }
+    match word.rsplit_once('.') {
+        Some((name, ext)) => {
+            !name.is_empty()
+                && ext.chars().all(|c| c.is_ascii_alphanumeric())
+                && ext.chars().any(|c| c.is_ascii_alphabetic())
+        }
+        None => false,
+    }
+}
+
+/// Collects the output of write!() and logs complete lines.
+/// Never returns an error.
+///
+/// Lines without a file continue the last message about a file
+/// and are dropped with it.
+pub struct LogWriter<'a> {
+    log: &'a Logger,
+    buf: String,
+    /// The last message about a file was suppressed.
+    suppressed: bool,
+}
+
+impl<'a> Write for LogWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.push_str(&String::from_utf8_lossy(buf));
+        if self.buf.ends_with('\n') {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            let msg = std::mem::take(&mut self.buf);
+            let msg = msg.trim_end_matches('\n').to_string();
+            self.log.log_similar(msg, &mut self.suppressed);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Drop for LogWriter<'a> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
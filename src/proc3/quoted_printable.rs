@@ -0,0 +1,142 @@
+//! Quoted-printable bodies of mails and news, saved as plain text.
+
+use std::borrow::Cow;
+
+/// At least this many =XX escapes.
+const MIN_ESCAPES: usize = 3;
+
+/// At least one in this many lines has an escape or a soft line-break.
+const LINE_SHARE: usize = 20;
+
+/// Text of a loaded file. Quoted-printable is decoded, which is
+/// reported with the flag.
+///
+/// The charset of the decoded bytes is unknown. If they are not UTF-8
+/// they are taken as Latin-1.
+pub fn decode_text(buf: &[u8]) -> (Cow<'_, str>, bool) {
+    if !is_quoted_printable(buf) {
+        return (String::from_utf8_lossy(buf), false);
+    }
+
+    let txt = match String::from_utf8(decode_quoted_printable(buf)) {
+        Ok(v) => v,
+        Err(e) => e.into_bytes().into_iter().map(char::from).collect(),
+    };
+    (Cow::Owned(txt), true)
+}
+
+/// Guesses by the share of lines with =XX escapes or soft line-breaks.
+///
+/// Only 7-bit text can be quoted-printable, and the escapes must use
+/// uppercase hex as the encoders do.
+pub fn is_quoted_printable(buf: &[u8]) -> bool {
+    if !buf.is_ascii() {
+        return false;
+    }
+
+    let mut lines = 0;
+    let mut marked = 0;
+    let mut escapes = 0;
+    for line in buf.split(|v| *v == b'\n') {
+        lines += 1;
+
+        let n = count_escapes(line);
+        escapes += n;
+        if n > 0 || soft_break(line).is_some() {
+            marked += 1;
+        }
+    }
+
+    escapes >= MIN_ESCAPES && marked * LINE_SHARE >= lines
+}
+
+/// Decodes the escapes and joins the soft line-breaks.
+/// Malformed escapes are passed through.
+///
+/// The newline of a soft line-break is moved behind the joined line,
+/// this way each line stays at the line number it has in the file.
+pub fn decode_quoted_printable(buf: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(buf.len());
+
+    let mut deferred = 0;
+    let mut lines = buf.split(|v| *v == b'\n').peekable();
+    while let Some(line) = lines.next() {
+        let last = lines.peek().is_none();
+        let soft = soft_break(line);
+
+        match soft {
+            Some(body) => {
+                decode_escapes(body, &mut out);
+                if !last {
+                    deferred += 1;
+                }
+            }
+            None => {
+                let (body, cr) = match line.strip_suffix(b"\r") {
+                    Some(body) => (body, true),
+                    None => (line, false),
+                };
+                decode_escapes(body, &mut out);
+                if !last {
+                    if cr {
+                        out.push(b'\r');
+                    }
+                    out.push(b'\n');
+                }
+            }
+        }
+
+        if deferred > 0 && (last || soft.is_none()) {
+            out.resize(out.len() + deferred, b'\n');
+            deferred = 0;
+        }
+    }
+
+    out
+}
+
+/// The line without the trailing = and whitespace, if it ends
+/// with a soft line-break.
+fn soft_break(line: &[u8]) -> Option<&[u8]> {
+    let end = line
+        .iter()
+        .rposition(|v| !matches!(*v, b' ' | b'\t' | b'\r'))
+        .map(|v| v + 1)
+        .unwrap_or(0);
+    line[..end].strip_suffix(b"=")
+}
+
+fn count_escapes(line: &[u8]) -> usize {
+    line.windows(3)
+        .filter(|v| v[0] == b'=' && is_upper_hex(v[1]) && is_upper_hex(v[2]))
+        .count()
+}
+
+fn decode_escapes(line: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < line.len() {
+        if line[i] == b'=' && i + 2 < line.len() {
+            if let (Some(hi), Some(lo)) = (hex_value(line[i + 1]), hex_value(line[i + 2])) {
+                out.push((hi << 4) | lo);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(line[i]);
+        i += 1;
+    }
+}
+
+fn is_upper_hex(c: u8) -> bool {
+    c.is_ascii_digit() || (b'A'..=b'F').contains(&c)
+}
+
+/// Decoding accepts lowercase too.
+fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        _ => None,
+    }
+}
@@ -1,3 +1,48 @@
+use std::collections::HashSet;
+use std::io;
+
+/// Replaces the built-in stop-words.
+pub const STOP_WORDS_FILE: &str = "stopwords.txt";
+
+/// One word per line. Empty lines and lines starting with # are skipped.
+/// A line with more than one word would never match and fails.
+pub fn parse_stop_words(txt: &str) -> Result<HashSet<String>, io::Error> {
+    let mut words = HashSet::new();
+    for (nr, line) in txt.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.contains(char::is_whitespace) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("stop-words line {}: more than one word '{}'", nr + 1, line),
+            ));
+        }
+        words.insert(line.to_lowercase());
+    }
+    Ok(words)
+}
+
+/// The stop-words in the format of the file.
+pub fn format_stop_words<'a>(words: impl Iterator<Item = &'a str>) -> String {
+    let mut words = words.collect::<Vec<_>>();
+    words.sort();
+    let mut txt = String::new();
+    for word in words {
+        txt.push_str(word);
+        txt.push('\n');
+    }
+    txt
+}
+
+/// Is this a stop-word. Expects a lowercase word.
+pub fn is_stop_word(word: &str) -> bool {
+    STOP_WORDS
+        .binary_search_by(|probe| (*probe).cmp(word))
+        .is_ok()
+}
+
 pub const STOP_WORDS: &[&str] = &[
     "a",
     "ab",
@@ -1,20 +1,28 @@
 use crate::error::AppError;
 use crate::index2::tmp_index::TmpWords;
+use crate::proc3::events::Value;
+use crate::proc3::logger::Logger;
 use crate::proc3::{
-    auto_save, delete_file, indexing, load_file, merge_words, name_filter, print_, print_err_,
-    Data, FileFilter,
+    auto_save, delete_file, delete_files, exclude_files, indexing, load_file, merge_words, print_,
+    print_err_, start_verify, stop_word_summary, verify_step, Data, FileFilter, MemTicket,
+    VERIFY_TICK,
 };
-use crossbeam::channel::{bounded, Receiver, Sender, TryRecvError};
+use crossbeam::channel::{bounded, Receiver, RecvTimeoutError, Sender, TryRecvError, TrySendError};
 use rustyline::ExternalPrinter;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::io;
 use std::io::Write;
 use std::iter::Flatten;
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
 use std::thread;
-use std::thread::JoinHandle;
+use std::thread::{sleep, JoinHandle};
+use std::time::{Duration, Instant};
 #[cfg(feature = "allocator")]
 use tracking_allocator::AllocationGroupToken;
-use walkdir::WalkDir;
+use walkdir::{DirEntry, FilterEntry, WalkDir};
 
 #[derive(Debug)]
 pub enum Msg {
@@ -23,31 +31,130 @@ pub enum Msg {
     WalkFinished(PathBuf),
     Load(u32, FileFilter, PathBuf, String),
     Index(u32, FileFilter, PathBuf, String, Vec<u8>),
-    MergeWords(u32, TmpWords),
+    /// The ticket keeps the words counted in MemUsage until they
+    /// are merged or dropped.
+    MergeWords(u32, TmpWords, MemTicket),
     DeleteFile(String),
-    Debug,
+    /// Deletes a batch of files at once. A running walk
+    /// doesn't index them again.
+    DeleteFiles(Vec<String>),
+    /// Each stage prints a line with the id, the terminal worker
+    /// adds "debug <id> complete".
+    Debug(u64),
     AutoSave,
+    /// Starts the background verify in the terminal worker.
+    Verify,
+    /// Each stage passes it on and waits for the Resume. Debug and
+    /// Quit are still answered, AutoSave goes through, everything
+    /// else is held back.
+    Pause,
+    Resume,
 }
 
 #[derive(Default)]
 pub struct WorkerState {
     pub state: u64,
     pub msg: String,
+    /// Waiting after a Pause.
+    pub paused: bool,
+}
+
+/// Kind of worker. Each kind serves one stage of the pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WorkerKind {
+    Walk,
+    Load,
+    Index,
+    Merge,
+    Terminal,
+}
+
+impl WorkerKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            WorkerKind::Walk => "walk",
+            WorkerKind::Load => "load",
+            WorkerKind::Index => "index",
+            WorkerKind::Merge => "merge",
+            WorkerKind::Terminal => "terminal",
+        }
+    }
+}
+
+/// Id of a worker. The nr counts from 1 for each kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WorkerId {
+    pub kind: WorkerKind,
+    pub nr: u32,
+}
+
+impl Display for WorkerId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.kind.name(), self.nr)
+    }
 }
 
 pub struct Worker {
-    pub name: &'static str,
+    pub id: WorkerId,
+    /// Incremented with each respawn.
+    pub incarnation: u32,
     pub handle: JoinHandle<()>,
     pub state: Arc<Mutex<WorkerState>>,
 }
 
-impl Worker {
-    pub fn new(name: &'static str, handle: JoinHandle<()>, state: Arc<Mutex<WorkerState>>) -> Self {
-        Self {
-            name,
+/// All workers in the order they were registered.
+/// A respawned worker keeps its id and state.
+#[derive(Default)]
+pub struct WorkerRegistry {
+    workers: Vec<Worker>,
+}
+
+impl WorkerRegistry {
+    /// Next free id for this kind of worker.
+    pub fn next_id(&self, kind: WorkerKind) -> WorkerId {
+        let nr = self.workers.iter().filter(|v| v.id.kind == kind).count() as u32 + 1;
+        WorkerId { kind, nr }
+    }
+
+    /// Add a new worker.
+    pub fn register(
+        &mut self,
+        kind: WorkerKind,
+        handle: JoinHandle<()>,
+        state: Arc<Mutex<WorkerState>>,
+    ) -> WorkerId {
+        let id = self.next_id(kind);
+        self.workers.push(Worker {
+            id,
+            incarnation: 0,
             handle,
             state,
-        }
+        });
+        id
+    }
+
+    /// Replace the thread of an existing worker. Returns the new incarnation.
+    pub fn replace(&mut self, id: WorkerId, handle: JoinHandle<()>) -> Option<u32> {
+        let worker = self.workers.iter_mut().find(|v| v.id == id)?;
+        worker.handle = handle;
+        worker.incarnation += 1;
+        Some(worker.incarnation)
+    }
+
+    pub fn get(&self, id: WorkerId) -> Option<&Worker> {
+        self.workers.iter().find(|v| v.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Worker> {
+        self.workers.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
     }
 }
 
@@ -55,14 +162,218 @@ pub struct Work {
     pub send: Sender<Msg>,
     pub recv_send: [(Receiver<Msg>, Sender<Msg>); 4],
     pub recv: Receiver<Msg>,
-    pub workers: [Worker; 8],
+    pub workers: Mutex<WorkerRegistry>,
 
     pub printer: Arc<Mutex<dyn ExternalPrinter + Send>>,
+
+    /// Indexers running. They share one queue, the last one
+    /// to quit passes the Quit on.
+    index_alive: Arc<AtomicUsize>,
+    /// Indexers paused. The last one to pause passes the Pause on,
+    /// the last one to resume the Resume.
+    index_paused: Arc<AtomicUsize>,
+    /// Pause sent and no Resume yet. The loader checks it while it
+    /// waits for the mem-limit.
+    paused: Arc<AtomicBool>,
+    /// Quit sent, stopped workers are not restarted.
+    quitting: Arc<AtomicBool>,
+    /// Last id of a Msg::Debug.
+    debug_id: AtomicU64,
+
+    data: &'static Data,
+}
+
+impl Work {
+    fn spawn_kind(&self, kind: WorkerKind, state: Arc<Mutex<WorkerState>>) -> JoinHandle<()> {
+        let printer = self.printer.clone();
+        match kind {
+            WorkerKind::Walk => spawn_walking(
+                self.recv_send[0].0.clone(),
+                self.recv_send[0].1.clone(),
+                state,
+                self.data,
+                printer,
+            ),
+            WorkerKind::Load => spawn_loading(
+                self.recv_send[1].0.clone(),
+                self.recv_send[1].1.clone(),
+                Arc::clone(&self.quitting),
+                Arc::clone(&self.paused),
+                state,
+                self.data,
+                printer,
+            ),
+            WorkerKind::Index => {
+                self.index_alive.fetch_add(1, Ordering::AcqRel);
+                spawn_indexing(
+                    self.recv_send[2].0.clone(),
+                    self.recv_send[2].1.clone(),
+                    self.recv_send[1].1.clone(),
+                    Arc::clone(&self.index_alive),
+                    Arc::clone(&self.index_paused),
+                    state,
+                    self.data,
+                    printer,
+                )
+            }
+            WorkerKind::Merge => spawn_merge_words(
+                self.recv_send[3].0.clone(),
+                self.recv_send[3].1.clone(),
+                state,
+                self.data,
+                printer,
+            ),
+            WorkerKind::Terminal => spawn_terminal(self.recv.clone(), state, self.data, printer),
+        }
+    }
+
+    /// Start a new worker.
+    pub fn spawn(&self, kind: WorkerKind) -> WorkerId {
+        let state = Arc::new(Mutex::new(WorkerState::default()));
+        let handle = self.spawn_kind(kind, Arc::clone(&state));
+        self.workers
+            .lock()
+            .expect("workers")
+            .register(kind, handle, state)
+    }
+
+    /// Restart a worker whose thread has finished.
+    /// Returns the new incarnation or None if the worker is still running.
+    pub fn respawn(&self, id: WorkerId) -> Option<u32> {
+        let mut workers = self.workers.lock().expect("workers");
+
+        let state = match workers.get(id) {
+            Some(w) if w.handle.is_finished() => Arc::clone(&w.state),
+            _ => return None,
+        };
+        // a panic leaves it poisoned.
+        *state.lock().unwrap_or_else(PoisonError::into_inner) = WorkerState::default();
+        state.clear_poison();
+
+        let handle = self.spawn_kind(id.kind, state);
+        workers.replace(id, handle)
+    }
+
+    /// Restart all workers that stopped on an error or a panic.
+    /// Does nothing after quit().
+    pub fn respawn_stopped(&self) -> Vec<(WorkerId, u32)> {
+        if self.quitting.load(Ordering::Acquire) {
+            return Vec::new();
+        }
+        let stopped: Vec<WorkerId> = match self.workers.lock() {
+            Ok(workers) => workers
+                .iter()
+                .filter(|w| w.handle.is_finished())
+                .map(|w| w.id)
+                .collect(),
+            Err(_) => return Vec::new(),
+        };
+        stopped
+            .into_iter()
+            .filter_map(|id| Some((id, self.respawn(id)?)))
+            .collect()
+    }
+
+    /// Sends the Quit through the pipeline.
+    pub fn quit(&self) -> Result<(), AppError> {
+        self.quitting.store(true, Ordering::Release);
+        self.send.send(Msg::Quit)?;
+        Ok(())
+    }
+
+    /// Holds the pipeline, see Msg::Pause.
+    /// Returns false if it is paused already.
+    pub fn pause(&self) -> Result<bool, AppError> {
+        if self.paused.swap(true, Ordering::AcqRel) {
+            return Ok(false);
+        }
+        self.send.send(Msg::Pause)?;
+        Ok(true)
+    }
+
+    /// Returns false if it isn't paused.
+    pub fn resume(&self) -> Result<bool, AppError> {
+        if !self.paused.swap(false, Ordering::AcqRel) {
+            return Ok(false);
+        }
+        self.send.send(Msg::Resume)?;
+        Ok(true)
+    }
+
+    /// Sends a Msg::Debug through the pipeline and returns its id.
+    pub fn debug(&self) -> Result<u64, AppError> {
+        let id = self.debug_id.fetch_add(1, Ordering::AcqRel) + 1;
+        self.send.send(Msg::Debug(id))?;
+        Ok(id)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Acquire)
+    }
+
+    /// Every running worker has reached the waiting state.
+    pub fn all_paused(&self) -> bool {
+        let Ok(workers) = self.workers.lock() else {
+            return false;
+        };
+        workers
+            .iter()
+            .all(|w| w.handle.is_finished() || w.state.lock().expect("state").paused)
+    }
+
+    /// Waits until all workers have finished, after a Quit.
+    /// Returns false after the timeout.
+    pub fn wait_finished(&self, timeout: Duration) -> bool {
+        let wait = Instant::now();
+        let Ok(workers) = self.workers.lock() else {
+            return false;
+        };
+        for w in workers.iter() {
+            while !w.handle.is_finished() {
+                if wait.elapsed() >= timeout {
+                    return false;
+                }
+                sleep(Duration::from_millis(1));
+            }
+        }
+        true
+    }
+}
+
+/// The printer of the workers.
+///
+/// Once the terminal is gone, rustyline closes the channel of its
+/// printer when the REPL exits, the messages go to the log instead.
+/// This is noted once on stderr and in the log.
+struct FallbackPrinter<P> {
+    printer: P,
+    log: &'static Logger,
+    gone: bool,
+}
+
+impl<P: ExternalPrinter> ExternalPrinter for FallbackPrinter<P> {
+    /// Fails if the message only went to the log.
+    fn print(&mut self, msg: String) -> rustyline::Result<()> {
+        if !self.gone {
+            match self.printer.print(msg.clone()) {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    self.gone = true;
+                    let note = format!("terminal is gone, messages go to the log: {:?}", e);
+                    eprintln!("{}", note);
+                    self.log.log(note);
+                }
+            }
+        }
+        self.log.log(msg);
+        Err(io::ErrorKind::BrokenPipe.into())
+    }
 }
 
 pub fn init_work<P: ExternalPrinter + Send + Sync + 'static>(
     printer: P,
     data: &'static Data,
+    n_index: usize,
 ) -> Work {
     #[cfg(feature = "allocator")]
     let mut local_token = AllocationGroupToken::register().expect("token");
@@ -71,7 +382,11 @@ pub fn init_work<P: ExternalPrinter + Send + Sync + 'static>(
     #[cfg(feature = "allocator")]
     let local_guard = local_token.enter();
 
-    let printer = Arc::new(Mutex::new(printer));
+    let printer: Arc<Mutex<dyn ExternalPrinter + Send>> = Arc::new(Mutex::new(FallbackPrinter {
+        printer,
+        log: &data.log,
+        gone: false,
+    }));
 
     let (s0, r1) = bounded::<Msg>(10);
     let (s1, r2) = bounded::<Msg>(10);
@@ -79,92 +394,86 @@ pub fn init_work<P: ExternalPrinter + Send + Sync + 'static>(
     let (s3, r4) = bounded::<Msg>(10);
     let (s4, r5) = bounded::<Msg>(10);
 
-    let n1 = "walking";
-    let st1 = Arc::new(Mutex::new(WorkerState::default()));
-    let h1 = spawn_walking(
-        r1.clone(),
-        s1.clone(),
-        Arc::clone(&st1),
-        data,
-        printer.clone(),
-    );
-    let n2 = "loading";
-    let st2 = Arc::new(Mutex::new(WorkerState::default()));
-    let h2 = spawn_loading(
-        r2.clone(),
-        s2.clone(),
-        Arc::clone(&st2),
-        data,
-        printer.clone(),
-    );
-    let n3_1 = "index 1";
-    let st3_1 = Arc::new(Mutex::new(WorkerState::default()));
-    let h3_1 = spawn_indexing(
-        r3.clone(),
-        s3.clone(),
-        Arc::clone(&st3_1),
-        data,
-        printer.clone(),
-    );
-    let n3_2 = "index 2";
-    let st3_2 = Arc::new(Mutex::new(WorkerState::default()));
-    let h3_2 = spawn_indexing(
-        r3.clone(),
-        s3.clone(),
-        Arc::clone(&st3_2),
-        data,
-        printer.clone(),
-    );
-    let n3_3 = "index 3";
-    let st3_3 = Arc::new(Mutex::new(WorkerState::default()));
-    let h3_3 = spawn_indexing(
-        r3.clone(),
-        s3.clone(),
-        Arc::clone(&st3_3),
-        data,
-        printer.clone(),
-    );
-    let n3_4 = "index 4";
-    let st3_4 = Arc::new(Mutex::new(WorkerState::default()));
-    let h3_4 = spawn_indexing(
-        r3.clone(),
-        s3.clone(),
-        Arc::clone(&st3_4),
-        data,
-        printer.clone(),
-    );
-    let n4 = "merge";
-    let st4 = Arc::new(Mutex::new(WorkerState::default()));
-    let h4 = spawn_merge_words(
-        r4.clone(),
-        s4.clone(),
-        Arc::clone(&st4),
+    let work = Work {
+        send: s0,
+        recv_send: [(r1, s1), (r2, s2), (r3, s3), (r4, s4)],
+        recv: r5,
+        workers: Mutex::new(WorkerRegistry::default()),
+        printer,
+        index_alive: Arc::new(AtomicUsize::new(0)),
+        index_paused: Arc::new(AtomicUsize::new(0)),
+        paused: Arc::new(AtomicBool::new(false)),
+        quitting: Arc::new(AtomicBool::new(false)),
+        debug_id: AtomicU64::new(0),
         data,
-        printer.clone(),
-    );
-    let n5 = "terminal";
-    let st5 = Arc::new(Mutex::new(WorkerState::default()));
-    let h5 = spawn_terminal(r5.clone(), Arc::clone(&st5), data, printer.clone());
+    };
+
+    work.spawn(WorkerKind::Walk);
+    work.spawn(WorkerKind::Load);
+    for _ in 0..n_index {
+        work.spawn(WorkerKind::Index);
+    }
+    work.spawn(WorkerKind::Merge);
+    work.spawn(WorkerKind::Terminal);
 
     #[cfg(feature = "allocator")]
     drop(local_guard);
 
-    Work {
-        send: s0,
-        recv_send: [(r1, s1), (r2, s2), (r3, s3), (r4, s4)],
-        recv: r5,
-        workers: [
-            Worker::new(n1, h1, st1),
-            Worker::new(n2, h2, st2),
-            Worker::new(n3_1, h3_1, st3_1),
-            Worker::new(n3_2, h3_2, st3_2),
-            Worker::new(n3_3, h3_3, st3_3),
-            Worker::new(n3_4, h3_4, st3_4),
-            Worker::new(n4, h4, st4),
-            Worker::new(n5, h5, st5),
-        ],
-        printer,
+    work
+}
+
+/// An indexer waits this long before it passes a Pause or Resume
+/// meant for the other indexers back to the queue.
+const PAUSE_RETRY: Duration = Duration::from_millis(5);
+
+/// What ended the waiting state.
+enum PauseEnd {
+    Resume,
+    Quit,
+}
+
+/// The line of one stage for a Msg::Debug.
+fn debug_line(id: u64, line: impl Display) -> String {
+    format!("debug {}: {}", id, line)
+}
+
+/// Waiting state after a Pause. Debug, AutoSave and a repeated Pause
+/// go to answer, everything else is held back until the Resume.
+fn wait_paused(
+    recv: &Receiver<Msg>,
+    state: &Arc<Mutex<WorkerState>>,
+    held: &mut VecDeque<Msg>,
+    mut answer: impl FnMut(Msg) -> Result<(), AppError>,
+) -> Result<PauseEnd, AppError> {
+    state.lock().unwrap().paused = true;
+    let end = loop {
+        match recv.recv()? {
+            Msg::Resume => break PauseEnd::Resume,
+            Msg::Quit => break PauseEnd::Quit,
+            msg @ (Msg::Debug(_) | Msg::AutoSave | Msg::Pause) => answer(msg)?,
+            msg => held.push_back(msg),
+        }
+    };
+    state.lock().unwrap().paused = false;
+    Ok(end)
+}
+
+/// Answer of the loader and the merge while paused.
+fn pass_paused(
+    printer: &Arc<Mutex<dyn ExternalPrinter + Send>>,
+    send: &Sender<Msg>,
+    msg: Msg,
+    debug: String,
+) -> Result<(), AppError> {
+    match msg {
+        Msg::Pause => {}
+        Msg::Debug(id) => {
+            print_(printer, debug_line(id, format!("{} paused", debug)));
+            send.send(Msg::Debug(id))?;
+        }
+        msg => send.send(msg)?,
     }
+    Ok(())
 }
 
 fn spawn_walking(
@@ -184,7 +493,7 @@ fn spawn_walking(
 
         print_err_(
             &printer,
-            data.log.try_clone().unwrap(),
+            data,
             "walker",
             walk_proc(recv, send, state, data, &printer),
         );
@@ -196,10 +505,26 @@ fn spawn_walking(
 
 struct WalkingProc {
     path: PathBuf,
-    tree_iter: Flatten<walkdir::IntoIter>,
+    tree_iter: Flatten<FilterEntry<walkdir::IntoIter, fn(&DirEntry) -> bool>>,
     count: u32,
 }
 
+/// Skips hidden files and doesn't descend into dot-directories.
+/// The root is walked even if it's hidden itself.
+fn is_visible(entry: &DirEntry) -> bool {
+    entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.')
+}
+
+fn is_any(_entry: &DirEntry) -> bool {
+    true
+}
+
+/// Pause of the walker when the next stage is full.
+const WALK_RETRY: Duration = Duration::from_millis(5);
+
+/// Wait at most this long to pass on the Quit.
+const QUIT_TIMEOUT: Duration = Duration::from_secs(1);
+
 fn walk_proc(
     recv: Receiver<Msg>,
     send: Sender<Msg>,
@@ -210,104 +535,211 @@ fn walk_proc(
     // This is a bit more complicated, as we need to keep up the message flow
     // while traversing the directory tree. We interweave each step of the tree iteration
     // and message processing.
+    //
+    // Nothing is sent with a blocking send. If the loader stalls the messages wait
+    // in the outbox and the walker keeps answering Quit and Debug.
 
-    let mut proc = None;
+    let mut proc: Option<WalkingProc> = None;
+    let mut outbox: VecDeque<Msg> = VecDeque::new();
+    // arrived while paused.
+    let mut held: VecDeque<Msg> = VecDeque::new();
+    let mut paused = false;
 
     loop {
-        match &mut proc {
-            None => match recv.recv()? {
-                Msg::Quit => {
-                    state.lock().unwrap().state = 1;
-                    send.send(Msg::Quit)?;
+        let msg = if !paused && !held.is_empty() {
+            held.pop_front()
+        } else if (proc.is_none() || paused) && outbox.is_empty() {
+            Some(recv.recv()?)
+        } else {
+            match recv.try_recv() {
+                Ok(msg) => Some(msg),
+                Err(TryRecvError::Empty) => {
+                    state.lock().unwrap().state = 9;
+                    None
+                }
+                Err(TryRecvError::Disconnected) => {
+                    state.lock().unwrap().state = 10;
                     break;
                 }
-                Msg::Debug => {
-                    state.lock().unwrap().state = 2;
-                    print_(printer, "walk_tree empty");
-                    send.send(Msg::Debug)?;
+            }
+        };
+
+        match msg {
+            Some(Msg::Quit) => {
+                state.lock().unwrap().state = 1;
+                // pending files are dropped, they leave the pipeline here.
+                for msg in outbox.drain(..) {
+                    if matches!(msg, Msg::Load(..)) {
+                        data.progress.done();
+                    }
+                }
+                // the next stage must see it, even if it takes a while.
+                if send.send_timeout(Msg::Quit, QUIT_TIMEOUT).is_err() {
+                    print_(printer, "walk_tree: queue full, waiting to pass on quit");
+                    let _ = send.send(Msg::Quit);
+                }
+                break;
+            }
+            Some(Msg::Debug(id)) => {
+                state.lock().unwrap().state = 2;
+                let suffix = if paused { " paused" } else { "" };
+                match &proc {
+                    Some(rproc) => print_(
+                        printer,
+                        debug_line(
+                            id,
+                            format!(
+                                "walk_tree {} queued {} waiting {}{}",
+                                rproc.count,
+                                send.len(),
+                                outbox.len(),
+                                suffix
+                            ),
+                        ),
+                    ),
+                    None => print_(
+                        printer,
+                        debug_line(id, format!("walk_tree empty{}", suffix)),
+                    ),
+                }
+                outbox.push_back(Msg::Debug(id));
+            }
+            Some(Msg::Pause) => {
+                state.lock().unwrap().state = 12;
+                if !paused {
+                    paused = true;
+                    state.lock().unwrap().paused = true;
+                    outbox.push_back(Msg::Pause);
+                }
+            }
+            Some(Msg::Resume) => {
+                state.lock().unwrap().state = 13;
+                if paused {
+                    paused = false;
+                    state.lock().unwrap().paused = false;
+                    outbox.push_back(Msg::Resume);
                 }
-                Msg::WalkTree(path) => {
-                    state.lock().unwrap().state = 3;
+            }
+            Some(Msg::AutoSave) => {
+                state.lock().unwrap().state = 4;
+                outbox.push_back(Msg::AutoSave);
+            }
+            Some(msg) if paused => {
+                state.lock().unwrap().state = 14;
+                held.push_back(msg);
+            }
+            Some(Msg::WalkTree(path)) => {
+                state.lock().unwrap().state = 3;
+                if proc.is_some() {
+                    print_(
+                        printer,
+                        "new tree walk ignored, still working on the last one.",
+                    );
+                } else {
+                    data.walk.lock()?.active = true;
+                    data.events.emit(
+                        "walk_start",
+                        &[("path", Value::Str(&path.to_string_lossy()))],
+                    );
+                    let settings = data.settings.get();
+                    if settings.prealloc > 0 {
+                        // the walk works without.
+                        let prealloc = data.words.lock()?.prealloc_blocks(settings.prealloc);
+                        match prealloc {
+                            Ok(0) => {}
+                            Ok(n) => print_(printer, format!("{} blocks pre-allocated", n)),
+                            Err(e) => print_err_(printer, data, "prealloc", Err(e.into())),
+                        }
+                    }
+                    let filter: fn(&DirEntry) -> bool = if settings.index_hidden {
+                        is_any
+                    } else {
+                        is_visible
+                    };
                     proc = Some(WalkingProc {
                         path: path.clone(),
-                        tree_iter: WalkDir::new(path).into_iter().flatten(),
+                        tree_iter: WalkDir::new(path)
+                            .into_iter()
+                            .filter_entry(filter)
+                            .flatten(),
                         count: 0,
                     });
                 }
-                msg => {
-                    state.lock().unwrap().state = 4;
-                    send.send(msg)?;
+            }
+            Some(Msg::DeleteFiles(files)) => {
+                state.lock().unwrap().state = 11;
+                // the last walk may still be in the pipeline.
+                exclude_files(data, &files)?;
+                outbox.push_back(Msg::DeleteFiles(files));
+            }
+            Some(msg) => {
+                state.lock().unwrap().state = 4;
+                outbox.push_back(msg);
+            }
+            None => {}
+        }
+
+        // in order, as far as the next stage takes them.
+        while let Some(msg) = outbox.pop_front() {
+            match send.try_send(msg) {
+                Ok(()) => {}
+                Err(TrySendError::Full(msg)) => {
+                    outbox.push_front(msg);
+                    break;
                 }
-            },
-            Some(rproc) => {
-                match recv.try_recv() {
-                    Ok(Msg::Quit) => {
-                        state.lock().unwrap().state = 5;
-                        send.send(Msg::Quit)?;
-                        break;
-                    }
-                    Ok(Msg::Debug) => {
-                        state.lock().unwrap().state = 6;
-                        print_(printer, format!("walk_tree {}", rproc.count));
-                        send.send(Msg::Debug)?;
-                    }
-                    Ok(Msg::WalkTree(_)) => {
-                        state.lock().unwrap().state = 7;
-                        if let Ok(mut print) = printer.lock() {
-                            let _ = print.print(
-                                "new tree walk ignored, still working on the last one.".to_string(),
-                            );
-                        }
-                    }
-                    Ok(msg) => {
-                        state.lock().unwrap().state = 8;
-                        send.send(msg)?;
-                    }
-                    Err(TryRecvError::Empty) => {
-                        state.lock().unwrap().state = 9;
-                    }
-                    Err(TryRecvError::Disconnected) => {
-                        state.lock().unwrap().state = 10;
-                        break;
-                    }
+                Err(e @ TrySendError::Disconnected(_)) => {
+                    return Err(e.into());
                 }
+            }
+        }
+        if !outbox.is_empty() {
+            state.lock().unwrap().state = 5;
+            sleep(WALK_RETRY);
+            continue;
+        }
 
-                if let Some(entry) = rproc.tree_iter.next() {
-                    state.lock().unwrap().state = 101;
-                    let meta = entry.metadata()?;
-                    if meta.is_file() {
-                        let absolute = entry.path();
-                        let relative = entry
-                            .path()
-                            .strip_prefix(&rproc.path)
-                            .unwrap_or(absolute)
-                            .to_string_lossy()
-                            .to_string();
-
-                        let filter = name_filter(absolute);
-                        if filter == FileFilter::Ignore {
-                            continue;
-                        }
+        // no new directory entries while paused.
+        if paused {
+            continue;
+        }
+        let Some(rproc) = &mut proc else {
+            continue;
+        };
+        if let Some(entry) = rproc.tree_iter.next() {
+            state.lock().unwrap().state = 101;
+            let meta = entry.metadata()?;
+            if meta.is_file() {
+                let absolute = entry.path();
+                let relative = entry
+                    .path()
+                    .strip_prefix(&rproc.path)
+                    .unwrap_or(absolute)
+                    .to_string_lossy()
+                    .to_string();
 
-                        let do_send = {
-                            state.lock().unwrap().state = 102;
-                            let words = data.words.lock()?;
-                            !words.have_file(&relative)
-                        };
-                        if do_send {
-                            state.lock().unwrap().state = 103;
-                            rproc.count += 1;
-                            send.send(Msg::Load(rproc.count, filter, absolute.into(), relative))?;
-                        }
-                    }
-                } else {
-                    state.lock().unwrap().state = 104;
-                    send.send(Msg::AutoSave)?;
-                    state.lock().unwrap().state = 105;
-                    send.send(Msg::WalkFinished(rproc.path.clone()))?;
-                    proc = None;
+                let filter = data.filter.lock()?.classify(&relative, absolute).0;
+                if filter == FileFilter::Ignore {
+                    continue;
+                }
+
+                let do_send = {
+                    state.lock().unwrap().state = 102;
+                    let words = data.words.lock()?;
+                    !words.have_file(&relative)
+                };
+                let excluded = data.walk.lock()?.excluded.contains(&relative);
+                if do_send && !excluded {
+                    state.lock().unwrap().state = 103;
+                    rproc.count += 1;
+                    data.progress.dispatched();
+                    outbox.push_back(Msg::Load(rproc.count, filter, absolute.into(), relative));
                 }
             }
+        } else {
+            state.lock().unwrap().state = 104;
+            outbox.push_back(Msg::AutoSave);
+            outbox.push_back(Msg::WalkFinished(rproc.path.clone()));
+            proc = None;
         }
     }
 
@@ -317,6 +749,8 @@ fn walk_proc(
 fn spawn_loading(
     recv: Receiver<Msg>,
     send: Sender<Msg>,
+    quitting: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
     state: Arc<Mutex<WorkerState>>,
     data: &'static Data,
     printer: Arc<Mutex<dyn ExternalPrinter + Send>>,
@@ -331,9 +765,9 @@ fn spawn_loading(
 
         print_err_(
             &printer,
-            data.log.try_clone().unwrap(),
+            data,
             "loading",
-            load_proc(recv, send, state, data, &printer),
+            load_proc(recv, send, &quitting, &paused, state, data, &printer),
         );
 
         #[cfg(feature = "allocator")]
@@ -341,37 +775,144 @@ fn spawn_loading(
     })
 }
 
+/// Pause of the loader while the words in flight are over the mem-limit.
+const MEM_RETRY: Duration = Duration::from_millis(5);
+
+/// End of wait_mem.
+enum MemWait {
+    Free,
+    /// A Quit follows, the file is not loaded.
+    Quit,
+    /// A Pause follows, the file waits behind it.
+    Pause,
+}
+
+/// Holds the loader while the TmpWords between indexer and merge are
+/// over the mem-limit. The merge brings them down again.
+///
+/// A Quit or Pause is still in the queue behind the file, so the
+/// flags of Work are checked instead.
+fn wait_mem(
+    printer: &Arc<Mutex<dyn ExternalPrinter + Send>>,
+    quitting: &AtomicBool,
+    paused: &AtomicBool,
+    state: &Arc<Mutex<WorkerState>>,
+    data: &'static Data,
+) -> MemWait {
+    loop {
+        let limit = data.settings.get().mem_limit;
+        let over = data.mem.should_pause(limit);
+        if data.mem.warn_once(over) {
+            print_(
+                printer,
+                format!(
+                    "{} bytes of words in flight, over the mem-limit. loading waits",
+                    data.mem.tmp_words()
+                ),
+            );
+        }
+        if !over {
+            break MemWait::Free;
+        }
+        if quitting.load(Ordering::Acquire) {
+            break MemWait::Quit;
+        }
+        if paused.load(Ordering::Acquire) {
+            break MemWait::Pause;
+        }
+        state.lock().unwrap().state = 7;
+        sleep(MEM_RETRY);
+    }
+}
+
 fn load_proc(
     recv: Receiver<Msg>,
     send: Sender<Msg>,
+    quitting: &AtomicBool,
+    paused: &AtomicBool,
     state: Arc<Mutex<WorkerState>>,
     data: &'static Data,
     printer: &Arc<Mutex<dyn ExternalPrinter + Send>>,
 ) -> Result<(), AppError> {
     let mut last_count = 0;
+    let mut held = VecDeque::new();
 
     loop {
-        match recv.recv()? {
+        let msg = match held.pop_front() {
+            Some(msg) => msg,
+            None => recv.recv()?,
+        };
+        match msg {
             Msg::Quit => {
                 state.lock().unwrap().state = 1;
                 send.send(Msg::Quit)?;
                 break;
             }
-            Msg::Debug => {
+            Msg::Debug(id) => {
                 state.lock().unwrap().state = 2;
-                print_(printer, format!("loading {}", last_count));
-                send.send(Msg::Debug)?;
+                print_(printer, debug_line(id, format!("loading {}", last_count)));
+                send.send(Msg::Debug(id))?;
+            }
+            Msg::Pause => {
+                state.lock().unwrap().state = 5;
+                send.send(Msg::Pause)?;
+                let debug = format!("loading {}", last_count);
+                match wait_paused(&recv, &state, &mut held, |msg| {
+                    pass_paused(printer, &send, msg, debug.clone())
+                })? {
+                    PauseEnd::Resume => send.send(Msg::Resume)?,
+                    PauseEnd::Quit => {
+                        state.lock().unwrap().state = 1;
+                        send.send(Msg::Quit)?;
+                        break;
+                    }
+                }
             }
             Msg::Load(count, filter, absolute, relative) => {
                 state.lock().unwrap().state = 3;
                 last_count = count;
-                let (filter, txt) = load_file(filter, &absolute)?;
-                if filter == FileFilter::Ignore {
-                    if let Ok(mut log) = data.log.try_clone() {
-                        let _ = writeln!(log, "maybe binary file {}", relative);
+                match wait_mem(printer, quitting, paused, &state, data) {
+                    MemWait::Free => {}
+                    MemWait::Quit => {
+                        data.progress.done();
+                        continue;
+                    }
+                    MemWait::Pause => {
+                        // the Pause goes first, the rest keeps its order.
+                        held.push_front(Msg::Load(count, filter, absolute, relative));
+                        loop {
+                            match recv.recv()? {
+                                msg @ (Msg::Pause | Msg::Quit) => {
+                                    held.push_front(msg);
+                                    break;
+                                }
+                                msg => held.push_back(msg),
+                            }
+                        }
+                        continue;
+                    }
+                }
+                match load_file(filter, &absolute) {
+                    Ok((FileFilter::Ignore, _)) => {
+                        data.log.log(format!("maybe binary file {}", relative));
+                        data.progress.done();
+                    }
+                    Ok((filter, txt)) => {
+                        send.send(Msg::Index(count, filter, absolute, relative, txt))?;
+                    }
+                    Err(e) => {
+                        // gone or not readable, the next file.
+                        data.log.log(format!("load {} {:?}", relative, e));
+                        data.events.emit(
+                            "error",
+                            &[
+                                ("task", Value::Str("load")),
+                                ("file", Value::Str(&relative)),
+                                ("msg", Value::Str(&format!("{:?}", e))),
+                            ],
+                        );
+                        data.progress.done();
                     }
-                } else if filter != FileFilter::Ignore {
-                    send.send(Msg::Index(count, filter, absolute, relative, txt))?;
                 }
             }
             msg => {
@@ -383,9 +924,13 @@ fn load_proc(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn spawn_indexing(
     recv: Receiver<Msg>,
     send: Sender<Msg>,
+    requeue: Sender<Msg>,
+    alive: Arc<AtomicUsize>,
+    paused: Arc<AtomicUsize>,
     state: Arc<Mutex<WorkerState>>,
     data: &'static Data,
     printer: Arc<Mutex<dyn ExternalPrinter + Send>>,
@@ -407,30 +952,35 @@ fn spawn_indexing(
             tok_tmpwords.id().as_usize().get()
         );
 
-        print_err_(
+        let res = index_proc(
+            recv,
+            send,
+            requeue,
+            &alive,
+            &paused,
+            state,
+            #[cfg(feature = "allocator")]
+            &mut tok_txt,
+            #[cfg(feature = "allocator")]
+            &mut tok_html,
+            #[cfg(feature = "allocator")]
+            &mut tok_tmpwords,
+            data,
             &printer,
-            data.log.try_clone().unwrap(),
-            "indexing",
-            index_proc(
-                recv,
-                send,
-                state,
-                #[cfg(feature = "allocator")]
-                &mut tok_txt,
-                #[cfg(feature = "allocator")]
-                &mut tok_html,
-                #[cfg(feature = "allocator")]
-                &mut tok_tmpwords,
-                data,
-                &printer,
-            ),
         );
+        if res.is_err() {
+            alive.fetch_sub(1, Ordering::AcqRel);
+        }
+        print_err_(&printer, data, "indexing", res);
     })
 }
 
 fn index_proc(
     recv: Receiver<Msg>,
     send: Sender<Msg>,
+    requeue: Sender<Msg>,
+    alive: &AtomicUsize,
+    paused: &AtomicUsize,
     state: Arc<Mutex<WorkerState>>,
     #[cfg(feature = "allocator")] tok_txt: &mut AllocationGroupToken,
     #[cfg(feature = "allocator")] tok_html: &mut AllocationGroupToken,
@@ -439,26 +989,91 @@ fn index_proc(
     printer: &Arc<Mutex<dyn ExternalPrinter + Send>>,
 ) -> Result<(), AppError> {
     let mut last_count = 0;
+    let mut held = VecDeque::new();
+    let mut settings_view = data.settings.view();
+
+    // the last one passes it on, the others get it again.
+    let pass_on = |msg: Msg, last: bool| -> Result<(), AppError> {
+        if last {
+            send.send(msg)?;
+        } else {
+            sleep(PAUSE_RETRY);
+            requeue.send(msg)?;
+        }
+        Ok(())
+    };
 
     loop {
-        match recv.recv()? {
+        let msg = match held.pop_front() {
+            Some(msg) => msg,
+            None => recv.recv()?,
+        };
+        match msg {
             Msg::Quit => {
                 state.lock().unwrap().state = 1;
-                send.send(Msg::Quit)?;
+                if alive.fetch_sub(1, Ordering::AcqRel) == 1 {
+                    send.send(Msg::Quit)?;
+                } else {
+                    // for the other indexers.
+                    let _ = requeue.try_send(Msg::Quit);
+                }
                 break;
             }
-            Msg::Debug => {
+            Msg::Debug(id) => {
                 state.lock().unwrap().state = 2;
-                print_(printer, format!("indexing {}", last_count));
-                send.send(Msg::Debug)?;
+                print_(printer, debug_line(id, format!("indexing {}", last_count)));
+                send.send(Msg::Debug(id))?;
+            }
+            Msg::Pause => {
+                state.lock().unwrap().state = 5;
+                let n = paused.fetch_add(1, Ordering::AcqRel) + 1;
+                pass_on(Msg::Pause, n >= alive.load(Ordering::Acquire))?;
+
+                let end = wait_paused(&recv, &state, &mut held, |msg| match msg {
+                    // for an indexer still working.
+                    Msg::Pause => pass_on(
+                        Msg::Pause,
+                        paused.load(Ordering::Acquire) >= alive.load(Ordering::Acquire),
+                    ),
+                    Msg::Debug(id) => {
+                        print_(
+                            printer,
+                            debug_line(id, format!("indexing {} paused", last_count)),
+                        );
+                        send.send(Msg::Debug(id))?;
+                        Ok(())
+                    }
+                    msg => {
+                        send.send(msg)?;
+                        Ok(())
+                    }
+                })?;
+                let last = paused.fetch_sub(1, Ordering::AcqRel) == 1;
+                match end {
+                    PauseEnd::Resume => pass_on(Msg::Resume, last)?,
+                    PauseEnd::Quit => {
+                        state.lock().unwrap().state = 1;
+                        if alive.fetch_sub(1, Ordering::AcqRel) == 1 {
+                            send.send(Msg::Quit)?;
+                        } else {
+                            let _ = requeue.try_send(Msg::Quit);
+                        }
+                        break;
+                    }
+                }
+            }
+            Msg::Resume => {
+                // for an indexer still paused.
+                state.lock().unwrap().state = 6;
+                pass_on(Msg::Resume, paused.load(Ordering::Acquire) == 0)?;
             }
             Msg::Index(count, filter, _absolute, relative, txt) => {
-                let Ok(mut log) = data.log.try_clone() else {
-                    panic!();
-                };
+                data.activity.touch();
+                let mut log = data.log.writer();
 
                 state.lock().unwrap().state = 3;
                 last_count = count;
+                let settings = data.settings.refresh(&mut settings_view);
                 let (filter, words) = indexing(
                     &mut log,
                     #[cfg(feature = "allocator")]
@@ -467,6 +1082,7 @@ fn index_proc(
                     tok_html,
                     #[cfg(feature = "allocator")]
                     tok_tmpwords,
+                    &settings,
                     filter,
                     &relative,
                     &txt,
@@ -474,10 +1090,12 @@ fn index_proc(
                 match filter {
                     FileFilter::Ignore => {
                         let _ = writeln!(log, "binary file {}", relative);
-                        // send.send(Msg::MergeWords(count, words))?;
+                        data.progress.done();
+                        // send.send(Msg::MergeWords(count, words, ticket))?;
                     }
                     FileFilter::Text | FileFilter::Html => {
-                        send.send(Msg::MergeWords(count, words))?;
+                        let ticket = data.mem.track(words.mem_size());
+                        send.send(Msg::MergeWords(count, words, ticket))?;
                     }
                     _ => {
                         unimplemented!()
@@ -510,7 +1128,7 @@ fn spawn_merge_words(
 
         print_err_(
             &printer,
-            data.log.try_clone().unwrap(),
+            data,
             "merge_words",
             merge_words_proc(recv, send, state, data, &printer),
         );
@@ -528,28 +1146,53 @@ fn merge_words_proc(
     printer: &Arc<Mutex<dyn ExternalPrinter + Send>>,
 ) -> Result<(), AppError> {
     let mut last_count = 0;
+    let mut held = VecDeque::new();
 
     loop {
-        match recv.recv()? {
+        let msg = match held.pop_front() {
+            Some(msg) => msg,
+            None => recv.recv()?,
+        };
+        match msg {
             Msg::Quit => {
                 state.lock().unwrap().state = 1;
                 send.send(Msg::Quit)?;
                 break;
             }
-            Msg::Debug => {
+            Msg::Debug(id) => {
                 state.lock().unwrap().state = 2;
-                print_(printer, format!("merge words {}", last_count));
-                send.send(Msg::Debug)?;
+                print_(
+                    printer,
+                    debug_line(id, format!("merge words {}", last_count)),
+                );
+                send.send(Msg::Debug(id))?;
             }
-            Msg::MergeWords(count, words) => {
+            Msg::Pause => {
+                state.lock().unwrap().state = 5;
+                send.send(Msg::Pause)?;
+                let debug = format!("merge words {}", last_count);
+                match wait_paused(&recv, &state, &mut held, |msg| {
+                    pass_paused(printer, &send, msg, debug.clone())
+                })? {
+                    PauseEnd::Resume => send.send(Msg::Resume)?,
+                    PauseEnd::Quit => {
+                        state.lock().unwrap().state = 1;
+                        send.send(Msg::Quit)?;
+                        break;
+                    }
+                }
+            }
+            Msg::MergeWords(count, words, ticket) => {
                 state.lock().unwrap().state = 3;
                 last_count = count;
                 print_err_(
                     printer,
-                    data.log.try_clone().unwrap(),
+                    data,
                     "merge_words",
-                    merge_words(data, &state, words, printer),
+                    merge_words(data, &state, words, &send, printer),
                 );
+                drop(ticket);
+                data.progress.done();
             }
             msg => {
                 state.lock().unwrap().state = 4;
@@ -576,7 +1219,7 @@ fn spawn_terminal(
 
         print_err_(
             &printer,
-            data.log.try_clone().unwrap(),
+            data,
             "terminal",
             terminal_proc(&recv, state, data, &printer),
         );
@@ -592,34 +1235,88 @@ fn terminal_proc(
     data: &'static Data,
     printer: &Arc<Mutex<dyn ExternalPrinter + Send>>,
 ) -> Result<(), AppError> {
+    let mut held = VecDeque::new();
+
     loop {
-        match recv.recv()? {
+        // the background verify runs when the queue is empty.
+        let msg = if let Some(msg) = held.pop_front() {
+            msg
+        } else if data.verify.lock()?.is_running() {
+            match recv.recv_timeout(VERIFY_TICK) {
+                Ok(msg) => msg,
+                Err(RecvTimeoutError::Timeout) => {
+                    state.lock().unwrap().state = 9;
+                    print_err_(printer, data, "verify", verify_step(printer, data));
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    state.lock().unwrap().state = 10;
+                    break;
+                }
+            }
+        } else {
+            recv.recv()?
+        };
+
+        match msg {
             Msg::Quit => {
                 state.lock().unwrap().state = 1;
                 break;
             }
-            Msg::Debug => {
+            Msg::Debug(id) => {
                 state.lock().unwrap().state = 2;
-                print_(printer, "terminal");
+                print_(printer, debug_line(id, "terminal"));
+                print_(printer, format!("debug {} complete", id));
+            }
+            Msg::Pause => {
+                state.lock().unwrap().state = 11;
+                let end = wait_paused(recv, &state, &mut held, |msg| {
+                    match msg {
+                        Msg::Debug(id) => {
+                            print_(printer, debug_line(id, "terminal paused"));
+                            print_(printer, format!("debug {} complete", id));
+                        }
+                        Msg::AutoSave => {
+                            print_err_(printer, data, "auto_save", auto_save(printer, data))
+                        }
+                        _ => {}
+                    }
+                    Ok(())
+                })?;
+                if let PauseEnd::Quit = end {
+                    state.lock().unwrap().state = 1;
+                    break;
+                }
+            }
+            Msg::Resume => {
+                state.lock().unwrap().state = 12;
+            }
+            Msg::Verify => {
+                state.lock().unwrap().state = 8;
+                print_err_(printer, data, "verify", start_verify(printer, data));
             }
             Msg::AutoSave => {
                 state.lock().unwrap().state = 3;
-                print_err_(
-                    printer,
-                    data.log.try_clone().unwrap(),
-                    "auto_save",
-                    auto_save(printer, data),
-                );
+                print_err_(printer, data, "auto_save", auto_save(printer, data));
             }
             Msg::DeleteFile(file) => {
                 state.lock().unwrap().state = 4;
                 print_err_(
                     printer,
-                    data.log.try_clone().unwrap(),
+                    data,
                     "delete_file",
                     delete_file(printer, data, file),
                 );
             }
+            Msg::DeleteFiles(files) => {
+                state.lock().unwrap().state = 7;
+                print_err_(
+                    printer,
+                    data,
+                    "delete_files",
+                    delete_files(printer, data, files),
+                );
+            }
             Msg::WalkFinished(file) => {
                 state.lock().unwrap().state = 5;
 
@@ -629,6 +1326,78 @@ fn terminal_proc(
                 words.write()?;
                 words.compact_blocks();
 
+                data.log.flush_similar();
+
+                let walk = std::mem::take(&mut *data.walk.lock()?);
+                print_(printer, format!("{} files indexed", walk.files));
+                if walk.decoded > 0 {
+                    print_(
+                        printer,
+                        format!("{} quoted-printable files decoded", walk.decoded),
+                    );
+                }
+                if walk.partial > 0 {
+                    print_(
+                        printer,
+                        format!(
+                            "{} files partially indexed, out of time. see stats errors",
+                            walk.partial
+                        ),
+                    );
+                }
+                print_(printer, stop_word_summary(words.stop_words(), &walk));
+                if words.pooled_blocks() > 0 {
+                    print_(
+                        printer,
+                        format!(
+                            "{} pre-allocated blocks kept for the next walk",
+                            words.pooled_blocks()
+                        ),
+                    );
+                }
+                if walk.short_words > 0 {
+                    print_(
+                        printer,
+                        format!(
+                            "{} words shorter than min-word-len {} skipped",
+                            walk.short_words,
+                            words.min_word_len()
+                        ),
+                    );
+                }
+                if walk.empty > 0 {
+                    print_(
+                        printer,
+                        format!("{} files without words not indexed", walk.empty),
+                    );
+                }
+                if walk.wordlists > 0 {
+                    print_(
+                        printer,
+                        format!(
+                            "{} files look like word lists, {} of them skipped. see stats files",
+                            walk.wordlists, walk.skipped
+                        ),
+                    );
+                }
+                print_(printer, format!("max merge stall {:?}", walk.max_stall));
+                data.events.emit(
+                    "walk_finished",
+                    &[
+                        ("path", Value::Str(&file.to_string_lossy())),
+                        ("files", Value::Num(walk.files as u64)),
+                        ("words", Value::Num(walk.words as u64)),
+                        ("stop_words", Value::Num(walk.stop_words as u64)),
+                        ("short_words", Value::Num(walk.short_words as u64)),
+                        ("decoded", Value::Num(walk.decoded as u64)),
+                        ("partial", Value::Num(walk.partial as u64)),
+                        ("wordlists", Value::Num(walk.wordlists as u64)),
+                        ("empty", Value::Num(walk.empty as u64)),
+                        ("dropped", Value::Num(data.events.dropped() as u64)),
+                    ],
+                );
+                data.progress.walk_finished();
+
                 print_(printer, format!("*** {:?} finished ***", file));
             }
             msg => {
@@ -639,3 +1408,398 @@ fn terminal_proc(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::index2::tmp_index::TmpWords;
+    use crate::index2::Words;
+    use crate::proc3::logger::Logger;
+    use crate::proc3::threads::{
+        spawn_loading, spawn_walking, Msg, WorkerKind, WorkerRegistry, WorkerState, QUIT_TIMEOUT,
+    };
+    use crate::proc3::{Data, FileFilter, MemUsage};
+    use crossbeam::channel::{bounded, Receiver, Sender};
+    use rustyline::ExternalPrinter;
+    use std::fs;
+    use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+
+    fn state() -> Arc<Mutex<WorkerState>> {
+        Arc::new(Mutex::new(WorkerState::default()))
+    }
+
+    #[test]
+    fn test_registry_ids() {
+        let mut reg = WorkerRegistry::default();
+        let w1 = reg.register(WorkerKind::Walk, thread::spawn(|| {}), state());
+        let i1 = reg.register(WorkerKind::Index, thread::spawn(|| {}), state());
+        let i2 = reg.register(WorkerKind::Index, thread::spawn(|| {}), state());
+        let l1 = reg.register(WorkerKind::Load, thread::spawn(|| {}), state());
+
+        assert_eq!(w1.to_string(), "walk-1");
+        assert_eq!(i1.to_string(), "index-1");
+        assert_eq!(i2.to_string(), "index-2");
+        assert_eq!(l1.to_string(), "load-1");
+        assert_eq!(reg.len(), 4);
+
+        // registration order is kept.
+        let ids = reg.iter().map(|v| v.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![w1, i1, i2, l1]);
+    }
+
+    #[test]
+    fn test_registry_respawn() {
+        let mut reg = WorkerRegistry::default();
+        let (s, r) = bounded::<()>(1);
+        let st = state();
+        let i1 = reg.register(
+            WorkerKind::Index,
+            thread::spawn(move || {
+                let _ = r.recv();
+            }),
+            Arc::clone(&st),
+        );
+        let i2 = reg.register(WorkerKind::Index, thread::spawn(|| {}), state());
+
+        assert!(!reg.get(i1).expect("worker").handle.is_finished());
+        drop(s);
+        while !reg.get(i1).expect("worker").handle.is_finished() {
+            sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(reg.replace(i1, thread::spawn(|| {})), Some(1));
+        assert_eq!(reg.replace(i1, thread::spawn(|| {})), Some(2));
+
+        // keeps id and state, doesn't disturb the others.
+        let w = reg.get(i1).expect("worker");
+        assert_eq!(w.id, i1);
+        assert_eq!(w.incarnation, 2);
+        assert!(Arc::ptr_eq(&w.state, &st));
+        assert_eq!(reg.get(i2).expect("worker").incarnation, 0);
+        assert_eq!(reg.len(), 2);
+
+        // new workers don't reuse ids.
+        let i3 = reg.register(WorkerKind::Index, thread::spawn(|| {}), state());
+        assert_eq!(i3.to_string(), "index-3");
+    }
+
+    struct Capture(Arc<Mutex<Vec<String>>>);
+
+    impl ExternalPrinter for Capture {
+        fn print(&mut self, msg: String) -> rustyline::Result<()> {
+            self.0.lock().expect("lines").push(msg);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_walk_stalled() {
+        let root = PathBuf::from("tmp/walk_stalled");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).expect("root");
+        for i in 0..20 {
+            fs::write(root.join(format!("f{}.txt", i)), "text").expect("file");
+        }
+        let words = Words::create(Path::new("tmp/walk_stalled.idx")).expect("words");
+        let data: &'static Data =
+            Box::leak(Box::new(Data::new(root.clone(), words, Logger::new(None))));
+
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let printer: Arc<Mutex<dyn ExternalPrinter + Send>> =
+            Arc::new(Mutex::new(Capture(Arc::clone(&lines))));
+
+        // nobody reads the output, like a stalled loader.
+        let (s_in, r_in) = bounded::<Msg>(10);
+        let (s_out, r_out) = bounded::<Msg>(2);
+        let handle = spawn_walking(r_in, s_out, state(), data, printer);
+
+        s_in.send(Msg::WalkTree(root)).expect("send");
+        while r_out.len() < 2 {
+            sleep(Duration::from_millis(1));
+        }
+
+        // still answers.
+        s_in.send(Msg::Debug(1)).expect("send");
+        let start = Instant::now();
+        while lines.lock().expect("lines").is_empty() && start.elapsed() < QUIT_TIMEOUT {
+            sleep(Duration::from_millis(1));
+        }
+        let debug = lines.lock().expect("lines").clone();
+        assert_eq!(debug.len(), 1);
+        assert!(
+            debug[0].starts_with("debug 1: walk_tree 3 queued 2"),
+            "{:?}",
+            debug
+        );
+
+        s_in.send(Msg::Quit).expect("send");
+        sleep(QUIT_TIMEOUT + Duration::from_millis(100));
+        // waits for the loader.
+        assert!(!handle.is_finished());
+
+        // the loader picks up again, and gets the quit after its files.
+        let mut loads = 0;
+        loop {
+            match r_out.recv_timeout(QUIT_TIMEOUT).expect("quit") {
+                Msg::Load(..) => {
+                    loads += 1;
+                    data.progress.done();
+                }
+                Msg::Quit => break,
+                _ => {}
+            }
+        }
+        assert_eq!(loads, 2);
+        handle.join().expect("walker");
+
+        // the dropped files are done as well.
+        assert!(data.progress.wait_idle(0, Duration::from_millis(10)));
+        assert!(lines
+            .lock()
+            .expect("lines")
+            .iter()
+            .any(|v| v.contains("waiting to pass on quit")));
+    }
+
+    fn walk_loads(s_in: &Sender<Msg>, r_out: &Receiver<Msg>, path: PathBuf) -> Vec<String> {
+        s_in.send(Msg::WalkTree(path)).expect("send");
+        let mut loaded = Vec::new();
+        loop {
+            match r_out.recv_timeout(QUIT_TIMEOUT).expect("walk") {
+                Msg::Load(_, _, _, relative) => loaded.push(relative.replace('\\', "/")),
+                Msg::WalkFinished(_) => break,
+                _ => {}
+            }
+        }
+        loaded.sort();
+        loaded
+    }
+
+    #[test]
+    fn test_walk_hidden() {
+        let root = PathBuf::from("tmp/walk_hidden");
+        let _ = fs::remove_dir_all(&root);
+        for dir in [".git", "sub/.cache", ".dotroot"] {
+            fs::create_dir_all(root.join(dir)).expect("dir");
+        }
+        for file in [
+            "a.txt",
+            ".h.txt",
+            ".git/x.txt",
+            "sub/b.txt",
+            "sub/.cache/c.txt",
+            ".dotroot/d.txt",
+        ] {
+            fs::write(root.join(file), "text").expect("file");
+        }
+        let words = Words::create(Path::new("tmp/walk_hidden.idx")).expect("words");
+        let data: &'static Data =
+            Box::leak(Box::new(Data::new(root.clone(), words, Logger::new(None))));
+        let printer: Arc<Mutex<dyn ExternalPrinter + Send>> =
+            Arc::new(Mutex::new(Capture(Arc::new(Mutex::new(Vec::new())))));
+
+        let (s_in, r_in) = bounded::<Msg>(10);
+        let (s_out, r_out) = bounded::<Msg>(10);
+        let handle = spawn_walking(r_in, s_out, state(), data, printer);
+
+        // default is off.
+        assert_eq!(
+            walk_loads(&s_in, &r_out, root.clone()),
+            vec!["a.txt", "sub/b.txt"]
+        );
+        // a hidden root is walked anyway.
+        assert_eq!(
+            walk_loads(&s_in, &r_out, root.join(".dotroot")),
+            vec!["d.txt"]
+        );
+
+        data.settings.update(|s| s.index_hidden = true);
+        assert_eq!(
+            walk_loads(&s_in, &r_out, root.clone()),
+            vec![
+                ".dotroot/d.txt",
+                ".git/x.txt",
+                ".h.txt",
+                "a.txt",
+                "sub/.cache/c.txt",
+                "sub/b.txt"
+            ]
+        );
+
+        s_in.send(Msg::Quit).expect("send");
+        handle.join().expect("walker");
+    }
+
+    #[test]
+    fn test_walk_fold_case() {
+        let root = PathBuf::from("tmp/walk_fold_case");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("Sub")).expect("dir");
+        for file in ["Readme.TXT", "Sub/Notes.txt", "new.txt"] {
+            fs::write(root.join(file), "text").expect("file");
+        }
+        let mut words = Words::create(Path::new("tmp/walk_fold_case.idx")).expect("words");
+        words.add_file("readme.txt".into());
+        words.add_file(format!("sub{}notes.txt", MAIN_SEPARATOR));
+        let data: &'static Data =
+            Box::leak(Box::new(Data::new(root.clone(), words, Logger::new(None))));
+        let printer: Arc<Mutex<dyn ExternalPrinter + Send>> =
+            Arc::new(Mutex::new(Capture(Arc::new(Mutex::new(Vec::new())))));
+
+        let (s_in, r_in) = bounded::<Msg>(10);
+        let (s_out, r_out) = bounded::<Msg>(10);
+        let handle = spawn_walking(r_in, s_out, state(), data, printer);
+
+        data.words.lock().expect("words").set_fold_case(true);
+        assert_eq!(walk_loads(&s_in, &r_out, root.clone()), vec!["new.txt"]);
+
+        data.words.lock().expect("words").set_fold_case(false);
+        assert_eq!(
+            walk_loads(&s_in, &r_out, root.clone()),
+            vec!["Readme.TXT", "Sub/Notes.txt", "new.txt"]
+        );
+
+        s_in.send(Msg::Quit).expect("send");
+        handle.join().expect("walker");
+    }
+
+    #[test]
+    fn test_load_mem_limit() {
+        let root = PathBuf::from("tmp/load_mem_limit");
+        fs::create_dir_all(&root).expect("root");
+        let file = root.join("a.txt");
+        fs::write(&file, "text").expect("file");
+        let words = Words::create(Path::new("tmp/load_mem_limit.idx")).expect("words");
+        let data: &'static Data =
+            Box::leak(Box::new(Data::new(root.clone(), words, Logger::new(None))));
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let printer: Arc<Mutex<dyn ExternalPrinter + Send>> =
+            Arc::new(Mutex::new(Capture(Arc::clone(&lines))));
+
+        let (s_in, r_in) = bounded::<Msg>(10);
+        let (s_out, r_out) = bounded::<Msg>(10);
+        let st = state();
+        let handle = spawn_loading(
+            r_in,
+            s_out,
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(false)),
+            Arc::clone(&st),
+            data,
+            printer,
+        );
+
+        // at the limit it still loads.
+        data.settings.update(|s| s.mem_limit = 1000);
+        data.mem.add_tmp_words(1000);
+        s_in.send(Msg::Load(1, FileFilter::Text, file.clone(), "a.txt".into()))
+            .expect("send");
+        assert!(matches!(
+            r_out.recv_timeout(QUIT_TIMEOUT),
+            Ok(Msg::Index(1, ..))
+        ));
+
+        // over it waits.
+        data.mem.add_tmp_words(1);
+        s_in.send(Msg::Load(2, FileFilter::Text, file.clone(), "a.txt".into()))
+            .expect("send");
+        sleep(Duration::from_millis(50));
+        assert!(r_out.is_empty());
+        assert_eq!(st.lock().expect("state").state, 7);
+        assert_eq!(lines.lock().expect("lines").len(), 1);
+
+        // merged.
+        data.mem.sub_tmp_words(500);
+        assert!(matches!(
+            r_out.recv_timeout(QUIT_TIMEOUT),
+            Ok(Msg::Index(2, ..))
+        ));
+
+        s_in.send(Msg::Quit).expect("send");
+        handle.join().expect("loader");
+    }
+
+    #[test]
+    fn test_load_mem_quit_pause() {
+        let root = PathBuf::from("tmp/load_mem_quit_pause");
+        fs::create_dir_all(&root).expect("root");
+        let file = root.join("a.txt");
+        fs::write(&file, "text").expect("file");
+        let words = Words::create(Path::new("tmp/load_mem_quit_pause.idx")).expect("words");
+        let data: &'static Data =
+            Box::leak(Box::new(Data::new(root.clone(), words, Logger::new(None))));
+        let printer: Arc<Mutex<dyn ExternalPrinter + Send>> =
+            Arc::new(Mutex::new(Capture(Arc::new(Mutex::new(Vec::new())))));
+
+        let (s_in, r_in) = bounded::<Msg>(10);
+        let (s_out, r_out) = bounded::<Msg>(10);
+        let quitting = Arc::new(AtomicBool::new(false));
+        let paused = Arc::new(AtomicBool::new(false));
+        let handle = spawn_loading(
+            r_in,
+            s_out,
+            Arc::clone(&quitting),
+            Arc::clone(&paused),
+            state(),
+            data,
+            printer,
+        );
+
+        // over the limit, the pause gets through.
+        data.settings.update(|s| s.mem_limit = 1000);
+        data.mem.add_tmp_words(1001);
+        s_in.send(Msg::Load(1, FileFilter::Text, file.clone(), "a.txt".into()))
+            .expect("send");
+        paused.store(true, Ordering::Release);
+        s_in.send(Msg::Pause).expect("send");
+        assert!(matches!(r_out.recv_timeout(QUIT_TIMEOUT), Ok(Msg::Pause)));
+
+        // the file is loaded after the resume.
+        data.mem.sub_tmp_words(1001);
+        paused.store(false, Ordering::Release);
+        s_in.send(Msg::Resume).expect("send");
+        assert!(matches!(r_out.recv_timeout(QUIT_TIMEOUT), Ok(Msg::Resume)));
+        assert!(matches!(
+            r_out.recv_timeout(QUIT_TIMEOUT),
+            Ok(Msg::Index(1, ..))
+        ));
+
+        // the quit drops the waiting file.
+        data.mem.add_tmp_words(1001);
+        s_in.send(Msg::Load(2, FileFilter::Text, file.clone(), "a.txt".into()))
+            .expect("send");
+        quitting.store(true, Ordering::Release);
+        s_in.send(Msg::Quit).expect("send");
+        assert!(matches!(r_out.recv_timeout(QUIT_TIMEOUT), Ok(Msg::Quit)));
+        handle.join().expect("loader");
+    }
+
+    #[test]
+    fn test_mem_ticket() {
+        let mem: &'static MemUsage = Box::leak(Box::new(MemUsage::default()));
+
+        let (s_out, r_out) = bounded::<Msg>(10);
+        s_out
+            .send(Msg::MergeWords(1, TmpWords::new("a.txt"), mem.track(1000)))
+            .expect("send");
+        s_out
+            .send(Msg::MergeWords(2, TmpWords::new("b.txt"), mem.track(500)))
+            .expect("send");
+        assert_eq!(mem.tmp_words(), 1500);
+
+        // merged.
+        let msg = r_out.recv().expect("recv");
+        drop(msg);
+        assert_eq!(mem.tmp_words(), 500);
+
+        // never merged, dropped with the channel.
+        drop(s_out);
+        drop(r_out);
+        assert_eq!(mem.tmp_words(), 0);
+        assert_eq!(mem.max_tmp_words(), 1500);
+    }
+}
@@ -153,13 +153,59 @@ pub fn parse_nonword(input: Span<'_>) -> ParserResult<'_, TxtPart> {
     Ok((rest, TxtPart::NonText))
 }
 
+/// A word is a run of letters. Apostrophes and hyphens are part of the word
+/// when they sit between two letters ("don't", "e-mail"), at the edges they are not.
 #[inline]
 pub fn tok_word(input: Span<'_>) -> TokenizerResult<'_> {
-    track(
-        TxtCode::WordTok,
-        recognize(take_while1(|c: char| c.is_alphabetic())),
-    )(input)
-    .with_code(TxtCode::Word)
+    Track.enter(TxtCode::WordTok, input);
+
+    let mut end = 0usize;
+    let mut it = input.char_indices().peekable();
+    while let Some((idx, c)) = it.next() {
+        if c.is_alphabetic() {
+            end = idx + c.len_utf8();
+        } else if end > 0 && is_joiner(c) {
+            match it.peek() {
+                Some((_, n)) if n.is_alphabetic() => {}
+                _ => break,
+            }
+        } else {
+            break;
+        }
+    }
+
+    if end > 0 {
+        Track.ok(input.slice(end..), input, input.slice(..end))
+    } else {
+        Track.err(TokenizerError::new(TxtCode::Word, input))
+    }
+}
+
+/// Apostrophes and hyphens. Includes the right single quote.
+#[inline]
+fn is_joiner(c: char) -> bool {
+    c == '\'' || c == '\u{2019}' || c == '-'
+}
+
+/// Normalized form of a word as it is stored in the index.
+pub fn normalize_word(word: &str) -> String {
+    word.to_lowercase().replace('\u{2019}', "'")
+}
+
+/// Splits a text-line into words, with the same rules as the indexer.
+pub fn split_words(mut text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    loop {
+        text = text.trim_start_matches(|c: char| !c.is_alphabetic());
+        match tok_word(text) {
+            Ok((rest, word)) => {
+                words.push(word);
+                text = rest;
+            }
+            Err(_) => break,
+        }
+    }
+    words
 }
 
 #[inline]
@@ -0,0 +1,88 @@
+#![cfg(feature = "test-hooks")]
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use textindex::error::AppError;
+use textindex::index2::ids::FileId;
+use textindex::index2::tmp_index::TmpWords;
+use textindex::index2::word_map::WordMap;
+use textindex::index2::Words;
+
+/// The files in the chain of the word, duplicates included.
+fn chain(w: &mut Words, word: &str) -> Result<Vec<FileId>, AppError> {
+    let data = w.word(word)?.expect("word");
+    let files = WordMap::iter_files(&mut w.db, data.file_map_block_nr, data.file_map_idx)
+        .collect::<Result<_, _>>()?;
+    Ok(files)
+}
+
+fn tmp_words(file: &str, words: &[&str]) -> TmpWords {
+    let mut t = TmpWords::new(file);
+    for word in words {
+        t.add_word(word);
+    }
+    t
+}
+
+#[test]
+fn test_append_failure() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/check_append_failure.idx")?;
+    let words = ["alpha", "beta", "delta", "epsilon", "zeta", "eta"];
+
+    let mut w = Words::create(&path)?;
+    // more files than fit one entry, the chain of alpha has a tail.
+    for n in 0..8 {
+        w.append(tmp_words(&format!("file{}", n), &["alpha", "beta"]))?;
+    }
+    w.write()?;
+
+    w.fail_map_after(Some(4));
+    assert!(w.append(tmp_words("failed", &words)).is_err());
+
+    // the file stays, and no word points to an unfinished entry.
+    assert!(w.have_file(&"failed".into()));
+    let report = w.check(None);
+    assert!(report.is_ok(), "{:?}", report.problems);
+    let new_words = words
+        .iter()
+        .filter(|v| !["alpha", "beta"].contains(v))
+        .filter(|v| w.find(&[v.to_string()]).expect("find").is_empty())
+        .count();
+    assert_eq!(new_words, 4);
+    // three writes went through, for known words or new ones.
+    let failed = w.files().file_id("failed").expect("failed");
+    let mut known = 0;
+    for word in ["alpha", "beta"] {
+        known += chain(&mut w, word)?
+            .iter()
+            .filter(|v| **v == failed)
+            .count();
+    }
+    assert_eq!(w.orphaned_slots().len(), 3 - known);
+    assert!(w.recover_words()?.recovered.is_empty());
+
+    // merged again.
+    assert!(w.replace(tmp_words("failed", &words))?);
+    let report = w.check(None);
+    assert!(report.is_ok(), "{:?}", report.problems);
+    for word in words {
+        let files = chain(&mut w, word)?;
+        assert_eq!(
+            files.iter().filter(|v| **v == failed).count(),
+            1,
+            "{}",
+            word
+        );
+    }
+    assert_eq!(chain(&mut w, "alpha")?.len(), 9);
+    w.write()?;
+    drop(w);
+
+    let mut w = Words::read(&path)?;
+    let report = w.check(None);
+    assert!(report.is_ok(), "{:?}", report.problems);
+    assert_eq!(report.files, 9);
+    assert!(w.recover_words()?.recovered.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,231 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use textindex::error::AppError;
+use textindex::index2::header::INDEX_VERSION;
+use textindex::index2::segments::Segments;
+use textindex::index2::Words;
+use textindex::proc3::logger::Logger;
+use textindex::proc3::{backup, restore, Data, FoundSnapshot};
+
+fn fill(w: &mut Words) -> Result<(), AppError> {
+    let fid = w.add_file("dir/file0.txt".into());
+    w.add_word("alpha", 1, fid)?;
+    w.add_word("beta", 1, fid)?;
+    let fid = w.add_file("dir/file1.txt".into());
+    w.add_word("alpha", 1, fid)?;
+    Ok(())
+}
+
+#[test]
+fn test_backup_restore() -> Result<(), AppError> {
+    let path = Path::new("tmp/backup_live.idx");
+    let dir = Path::new("tmp/backup_words");
+    let _ = fs::remove_dir_all(dir);
+
+    let mut w = Words::create(path)?;
+    fill(&mut w)?;
+    // not written yet, backup does that.
+    let copied = w.backup(dir)?;
+    assert_eq!(copied, vec![dir.join("backup_live.idx")]);
+    assert_eq!(w.check_backup(dir)?, INDEX_VERSION);
+
+    // lost with the restore.
+    let fid = w.add_file("dir/file2.txt".into());
+    w.add_word("gamma", 1, fid)?;
+    w.write()?;
+
+    // corrupt the live index.
+    let len = fs::metadata(path)?.len() as usize;
+    fs::write(path, vec![0xffu8; len])?;
+
+    w.restore(dir)?;
+    assert_eq!(w.files().len(), 2);
+    assert_eq!(
+        w.find(&["alpha".into()])?,
+        vec!["dir/file0.txt", "dir/file1.txt"]
+    );
+    assert_eq!(w.find(&["beta".into()])?, vec!["dir/file0.txt"]);
+    assert!(w.find(&["gamma".into()])?.is_empty());
+    assert!(w.check(None).is_ok());
+
+    // the backup is unchanged and can be restored again.
+    assert_eq!(w.check_backup(dir)?, INDEX_VERSION);
+
+    Ok(())
+}
+
+#[test]
+fn test_backup_invalid() -> Result<(), AppError> {
+    let path = Path::new("tmp/backup_invalid.idx");
+    let dir = Path::new("tmp/backup_invalid");
+    let _ = fs::remove_dir_all(dir);
+
+    let mut w = Words::create(path)?;
+    fill(&mut w)?;
+    w.write()?;
+
+    // nothing there.
+    assert!(w.check_backup(dir).is_err());
+    assert!(w.restore(dir).is_err());
+
+    // an index that was not written cleanly.
+    fs::create_dir_all(dir)?;
+    {
+        let mut other = Words::create(&dir.join("backup_invalid.idx"))?;
+        fill(&mut other)?;
+        other.write()?;
+    }
+    // opening clears the clean flag, dropping doesn't set it again.
+    drop(Words::read(&dir.join("backup_invalid.idx"))?);
+    assert!(w.check_backup(dir).is_err());
+    assert!(w.restore(dir).is_err());
+
+    // the index is still there.
+    assert_eq!(w.find(&["beta".into()])?, vec!["dir/file0.txt"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_backup_data() -> Result<(), AppError> {
+    let root = PathBuf::from("tmp/backup_data");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root)?;
+    fs::write(root.join("stopwords.txt"), "alpha\n")?;
+
+    let mut words = Words::create(Path::new("tmp/backup_data.idx"))?;
+    fill(&mut words)?;
+    let data: &'static Data =
+        Box::leak(Box::new(Data::new(root.clone(), words, Logger::new(None))));
+
+    let target = backup(data, &root.join("bak"))?;
+    assert!(target.join("backup_data.idx").is_file());
+    assert!(target.join("stopwords.txt").is_file());
+
+    data.found.set(FoundSnapshot::new(
+        vec!["alpha".into()],
+        vec!["dir/file0.txt".into()],
+        80,
+    ))?;
+    fs::remove_file(root.join("stopwords.txt"))?;
+
+    restore(data, &target)?;
+    assert!(data.found.get()?.files.is_empty());
+    assert!(root.join("stopwords.txt").is_file());
+    let settings = data.settings.get();
+    assert!(settings.is_stop_word("alpha"));
+    assert!(!settings.is_stop_word("the"));
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_broken_backup() -> Result<(), AppError> {
+    let path = Path::new("tmp/backup_broken.idx");
+    let dir = Path::new("tmp/backup_broken");
+    let _ = fs::remove_dir_all(dir);
+
+    let mut w = Words::create(path)?;
+    fill(&mut w)?;
+    w.backup(dir)?;
+    let fid = w.add_file("dir/file2.txt".into());
+    w.add_word("gamma", 1, fid)?;
+    w.write()?;
+
+    // truncated.
+    let backup = dir.join("backup_broken.idx");
+    let len = fs::metadata(&backup)?.len();
+    let file = fs::OpenOptions::new().write(true).open(&backup)?;
+    file.set_len(len / 2)?;
+    drop(file);
+    assert!(w.restore(dir).is_err());
+    assert!(!Path::new("tmp/backup_broken.restore.idx").exists());
+
+    // missing.
+    fs::remove_file(&backup)?;
+    assert!(w.restore(dir).is_err());
+
+    // the index is as before, and still opens.
+    assert_eq!(w.find(&["gamma".into()])?, vec!["dir/file2.txt"]);
+    drop(w);
+    let mut w = Words::read(path)?;
+    assert_eq!(w.files().len(), 3);
+    assert_eq!(w.find(&["gamma".into()])?, vec!["dir/file2.txt"]);
+    assert!(w.check(None).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_rolled_back() -> Result<(), AppError> {
+    let path = Path::new("tmp/backup_rollback.idx");
+    let dir = Path::new("tmp/backup_rollback");
+    let aside = Path::new("tmp/backup_rollback.old.0001.idx");
+    let _ = fs::remove_dir_all(dir);
+    let _ = fs::remove_dir_all(aside);
+    Segments::remove(path);
+
+    let mut w = Words::create(path)?;
+    w.set_segment_size(1);
+    let mut n = 0;
+    while w.db.len() < 2 && n < 100 {
+        let fid = w.add_file(format!("file{}", n));
+        for i in 0..1000 {
+            w.add_word(format!("word{}", i).as_str(), 1, fid)?;
+        }
+        w.write()?;
+        n += 1;
+    }
+    assert_eq!(w.db.len(), 2);
+    w.backup(dir)?;
+    let fid = w.add_file("late.txt".into());
+    w.add_word("late", 1, fid)?;
+    w.write()?;
+
+    // the first segment is moved aside, the second one can't be.
+    fs::create_dir_all(aside)?;
+    assert!(w.restore(dir).is_err());
+    fs::remove_dir(aside)?;
+    assert!(!Path::new("tmp/backup_rollback.restore.idx").exists());
+    assert!(!Path::new("tmp/backup_rollback.old.idx").exists());
+
+    // the index is as before and keeps working.
+    assert_eq!(w.find(&["late".into()])?, vec!["late.txt"]);
+    let fid = w.add_file("after.txt".into());
+    w.add_word("after", 1, fid)?;
+    w.write()?;
+    assert!(w.check(None).is_ok());
+    drop(w);
+
+    let mut w = Words::read(path)?;
+    assert_eq!(w.files().len(), n + 2);
+    assert_eq!(w.find(&["late".into()])?, vec!["late.txt"]);
+    assert_eq!(w.find(&["after".into()])?, vec!["after.txt"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_reload_failed() -> Result<(), AppError> {
+    let path = Path::new("tmp/reload_failed.idx");
+    let _ = fs::remove_dir(path);
+
+    let mut w = Words::create(path)?;
+    fill(&mut w)?;
+    w.write()?;
+
+    // can't be opened anymore.
+    fs::remove_file(path)?;
+    fs::create_dir(path)?;
+    assert!(w.reload().is_err());
+
+    // still the old one.
+    assert_eq!(w.find(&["beta".into()])?, vec!["dir/file0.txt"]);
+    let fid = w.add_file("dir/file2.txt".into());
+    w.add_word("gamma", 1, fid)?;
+    assert_eq!(w.find(&["gamma".into()])?, vec!["dir/file2.txt"]);
+    drop(w);
+    fs::remove_dir(path)?;
+
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use std::path::Path;
+use textindex::error::AppError;
+use textindex::index2::words::RawWord;
+use textindex::index2::{IndexKind, Words};
+
+#[test]
+fn test_bad_word() -> Result<(), AppError> {
+    let path = Path::new("tmp/bad_word.idx");
+
+    let mut w = Words::create(path)?;
+    let fid = w.add_file("file0".into());
+    w.add_word("alpha", 1, fid)?;
+    w.add_word("beta", 1, fid)?;
+    w.add_word("gamma", 1, fid)?;
+    w.write()?;
+
+    // broken bytes for beta.
+    let beta = w.word("beta")?.expect("word");
+    {
+        let block = w.db.get_as_mut::<RawWord>(beta.block_nr)?;
+        block[beta.block_idx.as_usize()].word[1] = 0xff;
+    }
+    w.db.store()?;
+    drop(w);
+
+    match Words::read(path) {
+        Err(e) => match e.kind {
+            IndexKind::BadWord(bad) => {
+                assert_eq!(bad.block_nr, beta.block_nr);
+                assert_eq!(bad.block_idx, beta.block_idx);
+                assert_eq!(&bad.bytes[..4], &[b'b', 0xff, b't', b'a']);
+            }
+            v => panic!("{:?}", v),
+        },
+        Ok(_) => panic!("read a broken word"),
+    }
+
+    let mut w = Words::recover(path)?;
+    assert!(w.word("beta")?.is_none());
+    assert_eq!(w.find(&["gamma".into()])?, vec!["file0"]);
+
+    let bad = w.bad_words()?;
+    assert_eq!(bad.len(), 1);
+    assert_eq!(bad[0].block_nr, beta.block_nr);
+    assert_eq!(bad[0].block_idx, beta.block_idx);
+
+    let report = w.check(None);
+    let location = format!("word {}:{}", beta.block_nr, beta.block_idx);
+    assert!(
+        report.problems.iter().any(|v| v.contains(&location)),
+        "{:?}",
+        report.problems
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_bad_word_id() -> Result<(), AppError> {
+    let path = Path::new("tmp/bad_word_id.idx");
+
+    let mut w = Words::create(path)?;
+    let fid = w.add_file("file0".into());
+    w.add_word("alpha", 1, fid)?;
+    w.add_word("beta", 1, fid)?;
+    w.write()?;
+
+    // the word with the highest id is broken.
+    let beta = w.word("beta")?.expect("word");
+    {
+        let block = w.db.get_as_mut::<RawWord>(beta.block_nr)?;
+        block[beta.block_idx.as_usize()].word[1] = 0xff;
+    }
+    w.db.store()?;
+    drop(w);
+
+    let mut w = Words::recover(path)?;
+    w.add_word("gamma", 1, fid)?;
+    let gamma = w.word("gamma")?.expect("word");
+    assert!(gamma.id > beta.id, "{:?} {:?}", gamma.id, beta.id);
+
+    Ok(())
+}
+
+#[test]
+fn test_bad_word_steps() -> Result<(), AppError> {
+    let path = Path::new("tmp/bad_word_steps.idx");
+
+    let mut w = Words::create(path)?;
+    let fid = w.add_file("file0".into());
+    for i in 0..1000 {
+        w.add_word(format!("w{:04}", i), 1, fid)?;
+    }
+    w.write()?;
+
+    // stored last, in the last word block.
+    let last = w.word("w0999")?.expect("word");
+    {
+        let block = w.db.get_as_mut::<RawWord>(last.block_nr)?;
+        block[last.block_idx.as_usize()].word[1] = 0xff;
+    }
+    w.db.store()?;
+    drop(w);
+
+    let mut w = Words::recover(path)?;
+    let location = format!("word {}:{}", last.block_nr, last.block_idx);
+
+    // not all in the first step.
+    let mut scan = w.start_check(None, 0);
+    assert!(!w.check_step(&mut scan, 50));
+    assert!(!scan.report.problems.iter().any(|v| v.contains(&location)));
+
+    while !w.check_step(&mut scan, 50) {}
+    assert!(
+        scan.report.problems.iter().any(|v| v.contains(&location)),
+        "{:?}",
+        scan.report.problems
+    );
+
+    Ok(())
+}
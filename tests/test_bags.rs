@@ -0,0 +1,135 @@
+use std::path::Path;
+use textindex::error::AppError;
+use textindex::index2::word_map::{bag_for, RawBags, BAG_LEN, BAG_SCALE};
+use textindex::index2::{WordBlockType, Words};
+
+fn count_blocks(w: &Words, ty: WordBlockType) -> usize {
+    w.db.iter_metadata_filter(|_nr, t| t == ty).count()
+}
+
+fn read_bags(w: &mut Words) -> Result<RawBags, AppError> {
+    let (nr, _) =
+        w.db.iter_metadata_filter(|_nr, t| t == WordBlockType::WordMapBags)
+            .next()
+            .expect("bags");
+    Ok(w.db.get_as::<RawBags>(nr)?[0])
+}
+
+/// Overwrites the stored bags without touching anything else.
+fn write_bags(w: &mut Words, bags: RawBags) -> Result<(), AppError> {
+    let (nr, _) =
+        w.db.iter_metadata_filter(|_nr, t| t == WordBlockType::WordMapBags)
+            .next()
+            .expect("bags");
+    w.db.get_as_mut::<RawBags>(nr)?[0] = bags;
+    w.db.store()?;
+    Ok(())
+}
+
+fn fill(w: &mut Words, from: usize, to: usize) -> Result<(), AppError> {
+    for i in from..to {
+        let fid = w.add_file(format!("file{}", i));
+        // more than fit in the head entry, some go to the tail.
+        w.add_word("alpha", 1, fid)?;
+        w.add_word(format!("word{}", i), 1, fid)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_bags_missing() -> Result<(), AppError> {
+    let path = Path::new("tmp/bags_missing.idx");
+
+    let mut w = Words::create(path)?;
+    fill(&mut w, 0, 10)?;
+    w.write()?;
+    assert!(!w.bags_missing());
+    write_bags(&mut w, RawBags::default())?;
+    drop(w);
+
+    let mut w = Words::read(path)?;
+    assert!(w.bags_missing());
+    let heads = count_blocks(&w, WordBlockType::WordMapHead);
+    let tails = count_blocks(&w, WordBlockType::WordMapTail);
+
+    let report = w.rebuild_bags()?;
+    assert_eq!(report.head_blocks, heads);
+    assert_eq!(report.tail_blocks, tails);
+    assert!(!report.changes.is_empty());
+    assert!(!w.bags_missing());
+
+    // the old blocks are continued.
+    fill(&mut w, 10, 20)?;
+    w.write()?;
+    assert_eq!(count_blocks(&w, WordBlockType::WordMapHead), heads);
+    assert_eq!(count_blocks(&w, WordBlockType::WordMapTail), tails);
+
+    assert_eq!(w.find(&["alpha".into()])?.len(), 20);
+    for i in 0..20 {
+        assert_eq!(w.find(&[format!("word{}", i)])?, vec![format!("file{}", i)]);
+    }
+    assert!(w.check(None).is_ok());
+
+    // nothing left to do.
+    assert!(w.rebuild_bags()?.changes.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_bags_stale() -> Result<(), AppError> {
+    let path = Path::new("tmp/bags_stale.idx");
+
+    let mut w = Words::create(path)?;
+    fill(&mut w, 0, 10)?;
+    w.write()?;
+    let stale = read_bags(&mut w)?;
+
+    fill(&mut w, 10, 20)?;
+    w.write()?;
+    // the bags of an older save, the next adds would overwrite used slots.
+    write_bags(&mut w, stale)?;
+    drop(w);
+
+    let mut w = Words::read(path)?;
+    assert!(!w.bags_missing());
+    let report = w.rebuild_bags()?;
+    assert!(!report.changes.is_empty());
+
+    fill(&mut w, 20, 30)?;
+    w.write()?;
+
+    assert_eq!(w.find(&["alpha".into()])?.len(), 30);
+    for i in 0..30 {
+        assert_eq!(w.find(&[format!("word{}", i)])?, vec![format!("file{}", i)]);
+    }
+    assert!(w.check(None).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_bag_for() {
+    let last = BAG_LEN - 1;
+    // the share that fills one bag.
+    let total = BAG_LEN * BAG_SCALE;
+
+    assert_eq!(bag_for(0, 0), 0);
+    assert_eq!(bag_for(100, 0), 0);
+    assert_eq!(bag_for(0, total), 0);
+    assert_eq!(bag_for(1, total), 1);
+    assert_eq!(bag_for(1, total + 1), 0);
+    assert_eq!(bag_for(last, total), last);
+    assert_eq!(bag_for(last + 1, total), last);
+    // 5% and above.
+    assert_eq!(bag_for(5, 100), last);
+    assert_eq!(bag_for(4, 100), 204);
+    assert_eq!(bag_for(total, total), last);
+
+    // no overflow.
+    assert_eq!(bag_for(usize::MAX, usize::MAX), last);
+    assert_eq!(bag_for(usize::MAX / 2, usize::MAX), last);
+    assert_eq!(bag_for(usize::MAX / total, usize::MAX), 0);
+    assert_eq!(bag_for(usize::MAX / total * 10, usize::MAX), 9);
+    assert_eq!(bag_for(1, usize::MAX), 0);
+}
@@ -0,0 +1,69 @@
+use std::fs::File;
+use textindex::error::AppError;
+use textindex::index2::tmp_index::Bigrams;
+use textindex::proc3::{indexing, reservoir_sample, FileFilter, Settings};
+
+const TEXT: &str = "The quick brown fox jumps over the lazy dog.
+The quick brown fox sleeps. It is in the house.
+";
+
+#[test]
+fn test_bigrams_indexing() -> Result<(), AppError> {
+    let mut log = File::create("tmp/bigrams.log")?;
+
+    let settings = Settings {
+        bigrams: true,
+        ..Default::default()
+    };
+    let (_, words) = indexing(
+        &mut log,
+        &settings,
+        FileFilter::Text,
+        "text.txt",
+        &TEXT.as_bytes().to_vec(),
+    )?;
+    let bigrams = words.bigrams.expect("bigrams");
+
+    let top = bigrams.top(2);
+    assert_eq!(top[0], ("brown", "fox", 2));
+    assert_eq!(top[1], ("quick", "brown", 2));
+
+    // "in the" and "it is" are pairs of stop-words.
+    assert!(bigrams.stop_pairs >= 2);
+    assert!(bigrams.top(100).iter().all(|v| v.0 != "in" || v.1 != "the"));
+
+    // not without the setting.
+    let (_, words) = indexing(
+        &mut log,
+        &Settings::default(),
+        FileFilter::Text,
+        "text.txt",
+        &TEXT.as_bytes().to_vec(),
+    )?;
+    assert!(words.bigrams.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_bigrams_bounded() {
+    let mut bigrams = Bigrams::new(100);
+    for i in 0..1000 {
+        bigrams.add("common", false);
+        bigrams.add("pair", false);
+        bigrams.add(&format!("rare{}", i), false);
+    }
+    assert!(bigrams.len() <= 100);
+    assert!(bigrams.evicted > 0);
+    assert_eq!(bigrams.top(1)[0], ("common", "pair", 1000));
+}
+
+#[test]
+fn test_reservoir_sample() {
+    let sample = reservoir_sample(0..1000, 10, 4711);
+    assert_eq!(sample.len(), 10);
+    assert!(sample.iter().all(|v| *v < 1000));
+
+    // fewer items than the sample.
+    assert_eq!(reservoir_sample(0..5, 10, 4711), vec![0, 1, 2, 3, 4]);
+}
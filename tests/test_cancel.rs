@@ -0,0 +1,85 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use textindex::cancel;
+use textindex::error::AppError;
+use textindex::index2::merge::MergeConflict;
+use textindex::index2::Words;
+use textindex::proc3::logger::Logger;
+use textindex::proc3::{find_matched_lines, merge_index, Data, FoundSnapshot, DEFAULT_LINE_WIDTH};
+
+// All in one test, the flag is global.
+#[test]
+fn test_cancel() -> Result<(), AppError> {
+    let root = Path::new("tmp/cancel");
+    fs::create_dir_all(root)?;
+    let mut files = Vec::new();
+    for i in 0..3 {
+        let name = format!("file{}.txt", i);
+        fs::write(root.join(&name), "alpha beta\ngamma\n")?;
+        files.push(name);
+    }
+    let terms = vec!["alpha".to_string()];
+
+    cancel::reset();
+    let lines = find_matched_lines(root, &terms, &files, DEFAULT_LINE_WIDTH, false)?;
+    assert_eq!(lines.len(), 3);
+
+    cancel::cancel();
+    let lines = find_matched_lines(root, &terms, &files, DEFAULT_LINE_WIDTH, false)?;
+    assert!(lines.is_empty());
+
+    // a cancelled page is short, the rest comes later.
+    let found = FoundSnapshot::new(terms.clone(), files.clone(), DEFAULT_LINE_WIDTH);
+    assert!(found.page(root, 0, 2)?.is_empty());
+    assert!(cancel::reset());
+    assert_eq!(found.page(root, 0, 2)?.len(), 2);
+
+    // verify
+    let path = PathBuf::from_str("tmp/cancel.idx")?;
+    let mut w = Words::create(&path)?;
+    let fid = w.add_file("file0.txt".into());
+    w.add_word("alpha", 1, fid)?;
+    w.write()?;
+
+    cancel::cancel();
+    let report = w.check(None);
+    assert!(report.cancelled);
+    assert_eq!(report.words, 0);
+
+    cancel::reset();
+    let report = w.check(None);
+    assert!(!report.cancelled);
+    assert_eq!(report.words, 1);
+    drop(w);
+
+    // a cancelled merge isn't kept, and can be repeated.
+    let other = PathBuf::from_str("tmp/cancel_other.idx")?;
+    let mut o = Words::create(&other)?;
+    let fid = o.add_file("other.txt".into());
+    o.add_word("beta", 1, fid)?;
+    o.write()?;
+    drop(o);
+
+    let data: &'static Data = Box::leak(Box::new(Data::new(
+        root.into(),
+        Words::read(&path)?,
+        Logger::new(None),
+    )));
+    cancel::cancel();
+    let report = merge_index(data, &other, MergeConflict::Skip, |_| {})?;
+    assert!(report.cancelled);
+    assert_eq!(data.words.lock()?.files().len(), 1);
+    assert!(data.words.lock()?.find(&["beta".into()])?.is_empty());
+
+    cancel::reset();
+    let report = merge_index(data, &other, MergeConflict::Skip, |_| {})?;
+    assert!(!report.cancelled);
+    assert!(report.conflicts.is_empty());
+    assert_eq!(
+        data.words.lock()?.find(&["beta".into()])?,
+        vec!["other.txt"]
+    );
+
+    Ok(())
+}
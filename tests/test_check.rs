@@ -0,0 +1,185 @@
+use blockfile2::LogicalNr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use textindex::error::AppError;
+use textindex::index2::check::CheckScan;
+use textindex::index2::word_map::RawWordMap;
+use textindex::index2::{WordBlockType, Words};
+
+#[test]
+fn test_clean_shutdown() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/check_clean.idx")?;
+
+    let mut w = Words::create(&path)?;
+    assert!(w.was_clean());
+    let fid = w.add_file("file0".into());
+    w.add_word("alpha", 1, fid)?;
+    w.add_word("beta", 1, fid)?;
+    w.write()?;
+    drop(w);
+
+    let mut w = Words::read(&path)?;
+    assert!(w.was_clean());
+    let report = w.check(None);
+    assert!(report.is_ok(), "{:?}", report.problems);
+    assert_eq!(report.words, 2);
+    assert_eq!(report.files, 1);
+    drop(w);
+
+    // not written, not clean.
+    let w = Words::read(&path)?;
+    assert!(!w.was_clean());
+
+    Ok(())
+}
+
+#[test]
+fn test_check_broken_chain() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/check_broken.idx")?;
+
+    let mut w = Words::create(&path)?;
+    let fid = w.add_file("file0".into());
+    w.add_word("alpha", 1, fid)?;
+    w.write()?;
+
+    let nr =
+        w.db.iter_metadata_filter(|_nr, ty| ty == WordBlockType::WordMapHead)
+            .map(|(nr, _)| nr)
+            .next()
+            .expect("wordmap");
+    let maps = w.db.get_as_mut::<RawWordMap>(nr)?;
+    for map in maps.iter_mut() {
+        map.next_block_nr = LogicalNr(9999);
+    }
+
+    let report = w.check(None);
+    assert!(!report.is_ok());
+
+    Ok(())
+}
+
+fn fill(w: &mut Words, files: usize) -> Result<(), AppError> {
+    for n in 0..files {
+        let fid = w.add_file(format!("file{}", n));
+        for i in 0..20 {
+            w.add_word(format!("word{}", n * 20 + i).as_str(), 1, fid)?;
+        }
+    }
+    Ok(())
+}
+
+fn run(w: &mut Words, scan: &mut CheckScan, n: usize) -> usize {
+    let mut steps = 1;
+    while !w.check_step(scan, n) {
+        steps += 1;
+    }
+    steps
+}
+
+#[test]
+fn test_check_chunked() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/check_chunked.idx")?;
+
+    let mut w = Words::create(&path)?;
+    fill(&mut w, 10)?;
+    w.write()?;
+
+    let full = w.check(None);
+    let mut scan = w.start_check(None, 0);
+    assert_eq!(run(&mut w, &mut scan, 7), 29);
+    assert!(scan.report.is_ok(), "{:?}", scan.report.problems);
+    assert_eq!(scan.report.words, full.words);
+    assert_eq!(scan.report.chain_entries, full.chain_entries);
+    assert_eq!(scan.report.files, 10);
+    assert_eq!(scan.pos, 200);
+
+    // sampled
+    let mut scan = w.start_check(Some(20), 0);
+    run(&mut w, &mut scan, 3);
+    assert_eq!(scan.report.words, 20);
+    assert_eq!(scan.pos, 200);
+
+    Ok(())
+}
+
+#[test]
+fn test_check_chunked_changes() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/check_chunked_changes.idx")?;
+
+    let mut w = Words::create(&path)?;
+    fill(&mut w, 5)?;
+
+    let mut scan = w.start_check(None, 0);
+    assert!(!w.check_step(&mut scan, 50));
+    // before and after the position.
+    let fid = w.add_file("late".into());
+    w.add_word("aaa", 1, fid)?;
+    w.add_word("zzz", 1, fid)?;
+    run(&mut w, &mut scan, 50);
+
+    assert!(scan.report.is_ok(), "{:?}", scan.report.problems);
+    assert_eq!(scan.report.words, 101);
+
+    Ok(())
+}
+
+#[test]
+fn test_check_chunked_new_blocks() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/check_chunked_new_blocks.idx")?;
+
+    let mut w = Words::create(&path)?;
+    fill(&mut w, 5)?;
+    w.write()?;
+
+    let mut scan = w.start_check(None, 0);
+    assert!(!w.check_step(&mut scan, 50));
+    // the chains of these words are in blocks the scan doesn't know yet.
+    let before = w.db.iter_metadata_filter(|_nr, _ty| true).count();
+    let fid = w.add_file("late".into());
+    for i in 0..2000 {
+        w.add_word(format!("zz{}", i).as_str(), 1, fid)?;
+    }
+    assert!(w.db.iter_metadata_filter(|_nr, _ty| true).count() > before);
+    run(&mut w, &mut scan, 50);
+
+    assert!(scan.report.is_ok(), "{:?}", scan.report.problems);
+    assert_eq!(scan.report.words, 2100);
+
+    Ok(())
+}
+
+#[test]
+fn test_verify_pos() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/check_verify_pos.idx")?;
+
+    let mut w = Words::create(&path)?;
+    fill(&mut w, 5)?;
+    assert_eq!(w.verify_pos(), None);
+
+    let mut scan = w.start_check(None, 0);
+    w.check_step(&mut scan, 30);
+    w.set_verify_pos(Some(scan.pos));
+    w.write()?;
+    drop(w);
+
+    // resumes after a restart.
+    let mut w = Words::read(&path)?;
+    assert_eq!(w.verify_pos(), Some(30));
+    let mut scan = w.start_check(None, 30);
+    assert_eq!(scan.pos, 30);
+    run(&mut w, &mut scan, 30);
+    assert!(scan.report.is_ok(), "{:?}", scan.report.problems);
+    assert_eq!(scan.report.words, 70);
+
+    w.set_verify_pos(None);
+    w.write()?;
+    drop(w);
+
+    let w = Words::read(&path)?;
+    assert_eq!(w.verify_pos(), None);
+    // past the end starts again.
+    let scan = w.start_check(None, 1000);
+    assert_eq!(scan.pos, 0);
+
+    Ok(())
+}
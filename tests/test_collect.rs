@@ -0,0 +1,54 @@
+use std::fs;
+use std::path::Path;
+use textindex::cancel;
+use textindex::error::AppError;
+use textindex::proc3::{collect_files, collect_target_used, COLLECT_PROGRESS};
+
+// All in one test, the cancel flag is global.
+#[test]
+fn test_collect() -> Result<(), AppError> {
+    let root = Path::new("tmp/collect_root");
+    let target = Path::new("tmp/collect_out");
+    let _ = fs::remove_dir_all(target);
+    fs::create_dir_all(root.join("sub"))?;
+    fs::write(root.join("a.txt"), "alpha")?;
+    fs::write(root.join("sub/b.txt"), "beta gamma")?;
+    let _ = fs::remove_file(root.join("gone.txt"));
+
+    let files = vec![
+        "a.txt".to_string(),
+        "gone.txt".to_string(),
+        "sub/b.txt".to_string(),
+    ];
+
+    assert!(!collect_target_used(target)?);
+    cancel::reset();
+    let mut calls = 0;
+    let report = collect_files(root, &files, target, |_| calls += 1)?;
+    assert_eq!(report.files, 2);
+    assert_eq!(report.bytes, 15);
+    assert_eq!(report.missing, vec!["gone.txt"]);
+    assert!(!report.cancelled);
+    assert_eq!(calls, 0);
+
+    assert_eq!(fs::read_to_string(target.join("a.txt"))?, "alpha");
+    assert_eq!(fs::read_to_string(target.join("sub/b.txt"))?, "beta gamma");
+    assert!(collect_target_used(target)?);
+
+    // progress
+    let many = (0..COLLECT_PROGRESS * 2)
+        .map(|_| "a.txt".to_string())
+        .collect::<Vec<_>>();
+    let mut calls = Vec::new();
+    collect_files(root, &many, target, |n| calls.push(n))?;
+    assert_eq!(calls, vec![COLLECT_PROGRESS, COLLECT_PROGRESS * 2]);
+
+    // Ctrl-C
+    cancel::cancel();
+    let report = collect_files(root, &files, target, |_| {})?;
+    assert!(report.cancelled);
+    assert_eq!(report.files, 0);
+    cancel::reset();
+
+    Ok(())
+}
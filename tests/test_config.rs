@@ -0,0 +1,121 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use textindex::error::AppError;
+use textindex::index2::tmp_index::TmpWords;
+use textindex::index2::{IndexKind, Words};
+use textindex::proc3::indexer::index_txt2;
+use textindex::proc3::stop_words::{format_stop_words, parse_stop_words};
+use textindex::proc3::{config_text, ConfigSource, CONFIG_FILTER, CONFIG_STOP_WORDS};
+
+#[test]
+fn test_config_roundtrip() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/config_roundtrip.idx")?;
+
+    let mut w = Words::create(&path)?;
+    assert!(w.config().get(CONFIG_STOP_WORDS).is_none());
+    let fid = w.add_file("file0".into());
+    w.add_word("alpha", 1, fid)?;
+    w.set_config(CONFIG_STOP_WORDS, b"der\ndie\ndas\n".to_vec())?;
+    w.set_config(CONFIG_FILTER, b"html books\n".to_vec())?;
+    w.write()?;
+    drop(w);
+
+    let mut w = Words::read(&path)?;
+    assert_eq!(
+        w.config().get(CONFIG_STOP_WORDS),
+        Some(b"der\ndie\ndas\n".as_slice())
+    );
+    assert_eq!(
+        w.config().get(CONFIG_FILTER),
+        Some(b"html books\n".as_slice())
+    );
+    assert!(w.word("alpha")?.is_some());
+
+    // appended again, the last one wins.
+    w.set_config(CONFIG_STOP_WORDS, b"the\n".to_vec())?;
+    w.write()?;
+    drop(w);
+
+    let w = Words::read(&path)?;
+    assert_eq!(w.config().get(CONFIG_STOP_WORDS), Some(b"the\n".as_slice()));
+    assert_eq!(w.config().list().len(), 2);
+    assert_eq!(w.files().len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_config_precedence() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/config_precedence.idx")?;
+    let external = Path::new("tmp/config_precedence_stopwords.txt");
+    let _ = fs::remove_file(external);
+
+    let mut w = Words::create(&path)?;
+    assert_eq!(config_text(&w, CONFIG_STOP_WORDS, external)?, None);
+
+    w.set_config(CONFIG_STOP_WORDS, b"embedded\n".to_vec())?;
+    w.write()?;
+    drop(w);
+
+    let w = Words::read(&path)?;
+    assert_eq!(
+        config_text(&w, CONFIG_STOP_WORDS, external)?,
+        Some(("embedded\n".to_string(), ConfigSource::Embedded))
+    );
+
+    fs::write(external, "external\n")?;
+    assert_eq!(
+        config_text(&w, CONFIG_STOP_WORDS, external)?,
+        Some(("external\n".to_string(), ConfigSource::FileOverEmbedded))
+    );
+    // nothing embedded.
+    assert_eq!(
+        config_text(&w, CONFIG_FILTER, external)?,
+        Some(("external\n".to_string(), ConfigSource::File))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_stop_list() -> Result<(), AppError> {
+    let stop_list = parse_stop_words("# comment\nFish\n\n  chips \n")?;
+    assert_eq!(stop_list.len(), 2);
+    assert!(stop_list.contains("fish"));
+    assert_eq!(
+        format_stop_words(stop_list.iter().map(|v| v.as_str())),
+        "chips\nfish\n"
+    );
+
+    let mut log = File::create("tmp/stop_list.log")?;
+    let mut words = TmpWords::new("stop.txt");
+    words.stop_list = Some(Arc::new(stop_list));
+    index_txt2(&mut log, "stop.txt", &mut words, "the fish and chips")?;
+
+    // replaces the built-in list.
+    assert!(words.words.contains_key("the"));
+    assert!(words.words.contains_key("and"));
+    assert!(!words.words.contains_key("fish"));
+    assert!(!words.words.contains_key("chips"));
+    assert_eq!(words.stop_count, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_config_invalid() -> Result<(), AppError> {
+    // an error, not a panic.
+    let err = parse_stop_words("fish\nfish and chips\n").expect_err("two words");
+    assert!(err.to_string().contains("line 2"), "{}", err);
+
+    let mut w = Words::create(Path::new("tmp/config_invalid.idx"))?;
+    match w.set_config(&"x".repeat(70_000), b"the\n".to_vec()) {
+        Err(e) => assert!(matches!(e.kind, IndexKind::InvalidConfig(_))),
+        Ok(_) => panic!("embedded a name longer than 64KB"),
+    }
+    assert!(w.config().list().is_empty());
+
+    Ok(())
+}
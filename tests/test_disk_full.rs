@@ -0,0 +1,18 @@
+use std::io;
+use textindex::index2::{IndexError, IndexKind};
+
+#[test]
+fn test_disk_full() {
+    let full = IndexError::from(io::Error::from(io::ErrorKind::StorageFull));
+    assert!(full.is_disk_full());
+    assert!(matches!(full.or_disk_full().kind, IndexKind::DiskFull));
+
+    // ENOSPC.
+    #[cfg(unix)]
+    assert!(IndexError::from(io::Error::from_raw_os_error(28)).is_disk_full());
+
+    // the message doesn't count.
+    let other = IndexError::from(io::Error::other("StorageFull"));
+    assert!(!other.is_disk_full());
+    assert!(matches!(other.or_disk_full().kind, IndexKind::IOError(_)));
+}
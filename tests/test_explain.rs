@@ -0,0 +1,162 @@
+use std::path::Path;
+use textindex::error::AppError;
+use textindex::index2::plan::{MatchMode, QueryPlan, WordEstimate};
+use textindex::index2::Words;
+use textindex::proc3::{explain_query, Settings};
+
+fn fill(path: &Path) -> Result<Words, AppError> {
+    let mut w = Words::create(path)?;
+    let fid = w.add_file("file0.txt".into());
+    w.add_word("harbour", 1, fid)?;
+    w.add_word("harbor", 1, fid)?;
+    w.add_word("boy", 1, fid)?;
+    w.add_word("link:home.html", 1, fid)?;
+    let fid = w.add_file("file1.txt".into());
+    w.add_word("harbour", 1, fid)?;
+    w.add_word("bay", 1, fid)?;
+    w.add_word("box", 1, fid)?;
+    w.write()?;
+    Ok(w)
+}
+
+#[test]
+fn test_plan() {
+    let plan = QueryPlan::new(&["harbour".into(), "harb*".into(), "b?y".into(), "*x".into()]);
+    let modes: Vec<_> = plan.terms.iter().map(|v| v.mode).collect();
+    assert_eq!(
+        modes,
+        vec![
+            MatchMode::Exact,
+            MatchMode::Prefix,
+            MatchMode::Wildcard,
+            MatchMode::Wildcard
+        ]
+    );
+    assert_eq!(plan.terms[1].prefix, "harb");
+    assert_eq!(plan.terms[2].prefix, "b");
+    assert_eq!(plan.terms[3].prefix, "");
+
+    let plan = QueryPlan::new(&["link:*".into()]);
+    assert_eq!(plan.terms[0].mode, MatchMode::Prefix);
+    assert!(plan.terms[0].namespaced);
+}
+
+#[test]
+fn test_explain() -> Result<(), AppError> {
+    let mut w = fill(Path::new("tmp/explain.idx"))?;
+    let settings = Settings::default();
+
+    let terms = vec!["harb*".to_string(), "b?y".into(), "*x".into()];
+    let lines = explain_query(&mut w, &settings, &terms, 20)?;
+    assert_eq!(
+        lines,
+        vec![
+            "term 1: harb* - prefix, prefix \"harb\", 2 words",
+            "term 2: b?y - wildcard, prefix \"b\", 2 words",
+            "    reduces the files of the terms before",
+            "term 3: *x - wildcard, all words, 1 word",
+            "    reduces the files of the terms before",
+            "files: walks the file chains of 5 words",
+            "order: as the files were added, 20 files per page",
+            "lines: read from the files of each page, matching harb* b?y *x",
+        ]
+    );
+    // nothing was run.
+    assert_eq!(w.query_cache().misses, 0);
+    assert_eq!(w.query_cache().hits, 0);
+
+    // after a find the result is cached.
+    w.find(&terms)?;
+    let lines = explain_query(&mut w, &settings, &terms, 20)?;
+    assert!(lines.contains(&"files: from the query cache".to_string()));
+    assert_eq!(w.query_cache().misses, 1);
+    assert_eq!(w.query_cache().hits, 0);
+
+    let lines = explain_query(&mut w, &settings, &["link:*".into()], 20)?;
+    assert_eq!(
+        lines[0],
+        "term 1: link:* - prefix, namespaced, prefix \"link:\", 1 word"
+    );
+    assert_eq!(lines[3], "lines: none, only namespaced terms");
+
+    Ok(())
+}
+
+#[test]
+fn test_explain_notes() -> Result<(), AppError> {
+    let mut w = fill(Path::new("tmp/explain_notes.idx"))?;
+    w.set_min_word_len(3);
+    let settings = Settings {
+        match_limit: 1,
+        ..Default::default()
+    };
+
+    let lines = explain_query(&mut w, &settings, &["the".into()], 20)?;
+    assert_eq!(lines[0], "term 1: the - exact, 0 words");
+    assert_eq!(lines[1], "    is a stop-word and not indexed");
+
+    let lines = explain_query(&mut w, &settings, &["ab".into()], 20)?;
+    assert_eq!(
+        lines[1],
+        "    is shorter than min-word-len 3 and not indexed"
+    );
+
+    let lines = explain_query(&mut w, &settings, &["har*".into()], 20)?;
+    assert_eq!(
+        lines[1],
+        "    matches more than match-limit 1 words, find asks first"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_explain_cap() -> Result<(), AppError> {
+    let path = Path::new("tmp/explain_cap.idx");
+    let mut w = Words::create(path)?;
+    let fid = w.add_file("file0.txt".into());
+    for i in 0..5000 {
+        w.add_word(format!("w{}", i), 1, fid)?;
+    }
+    w.write()?;
+    drop(w);
+
+    let mut w = Words::read_with(path, 0)?;
+    let terms = vec!["w*".to_string()];
+
+    w.db.start_counting();
+    let full = w.explain(&terms, 10_000)?;
+    let all = w.db.stop_counting();
+    assert_eq!(
+        full.estimates[0],
+        WordEstimate {
+            words: 5000,
+            capped: false
+        }
+    );
+
+    // stops after cap+1 words, only the first blocks are read.
+    w.db.start_counting();
+    let capped = w.explain(&terms, 5)?;
+    let few = w.db.stop_counting();
+    assert_eq!(
+        capped.estimates[0],
+        WordEstimate {
+            words: 5,
+            capped: true
+        }
+    );
+    assert!(few.misses * 10 < all.misses, "{:?} {:?}", few, all);
+
+    // exactly cap words is not capped.
+    let exact = w.explain(&["w4999".to_string()], 1)?;
+    assert_eq!(
+        exact.estimates[0],
+        WordEstimate {
+            words: 1,
+            capped: false
+        }
+    );
+
+    Ok(())
+}
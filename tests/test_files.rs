@@ -0,0 +1,323 @@
+use std::io::Write;
+use std::path::Path;
+use textindex::error::AppError;
+use textindex::index2::files::split_name;
+use textindex::index2::ids::FileId;
+use textindex::index2::{match_file_names, WordBlockType, Words};
+
+const NAMES: &[&str] = &[
+    "top.txt",
+    "a/b/one.txt",
+    "a/b/two.txt",
+    "a/bc/three.txt",
+    "a/four.txt",
+    "a/b/sub/five.html",
+    "win\\dir\\six.txt",
+    "a/b/one.txt",
+];
+
+fn names(w: &Words) -> Vec<String> {
+    w.files().iter().map(|(_, v)| v.into_owned()).collect()
+}
+
+#[test]
+fn test_split_name() {
+    assert_eq!(split_name("a/b/c.txt"), ("a/b/", "c.txt"));
+    assert_eq!(split_name("c.txt"), ("", "c.txt"));
+    assert_eq!(split_name("a\\c.txt"), ("a\\", "c.txt"));
+    assert_eq!(split_name("a/"), ("a/", ""));
+}
+
+#[test]
+fn test_files_roundtrip() -> Result<(), AppError> {
+    let path = Path::new("tmp/files_roundtrip.idx");
+
+    let mut w = Words::create(path)?;
+    for name in NAMES {
+        w.add_file(name.to_string());
+    }
+    assert_eq!(names(&w), NAMES);
+    w.write()?;
+    drop(w);
+
+    let mut w = Words::read(path)?;
+    assert_eq!(names(&w), NAMES);
+    // root, a/b/, a/bc/, a/, a/b/sub/, win\dir\
+    assert_eq!(w.files().dirs().len(), 6);
+    assert_eq!(w.files().file_id("a/b/one.txt"), Some(FileId(2)));
+    assert_eq!(w.files().file_id("top.txt"), Some(FileId(1)));
+    assert_eq!(w.files().file_id("a/b/none.txt"), None);
+    assert_eq!(w.files().file_id("x/top.txt"), None);
+    assert_eq!(w.file(FileId(6)), Some("a/b/sub/five.html".to_string()));
+
+    // continue in old and new directories.
+    w.add_file("a/b/seven.txt".into());
+    w.add_file("new/eight.txt".into());
+    w.write()?;
+    drop(w);
+
+    let w = Words::read(path)?;
+    let mut all = NAMES.to_vec();
+    all.push("a/b/seven.txt");
+    all.push("new/eight.txt");
+    assert_eq!(names(&w), all);
+    assert_eq!(w.files().dirs().len(), 7);
+    assert_eq!(w.files().last_file_id(), FileId(10));
+
+    Ok(())
+}
+
+#[test]
+fn test_files_flat_dir() -> Result<(), AppError> {
+    let path = Path::new("tmp/files_flat_dir.idx");
+
+    let mut w = Words::create(path)?;
+    w.set_fold_case(false);
+    let ids: Vec<_> = (0..5000)
+        .map(|i| w.add_file(format!("flat/file{}.txt", i)))
+        .collect();
+    w.write()?;
+    drop(w);
+
+    let w = Words::read(path)?;
+    for (i, file_id) in ids.iter().enumerate() {
+        let name = format!("flat/file{}.txt", i);
+        assert_eq!(w.files().file_id(&name), Some(*file_id));
+    }
+    assert_eq!(w.files().file_id("flat/File1.txt"), None);
+    assert_eq!(w.files().file_id("flat/file5000.txt"), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_files_match() -> Result<(), AppError> {
+    let path = Path::new("tmp/files_match.idx");
+
+    let mut w = Words::create(path)?;
+    for name in NAMES {
+        w.add_file(name.to_string());
+    }
+    w.write()?;
+    drop(w);
+    let w = Words::read(path)?;
+
+    for pattern in [
+        "a/*",
+        "a/b*",
+        "a/b/*",
+        "a/b/o*",
+        "a/b/one.txt",
+        "a/b/*.txt",
+        "a/b?/*",
+        "a/",
+        "top*",
+        "t*",
+        "win\\dir\\*",
+        "*.txt",
+        "*",
+        "none/*",
+    ] {
+        let slow = match_file_names(w.file_name_list(), pattern);
+        assert_eq!(w.find_file(pattern), slow, "{}", pattern);
+        if let Some(fast) = w.find_file_prefix(pattern) {
+            assert_eq!(fast, slow, "{}", pattern);
+        }
+    }
+
+    assert_eq!(
+        w.find_file("a/b/*"),
+        vec![
+            "a/b/one.txt",
+            "a/b/two.txt",
+            "a/b/sub/five.html",
+            "a/b/one.txt"
+        ]
+    );
+    assert_eq!(w.find_file("a/b*/*e*").len(), 4);
+    assert_eq!(w.find_file("t*"), vec!["top.txt"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_files_v1() -> Result<(), AppError> {
+    let path = Path::new("tmp/files_v1.idx");
+
+    let mut w = Words::create(path)?;
+    w.write()?;
+    // records with the full name as written up to version 2.
+    {
+        let mut s = w.db.append_stream(WordBlockType::FileList)?;
+        for (i, name) in ["old/one.txt", "old/two.txt", "root.txt"]
+            .iter()
+            .enumerate()
+        {
+            let mut buf = Vec::new();
+            buf.extend((i as u32 + 1).to_ne_bytes());
+            buf.extend((name.len() as u16).to_ne_bytes());
+            buf.extend(name.as_bytes());
+            s.write_all(&buf)?;
+        }
+    }
+    w.db.store()?;
+    drop(w);
+
+    let mut w = Words::read(path)?;
+    assert_eq!(names(&w), vec!["old/one.txt", "old/two.txt", "root.txt"]);
+    assert_eq!(w.files().last_file_id(), FileId(3));
+
+    w.add_file("old/three.txt".into());
+    w.write()?;
+    drop(w);
+
+    let mut w = Words::read(path)?;
+    assert_eq!(
+        names(&w),
+        vec!["old/one.txt", "old/two.txt", "root.txt", "old/three.txt"]
+    );
+    // the old records are not written again.
+    assert_eq!(w.files().len(), 4);
+    assert_eq!(w.find_file("old/*").len(), 3);
+    assert!(w.check(None).is_ok());
+
+    Ok(())
+}
+
+/// Positions of the records as (block_nr, block_idx).
+fn positions(w: &Words) -> (Vec<(u32, u32)>, Vec<(u32, u32)>) {
+    let files = w
+        .files()
+        .list()
+        .values()
+        .map(|v| (v.block_nr.0, v.block_idx.0))
+        .collect();
+    let dirs = w
+        .files()
+        .dirs()
+        .iter()
+        .map(|v| (v.block_nr.0, v.block_idx.0))
+        .collect();
+    (files, dirs)
+}
+
+#[test]
+fn test_files_positions() -> Result<(), AppError> {
+    let path = Path::new("tmp/files_positions.idx");
+
+    // records of different length, some of them straddle the blocks
+    // and some start right at the end of one.
+    let mut w = Words::create(path)?;
+    for i in 0..1500 {
+        let leaf = "x".repeat(1 + i % 37);
+        w.add_file(format!("directory{}/{}{}.txt", i % 400, leaf, i));
+        if i % 500 == 499 {
+            w.write()?;
+        }
+    }
+    let written = positions(&w);
+    drop(w);
+
+    let mut blocks: Vec<_> = written.0.iter().map(|v| v.0).collect();
+    blocks.dedup();
+    assert!(blocks.len() > 3, "{:?}", blocks);
+    let mut blocks: Vec<_> = written.1.iter().map(|v| v.0).collect();
+    blocks.dedup();
+    assert!(blocks.len() > 1, "{:?}", blocks);
+    assert!(written.0.iter().all(|v| v.0 != 0));
+
+    let mut w = Words::read(path)?;
+    assert_eq!(positions(&w), written);
+
+    // appended after the last record.
+    let file_id = w.add_file("directory0/late.txt".into());
+    w.write()?;
+    let late = {
+        let v = &w.files().list()[&file_id];
+        (v.block_nr.0, v.block_idx.0)
+    };
+    let written = positions(&w);
+    drop(w);
+
+    let w = Words::read(path)?;
+    assert_eq!(positions(&w), written);
+    assert_eq!(written.0.last(), Some(&late));
+
+    Ok(())
+}
+
+#[test]
+fn test_files_fold_case() -> Result<(), AppError> {
+    let path = Path::new("tmp/files_fold_case.idx");
+
+    let mut w = Words::create(path)?;
+    w.set_fold_case(false);
+    let first = w.add_file("docs/readme.txt".into());
+    w.add_file("Docs/Other.txt".into());
+    w.write()?;
+
+    // exact.
+    assert!(w.have_file(&"docs/readme.txt".to_string()));
+    assert!(!w.have_file(&"DOCS/Readme.TXT".to_string()));
+    assert!(!w.have_file(&"docs/other.txt".to_string()));
+
+    w.set_fold_case(true);
+    assert!(w.have_file(&"DOCS/Readme.TXT".to_string()));
+    assert!(w.have_file(&"docs/other.txt".to_string()));
+    assert_eq!(w.files().file_id("Docs/README.txt"), Some(first));
+    assert!(!w.have_file(&"docs/readme.md".to_string()));
+    // the names keep their case.
+    assert_eq!(names(&w), vec!["docs/readme.txt", "Docs/Other.txt"]);
+    assert!(w.case_duplicates().is_empty());
+
+    // added under two names while exact.
+    w.set_fold_case(false);
+    w.add_word("first", 1, first)?;
+    let second = w.add_file("DOCS/README.TXT".into());
+    w.add_word("second", 1, second)?;
+    w.write()?;
+    drop(w);
+
+    // the setting is stored.
+    let mut w = Words::read(path)?;
+    assert!(!w.fold_case());
+    assert_eq!(
+        w.case_duplicates(),
+        vec![vec![
+            "docs/readme.txt".to_string(),
+            "DOCS/README.TXT".to_string()
+        ]]
+    );
+    assert_eq!(w.find(&["second".into()])?, vec!["DOCS/README.TXT"]);
+
+    // merged into the first one.
+    w.set_fold_case(true);
+    assert!(w.case_duplicates().is_empty());
+    assert_eq!(w.files().file_id("docs/README.txt"), Some(first));
+    assert_eq!(w.files().len(), 2);
+    assert_eq!(
+        w.find(&["first".into(), "second".into()])?,
+        vec!["docs/readme.txt"]
+    );
+    w.write()?;
+    drop(w);
+
+    let mut w = Words::read(path)?;
+    assert!(w.fold_case());
+    assert_eq!(names(&w), vec!["docs/readme.txt", "Docs/Other.txt"]);
+    assert_eq!(w.find(&["second".into()])?, vec!["docs/readme.txt"]);
+    drop(w);
+
+    let mut w = Words::read_only(path)?;
+    assert_eq!(w.find(&["second".into()])?, vec!["docs/readme.txt"]);
+    drop(w);
+
+    // merged files stay merged when it is turned off.
+    let mut w = Words::read(path)?;
+    w.set_fold_case(false);
+    assert_eq!(w.find(&["second".into()])?, vec!["docs/readme.txt"]);
+    assert_eq!(w.files().file_id("DOCS/README.TXT"), Some(second));
+    assert!(w.case_duplicates().is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use std::fs;
+use std::path::Path;
+use textindex::error::AppError;
+use textindex::proc3::filter::{FilterConfig, FilterReason};
+use textindex::proc3::FileFilter;
+
+fn classify(config: &FilterConfig, relative: &str) -> (FileFilter, FilterReason) {
+    config.classify(relative, Path::new(relative))
+}
+
+#[test]
+fn test_force_nested() {
+    let mut config = FilterConfig::default();
+    config.set_force("books", Some(FileFilter::Html));
+    config.set_force("books/raw", Some(FileFilter::Text));
+    config.set_force("books/raw/blobs", Some(FileFilter::Ignore));
+    config.set_force("*/scans", Some(FileFilter::Ignore));
+
+    assert_eq!(
+        classify(&config, "books/a"),
+        (FileFilter::Html, FilterReason::Forced("books".into()))
+    );
+    assert_eq!(
+        classify(&config, "books/raw/b.txt"),
+        (FileFilter::Text, FilterReason::Forced("books/raw".into()))
+    );
+    assert_eq!(
+        classify(&config, "books/raw/blobs/c.txt").0,
+        FileFilter::Ignore
+    );
+    // the longest matching prefix, not the longest glob.
+    assert_eq!(
+        classify(&config, "books/raw/x/scans/d.txt"),
+        (FileFilter::Ignore, FilterReason::Forced("*/scans".into()))
+    );
+    // only whole path components.
+    assert_eq!(
+        classify(&config, "books2/e.txt"),
+        (FileFilter::Inspect, FilterReason::Content)
+    );
+    // before the extension tables.
+    assert_eq!(
+        classify(&config, "books/raw/cover.jpg"),
+        (FileFilter::Text, FilterReason::Forced("books/raw".into()))
+    );
+    assert_eq!(
+        classify(&config, "other/cover.jpg"),
+        (FileFilter::Ignore, FilterReason::Extension)
+    );
+    assert_eq!(
+        classify(&config, "other/thumbs.db"),
+        (FileFilter::Ignore, FilterReason::Name)
+    );
+}
+
+#[test]
+fn test_force_same_prefix() {
+    let mut config = FilterConfig::default();
+    config.set_force("web/*", Some(FileFilter::Text));
+    config.set_force("web/pages", Some(FileFilter::Html));
+
+    // same prefix, the longer glob.
+    assert_eq!(classify(&config, "web/pages/a").0, FileFilter::Html);
+    assert_eq!(classify(&config, "web/misc/a").0, FileFilter::Text);
+    // the glob matches the file itself.
+    assert_eq!(classify(&config, "web/a").0, FileFilter::Text);
+    assert_eq!(classify(&config, r"web\pages\b").0, FileFilter::Html);
+
+    // replace and remove.
+    config.set_force("/web/pages/", Some(FileFilter::Ignore));
+    assert_eq!(config.force.len(), 2);
+    assert_eq!(classify(&config, "web/pages/a").0, FileFilter::Ignore);
+    config.set_force("web/pages", None);
+    assert_eq!(classify(&config, "web/pages/a").0, FileFilter::Text);
+}
+
+#[test]
+fn test_force_store() -> Result<(), AppError> {
+    let path = Path::new("tmp/filter.txt");
+    let _ = fs::remove_file(path);
+
+    let config = FilterConfig::load(path)?;
+    assert!(config.force.is_empty());
+
+    let mut config = FilterConfig::default();
+    config.set_force("books", Some(FileFilter::Html));
+    config.set_force("books/raw", Some(FileFilter::Text));
+    config.set_force("blobs", Some(FileFilter::Ignore));
+    config.store(path)?;
+
+    let config = FilterConfig::load(path)?;
+    let rules = config
+        .force
+        .iter()
+        .map(|v| (v.glob.as_str(), v.filter))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rules,
+        vec![
+            ("books", FileFilter::Html),
+            ("books/raw", FileFilter::Text),
+            ("blobs", FileFilter::Ignore)
+        ]
+    );
+    assert_eq!(classify(&config, "books/raw/a").0, FileFilter::Text);
+
+    Ok(())
+}
@@ -0,0 +1,423 @@
+use crossbeam::channel::bounded;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use textindex::error::AppError;
+use textindex::index2::diagnostics::FindDiagnostics;
+use textindex::index2::ids::{BlkIdx, FileId};
+use textindex::index2::query_cache::{QueryCache, QUERY_CACHE_LEN};
+use textindex::index2::word_map::RawWordMap;
+use textindex::index2::{literal_prefix, match_file_names, Words};
+use textindex::proc3::{broad_terms, find, Found, FoundSnapshot, DEFAULT_LINE_WIDTH, MATCH_TOP};
+
+#[test]
+fn test_find_files_only() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/find_files_only.idx")?;
+
+    let mut w = Words::create(&path)?;
+    let fid = w.add_file("dir/file0.txt".into());
+    w.add_word("alpha", 1, fid)?;
+    w.add_word("beta", 1, fid)?;
+    let fid = w.add_file("dir/file1.txt".into());
+    w.add_word("alpha", 1, fid)?;
+    w.write()?;
+
+    // the root doesn't exist, any attempt to read a file fails.
+    let root = Path::new("tmp/does_not_exist");
+
+    let found = find(&mut w, root, &["alpha".into()], true, None)?;
+    assert_eq!(found.files, vec!["dir/file0.txt", "dir/file1.txt"]);
+    assert!(found.lines.is_empty());
+
+    let found = find(&mut w, root, &["alpha".into(), "beta".into()], true, None)?;
+    assert_eq!(found.files, vec!["dir/file0.txt"]);
+
+    // extracting the lines must fail.
+    assert!(find(&mut w, root, &["alpha".into()], false, None).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_query_cache() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/query_cache.idx")?;
+
+    let mut w = Words::create(&path)?;
+    let fid = w.add_file("file0".into());
+    w.add_word("alpha", 1, fid)?;
+    w.add_word("beta", 1, fid)?;
+
+    let ids = w.find_ids(&["alpha".into(), "beta".into()])?;
+    assert_eq!(w.query_cache().misses, 1);
+    assert_eq!(w.query_cache().hits, 0);
+
+    // term order doesn't matter.
+    let ids2 = w.find_ids(&["beta".into(), "alpha".into()])?;
+    assert_eq!(ids, ids2);
+    assert_eq!(w.query_cache().hits, 1);
+
+    // any change invalidates.
+    let fid = w.add_file("file1".into());
+    w.add_word("alpha", 1, fid)?;
+    w.add_word("beta", 1, fid)?;
+    let ids3 = w.find_ids(&["alpha".into(), "beta".into()])?;
+    assert_eq!(ids3.len(), 2);
+    assert_eq!(w.query_cache().misses, 2);
+    assert_eq!(w.query_cache().len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_query_cache_lru() {
+    let mut cache = QueryCache::default();
+    for i in 0..QUERY_CACHE_LEN + 2 {
+        cache.insert(vec![format!("term{}", i)], 1, vec![FileId(i as u32)]);
+    }
+    assert_eq!(cache.len(), QUERY_CACHE_LEN);
+
+    // the oldest are gone.
+    assert!(cache.get(&["term0".into()], 1).is_none());
+    assert!(cache.get(&["term1".into()], 1).is_none());
+    assert_eq!(
+        cache.get(&["term2".into()], 1),
+        Some([FileId(2)].as_slice())
+    );
+
+    // term2 was used last, term3 is evicted next.
+    cache.insert(vec!["new".into()], 1, Vec::new());
+    assert!(cache.get(&["term3".into()], 1).is_none());
+    assert!(cache.get(&["term2".into()], 1).is_some());
+
+    // a new generation drops everything.
+    assert!(cache.get(&["term2".into()], 2).is_none());
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_find_diagnostics() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/find_diagnostics.idx")?;
+
+    let mut w = Words::create(&path)?;
+    for i in 0..3 {
+        let fid = w.add_file(format!("file{}", i));
+        w.add_word("alpha", 1, fid)?;
+        w.add_word("alpine", 1, fid)?;
+    }
+    let fid = w.add_file("file3".into());
+    w.add_word("beta", 1, fid)?;
+    w.write()?;
+
+    let mut diag = FindDiagnostics::default();
+    let ids = w.find_diagnostics(&["alp*".into(), "beta".into()], &mut diag)?;
+    assert!(ids.is_empty());
+    assert_eq!(diag.words.len(), 3);
+    assert_eq!(diag.term_sum("alp*").0, 2);
+    assert_eq!(diag.term_sum("alp*").2, 6);
+    assert_eq!(diag.term_sum("beta").2, 1);
+    assert!(diag.words.iter().all(|v| v.blocks >= 1));
+    assert!(diag.cache.hits + diag.cache.misses > 0);
+
+    // the query cache is not touched.
+    assert_eq!(w.query_cache().misses, 0);
+
+    // the same files as without diagnostics.
+    let mut diag = FindDiagnostics::default();
+    let ids = w.find_diagnostics(&["alp*".into()], &mut diag)?;
+    assert_eq!(ids, w.find_ids(&["alp*".into()])?);
+    assert_eq!(ids.len(), 3);
+    assert_eq!(diag.words.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_find_diagnostics_broken() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/find_diagnostics_broken.idx")?;
+
+    let mut w = Words::create(&path)?;
+    for i in 0..20 {
+        let fid = w.add_file(format!("file{}", i));
+        w.add_word("alpha", 1, fid)?;
+    }
+    w.write()?;
+
+    let mut diag = FindDiagnostics::default();
+    let ids = w.find_diagnostics(&["alpha".into()], &mut diag)?;
+    assert_eq!(ids.len(), 20);
+    assert_eq!(diag.words[0].file_ids, 20);
+    assert!(diag.words[0].entries > 1);
+    assert!(!diag.words[0].broken);
+
+    // the head points past the end of its next block.
+    let data = w.word("alpha")?.expect("alpha");
+    let maps = w.db.get_as_mut::<RawWordMap>(data.file_map_block_nr)?;
+    let head = &mut maps[data.file_map_idx.as_usize()];
+    assert!(head.next_block_nr != 0);
+    head.next_idx = BlkIdx(60000);
+
+    let mut diag = FindDiagnostics::default();
+    let ids = w.find_diagnostics(&["alpha".into()], &mut diag)?;
+    assert!(diag.words[0].broken);
+    assert_eq!(diag.words[0].entries, 1);
+    assert!(diag.words[0].file_ids < 20);
+    assert_eq!(ids.len(), diag.words[0].file_ids);
+
+    Ok(())
+}
+
+#[test]
+fn test_prefix_stats() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/prefix_stats.idx")?;
+
+    let mut w = Words::create(&path)?;
+    let fid = w.add_file("file0".into());
+    w.add_word("alpha", 3, fid)?;
+    w.add_word("alps", 1, fid)?;
+    w.add_word("al", 2, fid)?;
+    w.add_word("beta", 1, fid)?;
+    w.add_word("äpfel", 1, fid)?;
+    w.add_word("äpfeln", 2, fid)?;
+    w.add_word("link:alpha", 5, fid)?;
+
+    let stats = w.prefix_stats(2)?;
+    let stats = stats
+        .iter()
+        .map(|v| (v.prefix.as_str(), v.words, v.count))
+        .collect::<Vec<_>>();
+    assert_eq!(stats, vec![("al", 3, 6), ("äp", 2, 3), ("be", 1, 1)]);
+
+    // shorter words are their own group.
+    let stats = w.prefix_stats(3)?;
+    assert_eq!(stats[0].prefix, "alp");
+    assert_eq!(stats[0].words, 2);
+    assert!(stats.iter().any(|v| v.prefix == "al" && v.words == 1));
+
+    Ok(())
+}
+
+#[test]
+fn test_found_snapshot() -> Result<(), AppError> {
+    let root = Path::new("tmp/does_not_exist");
+    let found = Arc::new(Found::default());
+
+    found.set(FoundSnapshot::new(
+        Vec::new(),
+        vec!["old0".into(), "old1".into()],
+        DEFAULT_LINE_WIDTH,
+    ))?;
+
+    // a slow consumer keeps the old result.
+    let (s_taken, r_taken) = bounded(0);
+    let (s_go, r_go) = bounded(0);
+    let consumer = {
+        let found = Arc::clone(&found);
+        thread::spawn(move || {
+            let old = found.get().expect("found");
+            s_taken.send(()).expect("taken");
+            r_go.recv().expect("go");
+            old.page(root, 0, 10)
+                .expect("page")
+                .into_iter()
+                .map(|(f, _)| f)
+                .collect::<Vec<_>>()
+        })
+    };
+
+    r_taken.recv().expect("taken");
+    // doesn't wait for the consumer.
+    found.set(FoundSnapshot::new(
+        Vec::new(),
+        vec!["new0".into()],
+        DEFAULT_LINE_WIDTH,
+    ))?;
+    assert_eq!(found.get()?.files, vec!["new0"]);
+    assert_eq!(found.get()?.page(root, 0, 10)?.len(), 1);
+
+    s_go.send(()).expect("go");
+    let old = consumer.join().expect("consumer");
+    assert_eq!(old, vec!["old0", "old1"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_find_file_prefix() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/find_file_prefix.idx")?;
+
+    let mut w = Words::create(&path)?;
+    for name in [
+        "dir/b.txt",
+        "dir/a.txt",
+        "dir/sub/c.html",
+        "dir2/a.txt",
+        "other/dir/a.txt",
+        "dir/a.txt",
+    ] {
+        w.add_file(name.into());
+    }
+    w.write()?;
+    drop(w);
+    let w = Words::read(&path)?;
+
+    assert_eq!(literal_prefix("dir/*.txt"), "dir/");
+    assert_eq!(literal_prefix("di?/a.txt"), "di");
+    assert_eq!(literal_prefix("*.txt"), "");
+    assert_eq!(literal_prefix("dir/a.txt"), "dir/a.txt");
+
+    for pattern in [
+        "dir/*",
+        "dir*",
+        "dir/*.txt",
+        "dir/a.txt",
+        "di?/a.txt",
+        "dir/sub/*",
+        "nothing*",
+    ] {
+        let fast = w.find_file_prefix(pattern).expect("prefix");
+        let slow = match_file_names(w.file_name_list(), pattern);
+        assert_eq!(fast, slow, "{}", pattern);
+        assert_eq!(w.find_file(pattern), slow, "{}", pattern);
+    }
+    assert_eq!(
+        w.find_file("dir/*.txt"),
+        vec!["dir/b.txt", "dir/a.txt", "dir/a.txt"]
+    );
+
+    // no literal prefix.
+    assert!(w.find_file_prefix("*a.txt").is_none());
+    assert_eq!(
+        w.find_file("*a.txt"),
+        match_file_names(w.file_name_list(), "*a.txt")
+    );
+    assert_eq!(w.find_file("*a.txt").len(), 4);
+
+    assert!(w.have_file(&"dir/sub/c.html".into()));
+    assert!(!w.have_file(&"dir/sub".into()));
+
+    Ok(())
+}
+
+#[test]
+fn test_broad_terms() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/find_broad_terms.idx")?;
+
+    let mut w = Words::create(&path)?;
+    for f in 0..30 {
+        let fid = w.add_file(format!("file{}", f));
+        // s0 is in the most files, then s1 ...
+        for i in 0..30 - f {
+            w.add_word(format!("s{}", i), 1, fid)?;
+        }
+        w.add_word("other", 1000, fid)?;
+    }
+    w.write()?;
+    // a stored word gets another file.
+    let fid = w.add_file("file30".into());
+    w.add_word("s29", 1, fid)?;
+    w.write()?;
+    drop(w);
+
+    // the ranking is stored with the words.
+    let mut w = Words::read(&path)?;
+
+    assert!(broad_terms(&mut w, &["s*".into()], 30)?.is_empty());
+    assert!(broad_terms(&mut w, &["s*".into()], 0)?.is_empty());
+
+    let broad = broad_terms(&mut w, &["s*".into(), "o*".into()], 20)?;
+    assert_eq!(broad.len(), 1);
+    assert_eq!(broad[0].term, "s*");
+    assert_eq!(broad[0].words, 30);
+    assert_eq!(broad[0].top.len(), MATCH_TOP);
+    assert_eq!(broad[0].top[0], ("s0".to_string(), 30));
+    assert_eq!(broad[0].top[1], ("s1".to_string(), 29));
+    assert_eq!(w.word("s29")?.expect("s29").files, 2);
+
+    // the same words find uses.
+    assert_eq!(w.match_words("s1*")?.len(), 11);
+
+    Ok(())
+}
+
+#[test]
+fn test_found_missing() -> Result<(), AppError> {
+    let root = PathBuf::from_str("tmp/found_missing")?;
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root)?;
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        std::fs::write(root.join(name), "alpha beta\ngamma\n")?;
+    }
+
+    let found = FoundSnapshot::new(
+        vec!["alpha".into()],
+        vec!["a.txt".into(), "b.txt".into(), "c.txt".into()],
+        DEFAULT_LINE_WIDTH,
+    );
+    std::fs::remove_file(root.join("b.txt"))?;
+
+    // nothing looked at yet.
+    assert_eq!(found.missing_count()?, (0, 0));
+
+    let page = found.page(&root, 0, 2)?;
+    assert_eq!(page.len(), 2);
+    assert_eq!(page[0].0, "a.txt");
+    assert_eq!(page[0].1.len(), 1);
+    assert_eq!(page[1].0, "b.txt");
+    assert!(page[1].1.is_empty());
+    assert!(!found.is_missing(0)?);
+    assert!(found.is_missing(1)?);
+    assert_eq!(found.missing_count()?, (1, 2));
+
+    // each file is looked at once.
+    std::fs::write(root.join("b.txt"), "alpha\n")?;
+    let page = found.page(&root, 0, 10)?;
+    assert_eq!(page.len(), 3);
+    assert!(page[1].1.is_empty());
+    assert_eq!(page[2].1.len(), 1);
+    assert!(found.is_missing(1)?);
+    assert!(!found.is_missing(2)?);
+    assert_eq!(found.missing_count()?, (1, 3));
+
+    Ok(())
+}
+
+#[test]
+fn test_found_page_shared() -> Result<(), AppError> {
+    let root = PathBuf::from_str("tmp/found_page_shared")?;
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root)?;
+    let mut files = Vec::new();
+    for n in 0..40 {
+        let name = format!("f{}.txt", n);
+        std::fs::write(root.join(&name), format!("alpha {}\n", n))?;
+        files.push(name);
+    }
+
+    let found = Arc::new(FoundSnapshot::new(
+        vec!["alpha".into()],
+        files.clone(),
+        DEFAULT_LINE_WIDTH,
+    ));
+
+    // overlapping pages extract at the same time, each file once.
+    let threads: Vec<_> = (0..4)
+        .map(|n| {
+            let found = Arc::clone(&found);
+            let root = root.clone();
+            thread::spawn(move || found.page(&root, n * 5, 20).expect("page").len())
+        })
+        .collect();
+    for t in threads {
+        assert_eq!(t.join().expect("page"), 20);
+    }
+
+    let page = found.page(&root, 0, 100)?;
+    assert_eq!(page.len(), 40);
+    for (n, (file, lines)) in page.iter().enumerate() {
+        assert_eq!(file, &files[n]);
+        assert_eq!(lines.len(), 1);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use std::fs::{self, File};
+use textindex::error::AppError;
+use textindex::index2::tmp_index::TmpWords;
+use textindex::proc3::indexer::index_html2;
+
+#[test]
+fn test_html_diagnostics() -> Result<(), AppError> {
+    // a NUL is not accepted by the html parser.
+    let text = "<p>abc\0def</p>";
+
+    let mut log = File::create("tmp/html_diagnostics.log")?;
+    let mut words = TmpWords::new("bad.html");
+    index_html2(&mut log, "bad.html", &mut words, text)?;
+    drop(log);
+
+    // everything before the error is indexed.
+    assert!(words.words.contains_key("abc"));
+    assert!(!words.words.contains_key("def"));
+
+    let log = fs::read_to_string("tmp/html_diagnostics.log")?;
+    assert!(log.contains("bad.html at 6"));
+    assert!(log.contains("Html"));
+
+    Ok(())
+}
+
+#[test]
+fn test_html_untracked() -> Result<(), AppError> {
+    let text = "<html><body><p>Fish &amp; chips</p><!-- no --></body></html>";
+
+    let mut log = File::create("tmp/html_untracked.log")?;
+    let mut words = TmpWords::new("good.html");
+    index_html2(&mut log, "good.html", &mut words, text)?;
+    drop(log);
+
+    assert!(words.words.contains_key("fish"));
+    assert!(words.words.contains_key("chips"));
+    assert!(fs::read_to_string("tmp/html_untracked.log")?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_html_attributes() -> Result<(), AppError> {
+    let text = r#"<p>Lorem <img src="x.png" alt="Harbour at night"><a href="a.html" TITLE="Fish &amp; chips">ipsum</a></p>"#;
+
+    // off by default.
+    let mut log = File::create("tmp/html_attributes.log")?;
+    let mut words = TmpWords::new("attr.html");
+    index_html2(&mut log, "attr.html", &mut words, text)?;
+    assert!(words.words.contains_key("lorem"));
+    assert!(!words.words.contains_key("harbour"));
+    assert!(!words.words.contains_key("chips"));
+
+    let mut words = TmpWords::new("attr.html");
+    words.attributes = true;
+    index_html2(&mut log, "attr.html", &mut words, text)?;
+    assert!(words.words.contains_key("harbour"));
+    assert!(words.words.contains_key("night"));
+    assert!(words.words.contains_key("fish"));
+    assert!(words.words.contains_key("chips"));
+    assert!(!words.words.contains_key("amp"));
+    // not glued to the text.
+    assert!(words.words.contains_key("lorem"));
+    assert!(words.words.contains_key("ipsum"));
+
+    Ok(())
+}
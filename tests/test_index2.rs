@@ -1,12 +1,14 @@
-use blockfile2::Block;
+use blockfile2::{Block, BlockType, UserBlockType};
 use std::mem::{align_of, size_of};
 use std::path::PathBuf;
 use std::str::FromStr;
 use textindex::error::AppError;
+use textindex::index2::file_refs::RawFileRef;
+use textindex::index2::header::{RawHeader, INDEX_VERSION};
 use textindex::index2::ids::FileId;
 use textindex::index2::word_map::{RawBags, RawWordMap};
-use textindex::index2::words::RawWord;
-use textindex::index2::Words;
+use textindex::index2::words::{RawWord, RawWordV1};
+use textindex::index2::{IndexKind, WordBlockType, Words};
 
 #[test]
 fn test_sizes() {
@@ -32,6 +34,8 @@ fn test_sizes() {
 
     assert_eq!(0, BLOCK_SIZE % size_of::<RawWordMap>());
     assert_eq!(0, BLOCK_SIZE % size_of::<RawWord>());
+    assert_eq!(0, BLOCK_SIZE % size_of::<RawWordV1>());
+    assert_eq!(0, BLOCK_SIZE % size_of::<RawFileRef>());
 }
 
 #[test]
@@ -46,6 +50,34 @@ fn test_numeric() {
     panic!();
 }
 
+#[test]
+fn test_block_types() {
+    let mut seen = Vec::new();
+    for ty in WordBlockType::ALL {
+        let block_type = ty.block_type();
+        assert_eq!(WordBlockType::user_type(block_type), Some(ty), "{:?}", ty);
+        assert_eq!(
+            WordBlockType::try_from(block_type as u32),
+            Ok(ty),
+            "{:?}",
+            ty
+        );
+        assert_eq!(ty as isize, block_type as isize, "{:?}", ty);
+        assert!(!seen.contains(&(block_type as u32)), "{:?}", ty);
+        seen.push(block_type as u32);
+    }
+    assert!(WordBlockType::ReverseMap.is_reserved());
+    assert!(WordBlockType::LongWords.is_reserved());
+    assert!(!WordBlockType::WordList2.is_reserved());
+
+    // the types of the blockfile itself.
+    assert_eq!(WordBlockType::user_type(BlockType::Physical), None);
+    assert_eq!(WordBlockType::user_type(BlockType::Types), None);
+    let physical = BlockType::Physical as u32;
+    assert_eq!(WordBlockType::try_from(physical), Err(physical));
+    assert_eq!(WordBlockType::try_from(u32::MAX), Err(u32::MAX));
+}
+
 #[test]
 fn test_init() -> Result<(), AppError> {
     let path = PathBuf::from_str("tmp/init.idx")?;
@@ -69,7 +101,7 @@ fn test_files() -> Result<(), AppError> {
     w.write()?;
     let w = Words::read(&path)?;
 
-    assert!(w.files().contains_key(&FileId(1)));
+    assert!(w.files().contains(FileId(1)));
 
     Ok(())
 }
@@ -92,30 +124,35 @@ fn test_files2() -> Result<(), AppError> {
 
     // println!("{:#?}", w);
 
-    let mut it = w.files().iter();
+    let mut it = w.files().list().iter();
     let f0 = it.next().unwrap();
     assert_eq!(*f0.0, 1);
-    assert_eq!(f0.1.name, "file0");
-    assert_eq!(f0.1.block_nr, 4);
+    assert_eq!(f0.1.leaf, "file0");
+    assert_eq!(f0.1.dir, 0);
+    // the directories come first.
+    assert_eq!(f0.1.block_nr, 5);
     assert_eq!(f0.1.block_idx, 0);
 
     let f1 = it.next().unwrap();
     assert_eq!(*f1.0, 2);
-    assert_eq!(f1.1.name, "file1");
-    assert_eq!(f1.1.block_nr, 4);
-    assert_eq!(f1.1.block_idx, 11);
+    assert_eq!(f1.1.leaf, "file1");
+    assert_eq!(f1.1.dir, 0);
+    assert_eq!(f1.1.block_nr, 5);
+    assert_eq!(f1.1.block_idx, 15);
 
     let f2 = it.next().unwrap();
     assert_eq!(*f2.0, 3);
-    assert_eq!(f2.1.name, "file2");
-    assert_eq!(f2.1.block_nr, 4);
-    assert_eq!(f2.1.block_idx, 22);
+    assert_eq!(f2.1.leaf, "file2");
+    assert_eq!(f2.1.dir, 0);
+    assert_eq!(f2.1.block_nr, 5);
+    assert_eq!(f2.1.block_idx, 30);
 
     let f3 = it.next().unwrap();
     assert_eq!(*f3.0, 4);
-    assert_eq!(f3.1.name, "file3");
-    assert_eq!(f3.1.block_nr, 4);
-    assert_eq!(f3.1.block_idx, 33);
+    assert_eq!(f3.1.leaf, "file3");
+    assert_eq!(f3.1.dir, 0);
+    assert_eq!(f3.1.block_nr, 5);
+    assert_eq!(f3.1.block_idx, 45);
 
     Ok(())
 }
@@ -131,8 +168,8 @@ fn test_word() -> Result<(), AppError> {
 
     let mut w = Words::read(&path)?;
 
-    assert!(w.words().get("alpha").is_some());
-    if let Some(word) = w.words().get("alpha").cloned() {
+    assert!(w.word("alpha")?.is_some());
+    if let Some(word) = w.word("alpha")? {
         assert_eq!(word.file_map_block_nr, 4);
         assert_eq!(word.file_map_idx, 0);
         assert_eq!(word.id, 1);
@@ -157,13 +194,13 @@ fn test_word2() -> Result<(), AppError> {
     w.add_word("epsilon", 0, fid)?;
     w.write()?;
 
-    let w = Words::read(&path)?;
+    let mut w = Words::read(&path)?;
 
-    assert!(w.words().get("alpha").is_some());
-    assert!(w.words().get("beta").is_some());
-    assert!(w.words().get("gamma").is_some());
-    assert!(w.words().get("delta").is_some());
-    assert!(w.words().get("epsilon").is_some());
+    assert!(w.word("alpha")?.is_some());
+    assert!(w.word("beta")?.is_some());
+    assert!(w.word("gamma")?.is_some());
+    assert!(w.word("delta")?.is_some());
+    assert!(w.word("epsilon")?.is_some());
 
     Ok(())
 }
@@ -188,13 +225,13 @@ fn test_word3() -> Result<(), AppError> {
     let mut w = Words::read(&path)?;
     // println!("{:#1?}", w);
 
-    assert!(w.words().get("alpha").is_some());
-    assert!(w.words().get("beta").is_some());
-    assert!(w.words().get("gamma").is_some());
-    assert!(w.words().get("delta").is_some());
-    assert!(w.words().get("epsilon").is_some());
+    assert!(w.word("alpha")?.is_some());
+    assert!(w.word("beta")?.is_some());
+    assert!(w.word("gamma")?.is_some());
+    assert!(w.word("delta")?.is_some());
+    assert!(w.word("epsilon")?.is_some());
 
-    let wdata = w.words().get("alpha").cloned().unwrap();
+    let wdata = w.word("alpha")?.unwrap();
     assert_eq!(wdata.file_map_block_nr, 4);
     assert_eq!(wdata.file_map_idx, 0);
     {
@@ -204,7 +241,7 @@ fn test_word3() -> Result<(), AppError> {
         assert!(it.next().is_none());
     }
 
-    let wdata = w.words().get("beta").cloned().unwrap();
+    let wdata = w.word("beta")?.unwrap();
     assert_eq!(wdata.file_map_block_nr, 4);
     assert_eq!(wdata.file_map_idx, 1);
     let mut it = w.iter_word_files(wdata);
@@ -227,20 +264,20 @@ fn test_word4() -> Result<(), AppError> {
     w.add_word("delta", 0, fid)?;
     w.add_word("epsilon", 0, fid)?;
 
-    let _wdata = w.words().get("gamma").cloned().unwrap();
+    let _wdata = w.word("gamma")?.unwrap();
 
     let fid = w.add_file("file1".into());
     w.add_word("alpha", 0, fid)?;
     w.add_word("beta", 0, fid)?;
     w.add_word("gamma", 0, fid)?;
 
-    let _wdata = w.words().get("gamma").cloned().unwrap();
+    let _wdata = w.word("gamma")?.unwrap();
 
     for i in 0..14 {
         let fid = w.add_file(format!("file-x{}", i));
         w.add_word("gamma", 0, fid)?;
 
-        let _wdata = w.words().get("gamma").cloned().unwrap();
+        let _wdata = w.word("gamma")?.unwrap();
     }
     // println!("{:#2?}", w);
     w.write()?;
@@ -248,7 +285,7 @@ fn test_word4() -> Result<(), AppError> {
     let mut w = Words::read(&path)?;
     // println!("{:#2?}", w);
 
-    let wdata = w.words().get("gamma").cloned().unwrap();
+    let wdata = w.word("gamma")?.unwrap();
 
     let fid = w
         .iter_word_files(wdata)
@@ -275,7 +312,8 @@ fn test_word_utf8() -> Result<(), AppError> {
 
     let mut w = Words::read(&path)?;
 
-    let mut it = w.iter_words();
+    let words = w.prefix_words("")?;
+    let mut it = words.iter();
     let word = it.next().expect("word");
     assert_eq!(word.0, "abcdefghijklmnopqrs");
     let word = it.next().expect("word");
@@ -283,3 +321,78 @@ fn test_word_utf8() -> Result<(), AppError> {
 
     Ok(())
 }
+
+#[test]
+fn test_first_seen() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/first_seen.idx")?;
+
+    let mut w = Words::create(&path)?;
+    let fid0 = w.add_file("file0".into());
+    w.add_word("alpha", 1, fid0)?;
+    let fid1 = w.add_file("file1".into());
+    w.add_word("alpha", 1, fid1)?;
+    w.add_word("beta", 1, fid1)?;
+    // more than one block of words.
+    for i in 0..200 {
+        w.add_word(format!("w{}", i), 1, fid1)?;
+    }
+    w.write()?;
+
+    let mut w = Words::read(&path)?;
+    let fid2 = w.add_file("file2".into());
+    w.add_word("gamma", 1, fid2)?;
+    w.add_word("alpha", 1, fid2)?;
+    w.write()?;
+
+    let mut w = Words::read(&path)?;
+    assert_eq!(w.word_len(), 203);
+    assert_eq!(w.word("alpha")?.unwrap().first_file_id, fid0);
+    assert_eq!(w.word("beta")?.unwrap().first_file_id, fid1);
+    assert_eq!(w.word("w199")?.unwrap().first_file_id, fid1);
+    let gamma = w.word("gamma")?.unwrap();
+    assert_eq!(gamma.first_file_id, fid2);
+    assert_eq!(w.file(gamma.first_file_id), Some("file2".to_string()));
+
+    Ok(())
+}
+
+#[test]
+fn test_block_type_checked() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/block_type.idx")?;
+
+    let mut w = Words::create(&path)?;
+    let fid = w.add_file("file0".into());
+    w.add_word("alpha", 1, fid)?;
+    w.write()?;
+
+    let header_nr =
+        w.db.iter_metadata_filter(|_nr, ty| ty == WordBlockType::Header)
+            .map(|(nr, _)| nr)
+            .next()
+            .expect("header");
+    let map_nr =
+        w.db.iter_metadata_filter(|_nr, ty| ty == WordBlockType::WordMapHead)
+            .map(|(nr, _)| nr)
+            .next()
+            .expect("wordmap");
+
+    assert_eq!(
+        w.db.get_as::<RawHeader>(header_nr)?[0].version,
+        INDEX_VERSION
+    );
+    match w.db.get_as::<RawWordMap>(header_nr) {
+        Err(e) => assert!(matches!(e.kind, IndexKind::WrongBlockType(..))),
+        Ok(_) => panic!("header as wordmap"),
+    }
+
+    assert!(w.db.get_as::<RawWordMap>(map_nr).is_ok());
+    assert!(w.db.get_as::<RawBags>(map_nr).is_err());
+    assert!(!w.db.get(map_nr)?.is_dirty());
+    assert!(w.db.get_as_mut::<RawWord>(map_nr).is_err());
+    // a failed type check doesn't mark it for the next write.
+    assert!(!w.db.get(map_nr)?.is_dirty());
+    assert!(w.db.get_as_mut::<RawWordMap>(map_nr).is_ok());
+    assert!(w.db.get(map_nr)?.is_dirty());
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use textindex::error::AppError;
+use textindex::index2::Words;
+use textindex::proc3::logger::Logger;
+use textindex::proc3::{index_file, Data, FileFilter};
+
+fn data(name: &str) -> Result<(&'static Data, PathBuf), AppError> {
+    let root = PathBuf::from(format!("tmp/{}", name));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("dir"))?;
+
+    let words = Words::create(Path::new(&format!("tmp/{}.idx", name)))?;
+    let data: &'static Data =
+        Box::leak(Box::new(Data::new(root.clone(), words, Logger::new(None))));
+    Ok((data, root))
+}
+
+#[test]
+fn test_index_file() -> Result<(), AppError> {
+    let (data, root) = data("index_file")?;
+    fs::write(root.join("dir/new.txt"), "alpha beta gamma")?;
+
+    let report = index_file(data, "dir/new.txt", None)?;
+    assert_eq!(report.filter, FileFilter::Text);
+    assert_eq!(report.words, 3);
+    assert!(!report.listed);
+    assert!(!report.empty);
+    assert_eq!(
+        data.words.lock()?.find(&["beta".into()])?,
+        vec!["dir/new.txt"]
+    );
+
+    // again, with changed content. without removes it's refused.
+    fs::write(root.join("dir/new.txt"), "alpha delta")?;
+    let report = index_file(data, "dir/new.txt", None)?;
+    assert!(report.listed);
+    assert_eq!(report.words, 0);
+    {
+        let mut w = data.words.lock()?;
+        assert_eq!(w.files().len(), 1);
+        assert!(w.find(&["delta".into()])?.is_empty());
+        assert_eq!(w.find(&["beta".into()])?, vec!["dir/new.txt"]);
+    }
+
+    // content without a file.
+    let report = index_file(
+        data,
+        "piped.html",
+        Some(b"<html><p>epsilon</p></html>".to_vec()),
+    )?;
+    assert_eq!(report.filter, FileFilter::Html);
+    assert!(!report.listed);
+    assert_eq!(
+        data.words.lock()?.find(&["epsilon".into()])?,
+        vec!["piped.html"]
+    );
+
+    // it was written.
+    let mut w = Words::read(Path::new("tmp/index_file.idx"))?;
+    assert_eq!(w.find(&["epsilon".into()])?, vec!["piped.html"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_index_file_filtered() -> Result<(), AppError> {
+    let (data, root) = data("index_file_filtered")?;
+    fs::write(root.join("dir/pic.jpg"), "alpha beta gamma")?;
+    fs::write(root.join("dir/empty.txt"), "")?;
+
+    let report = index_file(data, "dir/pic.jpg", None)?;
+    assert_eq!(report.filter, FileFilter::Ignore);
+    assert_eq!(report.words, 0);
+
+    let report = index_file(data, "dir/empty.txt", None)?;
+    assert!(report.empty);
+
+    // binary content.
+    let report = index_file(data, "piped", Some(vec![0u8, 1, 2, 3, 0, 0, 0]))?;
+    assert_eq!(report.filter, FileFilter::Ignore);
+
+    assert!(data.words.lock()?.files().is_empty());
+    assert!(index_file(data, "dir/missing.txt", None).is_err());
+
+    Ok(())
+}
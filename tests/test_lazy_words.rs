@@ -0,0 +1,143 @@
+use blockfile2::Block;
+use std::path::PathBuf;
+use std::str::FromStr;
+use textindex::error::AppError;
+use textindex::index2::words::{RawWord, LAZY_BLOCKS};
+use textindex::index2::Words;
+
+const BLOCK_SIZE: usize = 4096;
+
+/// Two writes, the ranges of the blocks overlap.
+fn fill(path: &PathBuf, n: usize) -> Result<(), AppError> {
+    let mut w = Words::create(path)?;
+    let fid = w.add_file("file0".into());
+    for i in (0..n).step_by(2) {
+        w.add_word(format!("w{:05}", i), 1, fid)?;
+    }
+    w.write()?;
+    let fid = w.add_file("file1".into());
+    for i in (1..n).step_by(2) {
+        w.add_word(format!("w{:05}", i), 1, fid)?;
+    }
+    w.add_word("w00000", 1, fid)?;
+    w.add_word("alpha", 1, fid)?;
+    w.write()?;
+    Ok(())
+}
+
+#[test]
+fn test_lazy_words() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/lazy_words.idx")?;
+    fill(&path, 300)?;
+
+    let mut eager = Words::read(&path)?;
+    assert!(!eager.lazy_words());
+
+    let mut w = Words::read_with(&path, 0)?;
+    assert!(w.lazy_words());
+    assert_eq!(w.word_len(), 301);
+    assert_eq!(w.word_len(), eager.word_len());
+
+    let word = w.word("w00123")?.expect("word");
+    let eager_word = eager.word("w00123")?.expect("word");
+    assert_eq!(word.id, eager_word.id);
+    assert_eq!(word.file_map_block_nr, eager_word.file_map_block_nr);
+    assert_eq!(word.file_map_idx, eager_word.file_map_idx);
+    assert!(w.word("w00300")?.is_none());
+    assert!(w.word("beta")?.is_none());
+
+    let lazy_prefix: Vec<_> = w.prefix_words("w001")?.into_iter().map(|v| v.0).collect();
+    let eager_prefix: Vec<_> = eager
+        .prefix_words("w001")?
+        .into_iter()
+        .map(|v| v.0)
+        .collect();
+    assert_eq!(lazy_prefix.len(), 100);
+    assert_eq!(lazy_prefix, eager_prefix);
+    assert_eq!(w.prefix_words("")?.len(), 301);
+
+    assert_eq!(w.find(&["w00000".into()])?, vec!["file0", "file1"]);
+    assert_eq!(w.find(&["w0000*".into(), "alpha".into()])?, vec!["file1"]);
+
+    let report = w.check(None);
+    assert!(report.is_ok(), "{:?}", report.problems);
+    assert_eq!(report.words, 301);
+
+    // new words in lazy mode.
+    let fid = w.add_file("file2".into());
+    w.add_word("beta", 1, fid)?;
+    w.add_word("w00123", 1, fid)?;
+    assert_eq!(w.word_len(), 302);
+    assert_eq!(w.find(&["beta".into()])?, vec!["file2"]);
+    w.write()?;
+    assert!(w.word("beta")?.is_some());
+    drop(w);
+
+    let mut w = Words::read_with(&path, 0)?;
+    assert_eq!(w.word_len(), 302);
+    assert_eq!(w.find(&["w00123".into()])?, vec!["file1", "file2"]);
+    drop(w);
+
+    // switches to lazy while loading.
+    let w = Words::read_with(&path, 100)?;
+    assert!(w.lazy_words());
+    assert_eq!(w.word_len(), 302);
+
+    Ok(())
+}
+
+#[test]
+fn test_lazy_words_unload() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/lazy_words_unload.idx")?;
+    let per_block = Block::len_array::<RawWord>(BLOCK_SIZE);
+    let n = (LAZY_BLOCKS + 8) * per_block;
+    fill(&path, n)?;
+
+    let mut w = Words::read_with(&path, 0)?;
+    // every block once, the first ones are unloaded again.
+    for i in (0..n).step_by(per_block / 2) {
+        assert!(w.word(&format!("w{:05}", i))?.is_some(), "{}", i);
+    }
+    for i in (0..n).step_by(per_block / 2 + 1) {
+        assert!(w.word(&format!("w{:05}", i))?.is_some(), "{}", i);
+    }
+    assert_eq!(w.word_len(), n + 1);
+
+    // added after some blocks were unloaded.
+    let fid = w.add_file("file2".into());
+    w.add_word("w00001", 1, fid)?;
+    w.add_word("zulu", 1, fid)?;
+    w.write()?;
+    assert_eq!(w.word_len(), n + 2);
+    assert_eq!(w.find(&["w00001".into()])?, vec!["file1", "file2"]);
+    assert_eq!(w.find(&["zulu".into()])?, vec!["file2"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_lazy_words_miss() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/lazy_words_miss.idx")?;
+    let per_block = Block::len_array::<RawWord>(BLOCK_SIZE);
+    let n = (LAZY_BLOCKS + 8) * per_block;
+    fill(&path, n)?;
+
+    let mut w = Words::read_with(&path, 0)?;
+    // unknown words inside and outside the range of the blocks.
+    for i in 0..200 {
+        for word in [format!("w{:05}x", i), format!("x{:05}", i)] {
+            w.db.start_counting();
+            assert!(w.word(&word)?.is_none());
+            let counters = w.db.stop_counting();
+            assert!(counters.misses <= 1, "{} {:?}", word, counters);
+        }
+    }
+
+    // a known word is one block.
+    w.db.start_counting();
+    assert!(w.word("w01234")?.is_some());
+    let counters = w.db.stop_counting();
+    assert!(counters.misses <= 1, "{:?}", counters);
+
+    Ok(())
+}
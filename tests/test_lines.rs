@@ -0,0 +1,108 @@
+use std::fs;
+use std::path::Path;
+use textindex::error::AppError;
+use textindex::proc3::indexer::extract_html;
+use textindex::proc3::{find_matched_lines, read_line, truncate_line, DEFAULT_LINE_WIDTH};
+
+#[test]
+fn test_long_line() -> Result<(), AppError> {
+    let root = Path::new("tmp/lines");
+    fs::create_dir_all(root)?;
+
+    // 1 MB in one line, the match in the middle.
+    let filler = "function(a,b){return a+b};".repeat(20_000);
+    let line = format!("{} needle {}", filler, filler);
+    let text = format!("first line\n{}\nlast line\n", line);
+    fs::write(root.join("minified.js"), &text)?;
+
+    let files = vec!["minified.js".to_string()];
+    let lines = find_matched_lines(root, &["needle".into()], &files, DEFAULT_LINE_WIDTH, false)?;
+    let found = &lines[0].1;
+    assert_eq!(found.len(), 1);
+
+    let m = &found[0];
+    assert_eq!(m.nr, 1);
+    assert_eq!(m.len, line.len());
+    assert!(m.is_truncated());
+    assert!(m.text.contains("needle"));
+    assert!(m.text.starts_with('…'));
+    assert!(m.text.ends_with('…'));
+    assert!(m.text.len() <= DEFAULT_LINE_WIDTH + 2 * '…'.len_utf8());
+
+    // the full line on demand.
+    assert_eq!(read_line(root, "minified.js", m.nr)?, Some(line));
+
+    Ok(())
+}
+
+const HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>Harbour</title></head>
+<body class="needle">
+<p>The old
+<b>lighthouse</b> &amp; the
+pier</p><img
+  src="x.png" alt="seagull
+over the pier">
+<!-- lighthouse -->
+<p>Ferry &#x2192; island</p>
+</body>
+</html>
+"#;
+
+#[test]
+fn test_html_lines() -> Result<(), AppError> {
+    let root = Path::new("tmp/html_lines");
+    fs::create_dir_all(root)?;
+    fs::write(root.join("page.html"), HTML)?;
+
+    let html = extract_html(HTML, true, |_| Ok::<_, AppError>(false))?;
+    let pos = html.text.find("pier").expect("pier");
+    assert_eq!(html.lines.line(&html.text, pos), 6);
+    let pos = html.text.find("island").expect("island");
+    assert_eq!(html.lines.line(&html.text, pos), 10);
+
+    let files = vec!["page.html".to_string()];
+    let terms = ["lighthouse".into(), "seagull".into(), "ferry".into()];
+    let lines = find_matched_lines(root, &terms, &files, DEFAULT_LINE_WIDTH, true)?;
+    let nr: Vec<_> = lines[0].1.iter().map(|v| v.nr).collect();
+    assert_eq!(nr, vec![5, 7, 10]);
+
+    // the same text as indexed, without the attributes.
+    let lines = find_matched_lines(root, &terms, &files, DEFAULT_LINE_WIDTH, false)?;
+    let nr: Vec<_> = lines[0].1.iter().map(|v| v.nr).collect();
+    assert_eq!(nr, vec![5, 10]);
+    let lines = find_matched_lines(root, &terms, &files, DEFAULT_LINE_WIDTH, true)?;
+    assert_eq!(lines[0].1[0], "lighthouse & the");
+
+    // view shows the html.
+    assert_eq!(
+        read_line(root, "page.html", 7)?.as_deref(),
+        Some("  src=\"x.png\" alt=\"seagull")
+    );
+
+    // markup doesn't match.
+    let lines = find_matched_lines(root, &["needle".into()], &files, DEFAULT_LINE_WIDTH, false)?;
+    assert!(lines[0].1.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_truncate_utf8() {
+    let line = "äöü".repeat(100);
+
+    // every cut position must be a char boundary.
+    for pos in 0..line.len() {
+        for width in 1..20 {
+            let t = truncate_line(&line, pos, width);
+            assert!(t.len() <= width + 2 * '…'.len_utf8());
+        }
+    }
+
+    // short lines stay as they are.
+    assert_eq!(truncate_line("short", 2, 10), "short");
+    assert_eq!(truncate_line("0123456789", 0, 4), "0123…");
+    assert_eq!(truncate_line("0123456789", 9, 4), "…6789");
+    assert_eq!(truncate_line("0123456789", 5, 4), "…3456…");
+}
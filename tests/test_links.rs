@@ -0,0 +1,77 @@
+use std::fs::File;
+use std::path::PathBuf;
+use std::str::FromStr;
+use textindex::error::AppError;
+use textindex::index2::Words;
+use textindex::proc3::indexer::link_words;
+use textindex::proc3::{indexing, FileFilter, Settings};
+
+const LINKS_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>Links</title></head>
+<body>
+<p>See the <a href="https://www.example.com/docs/guide.html?page=2#top">guide</a>
+and <A HREF='http://mirror.org/archive/'>archive</A>.</p>
+<a name="anchor">no link</a>
+<a class=x href=../local/notes.html>notes</a>
+<a href="mailto:someone@example.com">mail</a>
+<img href="https://images.net/pic.png">
+</body>
+</html>
+"#;
+
+#[test]
+fn test_link_words() {
+    assert_eq!(
+        link_words("https://www.example.com/docs/guide.html?page=2#top"),
+        vec!["example.com", "www.example.com", "docs", "guide.html"]
+    );
+    assert_eq!(
+        link_words("HTTP://user@Mirror.org:8080/Archive/"),
+        vec!["mirror.org", "archive"]
+    );
+    assert_eq!(link_words("//cdn.net"), vec!["cdn.net"]);
+    assert_eq!(
+        link_words("../local/./notes.html"),
+        vec!["local", "notes.html"]
+    );
+    assert!(link_words("mailto:someone@example.com").is_empty());
+    assert!(link_words("javascript:void(0)").is_empty());
+    assert!(link_words("#top").is_empty());
+}
+
+#[test]
+fn test_links() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/links.idx")?;
+    let mut log = File::create("tmp/links.log")?;
+
+    let (filter, tmp_words) = indexing(
+        &mut log,
+        &Settings::default(),
+        FileFilter::Html,
+        "links.html",
+        &LINKS_HTML.as_bytes().to_vec(),
+    )?;
+    assert_eq!(filter, FileFilter::Html);
+    assert!(tmp_words.words.contains_key("link:example.com"));
+    assert!(tmp_words.words.contains_key("link:mirror.org"));
+    assert!(tmp_words.words.contains_key("link:notes.html"));
+    // not from an <a> or not a web-link.
+    assert!(!tmp_words.words.contains_key("link:images.net"));
+    assert!(!tmp_words.words.keys().any(|v| v.contains("someone")));
+
+    let mut w = Words::create(&path)?;
+    w.append(tmp_words)?;
+
+    assert_eq!(w.find(&["link:example.com".into()])?, vec!["links.html"]);
+    assert_eq!(w.find(&["link:*mirror*".into()])?, vec!["links.html"]);
+    assert!(w.find(&["link:nowhere.net".into()])?.is_empty());
+
+    // link-words don't show up in ordinary finds.
+    assert_eq!(w.find(&["archive".into()])?, vec!["links.html"]);
+    assert!(w.find(&["docs".into()])?.is_empty());
+    assert!(w.find(&["*example*".into()])?.is_empty());
+    assert!(w.find(&["guide".into(), "docs".into()])?.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,162 @@
+use rustyline::ExternalPrinter;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use textindex::error::AppError;
+use textindex::index2::Words;
+use textindex::proc3::logger::{Logger, LOG_RING_LEN, LOG_SIMILAR, LOG_SIMILAR_EVERY};
+use textindex::proc3::threads::{init_work, Msg};
+use textindex::proc3::{shut_down, Data};
+
+/// Every write fails like with a full disk.
+struct DiskFull;
+
+impl Write for DiskFull {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::from(io::ErrorKind::StorageFull))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::StorageFull))
+    }
+}
+
+struct NoPrinter;
+
+impl ExternalPrinter for NoPrinter {
+    fn print(&mut self, _msg: String) -> rustyline::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_log_ring() {
+    let log = Logger::new(Some(Box::new(DiskFull)));
+    for i in 0..LOG_RING_LEN + 5 {
+        log.log(format!("msg{}", i));
+    }
+    {
+        let mut w = log.writer();
+        let _ = writeln!(w, "from {}", "writer");
+    }
+
+    let recent = log.recent();
+    assert_eq!(recent.len(), LOG_RING_LEN);
+    assert_eq!(recent[0], "msg6");
+    assert_eq!(recent[LOG_RING_LEN - 1], "from writer");
+    assert_eq!(log.failures(), LOG_RING_LEN + 6);
+    assert!(log.last_failure().is_some());
+
+    // no file at all.
+    let log = Logger::new(None);
+    log.log("only memory");
+    assert_eq!(log.recent(), vec!["only memory"]);
+    assert_eq!(log.failures(), 0);
+}
+
+#[test]
+fn test_log_similar() {
+    let log = Logger::new(None);
+    let n = LOG_SIMILAR + LOG_SIMILAR_EVERY + 10;
+    for i in 0..n {
+        log.log(format!("maybe binary file img/pic{}.jpg", i));
+    }
+    log.log("maybe binary file doc/a.bin");
+    log.log("maybe binary file top.bin");
+    // not about a file.
+    log.log("msg");
+    log.log("msg");
+    log.log("msg");
+    log.log("msg");
+    // the details of a suppressed message go with it.
+    for i in 0..LOG_SIMILAR + 1 {
+        let mut w = log.writer();
+        let _ = writeln!(w, "site/page{}.html at {}", i, i * 10);
+        let _ = writeln!(w, "details {}", i);
+    }
+
+    let recent = log.recent();
+    let pics: Vec<_> = recent.iter().filter(|v| v.contains("img/pic")).collect();
+    assert_eq!(
+        pics,
+        vec![
+            "maybe binary file img/pic0.jpg",
+            "maybe binary file img/pic1.jpg",
+            "maybe binary file img/pic2.jpg"
+        ]
+    );
+    assert!(recent.contains(&format!("... and {} similar in img", LOG_SIMILAR_EVERY)));
+    assert!(recent.contains(&"maybe binary file doc/a.bin".to_string()));
+    assert!(recent.contains(&"maybe binary file top.bin".to_string()));
+    assert_eq!(recent.iter().filter(|v| *v == "msg").count(), 4);
+    assert_eq!(
+        recent.iter().filter(|v| v.starts_with("details")).count(),
+        LOG_SIMILAR
+    );
+    assert!(!recent.contains(&format!(
+        "site/page{}.html at {}",
+        LOG_SIMILAR,
+        LOG_SIMILAR * 10
+    )));
+
+    log.flush_similar();
+    let recent = log.recent();
+    assert_eq!(recent[recent.len() - 2], "... and 10 similar in img");
+    assert_eq!(recent[recent.len() - 1], "... and 1 similar in site");
+    // nothing more.
+    log.flush_similar();
+    assert_eq!(log.recent().len(), recent.len());
+
+    let similar = log.similar();
+    assert_eq!(similar.len(), 2);
+    assert_eq!(similar[0].template, "maybe binary file {}");
+    assert_eq!(similar[0].dir, "img");
+    assert_eq!(similar[0].count, n);
+    assert_eq!(similar[1].template, "{} at #");
+    assert_eq!(similar[1].dir, "site");
+    assert_eq!(similar[1].count, LOG_SIMILAR + 1);
+}
+
+#[test]
+fn test_workers_survive_log_failure() -> Result<(), AppError> {
+    let root = PathBuf::from("tmp/log_failure");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root)?;
+    fs::write(root.join("good.txt"), "alpha beta gamma")?;
+    // a NUL is not accepted by the html parser, this is logged.
+    fs::write(root.join("bad.html"), "<p>abc\0def</p>")?;
+
+    let words = Words::create(Path::new("tmp/log_failure.idx"))?;
+    let data: &'static Data = Box::leak(Box::new(Data::new(
+        root.clone(),
+        words,
+        Logger::new(Some(Box::new(DiskFull))),
+    )));
+
+    let work = init_work(NoPrinter, data, 2);
+    work.send.send(Msg::WalkTree(root.clone()))?;
+
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(10) {
+        let indexed = data.words.lock()?.word("alpha")?.is_some();
+        if indexed && data.log.failures() > 0 {
+            break;
+        }
+        sleep(Duration::from_millis(10));
+    }
+    sleep(Duration::from_millis(100));
+
+    assert!(data.words.lock()?.word("alpha")?.is_some());
+    assert!(data.log.failures() > 0);
+    assert!(!data.log.recent().is_empty());
+    for worker in work.workers.lock().expect("workers").iter() {
+        assert!(!worker.handle.is_finished(), "{:?} died", worker.id);
+    }
+
+    shut_down(&work);
+
+    Ok(())
+}
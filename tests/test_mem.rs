@@ -0,0 +1,74 @@
+use std::fs;
+use std::path::Path;
+use textindex::error::AppError;
+use textindex::index2::tmp_index::TmpWords;
+use textindex::proc3::{FoundSnapshot, MemUsage};
+
+#[test]
+fn test_mem_usage() {
+    let mem = MemUsage::default();
+
+    let mut words = TmpWords::new("file.txt");
+    for i in 0..20 {
+        words.add_word(format!("word{}", i));
+    }
+    let bytes = words.mem_size();
+    assert!(bytes > 20 * "word00".len(), "{}", bytes);
+    let limit = 10 * bytes;
+
+    // up to the limit.
+    let mut sent = 0;
+    while mem.tmp_words() + bytes <= limit {
+        mem.add_tmp_words(bytes);
+        sent += 1;
+        assert!(!mem.should_pause(limit));
+        assert!(!mem.warn_once(false));
+    }
+    assert_eq!(sent, 10);
+    assert_eq!(mem.tmp_words(), limit);
+
+    // one more and the loader waits, with one warning.
+    mem.add_tmp_words(bytes);
+    assert!(mem.should_pause(limit));
+    assert!(mem.warn_once(true));
+    assert!(!mem.warn_once(true));
+    assert!(!mem.should_pause(0));
+
+    // merged.
+    mem.sub_tmp_words(bytes);
+    assert!(!mem.should_pause(limit));
+    assert!(!mem.warn_once(false));
+    assert!(mem.warn_once(true));
+    assert_eq!(mem.max_tmp_words(), (sent + 1) * bytes);
+
+    for _ in 0..sent + 5 {
+        mem.sub_tmp_words(bytes);
+    }
+    assert_eq!(mem.tmp_words(), 0);
+}
+
+#[test]
+fn test_mem_found_lines() -> Result<(), AppError> {
+    let root = Path::new("tmp/mem_found");
+    fs::create_dir_all(root)?;
+    let mut files = Vec::new();
+    for i in 0..4 {
+        let file = format!("f{}.txt", i);
+        fs::write(root.join(&file), "needle one\nnothing\nneedle two\n")?;
+        files.push(file);
+    }
+
+    let found = FoundSnapshot::new(vec!["needle".into()], files, 100);
+    assert_eq!(found.line_bytes(), 0);
+    found.page(root, 0, 2)?;
+    let two = found.line_bytes();
+    assert!(two > 2 * "needle one".len());
+
+    // the same page again is cached.
+    found.page(root, 0, 2)?;
+    assert_eq!(found.line_bytes(), two);
+    found.page(root, 2, 2)?;
+    assert_eq!(found.line_bytes(), 2 * two);
+
+    Ok(())
+}
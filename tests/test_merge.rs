@@ -0,0 +1,153 @@
+use std::fs::File;
+use std::path::Path;
+use textindex::error::AppError;
+use textindex::index2::merge::{MergeConflict, MergeReport};
+use textindex::index2::{IndexError, IndexKind, Words};
+use textindex::proc3::{indexing, FileFilter, Settings};
+
+const FIRST: &[(&str, &str)] = &[
+    ("home/a/notes.txt", "harbour lights over the bay"),
+    ("home/a/list.txt", "apples pears harbour"),
+];
+
+const SECOND: &[(&str, &str)] = &[
+    ("work\\b\\report.txt", "quarterly harbour report"),
+    ("work/b/todo.txt", "pears and plums"),
+];
+
+fn build(path: &Path, files: &[(&str, &str)]) -> Result<(), AppError> {
+    let mut log = File::create(path.with_extension("log"))?;
+    let settings = Settings::default();
+    let mut w = Words::create(path)?;
+    for (name, txt) in files {
+        let (_, words) = indexing(
+            &mut log,
+            &settings,
+            FileFilter::Text,
+            name,
+            &txt.as_bytes().to_vec(),
+        )?;
+        w.append(words)?;
+    }
+    w.write()?;
+    Ok(())
+}
+
+fn merge(
+    path: &Path,
+    other: &Path,
+    conflict: MergeConflict,
+) -> Result<(Words, MergeReport), AppError> {
+    let mut w = Words::read(path)?;
+    let mut o = Words::read_only(other)?;
+    let report = w.merge(&mut o, conflict, |_| {})?;
+    Ok((w, report))
+}
+
+#[test]
+fn test_merge() -> Result<(), AppError> {
+    let first = Path::new("tmp/merge_first.idx");
+    let second = Path::new("tmp/merge_second.idx");
+    build(first, FIRST)?;
+    build(second, SECOND)?;
+
+    let (mut w, report) = merge(first, second, MergeConflict::Error)?;
+    assert_eq!(report.files, 2);
+    assert!(report.conflicts.is_empty());
+    assert!(!report.cancelled);
+    w.write()?;
+    drop(w);
+
+    let mut w = Words::read(first)?;
+    assert_eq!(w.files().len(), 4);
+    assert_eq!(
+        w.find(&["harbour".into()])?,
+        vec!["home/a/notes.txt", "home/a/list.txt", "work\\b\\report.txt"]
+    );
+    assert_eq!(
+        w.find(&["pears".into()])?,
+        vec!["home/a/list.txt", "work/b/todo.txt"]
+    );
+    assert_eq!(w.find(&["plums".into()])?, vec!["work/b/todo.txt"]);
+    assert_eq!(
+        w.find(&["harbour".into(), "quarterly".into()])?,
+        vec!["work\\b\\report.txt"]
+    );
+    assert!(w.check(None).is_ok());
+    drop(w);
+
+    // the other index is unchanged.
+    let mut o = Words::read(second)?;
+    assert_eq!(o.files().len(), 2);
+    assert!(o.find(&["apples".into()])?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_conflict() -> Result<(), AppError> {
+    let first = Path::new("tmp/merge_conflict_first.idx");
+    let second = Path::new("tmp/merge_conflict_second.idx");
+    build(first, FIRST)?;
+    // the same file with backslashes, and a new one.
+    build(
+        second,
+        &[
+            ("home\\a\\notes.txt", "lighthouse"),
+            ("home/c/new.txt", "lighthouse"),
+        ],
+    )?;
+
+    match merge(first, second, MergeConflict::Error) {
+        Err(e) => {
+            let e = e.err.downcast_ref::<IndexError>();
+            assert!(matches!(
+                e.map(|v| &v.kind),
+                Some(IndexKind::MergeConflict(v)) if v == "home\\a\\notes.txt"
+            ));
+        }
+        Ok(_) => panic!("merged a conflict"),
+    }
+
+    let (mut w, report) = merge(first, second, MergeConflict::Skip)?;
+    assert_eq!(report.files, 1);
+    assert_eq!(report.conflicts, vec!["home\\a\\notes.txt"]);
+    assert_eq!(w.files().len(), 3);
+    assert_eq!(w.find(&["lighthouse".into()])?, vec!["home/c/new.txt"]);
+    drop(w);
+
+    let (mut w, report) = merge(first, second, MergeConflict::Replace)?;
+    assert_eq!(report.files, 1);
+    assert_eq!(report.conflicts.len(), 1);
+    assert_eq!(w.files().len(), 3);
+    assert_eq!(
+        w.find(&["lighthouse".into()])?,
+        vec!["home/a/notes.txt", "home/c/new.txt"]
+    );
+    // the old words of the file are gone.
+    assert_eq!(w.find(&["harbour".into()])?, vec!["home/a/list.txt"]);
+    assert!(w.find(&["bay".into()])?.is_empty());
+    assert!(w.have_file(&"home/a/notes.txt".into()));
+    w.write()?;
+    drop(w);
+
+    let mut w = Words::read(first)?;
+    assert_eq!(w.files().len(), 3);
+    assert_eq!(
+        w.find(&["lighthouse".into()])?,
+        vec!["home/a/notes.txt", "home/c/new.txt"]
+    );
+    assert_eq!(w.find(&["harbour".into()])?, vec!["home/a/list.txt"]);
+    assert!(w.check(None).is_ok());
+    drop(w);
+
+    // replaced again.
+    let third = Path::new("tmp/merge_conflict_third.idx");
+    build(third, &[("home/a/notes.txt", "beacon")])?;
+    let (mut w, _) = merge(first, third, MergeConflict::Replace)?;
+    assert_eq!(w.files().len(), 3);
+    assert_eq!(w.find(&["lighthouse".into()])?, vec!["home/c/new.txt"]);
+    assert_eq!(w.find(&["beacon".into()])?, vec!["home/a/notes.txt"]);
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::path::Path;
+use textindex::error::AppError;
+use textindex::index2::Words;
+use textindex::proc3::{indexing, short_terms, FileFilter, Settings};
+
+const TEXT: &str = r#"<p>Qt and Go on an xy-plot, <a href="a.html">ab</a> extra</p>"#;
+
+#[test]
+fn test_min_word_len() -> Result<(), AppError> {
+    let mut log = File::create("tmp/min_word_len.log")?;
+
+    let index = |log: &mut File, min_word_len: usize| {
+        let settings = Settings {
+            stop_words: false,
+            min_word_len,
+            ..Default::default()
+        };
+        indexing(
+            log,
+            &settings,
+            FileFilter::Html,
+            "page.html",
+            &TEXT.as_bytes().to_vec(),
+        )
+    };
+
+    let (_, all) = index(&mut log, 1)?;
+    assert_eq!(all.short_count, 0);
+    let (_, long) = index(&mut log, 3)?;
+
+    let all_words: BTreeSet<_> = all.words.keys().cloned().collect();
+    let long_words: BTreeSet<_> = long.words.keys().cloned().collect();
+    let dropped: Vec<_> = all_words.difference(&long_words).cloned().collect();
+    assert_eq!(dropped, vec!["ab", "an", "go", "on", "qt"]);
+    assert_eq!(long.short_count, 5);
+    assert!(long_words.contains("and"));
+    assert!(long_words.contains("xy-plot"));
+    assert!(long_words.contains("extra"));
+    // links are kept.
+    assert!(long_words.iter().any(|v| v.starts_with("link:")));
+
+    Ok(())
+}
+
+#[test]
+fn test_min_word_len_header() -> Result<(), AppError> {
+    let path = Path::new("tmp/min_word_len.idx");
+
+    let mut w = Words::create(path)?;
+    assert_eq!(w.min_word_len(), 1);
+    w.set_min_word_len(3);
+    w.write()?;
+    drop(w);
+
+    let w = Words::read(path)?;
+    assert_eq!(w.min_word_len(), 3);
+
+    // the warning on find.
+    let terms = vec![
+        "go".to_string(),
+        "go*".to_string(),
+        "g?".to_string(),
+        "extra".to_string(),
+        "link:ab".to_string(),
+    ];
+    assert_eq!(short_terms(&terms, w.min_word_len()), vec!["go"]);
+    assert!(short_terms(&terms, 1).is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use textindex::error::AppError;
+use textindex::index2::pattern::Pattern;
+use textindex::index2::{Words, LINK_NS};
+
+#[test]
+fn test_wildcards() {
+    assert!(Pattern::new("alpha").matches("alpha"));
+    assert!(!Pattern::new("alpha").matches("alphabet"));
+    assert!(Pattern::new("alp*").matches("alphabet"));
+    assert!(Pattern::new("*bet").matches("alphabet"));
+    assert!(Pattern::new("a*a*t").matches("alphabet"));
+    assert!(!Pattern::new("a*a*x").matches("alphabet"));
+    assert!(Pattern::new("alph?bet").matches("alphabet"));
+    assert!(!Pattern::new("alph?bet").matches("alphbet"));
+    assert!(Pattern::new("*").matches(""));
+    assert!(Pattern::new("gr??e").matches("grüße"));
+}
+
+#[test]
+fn test_escaped() {
+    let p = Pattern::new(r"a\*b");
+    assert_eq!(p.literal(), Some("a*b"));
+    assert!(p.matches("a*b"));
+    assert!(!p.matches("axb"));
+
+    let p = Pattern::new(r"x\?*");
+    assert_eq!(p.literal(), None);
+    assert!(p.matches("x?y"));
+    assert!(!p.matches("xy"));
+
+    assert!(Pattern::new(r"a\\b").matches(r"a\b"));
+    // trailing backslash
+    assert!(Pattern::new(r"a\").matches(r"a\"));
+
+    assert_eq!(Pattern::escape(r"a*b?c\d"), r"a\*b\?c\\d");
+    assert!(Pattern::new(&Pattern::escape("*?")).matches("*?"));
+    assert!(!Pattern::new(&Pattern::escape("*")).matches("abc"));
+}
+
+#[test]
+fn test_find_literal() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/find_literal.idx")?;
+
+    let mut w = Words::create(&path)?;
+    let fid = w.add_file("file0".into());
+    w.add_word(format!("{}page?id=1", LINK_NS), 1, fid)?;
+    let fid = w.add_file("file1".into());
+    w.add_word(format!("{}pagexid=1", LINK_NS), 1, fid)?;
+
+    let found = w.find(&[format!("{}page?id=1", LINK_NS)])?;
+    assert_eq!(found, vec!["file0", "file1"]);
+
+    let found = w.find(&[Pattern::escape(&format!("{}page?id=1", LINK_NS))])?;
+    assert_eq!(found, vec!["file0"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_multibyte() {
+    // one char each, several bytes.
+    assert!(Pattern::new("m?ller").matches("müller"));
+    assert!(!Pattern::new("m??ller").matches("müller"));
+    assert!(Pattern::new("東?").matches("東京"));
+    assert!(!Pattern::new("東?").matches("東京都"));
+    assert!(Pattern::new("東*").matches("東京都"));
+    assert!(Pattern::new("*京*").matches("東京都"));
+    assert!(Pattern::new("*üü").matches("üüü"));
+    assert!(Pattern::new("?*?").matches("üß"));
+    assert!(!Pattern::new("?*?").matches("ü"));
+    assert_eq!(Pattern::new("müller").literal(), Some("müller"));
+    assert_eq!(Pattern::new("mü*").prefix(), "mü");
+
+    // no escapes for file names.
+    assert!(Pattern::glob(r"win\dir\*").matches(r"win\dir\käse.txt"));
+    assert!(!Pattern::new(r"win\dir\*").matches(r"win\dir\käse.txt"));
+    assert!(Pattern::glob("*üü.txt").matches("üüü.txt"));
+}
+
+#[test]
+fn test_find_multibyte() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/find_multibyte.idx")?;
+
+    let mut w = Words::create(&path)?;
+    let fid = w.add_file("de/müller.txt".into());
+    w.add_word("müller", 1, fid)?;
+    w.add_word("grüße", 1, fid)?;
+    let fid = w.add_file("de/miller.txt".into());
+    w.add_word("miller", 1, fid)?;
+    let fid = w.add_file("ja/東京.txt".into());
+    w.add_word("東京", 1, fid)?;
+    w.add_word("東京都", 1, fid)?;
+
+    assert_eq!(
+        w.find(&["m?ller".into()])?,
+        vec!["de/müller.txt", "de/miller.txt"]
+    );
+    assert_eq!(w.find(&["mü*".into()])?, vec!["de/müller.txt"]);
+    assert_eq!(w.find(&["müller".into()])?, vec!["de/müller.txt"]);
+    assert_eq!(w.find(&["gr??e".into()])?, vec!["de/müller.txt"]);
+    assert!(w.find(&["gr?e".into()])?.is_empty());
+    assert_eq!(w.find(&["東?".into()])?, vec!["ja/東京.txt"]);
+    assert_eq!(w.find(&["*京".into()])?, vec!["ja/東京.txt"]);
+    assert!(w.find(&["東?都".into(), "müller".into()])?.is_empty());
+
+    assert_eq!(
+        w.find_file("de/m?ller.txt"),
+        vec!["de/müller.txt", "de/miller.txt"]
+    );
+    assert_eq!(w.find_file("de/mü*"), vec!["de/müller.txt"]);
+    assert_eq!(w.find_file("*/東?.txt"), vec!["ja/東京.txt"]);
+    assert_eq!(w.find_file("ja/東京.txt"), vec!["ja/東京.txt"]);
+    assert!(w.find_file("ja/東.txt").is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use std::path::Path;
+use std::time::Instant;
+use textindex::error::AppError;
+use textindex::index2::{WordBlockType, Words};
+
+const FILES: usize = 2000;
+const PREALLOC: usize = 128;
+
+fn fill(path: &Path, prealloc: usize) -> Result<(Words, usize), AppError> {
+    let mut w = Words::create(path)?;
+    if prealloc > 0 {
+        assert_eq!(w.prealloc_blocks(prealloc)?, 3 * prealloc);
+        assert_eq!(w.pooled_blocks(), 3 * prealloc);
+    }
+    for i in 0..FILES {
+        let fid = w.add_file(format!("file{}.txt", i));
+        for j in 0..5 {
+            w.add_word(format!("w{}x{}", i % 200, j), 1, fid)?;
+            w.add_word(format!("v{}x{}", i % 1000, j), 1, fid)?;
+        }
+        if i % 200 == 199 {
+            w.write()?;
+        }
+    }
+    let unused = w.release_blocks();
+    assert_eq!(w.pooled_blocks(), 0);
+    w.write()?;
+    Ok((w, unused))
+}
+
+#[test]
+fn test_prealloc() -> Result<(), AppError> {
+    let start = Instant::now();
+    let (plain, unused) = fill(Path::new("tmp/prealloc_off.idx"), 0)?;
+    let plain_time = start.elapsed();
+    assert_eq!(unused, 0);
+    let start = Instant::now();
+    let (mut pooled, unused) = fill(Path::new("tmp/prealloc_on.idx"), PREALLOC)?;
+    let pooled_time = start.elapsed();
+    assert!(unused > 0 && unused < 3 * PREALLOC);
+    // only reported, run with --nocapture.
+    println!("plain {:?} pooled {:?}", plain_time, pooled_time);
+
+    for ty in [
+        WordBlockType::WordMapHead,
+        WordBlockType::WordMapTail,
+        WordBlockType::WordList2,
+    ] {
+        let (_, plain_runs) = plain.block_runs(ty);
+        let (blocks, runs) = pooled.block_runs(ty);
+        assert_eq!(blocks, PREALLOC, "{:?}", ty);
+        assert_eq!(runs, 1, "{:?}", ty);
+        assert!(runs <= plain_runs, "{:?}", ty);
+    }
+
+    assert_eq!(pooled.find(&["w7x3".into()])?.len(), FILES / 200);
+    assert_eq!(
+        pooled.find(&["v7x3".into()])?,
+        vec!["file7.txt", "file1007.txt"]
+    );
+    assert!(pooled.check(None).is_ok());
+    drop(pooled);
+
+    // the unused blocks are only empty blocks.
+    let mut w = Words::read(Path::new("tmp/prealloc_on.idx"))?;
+    assert_eq!(w.find(&["w7x3".into()])?.len(), FILES / 200);
+    assert!(w.check(None).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_prealloc_drained() -> Result<(), AppError> {
+    // the pool runs dry, the rest is allocated as usual.
+    let (mut w, unused) = fill(Path::new("tmp/prealloc_drained.idx"), 1)?;
+    assert_eq!(unused, 0);
+    let (blocks, _) = w.block_runs(WordBlockType::WordMapHead);
+    assert!(blocks > 1);
+    assert_eq!(w.find(&["w7x3".into()])?.len(), FILES / 200);
+
+    Ok(())
+}
+
+#[test]
+fn test_prealloc_kept() -> Result<(), AppError> {
+    let mut w = Words::create(Path::new("tmp/prealloc_kept.idx"))?;
+    assert_eq!(w.prealloc_blocks(PREALLOC)?, 3 * PREALLOC);
+    let fid = w.add_file("file0.txt".into());
+    w.add_word("alpha", 1, fid)?;
+    w.write()?;
+    w.compact_blocks();
+
+    // the next walk only tops up what was used.
+    let left = w.pooled_blocks();
+    assert!(left > 0 && left < 3 * PREALLOC);
+    assert_eq!(w.prealloc_blocks(PREALLOC)?, 3 * PREALLOC - left);
+    assert_eq!(w.pooled_blocks(), 3 * PREALLOC);
+
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use std::fs::{self, File};
+use std::path::Path;
+use textindex::error::AppError;
+use textindex::proc3::quoted_printable::{
+    decode_quoted_printable, decode_text, is_quoted_printable,
+};
+use textindex::proc3::{
+    find_matched_lines, indexing, read_line, FileFilter, Settings, DEFAULT_LINE_WIDTH,
+};
+
+const QP_UTF8: &str = "From: someone@example.com
+Subject: Gr=C3=BC=C3=9Fe
+Content-Transfer-Encoding: quoted-printable
+
+Das Wetter ist sch=C3=B6n und die Stra=C3=9Fe zum Bahnhofsvor=
+platz ist frei.
+Preis: 5 =3D f=C3=BCnf
+";
+
+const QP_LATIN1: &str = "Subject: =E4rger
+
+Sch=F6ne Gr=FC=DFe aus M=FCnchen.
+";
+
+#[test]
+fn test_qp_detect() {
+    assert!(is_quoted_printable(QP_UTF8.as_bytes()));
+    assert!(is_quoted_printable(QP_LATIN1.as_bytes()));
+
+    // plain text, code and base64 padding.
+    assert!(!is_quoted_printable(
+        b"Just some text.\nNothing = special.\n"
+    ));
+    assert!(!is_quoted_printable(
+        b"if a == b { x = 0xFF; }\nlet y = 1;\n"
+    ));
+    assert!(!is_quoted_printable(b"QUJDREVGRw==\nSGVsbG8=\nV29ybGQ=\n"));
+    // already 8-bit.
+    assert!(!is_quoted_printable(
+        "schön =C3=B6 =C3=B6 =C3=B6".as_bytes()
+    ));
+}
+
+#[test]
+fn test_qp_decode() {
+    let decoded = String::from_utf8(decode_quoted_printable(QP_UTF8.as_bytes())).expect("utf8");
+    assert!(decoded.contains("Subject: Grüße\n"));
+    assert!(decoded.contains("zum Bahnhofsvorplatz ist frei.\n"));
+    assert!(decoded.contains("Preis: 5 = fünf\n"));
+
+    // soft line-breaks don't shift the lines.
+    assert_eq!(decoded.lines().count(), QP_UTF8.lines().count());
+    assert_eq!(decoded.lines().nth(5), Some(""));
+    assert_eq!(decoded.lines().nth(6), Some("Preis: 5 = fünf"));
+
+    // malformed sequences pass through.
+    assert_eq!(decode_quoted_printable(b"a=ZZb =4 c="), b"a=ZZb =4 c");
+    assert_eq!(decode_quoted_printable(b"x=3d\r\ny\r\n"), b"x=\r\ny\r\n");
+
+    let (txt, decoded) = decode_text(QP_LATIN1.as_bytes());
+    assert!(decoded);
+    assert_eq!(txt, "Subject: ärger\n\nSchöne Grüße aus München.\n");
+
+    let (txt, decoded) = decode_text(b"plain =C3=B6");
+    assert!(!decoded);
+    assert_eq!(txt, "plain =C3=B6");
+}
+
+#[test]
+fn test_qp_indexing() -> Result<(), AppError> {
+    let mut log = File::create("tmp/qp_indexing.log")?;
+
+    let (_, words) = indexing(
+        &mut log,
+        &Settings::default(),
+        FileFilter::Text,
+        "mail.txt",
+        &QP_UTF8.as_bytes().to_vec(),
+    )?;
+    assert!(words.decoded);
+    assert!(words.words.contains_key("schön"));
+    assert!(words.words.contains_key("straße"));
+    assert!(words.words.contains_key("bahnhofsvorplatz"));
+    assert!(!words.words.contains_key("b6n"));
+
+    let (_, words) = indexing(
+        &mut log,
+        &Settings::default(),
+        FileFilter::Text,
+        "latin1.txt",
+        &QP_LATIN1.as_bytes().to_vec(),
+    )?;
+    assert!(words.decoded);
+    assert!(words.words.contains_key("münchen"));
+    assert!(words.words.contains_key("grüße"));
+
+    Ok(())
+}
+
+#[test]
+fn test_qp_lines() -> Result<(), AppError> {
+    let root = Path::new("tmp/qp_lines");
+    fs::create_dir_all(root)?;
+    fs::write(root.join("mail.txt"), QP_UTF8)?;
+
+    let files = vec!["mail.txt".to_string()];
+    let lines = find_matched_lines(root, &["schön".into()], &files, DEFAULT_LINE_WIDTH, false)?;
+    let found = &lines[0].1;
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].nr, 4);
+
+    let line = read_line(root, "mail.txt", found[0].nr)?.expect("line");
+    assert!(line.ends_with("Bahnhofsvorplatz ist frei."));
+
+    Ok(())
+}
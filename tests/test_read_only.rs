@@ -0,0 +1,167 @@
+use std::path::Path;
+use textindex::error::AppError;
+use textindex::index2::{IndexKind, WordBlockType, Words};
+
+const FILES: usize = 3000;
+
+fn name(i: usize) -> String {
+    format!("dir{}/file{}.txt", i % 50, i)
+}
+
+fn fill(path: &Path) -> Result<(), AppError> {
+    let mut w = Words::create(path)?;
+    for i in 0..FILES {
+        let fid = w.add_file(name(i));
+        w.add_word("common", 1, fid)?;
+        if i % 1000 == 7 {
+            w.add_word("rare", 1, fid)?;
+        }
+        if i % 100 == 3 {
+            w.add_word("some", 1, fid)?;
+        }
+        if i % 500 == 499 {
+            w.write()?;
+        }
+    }
+    w.write()?;
+    Ok(())
+}
+
+#[test]
+fn test_read_only() -> Result<(), AppError> {
+    let path = Path::new("tmp/read_only.idx");
+    fill(path)?;
+
+    let mut w = Words::read_only(path)?;
+    assert!(w.is_read_only());
+    assert!(w.files().is_empty());
+    let file_blocks =
+        w.db.iter_metadata_filter(|_nr, ty| {
+            ty == WordBlockType::FileList2
+                || ty == WordBlockType::FileRefs
+                || ty == WordBlockType::FileLeaves
+        })
+        .count();
+
+    // only the found files are loaded, with a few blocks each.
+    w.db.start_counting();
+    let found = w.find(&["rare".into()])?;
+    let rare = w.db.stop_counting();
+    assert_eq!(found, vec![name(7), name(1007), name(2007)]);
+    assert_eq!(w.files().len(), 3);
+    assert!(rare.misses < file_blocks as u64, "{:?}", rare);
+
+    w.db.start_counting();
+    let found = w.find(&["some".into()])?;
+    let some = w.db.stop_counting();
+    assert_eq!(found.len(), 30);
+    assert_eq!(found[0], name(3));
+    assert_eq!(w.files().len(), 33);
+    assert!(some.misses <= 30 * 4, "{:?}", some);
+
+    assert_eq!(
+        w.find(&["some".into(), "rare".into()])?,
+        Vec::<String>::new()
+    );
+    assert!(w.find(&["missing".into()])?.is_empty());
+
+    match w.write() {
+        Err(e) => assert!(matches!(e.kind, IndexKind::ReadOnly)),
+        Ok(_) => panic!("wrote a read-only index"),
+    }
+    drop(w);
+
+    // the same as the full index.
+    let mut w = Words::read(path)?;
+    assert!(!w.is_read_only());
+    assert_eq!(
+        w.find(&["rare".into()])?,
+        vec![name(7), name(1007), name(2007)]
+    );
+    assert_eq!(w.files().len(), FILES);
+
+    Ok(())
+}
+
+#[test]
+fn test_read_only_fallback() -> Result<(), AppError> {
+    let path = Path::new("tmp/read_only_fallback.idx");
+
+    // a leaf longer than a block is only in the file-list.
+    let long = format!("dir/{}.txt", "x".repeat(5000));
+    let mut w = Words::create(path)?;
+    let fid = w.add_file("dir/short.txt".into());
+    w.add_word("short", 1, fid)?;
+    let fid = w.add_file(long.clone());
+    w.add_word("long", 1, fid)?;
+    w.write()?;
+    drop(w);
+
+    let mut w = Words::read_only(path)?;
+    assert_eq!(w.find(&["short".into()])?, vec!["dir/short.txt"]);
+    assert_eq!(w.files().len(), 1);
+    assert_eq!(w.find(&["long".into()])?, vec![long]);
+    assert_eq!(w.files().len(), 2);
+    assert!(!w.files().is_lookup());
+    assert!(w.is_read_only());
+
+    Ok(())
+}
+
+#[test]
+fn test_read_only_open() -> Result<(), AppError> {
+    let path = Path::new("tmp/read_only_open.idx");
+
+    let mut w = Words::create(path)?;
+    for i in 0..100 {
+        let fid = w.add_file(name(i));
+        for j in 0..50 {
+            w.add_word(format!("word{}", i * 50 + j), 1, fid)?;
+        }
+    }
+    w.write()?;
+    drop(w);
+
+    let mut w = Words::read_only(path)?;
+    let word_blocks =
+        w.db.iter_metadata_filter(|_nr, ty| ty == WordBlockType::WordList2)
+            .count();
+    assert!(word_blocks > 10, "{}", word_blocks);
+
+    // the directory is much smaller than the word-list.
+    let dir_blocks =
+        w.db.iter_metadata_filter(|_nr, ty| ty == WordBlockType::WordDir)
+            .count();
+    assert!(dir_blocks > 0);
+    assert!(
+        dir_blocks * 10 < word_blocks,
+        "{} {}",
+        dir_blocks,
+        word_blocks
+    );
+
+    // no word block is read at open.
+    let opened = w.db.reads();
+    assert!(opened < word_blocks as u64, "{} {}", opened, word_blocks);
+
+    // nor by a lookup, only the block of the word.
+    assert_eq!(w.find(&["word4321".into()])?, vec![name(86)]);
+    let found = w.db.reads() - opened;
+    assert!(found < word_blocks as u64 / 4, "{} {}", found, word_blocks);
+
+    let before = w.db.reads();
+    assert!(w.find(&["word99999".into()])?.is_empty());
+    assert!(w.find(&["missing".into()])?.is_empty());
+    let missing = w.db.reads() - before;
+    assert!(missing < 5, "{}", missing);
+
+    assert_eq!(w.find(&["word17".into()])?, vec![name(0)]);
+    assert_eq!(w.word_len(), 5000);
+
+    match w.write() {
+        Err(e) => assert!(matches!(e.kind, IndexKind::ReadOnly)),
+        Ok(_) => panic!("wrote a read-only index"),
+    }
+
+    Ok(())
+}
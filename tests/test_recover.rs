@@ -0,0 +1,77 @@
+use std::path::Path;
+use textindex::error::AppError;
+use textindex::index2::words::RawWord;
+use textindex::index2::{WordBlockType, Words};
+
+/// Clears the word-list blocks without touching anything else.
+fn lose_words(w: &mut Words) -> Result<(), AppError> {
+    let blocks: Vec<_> =
+        w.db.iter_metadata_filter(|_nr, ty| ty == WordBlockType::WordList2)
+            .map(|(nr, _)| nr)
+            .collect();
+    assert!(!blocks.is_empty());
+    for nr in blocks {
+        for r in w.db.get_as_mut::<RawWord>(nr)?.iter_mut() {
+            *r = RawWord::default();
+        }
+    }
+    w.db.store()?;
+    Ok(())
+}
+
+#[test]
+fn test_recover_words() -> Result<(), AppError> {
+    let path = Path::new("tmp/recover_words.idx");
+
+    let mut w = Words::create(path)?;
+    for i in 0..20 {
+        let fid = w.add_file(format!("file{}", i));
+        // long enough for the tail blocks.
+        w.add_word("alpha", 1, fid)?;
+        w.add_word(format!("word{}", i % 5), 1, fid)?;
+    }
+    w.write()?;
+    let report = w.recover_words()?;
+    assert_eq!(report.chains, 6);
+    assert_eq!(report.orphans, 0);
+    assert!(!w.is_degraded());
+
+    lose_words(&mut w)?;
+    drop(w);
+
+    let mut w = Words::read(path)?;
+    assert!(w.find(&["alpha".into()])?.is_empty());
+
+    let report = w.recover_words()?;
+    assert_eq!(report.chains, 6);
+    assert_eq!(report.orphans, 6);
+    assert_eq!(report.recovered.len(), 6);
+    assert_eq!(report.files, 20);
+    assert_eq!(report.missing_files, 0);
+    assert!(w.is_degraded());
+    w.write()?;
+    drop(w);
+
+    let mut w = Words::read(path)?;
+    assert!(w.is_degraded());
+    let mut sizes: Vec<_> = Vec::new();
+    for word in &report.recovered {
+        sizes.push(w.find(&[word.clone()])?.len());
+    }
+    sizes.sort();
+    assert_eq!(sizes, vec![4, 4, 4, 4, 4, 20]);
+    assert_eq!(w.find(&["recovered-*".into()])?.len(), 20);
+    assert!(w.check(None).is_ok());
+
+    // nothing left to recover.
+    let again = w.recover_words()?;
+    assert_eq!(again.orphans, 0);
+    assert!(again.recovered.is_empty());
+
+    // new words go on as usual.
+    let fid = w.add_file("file20".into());
+    w.add_word("alpha", 1, fid)?;
+    assert_eq!(w.find(&["alpha".into()])?, vec!["file20"]);
+
+    Ok(())
+}
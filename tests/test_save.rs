@@ -0,0 +1,128 @@
+use crossbeam::channel::bounded;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use textindex::error::AppError;
+use textindex::index2::Words;
+
+fn fill(w: &mut Words, files: usize, prefix: &str) -> Result<(), AppError> {
+    for n in 0..files {
+        let fid = w.add_file(format!("{}{}", prefix, n));
+        for i in 0..100 {
+            w.add_word(format!("word{}", (n * 7 + i) % 5000).as_str(), 1, fid)?;
+        }
+        w.add_word(format!("{}{}", prefix, n).as_str(), 1, fid)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_chunked_write() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/chunked_write.idx")?;
+
+    let mut w = Words::create(&path)?;
+    fill(&mut w, 200, "file")?;
+
+    let mut state = w.start_write()?;
+    assert!(state.pending() > 1);
+
+    // changes between the batches.
+    let mut n = 0;
+    while !w.write_batch(&mut state, 4)? {
+        let fid = w.add_file(format!("late{}", n));
+        w.add_word(format!("late{}", n).as_str(), 1, fid)?;
+        n += 1;
+    }
+    assert!(state.batches > 1);
+    assert!(n > 0);
+    // changed meanwhile, not clean yet.
+    assert_ne!(w.generation(), state.generation);
+
+    w.write()?;
+
+    let mut w = Words::read(&path)?;
+    assert!(w.was_clean());
+    assert_eq!(w.files().len(), 200 + n);
+    assert!(w.word("file199")?.is_some());
+    assert!(w.word(format!("late{}", n - 1).as_str())?.is_some());
+
+    Ok(())
+}
+
+#[test]
+fn test_chunked_write_clean() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/chunked_write_clean.idx")?;
+
+    let mut w = Words::create(&path)?;
+    fill(&mut w, 50, "file")?;
+
+    let mut state = w.start_write()?;
+    while !w.write_batch(&mut state, 16)? {}
+    drop(w);
+
+    let mut w = Words::read(&path)?;
+    assert!(w.was_clean());
+    assert_eq!(w.files().len(), 50);
+    let found = w.find(&["file7".into()])?;
+    assert_eq!(found, vec!["file7"]);
+
+    Ok(())
+}
+
+/// A merge running while the index is saved only waits for one batch.
+#[test]
+fn test_merge_stall() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/merge_stall.idx")?;
+
+    let mut w = Words::create(&path)?;
+    fill(&mut w, 2000, "file")?;
+    let words = Arc::new(Mutex::new(w));
+
+    // one merge step after each batch.
+    let (step_send, step_recv) = bounded::<()>(0);
+    let (done_send, done_recv) = bounded::<()>(0);
+    let merge = {
+        let words = Arc::clone(&words);
+        thread::spawn(move || {
+            let mut n = 0;
+            while step_recv.recv().is_ok() {
+                let mut w = words.lock().expect("lock");
+                let fid = w.add_file(format!("merge{}", n));
+                w.add_word("merged", 1, fid).expect("add");
+                n += 1;
+                drop(w);
+                done_send.send(()).expect("done");
+            }
+        })
+    };
+
+    let mut state = words.lock().expect("lock").start_write()?;
+    let total = state.pending();
+    let mut merged = 0;
+    while !words.lock().expect("lock").write_batch(&mut state, 64)? {
+        // the lock is free between the batches.
+        step_send.send(()).expect("step");
+        done_recv.recv().expect("done");
+        merged += 1;
+    }
+    drop(step_send);
+    merge.join().expect("merge");
+
+    // no batch is larger than 64 blocks.
+    assert!(total > 64);
+    assert_eq!(state.blocks, total);
+    assert_eq!(state.batches, total.div_ceil(64));
+    assert_eq!(merged, state.batches - 1);
+
+    let mut w = words.lock().expect("lock");
+    w.write()?;
+    drop(w);
+
+    let mut w = Words::read(&path)?;
+    assert!(w.was_clean());
+    assert_eq!(w.files().len(), 2000 + merged);
+    assert_eq!(w.find_ids(&["merged".into()])?.len(), merged);
+
+    Ok(())
+}
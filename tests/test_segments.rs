@@ -0,0 +1,82 @@
+use blockfile2::LogicalNr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use textindex::error::AppError;
+use textindex::index2::segments::{join, segment_path, split, Segments};
+use textindex::index2::{IndexKind, Words};
+
+#[test]
+fn test_segment_nr() {
+    assert_eq!(
+        segment_path(Path::new("tmp/stored.idx"), 0),
+        PathBuf::from("tmp/stored.idx")
+    );
+    assert_eq!(
+        segment_path(Path::new("tmp/stored.idx"), 1),
+        PathBuf::from("tmp/stored.0001.idx")
+    );
+
+    // the first segment keeps the plain block-nrs.
+    assert_eq!(join(0, LogicalNr(17)), LogicalNr(17));
+    assert_eq!(split(LogicalNr(17)), (0, LogicalNr(17)));
+    assert_eq!(split(join(3, LogicalNr(17))), (3, LogicalNr(17)));
+}
+
+#[test]
+fn test_segments() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/segments.idx")?;
+
+    let mut w = Words::create(&path)?;
+    w.set_segment_size(1);
+
+    let mut n = 0;
+    while w.db.len() < 3 && n < 100 {
+        let fid = w.add_file(format!("file{}", n));
+        for i in 0..1000 {
+            w.add_word(format!("word{}", i).as_str(), 1, fid)?;
+        }
+        w.add_word(format!("only{}", n).as_str(), 1, fid)?;
+        w.write()?;
+        n += 1;
+    }
+    assert!(w.db.len() >= 3);
+    drop(w);
+
+    let mut w = Words::read(&path)?;
+    assert_eq!(w.segment_size(), 1);
+    assert!(w.db.len() >= 3);
+
+    // chains run across the segments.
+    assert_eq!(w.find_ids(&["word1".into()])?.len(), n);
+    assert_eq!(w.find_ids(&[format!("only{}", n - 1)])?.len(), 1);
+    assert!(w.check(None).is_ok());
+    drop(w);
+
+    Segments::remove(&path);
+    assert!(!segment_path(&path, 1).exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_unknown_segment() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/unknown_segment.idx")?;
+
+    let mut w = Words::create(&path)?;
+    let fid = w.add_file("file".into());
+    w.add_word("word", 1, fid)?;
+    w.write()?;
+
+    let bad = join(5, LogicalNr(1));
+    match w.db.get(bad) {
+        Err(e) => assert!(matches!(e.kind, IndexKind::UnknownSegment(nr) if nr == bad)),
+        Ok(_) => panic!("block in a missing segment"),
+    }
+    assert!(w.db.get_mut(bad).is_err());
+    assert!(w.db.discard(bad).is_err());
+
+    // the index is still usable.
+    assert_eq!(w.find(&["word".into()])?, vec!["file"]);
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use std::fs::File;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+use textindex::error::AppError;
+use textindex::proc3::{indexing, FileFilter, Settings, SharedSettings};
+
+const TEXT: &str = r#"<p>Lorem <img src="x.png" alt="Harbour at night"> ipsum</p>"#;
+
+#[test]
+fn test_settings_snapshot() -> Result<(), AppError> {
+    let mut log = File::create("tmp/settings_snapshot.log")?;
+    let shared = SharedSettings::new(Settings::default());
+    let mut view = shared.view();
+
+    // no change, the same snapshot.
+    let first = shared.refresh(&mut view);
+    assert!(Arc::ptr_eq(&first, &shared.refresh(&mut view)));
+    assert!(!first.attributes);
+
+    // changed while the first file is indexed.
+    shared.update(|s| s.attributes = true);
+    let (_, words) = indexing(
+        &mut log,
+        &first,
+        FileFilter::Html,
+        "first.html",
+        &TEXT.as_bytes().to_vec(),
+    )?;
+    assert!(words.words.contains_key("lorem"));
+    assert!(!words.words.contains_key("harbour"));
+
+    // the next file.
+    let next = shared.refresh(&mut view);
+    assert!(next.attributes);
+    let (_, words) = indexing(
+        &mut log,
+        &next,
+        FileFilter::Html,
+        "next.html",
+        &TEXT.as_bytes().to_vec(),
+    )?;
+    assert!(words.words.contains_key("harbour"));
+
+    // the old snapshot stays as it was.
+    assert!(!first.attributes);
+    assert!(shared.get().attributes);
+    assert_eq!(shared.get().match_limit, Settings::default().match_limit);
+
+    Ok(())
+}
+
+#[test]
+fn test_settings_poisoned() {
+    let shared = SharedSettings::new(Settings::default());
+
+    // a panic in the middle of an update leaves the settings alone.
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        shared.update(|s| {
+            s.attributes = true;
+            panic!("update");
+        })
+    }));
+    assert!(result.is_err());
+    assert!(!shared.get().attributes);
+
+    shared.update(|s| s.attributes = true);
+    assert!(shared.get().attributes);
+    let mut view = shared.view();
+    assert!(shared.refresh(&mut view).attributes);
+}
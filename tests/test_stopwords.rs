@@ -0,0 +1,88 @@
+use std::fs::File;
+use std::path::PathBuf;
+use std::str::FromStr;
+use textindex::error::AppError;
+use textindex::index2::Words;
+use textindex::proc3::{indexing, stop_word_summary, FileFilter, Settings, WalkStats};
+
+const TEXT: &str = "The quick brown fox jumps over the lazy dog.
+It was not the fox that was lazy, but the dog.
+";
+
+#[test]
+fn test_stopwords_on_off() -> Result<(), AppError> {
+    let mut log = File::create("tmp/stopwords.log")?;
+
+    let on = Settings {
+        stop_words: true,
+        ..Default::default()
+    };
+    let (_, with) = indexing(
+        &mut log,
+        &on,
+        FileFilter::Text,
+        "text.txt",
+        &TEXT.as_bytes().to_vec(),
+    )?;
+
+    let off = Settings {
+        stop_words: false,
+        ..Default::default()
+    };
+    let (_, without) = indexing(
+        &mut log,
+        &off,
+        FileFilter::Text,
+        "text.txt",
+        &TEXT.as_bytes().to_vec(),
+    )?;
+
+    // the same words were seen.
+    assert_eq!(with.total, without.total);
+    assert_eq!(with.stop_count, without.stop_count);
+    assert!(with.stop_count > 0);
+
+    // but only one kept the stop-words.
+    assert_eq!(with.count + with.stop_count, without.count);
+    assert_eq!(without.count, without.total);
+    assert!(without.words.len() > with.words.len());
+    assert!(without.words.contains_key("the"));
+    assert!(!with.words.contains_key("the"));
+    assert!(with.words.contains_key("fox"));
+
+    Ok(())
+}
+
+#[test]
+fn test_stopwords_header() -> Result<(), AppError> {
+    let path = PathBuf::from_str("tmp/stopwords.idx")?;
+
+    let mut w = Words::create(&path)?;
+    assert!(w.stop_words());
+    w.set_stop_words(false);
+    w.write()?;
+    drop(w);
+
+    let w = Words::read(&path)?;
+    assert!(!w.stop_words());
+
+    Ok(())
+}
+
+#[test]
+fn test_stopwords_summary() {
+    let walk = WalkStats {
+        files: 1,
+        words: 200,
+        stop_words: 50,
+        ..Default::default()
+    };
+    assert_eq!(
+        stop_word_summary(true, &walk),
+        "stop-words: 50 of 200 words (25.0%) skipped"
+    );
+    assert_eq!(
+        stop_word_summary(false, &walk),
+        "stop-words: 50 of 200 words (25.0%) would have been skipped"
+    );
+}
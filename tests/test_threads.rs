@@ -0,0 +1,485 @@
+use rustyline::ExternalPrinter;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use textindex::error::AppError;
+use textindex::index2::Words;
+use textindex::proc3::events::EVENT_FILES;
+use textindex::proc3::logger::Logger;
+use textindex::proc3::threads::{init_work, Msg, Work};
+use textindex::proc3::Data;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Keeps everything printed by the workers.
+#[derive(Clone, Default)]
+struct Capture {
+    inner: Arc<(Mutex<Vec<String>>, Condvar)>,
+}
+
+impl ExternalPrinter for Capture {
+    fn print(&mut self, msg: String) -> rustyline::Result<()> {
+        let (lines, cond) = &*self.inner;
+        lines.lock().expect("lines").push(msg);
+        cond.notify_all();
+        Ok(())
+    }
+}
+
+impl Capture {
+    /// Waits until the lines fulfill the condition.
+    fn wait_for(&self, f: impl Fn(&[String]) -> bool) -> bool {
+        let (lines, cond) = &*self.inner;
+        let lines = lines.lock().expect("lines");
+        let (_, result) = cond
+            .wait_timeout_while(lines, TIMEOUT, |v| !f(v))
+            .expect("lines");
+        !result.timed_out()
+    }
+
+    fn lines(&self) -> Vec<String> {
+        self.inner.0.lock().expect("lines").clone()
+    }
+}
+
+fn start(name: &str, n_index: usize) -> Result<(&'static Data, Work, Capture), AppError> {
+    let root = PathBuf::from(format!("tmp/{}", name));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root)?;
+
+    let words = Words::create(Path::new(&format!("tmp/{}.idx", name)))?;
+    let data: &'static Data = Box::leak(Box::new(Data::new(root, words, Logger::new(None))));
+    let printer = Capture::default();
+    let work = init_work(printer.clone(), data, n_index);
+
+    Ok((data, work, printer))
+}
+
+/// Waits until every worker is in the waiting state of the pause.
+fn wait_all_paused(work: &Work) -> bool {
+    let start = Instant::now();
+    while !work.all_paused() {
+        if start.elapsed() > TIMEOUT {
+            return false;
+        }
+        sleep(Duration::from_millis(1));
+    }
+    true
+}
+
+#[test]
+fn test_pipeline_walk() -> Result<(), AppError> {
+    let (data, work, printer) = start("pipeline_walk", 2)?;
+
+    let root = data.root.clone();
+    fs::create_dir_all(root.join("sub"))?;
+    fs::write(root.join("a.txt"), "alpha beta gamma")?;
+    fs::write(root.join("b.txt"), "beta delta")?;
+    fs::write(
+        root.join("sub/c.html"),
+        "<html><body><p>gamma epsilon</p></body></html>",
+    )?;
+    // dropped by the walker and by the loader.
+    fs::write(root.join("d.jpg"), "jpeg")?;
+    fs::write(root.join("e.bin"), [0u8, 1, 2, 3, 0, 1, 2, 3])?;
+
+    work.send.send(Msg::WalkTree(root.clone()))?;
+    assert!(data.progress.wait_idle(1, TIMEOUT));
+    assert!(printer.wait_for(|v| v.iter().any(|m| m.ends_with("finished ***"))));
+    assert_eq!(data.progress.files_done(), 4);
+
+    {
+        let mut words = data.words.lock()?;
+        assert_eq!(words.files().len(), 3);
+        assert_eq!(words.word_len(), 5);
+        for word in ["alpha", "beta", "gamma", "delta", "epsilon"] {
+            assert!(words.word(word)?.is_some(), "{}", word);
+        }
+
+        let mut found = words.find(&["beta".into()])?;
+        found.sort();
+        assert_eq!(found, vec!["a.txt", "b.txt"]);
+        let found = words.find(&["epsilon".into()])?;
+        assert_eq!(found.len(), 1);
+        assert!(found[0].ends_with("c.html"));
+    }
+
+    work.send.send(Msg::Quit)?;
+    assert!(work.wait_finished(TIMEOUT));
+
+    Ok(())
+}
+
+#[test]
+fn test_pipeline_empty() -> Result<(), AppError> {
+    let (data, work, printer) = start("pipeline_empty", 2)?;
+
+    let root = data.root.clone();
+    fs::write(root.join("a.txt"), "alpha beta")?;
+    fs::write(root.join("empty.txt"), "")?;
+    fs::write(root.join("blank.txt"), "  \n\t \n")?;
+
+    work.send.send(Msg::WalkTree(root.clone()))?;
+    assert!(printer.wait_for(|v| v.iter().any(|m| m.ends_with("finished ***"))));
+    assert!(printer
+        .lines()
+        .iter()
+        .any(|v| v == "2 files without words not indexed"));
+    assert_eq!(data.words.lock()?.file_name_list(), vec!["a.txt"]);
+
+    // they are looked at again with the next walk.
+    data.settings.update(|s| s.index_empty = true);
+    work.send.send(Msg::WalkTree(root.clone()))?;
+    assert!(printer.wait_for(|v| v.iter().filter(|m| m.ends_with("finished ***")).count() == 2));
+    let mut files = data.words.lock()?.file_name_list();
+    files.sort();
+    assert_eq!(files, vec!["a.txt", "blank.txt", "empty.txt"]);
+
+    work.send.send(Msg::Quit)?;
+    assert!(work.wait_finished(TIMEOUT));
+
+    Ok(())
+}
+
+#[test]
+fn test_pipeline_quit() -> Result<(), AppError> {
+    let (_data, work, _printer) = start("pipeline_quit", 3)?;
+
+    work.send.send(Msg::Quit)?;
+    assert!(work.wait_finished(TIMEOUT));
+    for worker in work.workers.lock().expect("workers").iter() {
+        assert!(worker.handle.is_finished(), "{} still running", worker.id);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_respawn_stopped() -> Result<(), AppError> {
+    let (data, work, _printer) = start("respawn_stopped", 2)?;
+
+    // stops merge and terminal, as if merge had failed.
+    work.recv_send[2].1.send(Msg::Quit)?;
+    let start = Instant::now();
+    while work
+        .workers
+        .lock()
+        .expect("workers")
+        .iter()
+        .filter(|w| w.handle.is_finished())
+        .count()
+        < 2
+    {
+        assert!(start.elapsed() < TIMEOUT);
+        sleep(Duration::from_millis(1));
+    }
+
+    let mut restarted: Vec<_> = work
+        .respawn_stopped()
+        .into_iter()
+        .map(|(id, n)| (id.to_string(), n))
+        .collect();
+    restarted.sort();
+    assert_eq!(
+        restarted,
+        vec![("merge-1".to_string(), 1), ("terminal-1".to_string(), 1)]
+    );
+    assert!(work.respawn_stopped().is_empty());
+
+    let root = data.root.clone();
+    fs::write(root.join("a.txt"), "alpha")?;
+    work.send.send(Msg::WalkTree(root.clone()))?;
+    assert!(data.progress.wait_idle(1, TIMEOUT));
+    assert!(data.words.lock()?.word("alpha")?.is_some());
+
+    work.quit()?;
+    assert!(work.wait_finished(TIMEOUT));
+    assert!(work.respawn_stopped().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_pipeline_debug() -> Result<(), AppError> {
+    let (_data, work, printer) = start("pipeline_debug", 2)?;
+
+    let id = work.debug()?;
+    let complete = format!("debug {} complete", id);
+    assert!(printer.wait_for(|v| v.iter().any(|m| *m == complete)));
+
+    // each stage in order.
+    let stages = [
+        "walk_tree empty",
+        "loading 0",
+        "indexing 0",
+        "merge words 0",
+        "terminal",
+    ];
+    let lines = printer.lines();
+    let mut pos = lines.iter();
+    for stage in stages {
+        let stage = format!("debug {}: {}", id, stage);
+        assert!(pos.any(|v| *v == stage), "{} missing in {:?}", stage, lines);
+    }
+    assert_eq!(lines.last(), Some(&complete));
+
+    work.send.send(Msg::Quit)?;
+    assert!(work.wait_finished(TIMEOUT));
+
+    Ok(())
+}
+
+#[test]
+fn test_pipeline_debug_ids() -> Result<(), AppError> {
+    let (_data, work, printer) = start("pipeline_debug_ids", 2)?;
+
+    // interleaved requests.
+    let ids = [work.debug()?, work.debug()?, work.debug()?];
+    assert!(ids[0] < ids[1] && ids[1] < ids[2]);
+    assert!(printer.wait_for(|v| ids
+        .iter()
+        .all(|id| v.contains(&format!("debug {} complete", id)))));
+
+    let lines = printer.lines();
+    for id in ids {
+        for stage in [
+            "walk_tree",
+            "loading",
+            "indexing",
+            "merge words",
+            "terminal",
+        ] {
+            let prefix = format!("debug {}: {}", id, stage);
+            let n = lines.iter().filter(|v| v.starts_with(&prefix)).count();
+            assert_eq!(n, 1, "{} in {:?}", prefix, lines);
+        }
+        let complete = format!("debug {} complete", id);
+        assert_eq!(lines.iter().filter(|v| **v == complete).count(), 1);
+    }
+
+    work.send.send(Msg::Quit)?;
+    assert!(work.wait_finished(TIMEOUT));
+
+    Ok(())
+}
+
+#[test]
+fn test_pipeline_pause() -> Result<(), AppError> {
+    let (data, work, printer) = start("pipeline_pause", 2)?;
+
+    let root = data.root.clone();
+    fs::write(root.join("a.txt"), "alpha beta")?;
+    fs::write(root.join("b.txt"), "beta gamma")?;
+
+    assert!(work.pause()?);
+    assert!(!work.pause()?);
+    assert!(wait_all_paused(&work));
+
+    // held back, but Debug is answered by each stage.
+    work.send.send(Msg::WalkTree(root.clone()))?;
+    let id = work.debug()?;
+    let complete = format!("debug {} complete", id);
+    assert!(printer.wait_for(|v| v.iter().any(|m| *m == complete)));
+    let stages = [
+        "walk_tree empty paused",
+        "loading 0 paused",
+        "indexing 0 paused",
+        "merge words 0 paused",
+        "terminal paused",
+    ];
+    let lines = printer.lines();
+    let mut pos = lines.iter();
+    for stage in stages {
+        let stage = format!("debug {}: {}", id, stage);
+        assert!(pos.any(|v| *v == stage), "{} missing in {:?}", stage, lines);
+    }
+
+    sleep(Duration::from_millis(100));
+    assert_eq!(data.progress.files_done(), 0);
+    assert_eq!(data.words.lock()?.files().len(), 0);
+    assert!(work.all_paused());
+
+    assert!(work.resume()?);
+    assert!(!work.resume()?);
+    assert!(data.progress.wait_idle(1, TIMEOUT));
+    assert!(printer.wait_for(|v| v.iter().any(|m| m.ends_with("finished ***"))));
+    assert_eq!(data.progress.files_done(), 2);
+    assert_eq!(data.words.lock()?.files().len(), 2);
+    for worker in work.workers.lock().expect("workers").iter() {
+        assert!(!worker.state.lock().expect("state").paused, "{}", worker.id);
+    }
+
+    work.send.send(Msg::Quit)?;
+    assert!(work.wait_finished(TIMEOUT));
+
+    Ok(())
+}
+
+#[test]
+fn test_pipeline_pause_quit() -> Result<(), AppError> {
+    let (_data, work, _printer) = start("pipeline_pause_quit", 3)?;
+
+    assert!(work.pause()?);
+    assert!(wait_all_paused(&work));
+
+    work.send.send(Msg::Quit)?;
+    assert!(work.wait_finished(TIMEOUT));
+    for worker in work.workers.lock().expect("workers").iter() {
+        assert!(worker.handle.is_finished(), "{} still running", worker.id);
+    }
+
+    Ok(())
+}
+
+/// The value of a field in one line of the progress file.
+fn field<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!("\"{}\":", name);
+    let start = line.find(&key)? + key.len();
+    let rest = &line[start..];
+    let end = rest.find([',', '}'])?;
+    Some(rest[..end].trim_matches('"'))
+}
+
+#[test]
+fn test_pipeline_events() -> Result<(), AppError> {
+    let (data, work, printer) = start("pipeline_events", 2)?;
+    let events = Path::new("tmp/pipeline_events.jsonl");
+    data.events.open(events)?;
+
+    let root = data.root.clone();
+    let n_files = EVENT_FILES + 5;
+    for i in 0..n_files {
+        fs::write(root.join(format!("f{}.txt", i)), "alpha beta")?;
+    }
+
+    work.send.send(Msg::WalkTree(root.clone()))?;
+    assert!(data.progress.wait_idle(1, TIMEOUT));
+    assert!(printer.wait_for(|v| v.iter().any(|m| m.ends_with("finished ***"))));
+    work.send.send(Msg::Quit)?;
+    assert!(work.wait_finished(TIMEOUT));
+    data.events.close();
+
+    let text = fs::read_to_string(events)?;
+    let lines: Vec<&str> = text.lines().collect();
+    for line in &lines {
+        assert!(line.starts_with("{\"ts\":"), "{}", line);
+        assert!(line.ends_with('}'), "{}", line);
+        let ts = field(line, "ts").expect("ts");
+        assert!(ts.parse::<u64>().is_ok(), "{}", line);
+    }
+
+    // auto-saves depend on the timing.
+    let sequence: Vec<&str> = lines
+        .iter()
+        .flat_map(|v| field(v, "event"))
+        .filter(|v| *v != "auto_save")
+        .collect();
+    assert_eq!(sequence, vec!["walk_start", "files", "walk_finished"]);
+
+    let files = lines
+        .iter()
+        .find(|v| field(v, "event") == Some("files"))
+        .expect("files");
+    assert_eq!(field(files, "files"), Some("1000"));
+
+    let finished = lines
+        .iter()
+        .find(|v| field(v, "event") == Some("walk_finished"))
+        .expect("walk_finished");
+    assert_eq!(field(finished, "files"), Some(n_files.to_string().as_str()));
+    assert_eq!(
+        field(finished, "words"),
+        Some((2 * n_files).to_string().as_str())
+    );
+    assert_eq!(field(finished, "dropped"), Some("0"));
+    assert!(field(finished, "path").is_some());
+
+    // nothing without the file.
+    data.events.emit("files", &[]);
+    assert!(!data.events.is_open());
+    assert_eq!(fs::read_to_string(events)?, text);
+
+    Ok(())
+}
+
+/// Prints to the capture until the terminal is gone.
+#[derive(Clone, Default)]
+struct Flaky {
+    capture: Capture,
+    gone: Arc<AtomicBool>,
+}
+
+impl ExternalPrinter for Flaky {
+    fn print(&mut self, msg: String) -> rustyline::Result<()> {
+        if self.gone.load(Ordering::Acquire) {
+            Err(rustyline::error::ReadlineError::Eof)
+        } else {
+            self.capture.print(msg)
+        }
+    }
+}
+
+/// Waits until the log fulfills the condition.
+fn wait_log(data: &Data, f: impl Fn(&[String]) -> bool) -> bool {
+    let start = Instant::now();
+    while !f(&data.log.recent()) {
+        if start.elapsed() > TIMEOUT {
+            return false;
+        }
+        sleep(Duration::from_millis(1));
+    }
+    true
+}
+
+#[test]
+fn test_pipeline_printer_gone() -> Result<(), AppError> {
+    let root = PathBuf::from("tmp/pipeline_printer_gone");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root)?;
+    let words = Words::create(Path::new("tmp/pipeline_printer_gone.idx"))?;
+    let data: &'static Data = Box::leak(Box::new(Data::new(root, words, Logger::new(None))));
+    let printer = Flaky::default();
+    let work = init_work(printer.clone(), data, 2);
+
+    let id = work.debug()?;
+    let terminal = format!("debug {}: terminal", id);
+    assert!(printer
+        .capture
+        .wait_for(|v| v.iter().any(|m| *m == terminal)));
+    assert!(!data.log.recent().iter().any(|m| *m == terminal));
+
+    // the stages keep printing, to the log.
+    printer.gone.store(true, Ordering::Release);
+    let id = work.debug()?;
+    let complete = format!("debug {} complete", id);
+    assert!(wait_log(data, |v| v.iter().any(|m| *m == complete)));
+
+    let log = data.log.recent();
+    let notes = log
+        .iter()
+        .filter(|v| v.starts_with("terminal is gone"))
+        .count();
+    assert_eq!(notes, 1);
+    let mut pos = log.iter();
+    for stage in ["walk_tree empty", "loading 0", "merge words 0", "terminal"] {
+        let stage = format!("debug {}: {}", id, stage);
+        assert!(pos.any(|v| *v == stage), "{} missing in {:?}", stage, log);
+    }
+    assert_eq!(
+        printer
+            .capture
+            .lines()
+            .iter()
+            .filter(|v| v.ends_with(": terminal"))
+            .count(),
+        1
+    );
+
+    work.send.send(Msg::Quit)?;
+    assert!(work.wait_finished(TIMEOUT));
+
+    Ok(())
+}
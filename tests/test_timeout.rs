@@ -0,0 +1,191 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+use textindex::error::AppError;
+use textindex::index2::tmp_index::TmpWords;
+use textindex::index2::Words;
+use textindex::proc3::indexer::{index_html2, index_txt2};
+use textindex::proc3::logger::Logger;
+use textindex::proc3::{indexing, reindex_partial, Data, FileFilter, Settings};
+
+const WORDS: usize = 20_000;
+
+/// Letters only, digits would not be a text-word.
+fn word(n: usize) -> String {
+    let digits = n.to_string().bytes().map(|v| (b'a' + v - b'0') as char);
+    format!("x{}", digits.collect::<String>())
+}
+
+fn slow() {
+    thread::sleep(Duration::from_millis(10));
+}
+
+fn slow_words(file: &str) -> TmpWords {
+    let mut words = TmpWords::new(file);
+    words.deadline = Some(Instant::now() + Duration::from_millis(30));
+    words.check_hook = Some(slow);
+    words
+}
+
+#[test]
+fn test_timeout_txt() -> Result<(), AppError> {
+    let text: String = (0..WORDS).map(|v| format!("{} ", word(v))).collect();
+
+    let mut log = File::create("tmp/timeout_txt.log")?;
+    let mut words = slow_words("slow.txt");
+    index_txt2(&mut log, "slow.txt", &mut words, &text)?;
+    drop(log);
+
+    assert!(words.partial);
+    assert!(words.words.contains_key(&word(0)));
+    assert!(!words.words.contains_key(&word(WORDS - 1)));
+    assert!(words.count < WORDS);
+
+    let log = fs::read_to_string("tmp/timeout_txt.log")?;
+    assert_eq!(log.matches("slow.txt partially indexed").count(), 1);
+
+    // without a deadline.
+    let mut log = File::create("tmp/timeout_txt.log")?;
+    let mut words = TmpWords::new("slow.txt");
+    words.check_hook = Some(slow);
+    index_txt2(&mut log, "slow.txt", &mut words, &text[..2000])?;
+    assert!(!words.partial);
+
+    Ok(())
+}
+
+#[test]
+fn test_timeout_html() -> Result<(), AppError> {
+    let text: String = (0..WORDS).map(|v| format!("<p>{} </p>", word(v))).collect();
+
+    let mut log = File::create("tmp/timeout_html.log")?;
+    let mut words = slow_words("slow.html");
+    index_html2(&mut log, "slow.html", &mut words, &text)?;
+    drop(log);
+
+    // the text up to the deadline is still indexed.
+    assert!(words.partial);
+    assert!(words.words.contains_key(&word(0)));
+    assert!(words.count < WORDS);
+
+    let log = fs::read_to_string("tmp/timeout_html.log")?;
+    assert_eq!(log.matches("slow.html partially indexed").count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_timeout_settings() -> Result<(), AppError> {
+    let mut log = File::create("tmp/timeout_settings.log")?;
+
+    let (_, words) = indexing(
+        &mut log,
+        &Settings::default(),
+        FileFilter::Text,
+        "quick.txt",
+        &b"fish and chips".to_vec(),
+    )?;
+    assert!(words.deadline.is_some());
+    assert!(!words.partial);
+
+    let settings = Settings {
+        index_timeout: Duration::ZERO,
+        ..Settings::default()
+    };
+    let (_, words) = indexing(
+        &mut log,
+        &settings,
+        FileFilter::Text,
+        "quick.txt",
+        &b"fish and chips".to_vec(),
+    )?;
+    assert!(words.deadline.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_timeout_flag() -> Result<(), AppError> {
+    let path = Path::new("tmp/timeout_flag.idx");
+
+    let mut w = Words::create(path)?;
+    let mut words = TmpWords::new("dir/complete.txt");
+    words.add_word("fish");
+    w.append(words)?;
+    let mut words = TmpWords::new("dir/partial.txt");
+    words.add_word("fish");
+    words.add_word("chips");
+    words.partial = true;
+    w.append(words)?;
+    assert_eq!(w.partial_files(), vec!["dir/partial.txt"]);
+    w.write()?;
+    drop(w);
+
+    let mut w = Words::read(path)?;
+    assert_eq!(w.partial_files(), vec!["dir/partial.txt"]);
+    // the partial words are merged.
+    assert_eq!(w.find(&["chips".into()])?, vec!["dir/partial.txt"]);
+
+    let mut words = TmpWords::new("dir/other.txt");
+    words.add_word("chips");
+    w.append(words)?;
+    w.write()?;
+    drop(w);
+
+    let mut w = Words::read(path)?;
+    assert_eq!(w.partial_files(), vec!["dir/partial.txt"]);
+    assert_eq!(w.files().len(), 3);
+    assert!(w.check(None).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_reindex_partial() -> Result<(), AppError> {
+    let root = PathBuf::from("tmp/reindex_partial");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("dir"))?;
+    fs::write(root.join("dir/partial.txt"), "fish chips vinegar")?;
+    let path = Path::new("tmp/reindex_partial.idx");
+
+    // as if the indexer ran out of time after the first word.
+    let mut w = Words::create(path)?;
+    let mut words = TmpWords::new("dir/partial.txt");
+    words.add_word("fish");
+    words.partial = true;
+    w.append(words)?;
+    let mut words = TmpWords::new("dir/gone.txt");
+    words.add_word("fish");
+    words.partial = true;
+    w.append(words)?;
+    w.write()?;
+    drop(w);
+
+    let w = Words::read(path)?;
+    let data: &'static Data = Box::leak(Box::new(Data::new(root, w, Logger::new(None))));
+
+    let report = reindex_partial(data)?;
+    assert_eq!(report.complete, vec!["dir/partial.txt"]);
+    assert!(report.partial.is_empty());
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].0, "dir/gone.txt");
+    {
+        let mut w = data.words.lock()?;
+        assert_eq!(w.partial_files(), vec!["dir/gone.txt"]);
+        assert_eq!(w.files().len(), 2);
+        assert_eq!(w.find(&["vinegar".into()])?, vec!["dir/partial.txt"]);
+    }
+
+    // the cleared flag is stored.
+    let mut w = Words::read(path)?;
+    assert_eq!(w.partial_files(), vec!["dir/gone.txt"]);
+    assert_eq!(w.find(&["vinegar".into()])?, vec!["dir/partial.txt"]);
+    assert_eq!(
+        w.find(&["fish".into()])?,
+        vec!["dir/partial.txt", "dir/gone.txt"]
+    );
+    assert!(w.check(None).is_ok());
+
+    Ok(())
+}
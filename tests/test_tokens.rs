@@ -0,0 +1,71 @@
+use std::fs::{self, File};
+use std::path::Path;
+use textindex::error::AppError;
+use textindex::proc3::txt_parse::{normalize_word, split_words};
+use textindex::proc3::{find_matched_lines, indexing, FileFilter, Settings, DEFAULT_LINE_WIDTH};
+
+#[test]
+fn test_split_words() {
+    assert_eq!(split_words("don't stop"), vec!["don't", "stop"]);
+    assert_eq!(split_words("send an e-mail."), vec!["send", "an", "e-mail"]);
+    assert_eq!(split_words("rock 'n' roll"), vec!["rock", "n", "roll"]);
+    assert_eq!(
+        split_words("well- known -edge-"),
+        vec!["well", "known", "edge"]
+    );
+    assert_eq!(split_words("a--b a-'b"), vec!["a", "b", "a", "b"]);
+    assert_eq!(split_words("the dogs' bones"), vec!["the", "dogs", "bones"]);
+    assert_eq!(split_words("l'état-major"), vec!["l'état-major"]);
+    assert_eq!(split_words("don\u{2019}t"), vec!["don\u{2019}t"]);
+    assert!(split_words(" -- ' 42 ").is_empty());
+}
+
+#[test]
+fn test_normalize_word() {
+    assert_eq!(normalize_word("Don\u{2019}t"), "don't");
+    assert_eq!(normalize_word("E-Mail"), "e-mail");
+}
+
+#[test]
+fn test_index_and_match_agree() -> Result<(), AppError> {
+    let text = "Don\u{2019}t send an E-mail\nrock 'n' roll\nhalf- baked\n";
+
+    let mut log = File::create("tmp/tokens.log")?;
+    let (_, words) = indexing(
+        &mut log,
+        &Settings {
+            stop_words: false,
+            ..Default::default()
+        },
+        FileFilter::Text,
+        "tokens.txt",
+        &text.as_bytes().to_vec(),
+    )?;
+    assert!(words.words.contains_key("don't"));
+    assert!(words.words.contains_key("e-mail"));
+    assert!(words.words.contains_key("half"));
+    assert!(!words.words.contains_key("don"));
+    assert!(!words.words.contains_key("t"));
+    assert!(!words.words.contains_key("mail"));
+    assert!(!words.words.contains_key("half-"));
+
+    let root = Path::new("tmp/tokens");
+    fs::create_dir_all(root)?;
+    fs::write(root.join("tokens.txt"), text)?;
+
+    let files = vec!["tokens.txt".to_string()];
+    let lines = find_matched_lines(
+        root,
+        &["don't".into(), "e-mail".into()],
+        &files,
+        DEFAULT_LINE_WIDTH,
+        false,
+    )?;
+    assert_eq!(lines[0].1, vec!["Don\u{2019}t send an E-mail"]);
+    let lines = find_matched_lines(root, &["half".into()], &files, DEFAULT_LINE_WIDTH, false)?;
+    assert_eq!(lines[0].1, vec!["half- baked"]);
+    let lines = find_matched_lines(root, &["mail".into()], &files, DEFAULT_LINE_WIDTH, false)?;
+    assert!(lines[0].1.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,196 @@
+use std::fs::File;
+use std::path::Path;
+use textindex::error::AppError;
+use textindex::index2::Words;
+use textindex::proc3::{indexing, FileFilter, Settings, WORDLIST_MIN_BYTES};
+
+const PROSE: &str = "The harbour was quiet in the early morning, and the boats \
+    lay still against the wall. A few gulls walked along the quay, looking \
+    for what the fishermen had left behind the evening before. ";
+
+/// One generated word per line, like a dictionary.
+fn dictionary() -> String {
+    let mut txt = String::new();
+    let mut i = 0usize;
+    while txt.len() < 2 * WORDLIST_MIN_BYTES {
+        let mut n = i;
+        let mut word = String::new();
+        for _ in 0..6 {
+            word.push((b'a' + (n % 26) as u8) as char);
+            n /= 26;
+        }
+        txt.push_str(&word);
+        txt.push('\n');
+        i += 1;
+    }
+    txt
+}
+
+/// Words of 6 to 12 letters, ~10 bytes per line like a real word list.
+fn realistic_words() -> Vec<String> {
+    let mut words = Vec::new();
+    let mut seed = 12345u32;
+    let mut len = 0usize;
+    while len < 2 * WORDLIST_MIN_BYTES {
+        let mut word = String::new();
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        for _ in 0..6 + (seed >> 16) % 7 {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            word.push((b'a' + ((seed >> 16) % 26) as u8) as char);
+        }
+        len += word.len() + 1;
+        words.push(word);
+    }
+    words
+}
+
+fn prose() -> String {
+    let mut txt = String::new();
+    let mut i = 0usize;
+    while txt.len() < 2 * WORDLIST_MIN_BYTES {
+        txt.push_str(&format!("Chapter {}. ", i));
+        txt.push_str(PROSE);
+        i += 1;
+    }
+    txt
+}
+
+#[test]
+fn test_wordlist() -> Result<(), AppError> {
+    let mut log = File::create("tmp/wordlist.log")?;
+    let settings = Settings {
+        stop_words: false,
+        ..Default::default()
+    };
+
+    let dict = dictionary();
+    let (_, words) = indexing(
+        &mut log,
+        &settings,
+        FileFilter::Text,
+        "words.txt",
+        &dict.as_bytes().to_vec(),
+    )?;
+    assert!(words.wordlist);
+    assert!(!words.skipped);
+    assert!(!words.words.is_empty());
+
+    let txt = prose();
+    let (_, words) = indexing(
+        &mut log,
+        &settings,
+        FileFilter::Text,
+        "prose.txt",
+        &txt.as_bytes().to_vec(),
+    )?;
+    assert!(!words.wordlist);
+    assert!(words.words.contains_key("harbour"));
+
+    // small files are not checked.
+    let (_, words) = indexing(
+        &mut log,
+        &settings,
+        FileFilter::Text,
+        "short.txt",
+        &dict.as_bytes()[..1000].to_vec(),
+    )?;
+    assert!(!words.wordlist);
+
+    // off.
+    let off = Settings {
+        wordlist_ratio: 0,
+        ..Default::default()
+    };
+    let (_, words) = indexing(
+        &mut log,
+        &off,
+        FileFilter::Text,
+        "words.txt",
+        &dict.as_bytes().to_vec(),
+    )?;
+    assert!(!words.wordlist);
+
+    Ok(())
+}
+
+#[test]
+fn test_wordlist_realistic() -> Result<(), AppError> {
+    let mut log = File::create("tmp/wordlist_realistic.log")?;
+    let settings = Settings {
+        stop_words: false,
+        ..Default::default()
+    };
+
+    let list = realistic_words();
+    let txt = list.join("\n");
+    let (_, words) = indexing(
+        &mut log,
+        &settings,
+        FileFilter::Text,
+        "words.txt",
+        &txt.as_bytes().to_vec(),
+    )?;
+    assert!(words.wordlist);
+    assert!(words.words_per_kb() > 90);
+
+    // the markup doesn't count.
+    let mut html = String::from("<html><body><ul>\n");
+    for word in &list {
+        html.push_str(&format!("<li class=\"entry\">{}</li>\n", word));
+    }
+    html.push_str("</ul></body></html>\n");
+    let (_, words) = indexing(
+        &mut log,
+        &settings,
+        FileFilter::Html,
+        "words.html",
+        &html.as_bytes().to_vec(),
+    )?;
+    assert!(words.text_len < html.len() / 2);
+    assert!(words.wordlist);
+
+    let txt = prose();
+    let (_, words) = indexing(
+        &mut log,
+        &settings,
+        FileFilter::Text,
+        "prose.txt",
+        &txt.as_bytes().to_vec(),
+    )?;
+    assert!(words.words_per_kb() < 20);
+    assert!(!words.wordlist);
+
+    Ok(())
+}
+
+#[test]
+fn test_wordlist_skip() -> Result<(), AppError> {
+    let path = Path::new("tmp/wordlist_skip.idx");
+    let mut log = File::create("tmp/wordlist_skip.log")?;
+    let settings = Settings {
+        skip_wordlists: true,
+        ..Default::default()
+    };
+
+    let mut w = Words::create(path)?;
+    for (name, txt) in [("words.txt", dictionary()), ("prose.txt", prose())] {
+        let (_, words) = indexing(
+            &mut log,
+            &settings,
+            FileFilter::Text,
+            name,
+            &txt.as_bytes().to_vec(),
+        )?;
+        w.append(words)?;
+    }
+    w.write()?;
+    drop(w);
+
+    let mut w = Words::read(path)?;
+    assert_eq!(w.files().len(), 2);
+    assert_eq!(w.wordlist_files(), vec![("words.txt".to_string(), true)]);
+    assert!(w.find(&["aaaaaa".into()])?.is_empty());
+    assert_eq!(w.find(&["harbour".into()])?, vec!["prose.txt"]);
+
+    Ok(())
+}